@@ -0,0 +1,47 @@
+/* C bindings of the two-three-tree crate. See src/ffi.rs. */
+
+#ifndef TWO_THREE_TREE_H
+#define TWO_THREE_TREE_H
+
+#include <stdbool.h>
+#include <stddef.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Opaque handles. */
+typedef struct TwoThreeTree TwoThreeTree;
+typedef struct Cursor Cursor;
+
+/* Creates an empty tree. Free it with tt_tree_free(). */
+TwoThreeTree *tt_tree_new(void);
+
+/* Frees a tree. Does nothing for NULL. */
+void tt_tree_free(TwoThreeTree *tree);
+
+size_t tt_tree_size(const TwoThreeTree *tree);
+
+void tt_tree_insert(TwoThreeTree *tree, size_t key, size_t value);
+
+/* Finds an element with the given key. Returns true and stores its value if found. */
+bool tt_tree_find(const TwoThreeTree *tree, size_t key, size_t *value);
+
+/* Deletes an element with the given key. Returns true if the element was found. */
+bool tt_tree_delete(TwoThreeTree *tree, size_t key);
+
+/* Creates a cursor before the first element of the tree. Free it with tt_cursor_free(). */
+Cursor *tt_cursor_new(const TwoThreeTree *tree);
+
+/* Frees a cursor. Does nothing for NULL. */
+void tt_cursor_free(Cursor *cursor);
+
+/* Advances the cursor and stores the next element in key order. Returns false at the end, or
+   if the tree has been mutated since the cursor was created. */
+bool tt_cursor_next(Cursor *cursor, const TwoThreeTree *tree, size_t *key, size_t *value);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,293 @@
+// Built-in augmentations for range aggregate queries over the values of a 2-3 Tree.
+//
+// A tree created with one of these augmentations answers sum, min or max queries over any key
+// range in O(log n). Stats maintains all three.
+//
+// Weighted<W> maintains the sum of per-element weights given by W, independently of the values:
+// select_by_weight() finds the element at a cumulative weight, e.g. a uniform random number
+// below total_weight() for weighted sampling.
+
+use std::marker::PhantomData;
+use std::ops::RangeBounds;
+
+use crate::two_three_tree::{Augment, Element, TwoThreeTree};
+
+// Sum of the values. Sums are computed in u128 so they cannot overflow.
+pub struct Sum;
+
+// Minimum of the values.
+pub struct Min;
+
+// Maximum of the values.
+pub struct Max;
+
+// Sum, minimum and maximum of the values.
+pub struct Stats;
+
+// Gives the weight of each element for Weighted, e.g. from some bits of the value or from a
+// table indexed by the key.
+pub trait Weight {
+    fn weight(element: &Element) -> u128;
+}
+
+// Sum of the weights of the elements, given by W.
+pub struct Weighted<W: Weight>(PhantomData<W>);
+
+// The aggregate maintained by Stats.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct ValueStats {
+    pub sum: u128,
+    pub min: Option<usize>,
+    pub max: Option<usize>,
+}
+
+impl Augment for Sum {
+    type Value = u128;
+
+    fn identity() -> u128 {
+        0
+    }
+
+    fn element(element: &Element) -> u128 {
+        element.value as u128
+    }
+
+    fn combine(left: &u128, right: &u128) -> u128 {
+        left + right
+    }
+}
+
+impl Augment for Min {
+    type Value = Option<usize>;
+
+    fn identity() -> Option<usize> {
+        None
+    }
+
+    fn element(element: &Element) -> Option<usize> {
+        Some(element.value)
+    }
+
+    fn combine(left: &Option<usize>, right: &Option<usize>) -> Option<usize> {
+        match (left, right) {
+            (Some(left), Some(right)) => Some(*left.min(right)),
+            _ => left.or(*right),
+        }
+    }
+}
+
+impl Augment for Max {
+    type Value = Option<usize>;
+
+    fn identity() -> Option<usize> {
+        None
+    }
+
+    fn element(element: &Element) -> Option<usize> {
+        Some(element.value)
+    }
+
+    fn combine(left: &Option<usize>, right: &Option<usize>) -> Option<usize> {
+        *left.max(right)
+    }
+}
+
+impl Augment for Stats {
+    type Value = ValueStats;
+
+    fn identity() -> ValueStats {
+        ValueStats {
+            sum: Sum::identity(),
+            min: Min::identity(),
+            max: Max::identity(),
+        }
+    }
+
+    fn element(element: &Element) -> ValueStats {
+        ValueStats {
+            sum: Sum::element(element),
+            min: Min::element(element),
+            max: Max::element(element),
+        }
+    }
+
+    fn combine(left: &ValueStats, right: &ValueStats) -> ValueStats {
+        ValueStats {
+            sum: Sum::combine(&left.sum, &right.sum),
+            min: Min::combine(&left.min, &right.min),
+            max: Max::combine(&left.max, &right.max),
+        }
+    }
+}
+
+impl<W: Weight> Augment for Weighted<W> {
+    type Value = u128;
+
+    fn identity() -> u128 {
+        0
+    }
+
+    fn element(element: &Element) -> u128 {
+        W::weight(element)
+    }
+
+    fn combine(left: &u128, right: &u128) -> u128 {
+        left + right
+    }
+}
+
+impl TwoThreeTree<Sum> {
+    // Returns the sum of the values with keys in the range.
+    pub fn range_sum(&self, range: impl RangeBounds<usize>) -> u128 {
+        self.query_range(range)
+    }
+}
+
+impl<W: Weight> TwoThreeTree<Weighted<W>> {
+    // Returns the sum of the weights of the elements with keys in the range.
+    pub fn range_weight(&self, range: impl RangeBounds<usize>) -> u128 {
+        self.query_range(range)
+    }
+
+    // Returns the sum of all the weights, in O(1).
+    pub fn total_weight(&self) -> u128 {
+        self.root_ref().map_or(0, |root| *root.aug())
+    }
+
+    // Returns the element whose weight spans the given cumulative weight, i.e. the first element
+    // whose weight, added to the weights before it in key order, exceeds the weight. Returns
+    // None if the weight is not below total_weight(). Elements of weight 0 are never returned.
+    pub fn select_by_weight(&self, weight: u128) -> Option<Element> {
+        let mut weight = weight;
+        let mut node = self.root_ref()?;
+        'descend: loop {
+            let mut children = node.children();
+            for element in node.elements() {
+                if let Some(child) = children.next() {
+                    if weight < *child.aug() {
+                        node = child;
+                        continue 'descend;
+                    }
+                    weight -= child.aug();
+                }
+                let element_weight = W::weight(&element);
+                if weight < element_weight {
+                    return Some(element);
+                }
+                weight -= element_weight;
+            }
+            node = children.next()?;
+        }
+    }
+}
+
+impl TwoThreeTree<Min> {
+    // Returns the minimum value with a key in the range, or None if the range is empty.
+    pub fn range_min(&self, range: impl RangeBounds<usize>) -> Option<usize> {
+        self.query_range(range)
+    }
+}
+
+impl TwoThreeTree<Max> {
+    // Returns the maximum value with a key in the range, or None if the range is empty.
+    pub fn range_max(&self, range: impl RangeBounds<usize>) -> Option<usize> {
+        self.query_range(range)
+    }
+}
+
+impl TwoThreeTree<Stats> {
+    // Returns the sum of the values with keys in the range.
+    pub fn range_sum(&self, range: impl RangeBounds<usize>) -> u128 {
+        self.query_range(range).sum
+    }
+
+    // Returns the minimum value with a key in the range, or None if the range is empty.
+    pub fn range_min(&self, range: impl RangeBounds<usize>) -> Option<usize> {
+        self.query_range(range).min
+    }
+
+    // Returns the maximum value with a key in the range, or None if the range is empty.
+    pub fn range_max(&self, range: impl RangeBounds<usize>) -> Option<usize> {
+        self.query_range(range).max
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Max, Stats, Weight, Weighted};
+    use crate::two_three_tree::{Element, TwoThreeTree};
+
+    // The low byte of the value is the weight, the rest a payload.
+    struct LowByte;
+
+    impl Weight for LowByte {
+        fn weight(element: &Element) -> u128 {
+            (element.value & 0xff) as u128
+        }
+    }
+
+    #[test]
+    fn test_range_stats() {
+        let mut tree = TwoThreeTree::<Stats>::with_augment();
+        let mut max_tree = TwoThreeTree::<Max>::with_augment();
+        for i in 0..300 {
+            let element = Element {
+                key: (i * 37) % 211,
+                value: (i * 7919) % 1000,
+            };
+            tree.insert(element);
+            max_tree.insert(element);
+        }
+        for key in (0..211).step_by(3) {
+            tree.delete(key);
+            max_tree.delete(key);
+        }
+        tree.validate();
+
+        let elements: Vec<Element> = tree.iter().collect();
+        for (start, end) in [(0, 211), (5, 6), (17, 90), (100, 100), (200, 300)] {
+            let values = || {
+                elements
+                    .iter()
+                    .filter(move |e| start <= e.key && e.key <= end)
+                    .map(|e| e.value)
+            };
+            assert!(tree.range_sum(start..=end) == values().map(|v| v as u128).sum::<u128>());
+            assert!(tree.range_min(start..=end) == values().min());
+            assert!(tree.range_max(start..=end) == values().max());
+            assert!(max_tree.range_max(start..=end) == values().max());
+        }
+        assert!(tree.range_min(1000..).is_none());
+    }
+
+    #[test]
+    fn test_select_by_weight() {
+        let mut tree = TwoThreeTree::<Weighted<LowByte>>::with_augment();
+        assert!(tree.total_weight() == 0 && tree.select_by_weight(0).is_none());
+        let weights: Vec<usize> = (0..100).map(|key| key % 4).collect();
+        for (key, &weight) in weights.iter().enumerate().rev() {
+            tree.insert(Element {
+                key,
+                value: key << 8 | weight,
+            });
+        }
+        let total: usize = weights.iter().sum();
+        assert!(tree.total_weight() == total as u128);
+
+        // Each key is selected by as many weights as its own weight.
+        let mut hits = vec![0; weights.len()];
+        for weight in 0..total {
+            hits[tree.select_by_weight(weight as u128).unwrap().key] += 1;
+        }
+        assert!(hits == weights);
+        assert!(tree.select_by_weight(total as u128).is_none());
+
+        assert!(tree.range_weight(..4) == 6);
+
+        tree.update(3, |value| *value = 3 << 8 | 10);
+        assert!(tree.total_weight() == total as u128 + 7);
+        let selected = tree.select_by_weight(3).unwrap();
+        assert!(selected.key == 3 && selected.value >> 8 == 3);
+        assert!(tree.select_by_weight(13).unwrap().key == 5);
+    }
+}
@@ -0,0 +1,93 @@
+// A slot arena, holding the values which the trees of some wrappers refer to by index.
+//
+// A removed value frees its slot, which the next insertion reuses, so the indexes stay small
+// and stable while the value is in the arena. Each slot counts its removals, so that a handle
+// can tell a reused slot from the one it was given.
+
+// A slot holding a value, or a free slot.
+struct Slot<T> {
+    // The number of values removed from the slot.
+    generation: u64,
+
+    value: Option<T>,
+}
+
+pub(crate) struct Arena<T> {
+    slots: Vec<Slot<T>>,
+
+    // Indexes of the free slots.
+    free: Vec<usize>,
+}
+
+impl<T> Arena<T> {
+    pub(crate) fn new() -> Arena<T> {
+        Arena {
+            slots: Vec::new(),
+            free: Vec::new(),
+        }
+    }
+
+    // Returns the number of slots, used or free.
+    #[cfg(test)]
+    pub(crate) fn slots(&self) -> usize {
+        self.slots.len()
+    }
+
+    // Stores a value in a free slot, or in a new one, and returns its index.
+    pub(crate) fn insert(&mut self, value: T) -> usize {
+        match self.free.pop() {
+            Some(index) => {
+                self.slots[index].value = Some(value);
+                index
+            }
+            None => {
+                self.slots.push(Slot {
+                    generation: 0,
+                    value: Some(value),
+                });
+                self.slots.len() - 1
+            }
+        }
+    }
+
+    pub(crate) fn get(&self, index: usize) -> Option<&T> {
+        self.slots.get(index)?.value.as_ref()
+    }
+
+    pub(crate) fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        self.slots.get_mut(index)?.value.as_mut()
+    }
+
+    // Removes the value of a slot, freeing it. Returns None if the slot is free.
+    pub(crate) fn remove(&mut self, index: usize) -> Option<T> {
+        let slot = self.slots.get_mut(index)?;
+        let value = slot.value.take()?;
+        slot.generation += 1;
+        self.free.push(index);
+        Some(value)
+    }
+
+    // Returns the number of values removed from a slot so far.
+    pub(crate) fn generation(&self, index: usize) -> u64 {
+        self.slots.get(index).map_or(0, |slot| slot.generation)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Arena;
+
+    #[test]
+    fn test_arena() {
+        let mut arena = Arena::new();
+        let indexes: Vec<usize> = (0..10).map(|i| arena.insert(i * 10)).collect();
+        assert!(indexes == (0..10).collect::<Vec<_>>());
+        *arena.get_mut(3).unwrap() += 1;
+        assert!(arena.get(3) == Some(&31) && arena.get(10).is_none());
+
+        assert!(arena.remove(3) == Some(31) && arena.remove(3).is_none());
+        assert!(arena.get(3).is_none() && arena.generation(3) == 1);
+        assert!(arena.insert(100) == 3 && arena.get(3) == Some(&100));
+        assert!(arena.slots() == 10 && arena.generation(3) == 1);
+    }
+}
@@ -0,0 +1,222 @@
+// An async API over paged 2-3 Trees, for use in tokio services.
+//
+// Store IO is blocking, so each operation runs on tokio's blocking thread pool instead of the
+// async worker threads. The tree is shared behind a mutex, so operations run one at a time.
+
+use std::collections::VecDeque;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+use futures_core::Stream;
+use tokio::task::JoinHandle;
+
+use crate::paged::{NodeStore, PagedTwoThreeTree};
+use crate::two_three_tree::Element;
+
+// Number of elements a stream reads from the store at a time.
+const STREAM_BATCH: usize = 256;
+
+// A paged tree with async operations. Clones share the same tree.
+pub struct AsyncPagedTwoThreeTree<S: NodeStore> {
+    tree: Arc<Mutex<PagedTwoThreeTree<S>>>,
+}
+
+impl<S: NodeStore> Clone for AsyncPagedTwoThreeTree<S> {
+    fn clone(&self) -> Self {
+        AsyncPagedTwoThreeTree {
+            tree: Arc::clone(&self.tree),
+        }
+    }
+}
+
+impl<S: NodeStore + Send + 'static> AsyncPagedTwoThreeTree<S> {
+    pub fn new(tree: PagedTwoThreeTree<S>) -> AsyncPagedTwoThreeTree<S> {
+        AsyncPagedTwoThreeTree {
+            tree: Arc::new(Mutex::new(tree)),
+        }
+    }
+
+    pub async fn find_async(&self, key: usize) -> io::Result<Option<Element>> {
+        self.run(move |tree| tree.find(key)).await
+    }
+
+    pub async fn insert_async(&self, element: Element) -> io::Result<()> {
+        self.run(move |tree| tree.insert(element)).await
+    }
+
+    // Deletes an element with the given key. Returns true if the element was found.
+    pub async fn delete_async(&self, key: usize) -> io::Result<bool> {
+        self.run(move |tree| tree.delete(key)).await
+    }
+
+    pub async fn flush_async(&self) -> io::Result<()> {
+        self.run(|tree| tree.flush()).await
+    }
+
+    // Returns a stream of the elements in key order, read in batches.
+    // Mutations between batches are seen by the batches after them.
+    pub fn stream(&self) -> ElementStream<S> {
+        ElementStream {
+            tree: self.clone(),
+            next: Some(0),
+            buffer: VecDeque::new(),
+            pending: None,
+        }
+    }
+
+    // Runs f on the tree in the blocking thread pool.
+    async fn run<R: Send + 'static>(
+        &self,
+        f: impl FnOnce(&mut PagedTwoThreeTree<S>) -> io::Result<R> + Send + 'static,
+    ) -> io::Result<R> {
+        let tree = Arc::clone(&self.tree);
+        tokio::task::spawn_blocking(move || f(&mut tree.lock().unwrap()))
+            .await
+            .map_err(io::Error::other)?
+    }
+}
+
+// A stream of the elements of an AsyncPagedTwoThreeTree.
+pub struct ElementStream<S: NodeStore> {
+    tree: AsyncPagedTwoThreeTree<S>,
+
+    // The key to read the next batch from, after the last returned key. None when the last
+    // batch has been read.
+    next: Option<usize>,
+
+    buffer: VecDeque<Element>,
+    pending: Option<JoinHandle<io::Result<Batch>>>,
+}
+
+// A batch read by a stream: the elements, which hold all the elements with their keys, and
+// whether elements may follow.
+type Batch = (Vec<Element>, bool);
+
+impl<S: NodeStore + Send + 'static> Stream for ElementStream<S> {
+    type Item = io::Result<Element>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            if let Some(element) = self.buffer.pop_front() {
+                return Poll::Ready(Some(Ok(element)));
+            }
+            let Some(key) = self.next else {
+                return Poll::Ready(None);
+            };
+            let tree = Arc::clone(&self.tree.tree);
+            let pending = self.pending.get_or_insert_with(|| {
+                tokio::task::spawn_blocking(move || {
+                    let mut tree = tree.lock().unwrap();
+                    // A batch ends with the last element of a key, so the next one resumes after
+                    // that key. A key with more elements than a batch takes a larger read.
+                    let mut limit = STREAM_BATCH;
+                    loop {
+                        let mut elements = tree.read_from(key, limit)?;
+                        if elements.len() < limit {
+                            return Ok((elements, false));
+                        }
+                        let last = elements.last().unwrap().key;
+                        let end = elements.partition_point(|e| e.key < last);
+                        if end > 0 {
+                            elements.truncate(end);
+                            return Ok((elements, true));
+                        }
+                        limit *= 2;
+                    }
+                })
+            });
+            let result = match Pin::new(pending).poll(cx) {
+                Poll::Ready(result) => result.map_err(io::Error::other).and_then(|r| r),
+                Poll::Pending => return Poll::Pending,
+            };
+            self.pending = None;
+            let (elements, more) = match result {
+                Ok(batch) => batch,
+                Err(err) => {
+                    self.next = None;
+                    return Poll::Ready(Some(Err(err)));
+                }
+            };
+
+            // Continue after the key of the last element.
+            self.next = match elements.last() {
+                Some(last) if more => last.key.checked_add(1),
+                _ => None,
+            };
+            self.buffer.extend(elements);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future::poll_fn;
+    use std::pin::Pin;
+
+    use futures_core::Stream;
+
+    use super::AsyncPagedTwoThreeTree;
+    use crate::paged::{MemoryNodeStore, PagedTwoThreeTree};
+    use crate::two_three_tree::Element;
+
+    #[test]
+    fn test_async_tree() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let tree = AsyncPagedTwoThreeTree::new(PagedTwoThreeTree::new(MemoryNodeStore::new()));
+            for i in 0..1000 {
+                tree.insert_async(Element {
+                    key: i / 2,
+                    value: i,
+                })
+                .await
+                .unwrap();
+            }
+            assert!(tree.find_async(100).await.unwrap().is_some());
+            assert!(tree.delete_async(100).await.unwrap());
+            assert!(tree.find_async(1000).await.unwrap().is_none());
+            // More elements with a key than in a batch.
+            for i in 0..300 {
+                tree.insert_async(Element {
+                    key: 1000,
+                    value: i,
+                })
+                .await
+                .unwrap();
+            }
+
+            let mut stream = tree.stream();
+            let mut keys = Vec::new();
+            while let Some(element) = poll_fn(|cx| Pin::new(&mut stream).poll_next(cx)).await {
+                keys.push(element.unwrap().key);
+            }
+            let mut expected: Vec<usize> = (0..1000).map(|i| i / 2).collect();
+            expected.remove(200);
+            expected.extend([1000; 300]);
+            assert!(keys == expected);
+
+            // Deleting the key the first batch ended with loses no later elements.
+            let mut stream = tree.stream();
+            let mut keys = Vec::new();
+            loop {
+                let element = poll_fn(|cx| Pin::new(&mut stream).poll_next(cx)).await;
+                keys.push(element.unwrap().unwrap().key);
+                if stream.buffer.is_empty() {
+                    break;
+                }
+            }
+            let (batch, last) = (keys.len(), *keys.last().unwrap());
+            while tree.delete_async(last).await.unwrap() {}
+            while let Some(element) = poll_fn(|cx| Pin::new(&mut stream).poll_next(cx)).await {
+                keys.push(element.unwrap().key);
+            }
+            // The batch held all the elements with the last key.
+            assert!(batch < super::STREAM_BATCH && keys == expected);
+        });
+    }
+}
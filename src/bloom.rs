@@ -0,0 +1,150 @@
+// An optional Bloom filter over the keys of a 2-3 Tree.
+//
+// The filter answers "definitely absent" for most missing keys without traversing the tree.
+// Bloom filters cannot forget keys, so the filter is rebuilt from the tree once it holds as
+// many deleted keys as live ones, or once it is full.
+
+use crate::fingerprint::mix64;
+use crate::two_three_tree::{Augment, TwoThreeTree};
+
+// A Bloom filter sized for a capacity and a false positive rate.
+pub(crate) struct BloomFilter {
+    bits: Vec<u64>,
+
+    // Number of hash functions.
+    num_hashes: u32,
+
+    // Number of keys the filter is sized for, and the false positive rate at that load.
+    capacity: usize,
+    false_positive_rate: f64,
+
+    // Keys added, and deletions since the filter was built.
+    inserted: usize,
+    removed: usize,
+}
+
+impl BloomFilter {
+    fn new(capacity: usize, false_positive_rate: f64) -> BloomFilter {
+        // Standard sizing: m = -n ln(p) / ln(2)^2 bits and k = m / n ln(2) hashes.
+        let ln2 = std::f64::consts::LN_2;
+        let num_bits = (-(capacity as f64) * false_positive_rate.ln() / (ln2 * ln2)).ceil();
+        let num_bits = (num_bits as usize).max(64);
+        let num_hashes = ((num_bits as f64 / capacity as f64) * ln2).round() as u32;
+        BloomFilter {
+            bits: vec![0; num_bits.div_ceil(64)],
+            num_hashes: num_hashes.max(1),
+            capacity,
+            false_positive_rate,
+            inserted: 0,
+            removed: 0,
+        }
+    }
+
+    fn insert(&mut self, key: usize) {
+        for bit in self.bit_positions(key) {
+            self.bits[bit / 64] |= 1 << (bit % 64);
+        }
+        self.inserted += 1;
+    }
+
+    // Returns false if the key was never inserted.
+    pub(crate) fn may_contain(&self, key: usize) -> bool {
+        self.bit_positions(key)
+            .all(|bit| self.bits[bit / 64] & (1 << (bit % 64)) != 0)
+    }
+
+    // Derives the bit positions by double hashing of two halves of a mixed key.
+    fn bit_positions(&self, key: usize) -> impl Iterator<Item = usize> {
+        let hash = mix64(key as u64);
+        let (h1, h2) = (hash & 0xffff_ffff, (hash >> 32) | 1);
+        let num_bits = (self.bits.len() * 64) as u64;
+        (0..self.num_hashes as u64)
+            .map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % num_bits) as usize)
+    }
+}
+
+impl<A: Augment> TwoThreeTree<A> {
+    // Enables a Bloom filter over the keys, with the given false positive rate.
+    pub fn enable_bloom_filter(&mut self, false_positive_rate: f64) {
+        assert!(false_positive_rate > 0.0 && false_positive_rate < 1.0);
+        self.bloom = Some(BloomFilter::new(0, false_positive_rate));
+        self.rebuild_bloom_filter();
+    }
+
+    pub fn disable_bloom_filter(&mut self) {
+        self.bloom = None;
+    }
+
+    // Adds a key to the Bloom filter, if any.
+    pub(crate) fn bloom_insert(&mut self, key: usize) {
+        let full = match self.bloom {
+            Some(ref mut bloom) => {
+                bloom.insert(key);
+                bloom.inserted > bloom.capacity
+            }
+            None => false,
+        };
+        if full {
+            self.rebuild_bloom_filter();
+        }
+    }
+
+    // Records the deletion of keys from the Bloom filter, if any.
+    pub(crate) fn bloom_remove(&mut self, count: usize) {
+        let stale = match self.bloom {
+            Some(ref mut bloom) => {
+                bloom.removed += count;
+                bloom.removed > self.size()
+            }
+            None => false,
+        };
+        if stale {
+            self.rebuild_bloom_filter();
+        }
+    }
+
+    // Rebuilds the Bloom filter from the keys in the tree, with room for as many more.
+    pub(crate) fn rebuild_bloom_filter(&mut self) {
+        if let Some(ref bloom) = self.bloom {
+            let mut rebuilt =
+                BloomFilter::new((2 * self.size()).max(64), bloom.false_positive_rate);
+            for element in self.iter() {
+                rebuilt.insert(element.key);
+            }
+            self.bloom = Some(rebuilt);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::two_three_tree::{Element, TwoThreeTree};
+
+    #[test]
+    fn test_bloom_filter() {
+        let mut tree = TwoThreeTree::new();
+        for i in 0..100 {
+            tree.insert(Element { key: i, value: i });
+        }
+        tree.enable_bloom_filter(0.01);
+        for i in 100..1000 {
+            tree.insert(Element { key: i, value: i });
+        }
+        for i in 0..500 {
+            assert!(tree.delete(i));
+        }
+        for i in 500..1000 {
+            assert!(tree.contains_key(i));
+        }
+        // Deleted keys stay in the filter until it is rebuilt.
+        let false_positives = (1000..10000)
+            .filter(|&key| tree.bloom.as_ref().unwrap().may_contain(key))
+            .count();
+        assert!(false_positives < 500);
+        assert!(tree.delete(500));
+        assert!(!tree.bloom.as_ref().unwrap().may_contain(250));
+        assert!((0..500)
+            .chain(1000..10000)
+            .all(|key| !tree.contains_key(key)));
+    }
+}
@@ -0,0 +1,541 @@
+// A 2-3 Tree in B+ tree layout, whose leaves are linked for scans.
+//
+// All the elements live in the leaves, which hold one or two elements each and link to the next
+// leaf in key order. Internal nodes have two or three children separated by keys only: every key
+// of a child is at most the separator after it and at least the separator before it. A scan
+// descends once to its first leaf, then walks the links instead of climbing back up.
+//
+// The nodes live in an arena and refer to each other by index.
+
+use std::ops::RangeBounds;
+
+use crate::two_three_tree::{inclusive_range, Augment, Element, TwoThreeTree};
+
+type NodeId = usize;
+
+enum Node {
+    Leaf {
+        elements: Vec<Element>,
+        next: Option<NodeId>,
+    },
+    Internal {
+        keys: Vec<usize>,
+        children: Vec<NodeId>,
+    },
+    // A released slot of the arena.
+    Free,
+}
+
+pub struct BPlusTwoThreeTree {
+    nodes: Vec<Node>,
+    free: Vec<NodeId>,
+    root: Option<NodeId>,
+    size: usize,
+}
+
+impl BPlusTwoThreeTree {
+    pub fn new() -> BPlusTwoThreeTree {
+        BPlusTwoThreeTree {
+            nodes: Vec::new(),
+            free: Vec::new(),
+            root: None,
+            size: 0,
+        }
+    }
+
+    // Builds a tree from elements sorted by key, in O(n), filling the leaves with two elements.
+    // Panics if the elements are not sorted.
+    pub fn from_sorted(elements: impl IntoIterator<Item = Element>) -> BPlusTwoThreeTree {
+        let elements: Vec<Element> = elements.into_iter().collect();
+        assert!(elements.windows(2).all(|pair| pair[0].key <= pair[1].key));
+
+        let mut tree = BPlusTwoThreeTree::new();
+        tree.size = elements.len();
+
+        // The nodes of the current level, with their first keys.
+        let mut level: Vec<(usize, NodeId)> = Vec::new();
+        for chunk in elements.chunks(2).rev() {
+            let next = level.last().map(|&(_, id)| id);
+            let id = tree.alloc(Node::Leaf {
+                elements: chunk.to_vec(),
+                next,
+            });
+            level.push((chunk[0].key, id));
+        }
+        level.reverse();
+
+        while level.len() > 1 {
+            // Group the nodes by 3, and the last 4 or 2 by 2.
+            let mut parents = Vec::with_capacity(level.len() / 2);
+            let mut rest = &level[..];
+            while !rest.is_empty() {
+                let len = if rest.len() == 4 || rest.len() == 2 {
+                    2
+                } else {
+                    3
+                };
+                let (group, tail) = rest.split_at(len);
+                let id = tree.alloc(Node::Internal {
+                    keys: group[1..].iter().map(|&(key, _)| key).collect(),
+                    children: group.iter().map(|&(_, id)| id).collect(),
+                });
+                parents.push((group[0].0, id));
+                rest = tail;
+            }
+            level = parents;
+        }
+        tree.root = level.first().map(|&(_, id)| id);
+        tree
+    }
+
+    // Returns the elements as a TwoThreeTree, in O(n).
+    pub fn to_two_three(&self) -> TwoThreeTree {
+        TwoThreeTree::from_sorted(self.iter())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    // Inserts an element after the elements with an equal key.
+    pub fn insert(&mut self, element: Element) {
+        self.size += 1;
+        let Some(root) = self.root else {
+            self.root = Some(self.alloc(Node::Leaf {
+                elements: vec![element],
+                next: None,
+            }));
+            return;
+        };
+        if let Some((key, right)) = self.insert_node(root, element) {
+            self.root = Some(self.alloc(Node::Internal {
+                keys: vec![key],
+                children: vec![root, right],
+            }));
+        }
+    }
+
+    // Finds an element with the given key.
+    pub fn find(&self, key: usize) -> Option<Element> {
+        self.range(key..=key).next()
+    }
+
+    pub fn delete(&mut self, key: usize) -> bool {
+        self.remove(key).is_some()
+    }
+
+    // Removes an element with the given key, and returns it.
+    pub fn remove(&mut self, key: usize) -> Option<Element> {
+        let root = self.root?;
+        let removed = self.remove_node(root, key)?;
+        self.size -= 1;
+        if self.len(root) == 0 {
+            // An empty leaf, or an internal node with a single child.
+            self.root = match self.take(root) {
+                Node::Internal { children, .. } => Some(children[0]),
+                _ => None,
+            };
+            self.release(root);
+        }
+        Some(removed)
+    }
+
+    // Returns the elements in key order.
+    pub fn iter(&self) -> LeafIter<'_> {
+        self.range(..)
+    }
+
+    // Returns the elements with keys in the range, in key order. Descends once to the first
+    // leaf, then follows the leaf links.
+    pub fn range(&self, range: impl RangeBounds<usize>) -> LeafIter<'_> {
+        let mut iter = LeafIter {
+            tree: self,
+            leaf: None,
+            index: 0,
+            end: 0,
+        };
+        let (Some((start, end)), Some(mut id)) = (inclusive_range(&range), self.root) else {
+            return iter;
+        };
+        while let Node::Internal { keys, children } = &self.nodes[id] {
+            id = children[keys.partition_point(|&k| k < start)];
+        }
+        let Node::Leaf { elements, .. } = &self.nodes[id] else {
+            unreachable!()
+        };
+        iter.leaf = Some(id);
+        iter.index = elements.partition_point(|e| e.key < start);
+        iter.end = end;
+        iter
+    }
+
+    // Checks the separators, the node sizes, the uniform depth of the leaves and the links.
+    pub fn validate(&self) {
+        let Some(root) = self.root else {
+            assert!(self.size == 0);
+            return;
+        };
+        let mut leaves = Vec::new();
+        self.validate_node(root, 0, None, None, &mut None, &mut leaves);
+
+        // The links visit the leaves in key order.
+        for pair in leaves.windows(2) {
+            assert!(
+                matches!(self.nodes[pair[0]], Node::Leaf { next, .. } if next == Some(pair[1]))
+            );
+        }
+        let last = *leaves.last().unwrap();
+        assert!(matches!(self.nodes[last], Node::Leaf { next: None, .. }));
+        assert!(self.iter().count() == self.size);
+        assert!(self
+            .iter()
+            .zip(self.iter().skip(1))
+            .all(|(a, b)| a.key <= b.key));
+    }
+
+    fn validate_node(
+        &self,
+        id: NodeId,
+        depth: usize,
+        low: Option<usize>,
+        high: Option<usize>,
+        leaf_depth: &mut Option<usize>,
+        leaves: &mut Vec<NodeId>,
+    ) {
+        let in_bounds =
+            |key: usize| low.is_none_or(|low| low <= key) && high.is_none_or(|high| key <= high);
+        match &self.nodes[id] {
+            Node::Leaf { elements, .. } => {
+                assert!(!elements.is_empty() && elements.len() <= 2);
+                assert!(elements.iter().all(|e| in_bounds(e.key)));
+                assert!(*leaf_depth.get_or_insert(depth) == depth);
+                leaves.push(id);
+            }
+            Node::Internal { keys, children } => {
+                assert!(children.len() == keys.len() + 1);
+                assert!(children.len() == 2 || children.len() == 3);
+                assert!(keys.iter().all(|&key| in_bounds(key)));
+                for (i, &child) in children.iter().enumerate() {
+                    let low = i.checked_sub(1).map(|i| keys[i]).or(low);
+                    let high = keys.get(i).copied().or(high);
+                    self.validate_node(child, depth + 1, low, high, leaf_depth, leaves);
+                }
+            }
+            Node::Free => panic!("a released node is still linked"),
+        }
+    }
+
+    // Inserts into a subtree. If the node overflows, it is split and the separator and the
+    // right half are returned.
+    fn insert_node(&mut self, id: NodeId, element: Element) -> Option<(usize, NodeId)> {
+        match &mut self.nodes[id] {
+            Node::Leaf { elements, next } => {
+                let index = elements.partition_point(|e| e.key <= element.key);
+                elements.insert(index, element);
+                if elements.len() <= 2 {
+                    return None;
+                }
+                let elements = elements.split_off(1);
+                let key = elements[0].key;
+                let next = *next;
+                let right = self.alloc(Node::Leaf { elements, next });
+                if let Node::Leaf { next, .. } = &mut self.nodes[id] {
+                    *next = Some(right);
+                }
+                Some((key, right))
+            }
+            Node::Internal { keys, children } => {
+                let index = keys.partition_point(|&k| k <= element.key);
+                let child = children[index];
+                let (key, right) = self.insert_node(child, element)?;
+                let Node::Internal { keys, children } = &mut self.nodes[id] else {
+                    unreachable!()
+                };
+                keys.insert(index, key);
+                children.insert(index + 1, right);
+                if children.len() <= 3 {
+                    return None;
+                }
+                let right = Node::Internal {
+                    keys: keys.split_off(2),
+                    children: children.split_off(2),
+                };
+                let key = keys.pop().unwrap();
+                Some((key, self.alloc(right)))
+            }
+            Node::Free => unreachable!(),
+        }
+    }
+
+    // Removes from a subtree, which may underflow.
+    fn remove_node(&mut self, id: NodeId, key: usize) -> Option<Element> {
+        let (keys, children) = match &mut self.nodes[id] {
+            Node::Leaf { elements, .. } => {
+                let index = elements.iter().position(|e| e.key == key)?;
+                return Some(elements.remove(index));
+            }
+            Node::Internal { keys, children } => (keys.clone(), children.clone()),
+            Node::Free => unreachable!(),
+        };
+
+        // Equal keys may span several children.
+        let mut index = keys.partition_point(|&k| k < key);
+        loop {
+            if let Some(removed) = self.remove_node(children[index], key) {
+                self.fix_child(id, index);
+                return Some(removed);
+            }
+            if keys.get(index) != Some(&key) {
+                return None;
+            }
+            index += 1;
+        }
+    }
+
+    // Refills an underflowing child by borrowing from a sibling, or merging with it.
+    fn fix_child(&mut self, id: NodeId, index: usize) {
+        let Node::Internal { keys, children } = &self.nodes[id] else {
+            unreachable!()
+        };
+        let (mut keys, mut children) = (keys.clone(), children.clone());
+        if self.len(children[index]) > 0 {
+            return;
+        }
+        let left = index.checked_sub(1).map(|i| children[i]);
+        let right = children.get(index + 1).copied();
+        let mut child = self.take(children[index]);
+
+        if let Some(left) = left.filter(|&left| self.len(left) > 1) {
+            // Borrow the last element or child of the left sibling.
+            match (&mut self.nodes[left], &mut child) {
+                (Node::Leaf { elements: from, .. }, Node::Leaf { elements, .. }) => {
+                    elements.insert(0, from.pop().unwrap());
+                    keys[index - 1] = elements[0].key;
+                }
+                (
+                    Node::Internal {
+                        keys: from_keys,
+                        children: from_children,
+                    },
+                    Node::Internal {
+                        keys: to_keys,
+                        children: to_children,
+                    },
+                ) => {
+                    to_children.insert(0, from_children.pop().unwrap());
+                    to_keys.insert(0, keys[index - 1]);
+                    keys[index - 1] = from_keys.pop().unwrap();
+                }
+                _ => unreachable!(),
+            }
+            self.nodes[children[index]] = child;
+        } else if let Some(right) = right.filter(|&right| self.len(right) > 1) {
+            // Borrow the first element or child of the right sibling.
+            match (&mut self.nodes[right], &mut child) {
+                (Node::Leaf { elements: from, .. }, Node::Leaf { elements, .. }) => {
+                    elements.push(from.remove(0));
+                    keys[index] = from[0].key;
+                }
+                (
+                    Node::Internal {
+                        keys: from_keys,
+                        children: from_children,
+                    },
+                    Node::Internal {
+                        keys: to_keys,
+                        children: to_children,
+                    },
+                ) => {
+                    to_children.push(from_children.remove(0));
+                    to_keys.push(keys[index]);
+                    keys[index] = from_keys.remove(0);
+                }
+                _ => unreachable!(),
+            }
+            self.nodes[children[index]] = child;
+        } else {
+            // Merge the child and a sibling into the left one of the two, so that the link of
+            // the leaf before them stays valid.
+            let (left, right, separator) = match left {
+                Some(left) => {
+                    self.nodes[children[index]] = child;
+                    (left, children[index], index - 1)
+                }
+                None => {
+                    self.nodes[children[index]] = child;
+                    (children[index], right.unwrap(), index)
+                }
+            };
+            let separator_key = keys.remove(separator);
+            children.remove(separator + 1);
+            match (self.take(right), &mut self.nodes[left]) {
+                (
+                    Node::Leaf {
+                        elements: from,
+                        next: from_next,
+                    },
+                    Node::Leaf { elements, next },
+                ) => {
+                    elements.extend(from);
+                    *next = from_next;
+                }
+                (
+                    Node::Internal {
+                        keys: from_keys,
+                        children: from_children,
+                    },
+                    Node::Internal {
+                        keys: to_keys,
+                        children: to_children,
+                    },
+                ) => {
+                    to_keys.push(separator_key);
+                    to_keys.extend(from_keys);
+                    to_children.extend(from_children);
+                }
+                _ => unreachable!(),
+            }
+            self.release(right);
+        }
+        self.nodes[id] = Node::Internal { keys, children };
+    }
+
+    // Returns the number of elements of a leaf, or of children of an internal node.
+    fn len(&self, id: NodeId) -> usize {
+        match &self.nodes[id] {
+            Node::Leaf { elements, .. } => elements.len(),
+            Node::Internal { children, .. } => children.len() - 1,
+            Node::Free => unreachable!(),
+        }
+    }
+
+    fn take(&mut self, id: NodeId) -> Node {
+        std::mem::replace(&mut self.nodes[id], Node::Free)
+    }
+
+    fn alloc(&mut self, node: Node) -> NodeId {
+        match self.free.pop() {
+            Some(id) => {
+                self.nodes[id] = node;
+                id
+            }
+            None => {
+                self.nodes.push(node);
+                self.nodes.len() - 1
+            }
+        }
+    }
+
+    fn release(&mut self, id: NodeId) {
+        self.nodes[id] = Node::Free;
+        self.free.push(id);
+    }
+}
+
+impl<A: Augment> TwoThreeTree<A> {
+    // Returns the elements in B+ tree layout, in O(n).
+    pub fn to_bplus(&self) -> BPlusTwoThreeTree {
+        BPlusTwoThreeTree::from_sorted(self.iter())
+    }
+}
+
+impl Default for BPlusTwoThreeTree {
+    fn default() -> BPlusTwoThreeTree {
+        BPlusTwoThreeTree::new()
+    }
+}
+
+// Iterator walking the linked leaves.
+pub struct LeafIter<'a> {
+    tree: &'a BPlusTwoThreeTree,
+    leaf: Option<NodeId>,
+    index: usize,
+
+    // The last key of the range.
+    end: usize,
+}
+
+impl Iterator for LeafIter<'_> {
+    type Item = Element;
+
+    fn next(&mut self) -> Option<Element> {
+        loop {
+            let Node::Leaf { elements, next } = &self.tree.nodes[self.leaf?] else {
+                unreachable!()
+            };
+            match elements.get(self.index) {
+                Some(element) if element.key <= self.end => {
+                    self.index += 1;
+                    return Some(*element);
+                }
+                Some(_) => {
+                    self.leaf = None;
+                    return None;
+                }
+                None => {
+                    self.leaf = *next;
+                    self.index = 0;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BPlusTwoThreeTree;
+    use crate::two_three_tree::{Element, TwoThreeTree};
+    use crate::workload::{self, WorkloadOp};
+
+    #[test]
+    fn test_bplus_tree() {
+        let mut tree = BPlusTwoThreeTree::new();
+        let keys = workload::random_insert_delete(100, 3000, |op| {
+            let found = match op {
+                WorkloadOp::Insert(element) => {
+                    tree.insert(element);
+                    true
+                }
+                WorkloadOp::Delete(key) => tree
+                    .remove(key)
+                    .inspect(|e| assert!(e.key == key))
+                    .is_some(),
+                WorkloadOp::Find(_) => unreachable!(),
+            };
+            tree.validate();
+            found
+        });
+        assert!(tree.size() == keys.len());
+        assert!(tree.iter().map(|e| e.key).eq(keys.iter().copied()));
+        assert!(tree
+            .range(20..40)
+            .map(|e| e.key)
+            .eq(keys.iter().copied().filter(|key| (20..40).contains(key))));
+        assert!((0..100).all(|key| tree.find(key).is_some() == keys.contains(&key)));
+
+        for key in keys {
+            assert!(tree.delete(key));
+        }
+        assert!(tree.is_empty() && tree.iter().next().is_none());
+    }
+
+    #[test]
+    fn test_bplus_from_sorted() {
+        for len in [0, 1, 2, 3, 5, 8, 13, 100] {
+            let tree = TwoThreeTree::from_sorted((0..len).map(|key| Element { key, value: key }));
+            let mut bplus = tree.to_bplus();
+            bplus.validate();
+            assert!(bplus.size() == len && bplus.iter().map(|e| e.key).eq(0..len));
+            assert!(bplus.to_two_three().iter().eq(tree.iter()));
+
+            // The bulk-loaded tree stays valid under updates.
+            bplus.insert(Element { key: 3, value: 0 });
+            bplus.delete(0);
+            bplus.validate();
+        }
+    }
+}
@@ -0,0 +1,161 @@
+// A 2-3 Tree with a sorted write buffer, for write-heavy phases.
+//
+// Inserts go to a small sorted buffer, and are merged into the tree in batches when it fills up
+// or on flush(). A batch small compared to the tree is inserted element by element; a larger one
+// is merged with the elements of the tree and bulk-loaded, in O(n). Reads consult both.
+
+use std::iter::Peekable;
+
+use crate::two_three_tree::{Element, Iter, TwoThreeTree};
+
+const DEFAULT_CAPACITY: usize = 64;
+
+pub struct BufferedTwoThreeTree {
+    tree: TwoThreeTree,
+
+    // The buffered elements, sorted by key and then in insertion order.
+    buffer: Vec<Element>,
+
+    capacity: usize,
+}
+
+impl BufferedTwoThreeTree {
+    // Creates a tree buffering up to capacity inserts. Panics if the capacity is 0.
+    pub fn new(capacity: usize) -> BufferedTwoThreeTree {
+        assert!(capacity > 0);
+        BufferedTwoThreeTree {
+            tree: TwoThreeTree::new(),
+            buffer: Vec::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.size() == 0
+    }
+
+    pub fn size(&self) -> usize {
+        self.tree.size() + self.buffer.len()
+    }
+
+    // Returns the number of buffered elements.
+    pub fn buffered(&self) -> usize {
+        self.buffer.len()
+    }
+
+    // The flushed elements.
+    pub fn tree(&self) -> &TwoThreeTree {
+        &self.tree
+    }
+
+    // Buffers an element, flushing the buffer if it is full.
+    pub fn insert(&mut self, element: Element) {
+        let index = self.buffer.partition_point(|e| e.key <= element.key);
+        self.buffer.insert(index, element);
+        if self.buffer.len() >= self.capacity {
+            self.flush();
+        }
+    }
+
+    // Returns an element with the given key, from the tree or the buffer.
+    pub fn find(&self, key: usize) -> Option<Element> {
+        self.tree.find(key).or_else(|| self.find_buffered(key))
+    }
+
+    // Removes an element with the given key, from the buffer first.
+    pub fn remove(&mut self, key: usize) -> Option<Element> {
+        match self.buffer.binary_search_by_key(&key, |e| e.key) {
+            Ok(index) => Some(self.buffer.remove(index)),
+            Err(_) => self.tree.remove(key),
+        }
+    }
+
+    // Returns the elements in key order. Equal keys come in insertion order, as flushed
+    // elements come before buffered ones.
+    pub fn iter(&self) -> BufferedIter<'_> {
+        BufferedIter {
+            tree: self.tree.iter().peekable(),
+            buffer: self.buffer.iter().copied().peekable(),
+        }
+    }
+
+    // Merges the buffered elements into the tree.
+    pub fn flush(&mut self) {
+        if self.buffer.is_empty() {
+            return;
+        }
+        let size = self.tree.size();
+        if self.buffer.len() * (size + 1).ilog2() as usize <= size {
+            for element in self.buffer.drain(..) {
+                self.tree.insert(element);
+            }
+        } else {
+            let tree = TwoThreeTree::from_sorted(self.iter());
+            self.tree = tree;
+            self.buffer.clear();
+        }
+    }
+
+    fn find_buffered(&self, key: usize) -> Option<Element> {
+        let index = self.buffer.binary_search_by_key(&key, |e| e.key).ok()?;
+        Some(self.buffer[index])
+    }
+}
+
+impl Default for BufferedTwoThreeTree {
+    fn default() -> BufferedTwoThreeTree {
+        BufferedTwoThreeTree::new(DEFAULT_CAPACITY)
+    }
+}
+
+// Iterator merging the flushed and the buffered elements.
+pub struct BufferedIter<'a> {
+    tree: Peekable<Iter<'a>>,
+    buffer: Peekable<std::iter::Copied<std::slice::Iter<'a, Element>>>,
+}
+
+impl Iterator for BufferedIter<'_> {
+    type Item = Element;
+
+    fn next(&mut self) -> Option<Element> {
+        match (self.tree.peek(), self.buffer.peek()) {
+            (Some(a), Some(b)) if b.key < a.key => self.buffer.next(),
+            (Some(_), _) => self.tree.next(),
+            (None, _) => self.buffer.next(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BufferedTwoThreeTree;
+    use crate::two_three_tree::Element;
+
+    #[test]
+    fn test_buffered() {
+        let mut tree = BufferedTwoThreeTree::new(8);
+        for i in 0..100 {
+            tree.insert(Element {
+                key: (i * 37) % 50,
+                value: i,
+            });
+            assert!(tree.buffered() < 8);
+        }
+        assert!(tree.size() == 100 && tree.buffered() == 4);
+        tree.tree().validate();
+
+        // Reads see buffered and flushed elements.
+        let mut keys: Vec<usize> = (0..100).map(|i| (i * 37) % 50).collect();
+        keys.sort();
+        assert!(tree.iter().map(|e| e.key).eq(keys.iter().copied()));
+        assert!((0..50).all(|key| tree.find(key).is_some()));
+
+        for key in 0..50 {
+            assert!(tree.remove(key).unwrap().key == key);
+        }
+        tree.flush();
+        assert!(tree.buffered() == 0 && tree.size() == 50);
+        tree.tree().validate();
+        assert!(tree.iter().map(|e| e.key).eq(0..50));
+    }
+}
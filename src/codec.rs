@@ -0,0 +1,230 @@
+// Compact MessagePack and CBOR encodings of the key-value pairs of a 2-3 Tree.
+//
+// Both encode an array with one [key, value] array per element, in key order, with the
+// integers in their shortest form. Encoding streams from the in-order iterator; decoding
+// bulk-loads the elements, sorting them first if needed.
+
+use std::io::{self, Read, Write};
+
+use crate::two_three_tree::{Augment, Element, TwoThreeTree};
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+fn read_u8(reader: &mut impl Read) -> io::Result<u8> {
+    let mut buf = [0; 1];
+    reader.read_exact(&mut buf)?;
+    Ok(buf[0])
+}
+
+// Reads a big-endian unsigned integer of the given number of bytes.
+fn read_be(reader: &mut impl Read, len: usize) -> io::Result<u64> {
+    let mut buf = [0; 8];
+    reader.read_exact(&mut buf[8 - len..])?;
+    Ok(u64::from_be_bytes(buf))
+}
+
+// Builds a tree from decoded elements.
+fn bulk_load(mut elements: Vec<Element>) -> TwoThreeTree {
+    if !elements.is_sorted_by_key(|e| e.key) {
+        elements.sort_by_key(|e| e.key);
+    }
+    TwoThreeTree::from_sorted(elements)
+}
+
+#[cfg(feature = "msgpack")]
+mod msgpack {
+    use super::*;
+
+    pub(super) fn write_array_len(writer: &mut impl Write, len: usize) -> io::Result<()> {
+        match len {
+            0..=15 => writer.write_all(&[0x90 | len as u8]),
+            16..=0xffff => {
+                writer.write_all(&[0xdc])?;
+                writer.write_all(&(len as u16).to_be_bytes())
+            }
+            _ => {
+                writer.write_all(&[0xdd])?;
+                let len = u32::try_from(len).map_err(|_| invalid("too many elements"))?;
+                writer.write_all(&len.to_be_bytes())
+            }
+        }
+    }
+
+    pub(super) fn write_uint(writer: &mut impl Write, n: usize) -> io::Result<()> {
+        let n = n as u64;
+        match n {
+            0..=0x7f => writer.write_all(&[n as u8]),
+            0x80..=0xff => writer.write_all(&[0xcc, n as u8]),
+            0x100..=0xffff => {
+                writer.write_all(&[0xcd])?;
+                writer.write_all(&(n as u16).to_be_bytes())
+            }
+            0x1_0000..=0xffff_ffff => {
+                writer.write_all(&[0xce])?;
+                writer.write_all(&(n as u32).to_be_bytes())
+            }
+            _ => {
+                writer.write_all(&[0xcf])?;
+                writer.write_all(&n.to_be_bytes())
+            }
+        }
+    }
+
+    pub(super) fn read_array_len(reader: &mut impl Read) -> io::Result<usize> {
+        match read_u8(reader)? {
+            tag @ 0x90..=0x9f => Ok((tag & 0x0f) as usize),
+            0xdc => Ok(read_be(reader, 2)? as usize),
+            0xdd => Ok(read_be(reader, 4)? as usize),
+            _ => Err(invalid("expected an array")),
+        }
+    }
+
+    pub(super) fn read_uint(reader: &mut impl Read) -> io::Result<usize> {
+        let n = match read_u8(reader)? {
+            tag @ 0x00..=0x7f => tag as u64,
+            0xcc => read_be(reader, 1)?,
+            0xcd => read_be(reader, 2)?,
+            0xce => read_be(reader, 4)?,
+            0xcf => read_be(reader, 8)?,
+            _ => return Err(invalid("expected an unsigned integer")),
+        };
+        usize::try_from(n).map_err(|_| invalid("integer out of range"))
+    }
+}
+
+#[cfg(feature = "cbor")]
+mod cbor {
+    use super::*;
+
+    // Major types of the CBOR data items used.
+    const UNSIGNED: u8 = 0;
+    const ARRAY: u8 = 4;
+
+    pub(super) fn write_head(writer: &mut impl Write, major: u8, n: usize) -> io::Result<()> {
+        let major = major << 5;
+        let n = n as u64;
+        match n {
+            0..=23 => writer.write_all(&[major | n as u8]),
+            24..=0xff => writer.write_all(&[major | 24, n as u8]),
+            0x100..=0xffff => {
+                writer.write_all(&[major | 25])?;
+                writer.write_all(&(n as u16).to_be_bytes())
+            }
+            0x1_0000..=0xffff_ffff => {
+                writer.write_all(&[major | 26])?;
+                writer.write_all(&(n as u32).to_be_bytes())
+            }
+            _ => {
+                writer.write_all(&[major | 27])?;
+                writer.write_all(&n.to_be_bytes())
+            }
+        }
+    }
+
+    pub(super) fn write_array_len(writer: &mut impl Write, len: usize) -> io::Result<()> {
+        write_head(writer, ARRAY, len)
+    }
+
+    pub(super) fn write_uint(writer: &mut impl Write, n: usize) -> io::Result<()> {
+        write_head(writer, UNSIGNED, n)
+    }
+
+    fn read_head(reader: &mut impl Read, major: u8) -> io::Result<usize> {
+        let initial = read_u8(reader)?;
+        if initial >> 5 != major {
+            return Err(invalid(match major {
+                ARRAY => "expected an array",
+                _ => "expected an unsigned integer",
+            }));
+        }
+        let n = match initial & 0x1f {
+            n @ 0..=23 => n as u64,
+            24 => read_be(reader, 1)?,
+            25 => read_be(reader, 2)?,
+            26 => read_be(reader, 4)?,
+            27 => read_be(reader, 8)?,
+            _ => return Err(invalid("unsupported length")),
+        };
+        usize::try_from(n).map_err(|_| invalid("integer out of range"))
+    }
+
+    pub(super) fn read_array_len(reader: &mut impl Read) -> io::Result<usize> {
+        read_head(reader, ARRAY)
+    }
+
+    pub(super) fn read_uint(reader: &mut impl Read) -> io::Result<usize> {
+        read_head(reader, UNSIGNED)
+    }
+}
+
+// Implements the encode and decode functions of a format over its primitives.
+macro_rules! codec {
+    ($feature:literal, $format:ident, $encode:ident, $decode:ident) => {
+        #[cfg(feature = $feature)]
+        impl<A: Augment> TwoThreeTree<A> {
+            pub fn $encode(&self, mut writer: impl Write) -> io::Result<()> {
+                $format::write_array_len(&mut writer, self.size())?;
+                for element in self.iter() {
+                    $format::write_array_len(&mut writer, 2)?;
+                    $format::write_uint(&mut writer, element.key)?;
+                    $format::write_uint(&mut writer, element.value)?;
+                }
+                writer.flush()
+            }
+        }
+
+        #[cfg(feature = $feature)]
+        impl TwoThreeTree {
+            pub fn $decode(mut reader: impl Read) -> io::Result<TwoThreeTree> {
+                let len = $format::read_array_len(&mut reader)?;
+                // The length is not trusted for the allocation.
+                let mut elements = Vec::with_capacity(len.min(1 << 16));
+                for _ in 0..len {
+                    if $format::read_array_len(&mut reader)? != 2 {
+                        return Err(invalid("expected a [key, value] pair"));
+                    }
+                    let key = $format::read_uint(&mut reader)?;
+                    let value = $format::read_uint(&mut reader)?;
+                    elements.push(Element { key, value });
+                }
+                Ok(bulk_load(elements))
+            }
+        }
+    };
+}
+
+codec!("msgpack", msgpack, to_msgpack, from_msgpack);
+codec!("cbor", cbor, to_cbor, from_cbor);
+
+#[cfg(test)]
+mod tests {
+    use crate::two_three_tree::TwoThreeTree;
+
+    fn sample() -> TwoThreeTree {
+        TwoThreeTree::from(vec![(1, 200), (70000, 5), (2, usize::MAX)])
+    }
+
+    #[cfg(feature = "msgpack")]
+    #[test]
+    fn test_msgpack() {
+        let mut buf = Vec::new();
+        sample().to_msgpack(&mut buf).unwrap();
+        assert!(buf[..8] == [0x93, 0x92, 0x01, 0xcc, 200, 0x92, 0x02, 0xcf]);
+        let tree = TwoThreeTree::from_msgpack(&buf[..]).unwrap();
+        assert!(tree.into_sorted_vec() == sample().into_sorted_vec());
+        assert!(TwoThreeTree::from_msgpack(&buf[..buf.len() - 1]).is_err());
+    }
+
+    #[cfg(feature = "cbor")]
+    #[test]
+    fn test_cbor() {
+        let mut buf = Vec::new();
+        sample().to_cbor(&mut buf).unwrap();
+        assert!(buf[..7] == [0x83, 0x82, 0x01, 0x18, 200, 0x82, 0x02]);
+        let tree = TwoThreeTree::from_cbor(&buf[..]).unwrap();
+        assert!(tree.into_sorted_vec() == sample().into_sorted_vec());
+        assert!(TwoThreeTree::from_cbor(&[0x81, 0x01][..]).is_err());
+    }
+}
@@ -0,0 +1,137 @@
+// Composite (major, minor) keys, e.g. (user_id, timestamp) for a secondary index.
+//
+// A pair of u32 packs into one usize key, the major part in the high half, so that the key
+// order is the tuple order. All the keys with a given major part are then one range of keys,
+// which range_prefix() scans.
+
+use std::ops::{Bound, RangeBounds};
+
+use crate::two_three_tree::{Element, TwoThreeTree};
+
+// Packs a tuple into a key in the same order.
+pub fn composite_key((major, minor): (u32, u32)) -> usize {
+    (major as usize) << 32 | minor as usize
+}
+
+// Unpacks a key packed by composite_key().
+pub fn split_key(key: usize) -> (u32, u32) {
+    ((key >> 32) as u32, key as u32)
+}
+
+pub struct CompositeTwoThreeTree {
+    // Elements keyed by composite_key().
+    tree: TwoThreeTree,
+}
+
+impl CompositeTwoThreeTree {
+    pub fn new() -> CompositeTwoThreeTree {
+        CompositeTwoThreeTree {
+            tree: TwoThreeTree::new(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    pub fn size(&self) -> usize {
+        self.tree.size()
+    }
+
+    // Inserts a key and its value, after the equal keys.
+    pub fn insert(&mut self, key: (u32, u32), value: usize) {
+        self.tree.insert(Element {
+            key: composite_key(key),
+            value,
+        });
+    }
+
+    // Returns the value of an element with the given key.
+    pub fn find(&self, key: (u32, u32)) -> Option<usize> {
+        Some(self.tree.find(composite_key(key))?.value)
+    }
+
+    // Removes an element with the given key, and returns its value.
+    pub fn remove(&mut self, key: (u32, u32)) -> Option<usize> {
+        Some(self.tree.remove(composite_key(key))?.value)
+    }
+
+    // Returns the keys and values in tuple order.
+    pub fn iter(&self) -> impl Iterator<Item = ((u32, u32), usize)> + '_ {
+        self.range(..)
+    }
+
+    // Returns the keys in the range and their values, in tuple order.
+    pub fn range(
+        &self,
+        range: impl RangeBounds<(u32, u32)>,
+    ) -> impl Iterator<Item = ((u32, u32), usize)> + '_ {
+        let bound = |bound: Bound<&(u32, u32)>| bound.map(|&key| composite_key(key));
+        let range = (bound(range.start_bound()), bound(range.end_bound()));
+        self.tree.range(range).map(|e| (split_key(e.key), e.value))
+    }
+
+    // Returns the keys with the major part and their values, in minor order.
+    pub fn range_prefix(&self, major: u32) -> impl Iterator<Item = ((u32, u32), usize)> + '_ {
+        self.range((major, 0)..=(major, u32::MAX))
+    }
+
+    // Returns the keys with the major part and a minor part in the range, and their values.
+    pub fn range_within(
+        &self,
+        major: u32,
+        minor: impl RangeBounds<u32>,
+    ) -> impl Iterator<Item = ((u32, u32), usize)> + '_ {
+        let bound = |bound: Bound<&u32>| bound.map(|&minor| (major, minor));
+        let start = match minor.start_bound() {
+            Bound::Unbounded => Bound::Included((major, 0)),
+            start => bound(start),
+        };
+        let end = match minor.end_bound() {
+            Bound::Unbounded => Bound::Included((major, u32::MAX)),
+            end => bound(end),
+        };
+        self.range((start, end))
+    }
+}
+
+impl Default for CompositeTwoThreeTree {
+    fn default() -> CompositeTwoThreeTree {
+        CompositeTwoThreeTree::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{composite_key, split_key, CompositeTwoThreeTree};
+
+    #[test]
+    fn test_composite_keys() {
+        assert!(composite_key((1, u32::MAX)) < composite_key((2, 0)));
+        assert!(split_key(composite_key((7, 9))) == (7, 9));
+
+        // Events indexed by (user_id, timestamp).
+        let mut tree = CompositeTwoThreeTree::new();
+        for user in [3, 1, 2, u32::MAX] {
+            for time in [30, 10, 20, u32::MAX, 0] {
+                tree.insert((user, time), (user as usize) * 100 + time as usize % 100);
+            }
+        }
+        assert!(tree.size() == 20 && tree.find((2, 20)) == Some(220));
+        assert!(tree
+            .range_prefix(2)
+            .map(|(key, _)| key.1)
+            .eq([0, 10, 20, 30, u32::MAX]));
+        assert!(tree.range_prefix(u32::MAX).count() == 5);
+        assert!(tree.range_prefix(4).next().is_none());
+        assert!(tree
+            .range_within(1, 10..30)
+            .map(|(key, _)| key)
+            .eq([(1, 10), (1, 20)]));
+        assert!(tree.range_within(3, 25..).count() == 2);
+
+        assert!(tree.remove((1, 0)).is_some() && tree.remove((1, 0)).is_none());
+        assert!(tree.iter().next().unwrap().0 == (1, 10));
+        assert!(tree.range((1, 20)..(2, 10)).count() == 4);
+    }
+}
@@ -0,0 +1,268 @@
+// Thread-safe wrappers of 2-3 Trees.
+//
+// ConcurrentTwoThreeMap spreads keys over a fixed number of shards by hash, each a tree behind
+// its own RwLock, so threads working on different shards do not contend. Each key has at most
+// one value.
+//
+// With the epoch feature, EpochTwoThreeTree lets readers traverse without locks. A single writer
+// at a time updates a copy of the tree and publishes it atomically. The tree is a
+// PersistentTwoThreeTree, so the copy shares all the nodes but those on the mutated paths.
+// Replaced trees are freed by epoch-based reclamation once no reader can still see them.
+
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+#[cfg(feature = "epoch")]
+use std::sync::atomic::Ordering;
+use std::sync::RwLock;
+
+use crate::fingerprint::mix64;
+#[cfg(feature = "epoch")]
+use crate::persistent::PersistentTwoThreeTree;
+use crate::two_three_tree::{Element, TwoThreeTree};
+
+#[cfg(feature = "epoch")]
+use crossbeam_epoch::{self as epoch, Atomic, Owned};
+#[cfg(feature = "epoch")]
+use std::sync::Mutex;
+
+// A map which can be shared between threads, e.g. in an Arc.
+pub struct ConcurrentTwoThreeMap {
+    shards: Vec<RwLock<TwoThreeTree>>,
+}
+
+impl ConcurrentTwoThreeMap {
+    // Creates an empty map with the given number of shards.
+    pub fn new(num_shards: usize) -> ConcurrentTwoThreeMap {
+        assert!(num_shards > 0);
+        ConcurrentTwoThreeMap {
+            shards: (0..num_shards)
+                .map(|_| RwLock::new(TwoThreeTree::new()))
+                .collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Returns the number of keys. Shards are counted one at a time, so the result may be stale
+    // under concurrent mutation.
+    pub fn len(&self) -> usize {
+        self.shards
+            .iter()
+            .map(|shard| shard.read().unwrap().size())
+            .sum()
+    }
+
+    // Returns the value of a key.
+    pub fn get(&self, key: usize) -> Option<usize> {
+        self.shard(key).read().unwrap().find(key).map(|e| e.value)
+    }
+
+    // Sets the value of a key. Returns the previous value, if any.
+    pub fn insert(&self, key: usize, value: usize) -> Option<usize> {
+        let mut tree = self.shard(key).write().unwrap();
+        let mut old = None;
+        tree.upsert(key, value, |v| old = Some(std::mem::replace(v, value)));
+        old
+    }
+
+    // Removes a key. Returns its value, if any.
+    pub fn remove(&self, key: usize) -> Option<usize> {
+        self.shard(key)
+            .write()
+            .unwrap()
+            .remove(key)
+            .map(|e| e.value)
+    }
+
+    // Returns all elements in key order, merged from a copy of each shard.
+    // Each shard is copied atomically, but not all shards at the same time.
+    pub fn iter(&self) -> impl Iterator<Item = Element> {
+        let shards: Vec<Vec<Element>> = self
+            .shards
+            .iter()
+            .map(|shard| shard.read().unwrap().iter().collect())
+            .collect();
+
+        // Merge the sorted shards with a heap of (key, shard, index).
+        let mut heap: BinaryHeap<Reverse<(usize, usize, usize)>> = shards
+            .iter()
+            .enumerate()
+            .filter(|(_, elements)| !elements.is_empty())
+            .map(|(shard, elements)| Reverse((elements[0].key, shard, 0)))
+            .collect();
+        std::iter::from_fn(move || {
+            let Reverse((_, shard, index)) = heap.pop()?;
+            if let Some(next) = shards[shard].get(index + 1) {
+                heap.push(Reverse((next.key, shard, index + 1)));
+            }
+            Some(shards[shard][index])
+        })
+    }
+
+    // Returns the shard holding the key.
+    fn shard(&self, key: usize) -> &RwLock<TwoThreeTree> {
+        &self.shards[(mix64(key as u64) % self.shards.len() as u64) as usize]
+    }
+}
+
+// A tree with lock-free reads and copy-on-write updates.
+// Each mutation of an update copies O(log n) nodes, and the replaced nodes are freed once no
+// reader can see them.
+#[cfg(feature = "epoch")]
+pub struct EpochTwoThreeTree {
+    current: Atomic<PersistentTwoThreeTree>,
+
+    // Serializes the writers.
+    writer: Mutex<()>,
+}
+
+#[cfg(feature = "epoch")]
+impl EpochTwoThreeTree {
+    pub fn new() -> EpochTwoThreeTree {
+        EpochTwoThreeTree {
+            current: Atomic::new(PersistentTwoThreeTree::new()),
+            writer: Mutex::new(()),
+        }
+    }
+
+    // Runs f on the current tree without taking a lock.
+    pub fn read<R>(&self, f: impl FnOnce(&PersistentTwoThreeTree) -> R) -> R {
+        let guard = epoch::pin();
+        let current = self.current.load(Ordering::Acquire, &guard);
+        // SAFETY: The tree is only freed after all readers pinned before it was replaced have
+        // unpinned, so it is alive while the guard is held.
+        f(unsafe { current.deref() })
+    }
+
+    pub fn size(&self) -> usize {
+        self.read(|tree| tree.size())
+    }
+
+    pub fn find(&self, key: usize) -> Option<Element> {
+        self.read(|tree| tree.find(key))
+    }
+
+    // Runs f on a copy of the current tree and publishes the copy. The copy takes O(1), sharing
+    // the nodes of the current tree. Readers see either the old or the new tree, never a partial
+    // update.
+    pub fn write<R>(&self, f: impl FnOnce(&mut PersistentTwoThreeTree) -> R) -> R {
+        let _writer = self.writer.lock().unwrap();
+        let guard = epoch::pin();
+        let current = self.current.load(Ordering::Acquire, &guard);
+        // SAFETY: As in read(). Also, no other writer can replace the tree meanwhile.
+        let mut copy = unsafe { current.deref() }.clone();
+        let result = f(&mut copy);
+        let old = self
+            .current
+            .swap(Owned::new(copy), Ordering::AcqRel, &guard);
+        // SAFETY: The old tree is unreachable for new readers, and is freed after the current
+        // readers unpin.
+        unsafe { guard.defer_destroy(old) };
+        result
+    }
+
+    pub fn insert(&self, element: Element) {
+        self.write(|tree| tree.insert(element))
+    }
+
+    // Deletes an element with the given key. Returns true if the element was found.
+    pub fn delete(&self, key: usize) -> bool {
+        self.write(|tree| tree.remove(key).is_some())
+    }
+}
+
+#[cfg(feature = "epoch")]
+impl Default for EpochTwoThreeTree {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "epoch")]
+impl Drop for EpochTwoThreeTree {
+    fn drop(&mut self) {
+        // SAFETY: No reader can hold a reference into the tree while it is being dropped.
+        unsafe {
+            let current = self.current.load(Ordering::Relaxed, epoch::unprotected());
+            drop(current.into_owned());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::thread;
+
+    use super::ConcurrentTwoThreeMap;
+
+    #[test]
+    fn test_concurrent_map() {
+        let map = Arc::new(ConcurrentTwoThreeMap::new(8));
+        let threads: Vec<_> = (0..4)
+            .map(|t| {
+                let map = Arc::clone(&map);
+                thread::spawn(move || {
+                    for i in 0..1000 {
+                        map.insert(i * 4 + t, i);
+                    }
+                    for i in 0..500 {
+                        assert!(map.remove(i * 4 + t) == Some(i));
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        assert!(map.len() == 2000);
+        assert!(map.insert(2000, 0) == Some(500));
+        assert!(map.get(2000) == Some(0) && map.get(0).is_none());
+        assert!(map.iter().map(|e| e.key).eq(2000..4000));
+    }
+
+    #[cfg(feature = "epoch")]
+    #[test]
+    fn test_epoch_tree() {
+        use super::EpochTwoThreeTree;
+        use crate::two_three_tree::Element;
+
+        let tree = Arc::new(EpochTwoThreeTree::new());
+        let writer = {
+            let tree = Arc::clone(&tree);
+            thread::spawn(move || {
+                for i in 0..200 {
+                    tree.insert(Element { key: i, value: i });
+                }
+            })
+        };
+        let readers: Vec<_> = (0..4)
+            .map(|_| {
+                let tree = Arc::clone(&tree);
+                thread::spawn(move || {
+                    let mut last = 0;
+                    while last < 200 {
+                        // Each reader sees a whole tree, which only grows.
+                        let size = tree.read(|tree| {
+                            assert!(tree.iter().map(|e| e.key).eq(0..tree.size()));
+                            tree.size()
+                        });
+                        assert!(size >= last);
+                        last = size;
+                    }
+                })
+            })
+            .collect();
+        writer.join().unwrap();
+        for reader in readers {
+            reader.join().unwrap();
+        }
+        // A snapshot taken in a read keeps the nodes the later writes replace.
+        let before = tree.read(|tree| tree.snapshot());
+        assert!(tree.delete(100) && !tree.delete(100));
+        assert!(tree.size() == 199 && tree.find(100).is_none());
+        assert!(before.size() == 200 && before.find(100).is_some());
+    }
+}
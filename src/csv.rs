@@ -0,0 +1,76 @@
+// CSV import and export of the key-value pairs of a 2-3 Tree.
+//
+// The format is a "key,value" header line followed by one "<key>,<value>" line per element.
+
+use std::io::{self, BufRead, Write};
+
+use crate::two_three_tree::{Augment, Element, TwoThreeTree};
+
+const HEADER: &str = "key,value";
+
+impl<A: Augment> TwoThreeTree<A> {
+    // Writes the elements in key order, streaming from the in-order iterator.
+    pub fn to_csv(&self, mut writer: impl Write) -> io::Result<()> {
+        writeln!(writer, "{}", HEADER)?;
+        for element in self.iter() {
+            writeln!(writer, "{},{}", element.key, element.value)?;
+        }
+        writer.flush()
+    }
+}
+
+impl TwoThreeTree {
+    // Reads a tree written by to_csv(). The header is optional, and so are spaces around the
+    // fields. The elements are bulk-loaded; they are sorted first if needed.
+    pub fn from_csv(reader: impl BufRead) -> io::Result<TwoThreeTree> {
+        let mut elements = Vec::new();
+        for (number, line) in (1..).zip(reader.lines()) {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || (number == 1 && line == HEADER) {
+                continue;
+            }
+            let parsed = line.split_once(',').and_then(|(key, value)| {
+                Some(Element {
+                    key: key.trim().parse().ok()?,
+                    value: value.trim().parse().ok()?,
+                })
+            });
+            let element = parsed.ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("line {}: expected <key>,<value>", number),
+                )
+            })?;
+            elements.push(element);
+        }
+        if !elements.is_sorted_by_key(|e| e.key) {
+            elements.sort_by_key(|e| e.key);
+        }
+        Ok(TwoThreeTree::from_sorted(elements))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::two_three_tree::TwoThreeTree;
+
+    #[test]
+    fn test_csv() {
+        let tree = TwoThreeTree::from(vec![(3, 30), (1, 10), (2, 20)]);
+        let mut buf = Vec::new();
+        tree.to_csv(&mut buf).unwrap();
+        assert!(buf == b"key,value\n1,10\n2,20\n3,30\n");
+
+        let read = TwoThreeTree::from_csv(&buf[..]).unwrap();
+        assert!(read.into_sorted_vec() == vec![(1, 10), (2, 20), (3, 30)]);
+        let read = TwoThreeTree::from_csv(&b"5, 50\n4,40\n\n"[..]).unwrap();
+        read.validate();
+        assert!(read.into_sorted_vec() == vec![(4, 40), (5, 50)]);
+
+        let error = TwoThreeTree::from_csv(&b"key,value\n1,10\n2;20\n"[..])
+            .err()
+            .unwrap();
+        assert!(error.to_string() == "line 3: expected <key>,<value>");
+    }
+}
@@ -0,0 +1,236 @@
+// Differences between two 2-3 Trees.
+//
+// A diff is an edit script which turns one tree into another. Both trees are walked together
+// in key order, so computing it takes O(n + m). Elements with equal keys are compared as
+// multisets of values, so the order of duplicates within a tree does not matter.
+
+use std::cmp::Ordering;
+use std::collections::{HashMap, VecDeque};
+use std::iter::Peekable;
+
+use crate::two_three_tree::{Augment, Comparator, Element, Iter, TwoThreeTree};
+
+// An edit turning one tree into another.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum DiffEntry {
+    // An element only in the other tree.
+    Added { key: usize, value: usize },
+
+    // An element only in this tree.
+    Removed { key: usize, value: usize },
+
+    // An element whose value differs between the trees.
+    Changed { key: usize, old: usize, new: usize },
+}
+
+// Iterator over the edits between two trees, in key order.
+pub struct Diff<'a, A: Augment = ()> {
+    old: Peekable<Iter<'a, A>>,
+    new: Peekable<Iter<'a, A>>,
+
+    // Orders the keys of both trees.
+    compare: Comparator,
+
+    // Edits for the last key found in both trees, not yet returned.
+    pending: VecDeque<DiffEntry>,
+}
+
+impl<A: Augment> TwoThreeTree<A> {
+    // Returns the edits which turn this tree into the other, which must order its keys the same.
+    pub fn diff<'a>(&'a self, other: &'a TwoThreeTree<A>) -> Diff<'a, A> {
+        Diff {
+            old: self.iter().peekable(),
+            new: other.iter().peekable(),
+            compare: self.comparator(),
+            pending: VecDeque::new(),
+        }
+    }
+
+    // Applies an edit script, e.g. from diff(), transactionally.
+    // Returns false and leaves the tree unchanged if an edit removes or changes an element
+    // which is not in the tree.
+    pub fn apply_diff(&mut self, edits: impl IntoIterator<Item = DiffEntry>) -> bool {
+        let edits: Vec<DiffEntry> = edits.into_iter().collect();
+
+        // Check phase: track the number of elements per (key, value) as the edits would leave it.
+        let mut counts: HashMap<(usize, usize), usize> = HashMap::new();
+        for edit in edits.iter() {
+            let (key, removed, added) = match *edit {
+                DiffEntry::Added { key, value } => (key, None, Some(value)),
+                DiffEntry::Removed { key, value } => (key, Some(value), None),
+                DiffEntry::Changed { key, old, new } => (key, Some(old), Some(new)),
+            };
+            if let Some(value) = removed {
+                let count = counts
+                    .entry((key, value))
+                    .or_insert_with(|| self.count(key, Some(value)));
+                if *count == 0 {
+                    return false;
+                }
+                *count -= 1;
+            }
+            if let Some(value) = added {
+                *counts
+                    .entry((key, value))
+                    .or_insert_with(|| self.count(key, Some(value))) += 1;
+            }
+        }
+
+        // Apply phase: none of the edits can fail now.
+        for edit in edits {
+            match edit {
+                DiffEntry::Added { key, value } => self.insert(Element { key, value }),
+                DiffEntry::Removed { key, value } => {
+                    let removed = self.remove_element(Element { key, value });
+                    debug_assert!(removed);
+                }
+                DiffEntry::Changed { key, old, new } => {
+                    let changed = self.set_value(Element { key, value: old }, new);
+                    debug_assert!(changed);
+                }
+            }
+        }
+        true
+    }
+}
+
+impl<A: Augment> Iterator for Diff<'_, A> {
+    type Item = DiffEntry;
+
+    fn next(&mut self) -> Option<DiffEntry> {
+        loop {
+            if let Some(entry) = self.pending.pop_front() {
+                return Some(entry);
+            }
+            let entry = match (self.old.peek().copied(), self.new.peek().copied()) {
+                (Some(old), Some(new)) if old.key == new.key => {
+                    self.diff_key(old.key);
+                    continue;
+                }
+                (Some(old), Some(new))
+                    if (self.compare)(&old.key, &new.key) == Ordering::Greater =>
+                {
+                    self.new.next();
+                    DiffEntry::Added {
+                        key: new.key,
+                        value: new.value,
+                    }
+                }
+                (None, Some(new)) => {
+                    self.new.next();
+                    DiffEntry::Added {
+                        key: new.key,
+                        value: new.value,
+                    }
+                }
+                (Some(old), _) => {
+                    self.old.next();
+                    DiffEntry::Removed {
+                        key: old.key,
+                        value: old.value,
+                    }
+                }
+                (None, None) => return None,
+            };
+            return Some(entry);
+        }
+    }
+}
+
+impl<A: Augment> Diff<'_, A> {
+    // Queues the edits between the elements with the given key in both trees.
+    // Values in both are skipped, the rest are paired up as changes in value order.
+    fn diff_key(&mut self, key: usize) {
+        let mut old = Vec::new();
+        while let Some(element) = self.old.next_if(|e| e.key == key) {
+            old.push(element.value);
+        }
+        let mut new = Vec::new();
+        while let Some(element) = self.new.next_if(|e| e.key == key) {
+            new.push(element.value);
+        }
+        old.sort_unstable();
+        new.sort_unstable();
+
+        let (mut removed, mut added) = (Vec::new(), Vec::new());
+        let (mut i, mut j) = (0, 0);
+        while i < old.len() || j < new.len() {
+            if i < old.len() && j < new.len() && old[i] == new[j] {
+                i += 1;
+                j += 1;
+            } else if j == new.len() || (i < old.len() && old[i] < new[j]) {
+                removed.push(old[i]);
+                i += 1;
+            } else {
+                added.push(new[j]);
+                j += 1;
+            }
+        }
+
+        let changed = removed.len().min(added.len());
+        for (&old, &new) in removed.iter().zip(added.iter()) {
+            self.pending.push_back(DiffEntry::Changed { key, old, new });
+        }
+        for &value in removed[changed..].iter() {
+            self.pending.push_back(DiffEntry::Removed { key, value });
+        }
+        for &value in added[changed..].iter() {
+            self.pending.push_back(DiffEntry::Added { key, value });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DiffEntry;
+    use crate::two_three_tree::{Element, TwoThreeTree};
+
+    #[test]
+    fn test_diff() {
+        let old = TwoThreeTree::from_sorted(
+            [(1, 10), (2, 20), (2, 21), (4, 40)].map(|(key, value)| Element { key, value }),
+        );
+        let new = TwoThreeTree::from_sorted(
+            [(2, 20), (2, 22), (3, 30), (4, 40), (4, 41)]
+                .map(|(key, value)| Element { key, value }),
+        );
+        let edits: Vec<DiffEntry> = old.diff(&new).collect();
+        let expected = [
+            DiffEntry::Removed { key: 1, value: 10 },
+            DiffEntry::Changed {
+                key: 2,
+                old: 21,
+                new: 22,
+            },
+            DiffEntry::Added { key: 3, value: 30 },
+            DiffEntry::Added { key: 4, value: 41 },
+        ];
+        assert!(edits == expected);
+        assert!(new.diff(&new).next().is_none());
+    }
+
+    #[test]
+    fn test_apply_diff() {
+        let mut old = TwoThreeTree::new();
+        let mut new = TwoThreeTree::new();
+        for i in 0..200 {
+            old.insert(Element {
+                key: i % 70,
+                value: i,
+            });
+            new.insert(Element {
+                key: i % 50,
+                value: i % 3,
+            });
+        }
+        let edits: Vec<DiffEntry> = old.diff(&new).collect();
+        assert!(old.apply_diff(edits.iter().copied()));
+        old.validate();
+        assert!(old.diff(&new).next().is_none());
+
+        // Applying the edits again fails without changing the tree.
+        let generation = old.generation();
+        assert!(!old.apply_diff(edits));
+        assert!(old.generation() == generation);
+    }
+}
@@ -0,0 +1,25 @@
+// The elements stored by the trees.
+//
+// This module only needs core, so that the trees built without the std feature, e.g.
+// StaticTwoThreeTree, can store elements too.
+
+// A key and its value. The trees of this crate store usize keys and values, the defaults.
+// Elements compare as (key, value) pairs: equal elements have equal keys and values, and
+// elements with equal keys are ordered by value. The tree itself orders by key only.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Element<K = usize, V = usize> {
+    pub key: K,
+    pub value: V,
+}
+
+impl<K, V> From<(K, V)> for Element<K, V> {
+    fn from((key, value): (K, V)) -> Element<K, V> {
+        Element { key, value }
+    }
+}
+
+impl<K, V> From<Element<K, V>> for (K, V) {
+    fn from(element: Element<K, V>) -> (K, V) {
+        (element.key, element.value)
+    }
+}
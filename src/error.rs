@@ -0,0 +1,164 @@
+// A single error type for the fallible operations, and Result-returning variants of the
+// operations which otherwise report failure with a bool, an Option or a panic.
+
+use std::fmt;
+use std::io;
+
+use crate::poison::PoisonedError;
+use crate::snapshot_file::LoadError;
+use crate::static_tree::StaticTwoThreeTree;
+use crate::two_three_tree::{Augment, Element, TwoThreeTree};
+
+#[derive(Debug)]
+pub enum TwoThreeError {
+    // No element has the key.
+    NotFound,
+
+    // An element with the key already exists.
+    Duplicate,
+
+    // A mutation panicked earlier. See PoisoningTwoThreeTree.
+    Poisoned,
+
+    Io(io::Error),
+
+    // Stored data is malformed or fails its checks.
+    Corrupt,
+
+    // A fixed-capacity tree has no room for the nodes needed.
+    AllocFail,
+
+    // The key is not accepted, e.g. a NaN under NanPolicy::Reject.
+    InvalidKey,
+}
+
+impl fmt::Display for TwoThreeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TwoThreeError::NotFound => write!(f, "key not found"),
+            TwoThreeError::Duplicate => write!(f, "duplicate key"),
+            TwoThreeError::Poisoned => write!(f, "{}", PoisonedError),
+            TwoThreeError::Io(error) => write!(f, "{}", error),
+            TwoThreeError::Corrupt => write!(f, "corrupt data"),
+            TwoThreeError::AllocFail => write!(f, "out of nodes"),
+            TwoThreeError::InvalidKey => write!(f, "invalid key"),
+        }
+    }
+}
+
+impl std::error::Error for TwoThreeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            TwoThreeError::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for TwoThreeError {
+    fn from(error: io::Error) -> TwoThreeError {
+        TwoThreeError::Io(error)
+    }
+}
+
+impl From<PoisonedError> for TwoThreeError {
+    fn from(_: PoisonedError) -> TwoThreeError {
+        TwoThreeError::Poisoned
+    }
+}
+
+impl From<LoadError> for TwoThreeError {
+    fn from(error: LoadError) -> TwoThreeError {
+        match error {
+            LoadError::Io(error) => TwoThreeError::Io(error),
+            LoadError::BadMagic | LoadError::UnsupportedVersion(_) | LoadError::Corrupt => {
+                TwoThreeError::Corrupt
+            }
+        }
+    }
+}
+
+impl<A: Augment> TwoThreeTree<A> {
+    // Returns an element with the given key.
+    pub fn try_get(&self, key: usize) -> Result<Element, TwoThreeError> {
+        self.find(key).ok_or(TwoThreeError::NotFound)
+    }
+
+    // Inserts an element unless an element with the same key exists.
+    pub fn try_insert_unique(&mut self, element: Element) -> Result<(), TwoThreeError> {
+        if self.find(element.key).is_some() {
+            return Err(TwoThreeError::Duplicate);
+        }
+        self.insert(element);
+        Ok(())
+    }
+
+    // Removes an element with the given key, and returns it.
+    pub fn try_remove(&mut self, key: usize) -> Result<Element, TwoThreeError> {
+        self.remove(key).ok_or(TwoThreeError::NotFound)
+    }
+
+    // Mutates the value of an element with the given key.
+    pub fn try_update(
+        &mut self,
+        key: usize,
+        f: impl FnOnce(&mut usize),
+    ) -> Result<(), TwoThreeError> {
+        if self.update(key, f) {
+            Ok(())
+        } else {
+            Err(TwoThreeError::NotFound)
+        }
+    }
+}
+
+impl<K: Ord + Copy + Default, V: Copy + Default, const N: usize> StaticTwoThreeTree<K, V, N> {
+    // Inserts an element, failing with AllocFail if there are not enough free nodes.
+    pub fn try_insert(&mut self, element: Element<K, V>) -> Result<(), TwoThreeError> {
+        self.insert(element).map_err(|_| TwoThreeError::AllocFail)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TwoThreeError;
+    use crate::static_tree::StaticTwoThreeTree;
+    use crate::two_three_tree::{Element, TwoThreeTree};
+
+    #[test]
+    fn test_errors() {
+        let mut tree = TwoThreeTree::new();
+        assert!(tree
+            .try_insert_unique(Element { key: 1, value: 10 })
+            .is_ok());
+        assert!(matches!(
+            tree.try_insert_unique(Element { key: 1, value: 11 }),
+            Err(TwoThreeError::Duplicate)
+        ));
+        assert!(tree.try_update(1, |value| *value += 1).is_ok());
+        assert!(tree.try_get(1).unwrap().value == 11);
+        assert!(matches!(
+            tree.try_update(2, |_| {}),
+            Err(TwoThreeError::NotFound)
+        ));
+        assert!(tree.try_remove(1).unwrap().value == 11);
+        assert!(matches!(tree.try_remove(1), Err(TwoThreeError::NotFound)));
+
+        let mut tree = StaticTwoThreeTree::<usize, usize, 4>::new();
+        let results: Vec<_> = (0..20)
+            .map(|key| tree.try_insert(Element { key, value: key }))
+            .collect();
+        assert!(matches!(
+            results.last(),
+            Some(Err(TwoThreeError::AllocFail))
+        ));
+
+        let error = TwoThreeError::from(
+            TwoThreeTree::load_from("/nonexistent/snapshot")
+                .err()
+                .unwrap(),
+        );
+        assert!(matches!(error, TwoThreeError::Io(_)));
+        assert!(std::error::Error::source(&error).is_some());
+    }
+}
@@ -0,0 +1,155 @@
+// A 2-3 Tree whose elements may carry a deadline, for TTL caches.
+//
+// A second tree orders the pending deadlines, so that expire_until() removes the k elements
+// past due in O(k log n) without scanning the others.
+
+use std::collections::HashMap;
+
+use crate::two_three_tree::{Element, TwoThreeTree};
+
+pub struct ExpiringTwoThreeTree {
+    tree: TwoThreeTree,
+
+    // The pending deadlines as elements keyed by deadline, with the entry id as value.
+    deadlines: TwoThreeTree,
+
+    // The element and deadline of each entry id.
+    entries: HashMap<usize, (Element, usize)>,
+
+    // The entry ids of the elements with a deadline, by key and value. Equal elements are
+    // interchangeable, so any of their deadlines may go when one of them is removed.
+    ids: HashMap<(usize, usize), Vec<usize>>,
+
+    next_id: usize,
+}
+
+impl ExpiringTwoThreeTree {
+    pub fn new() -> ExpiringTwoThreeTree {
+        ExpiringTwoThreeTree {
+            tree: TwoThreeTree::new(),
+            deadlines: TwoThreeTree::new(),
+            entries: HashMap::new(),
+            ids: HashMap::new(),
+            next_id: 0,
+        }
+    }
+
+    // The elements, with or without deadlines.
+    pub fn tree(&self) -> &TwoThreeTree {
+        &self.tree
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    pub fn size(&self) -> usize {
+        self.tree.size()
+    }
+
+    pub fn find(&self, key: usize) -> Option<Element> {
+        self.tree.find(key)
+    }
+
+    // Inserts an element which does not expire.
+    pub fn insert(&mut self, element: Element) {
+        self.tree.insert(element);
+    }
+
+    // Inserts an element to be removed by expire_until() once the deadline is reached.
+    pub fn insert_with_deadline(&mut self, element: Element, deadline: usize) {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.tree.insert(element);
+        self.deadlines.insert(Element {
+            key: deadline,
+            value: id,
+        });
+        self.entries.insert(id, (element, deadline));
+        self.ids
+            .entry((element.key, element.value))
+            .or_default()
+            .push(id);
+    }
+
+    // Removes an element with the given key, along with its deadline if any.
+    pub fn remove(&mut self, key: usize) -> Option<Element> {
+        let element = self.tree.remove(key)?;
+        if let Some(id) = self.take_id(element) {
+            let (_, deadline) = self.entries.remove(&id).unwrap();
+            self.deadlines.remove_element(Element {
+                key: deadline,
+                value: id,
+            });
+        }
+        Some(element)
+    }
+
+    // Returns the earliest pending deadline.
+    pub fn next_deadline(&self) -> Option<usize> {
+        self.deadlines.first().map(|e| e.key)
+    }
+
+    // Removes and returns the elements whose deadline is at or before now, by deadline.
+    pub fn expire_until(&mut self, now: usize) -> Vec<Element> {
+        let mut expired = Vec::new();
+        while self.next_deadline().is_some_and(|deadline| deadline <= now) {
+            let id = self.deadlines.delete_min().unwrap().value;
+            let (element, _) = self.entries.remove(&id).unwrap();
+            let ids = self.ids.get_mut(&(element.key, element.value)).unwrap();
+            ids.retain(|&other| other != id);
+            if ids.is_empty() {
+                self.ids.remove(&(element.key, element.value));
+            }
+            assert!(self.tree.remove_element(element));
+            expired.push(element);
+        }
+        expired
+    }
+
+    // Takes one of the entry ids of an element, if it has a deadline.
+    fn take_id(&mut self, element: Element) -> Option<usize> {
+        let key = (element.key, element.value);
+        let ids = self.ids.get_mut(&key)?;
+        let id = ids.pop();
+        if ids.is_empty() {
+            self.ids.remove(&key);
+        }
+        id
+    }
+}
+
+impl Default for ExpiringTwoThreeTree {
+    fn default() -> ExpiringTwoThreeTree {
+        ExpiringTwoThreeTree::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ExpiringTwoThreeTree;
+    use crate::two_three_tree::Element;
+
+    #[test]
+    fn test_expire_until() {
+        let mut tree = ExpiringTwoThreeTree::new();
+        for key in 0..10 {
+            tree.insert_with_deadline(Element { key, value: key }, 100 - key);
+        }
+        tree.insert(Element { key: 20, value: 0 });
+        assert!(tree.next_deadline() == Some(91));
+
+        // Removed elements do not expire.
+        assert!(tree.remove(9).is_some());
+        let expired: Vec<usize> = tree.expire_until(95).iter().map(|e| e.key).collect();
+        assert!(expired == vec![8, 7, 6, 5]);
+        assert!(tree.size() == 6);
+        assert!(tree.find(4).is_some());
+
+        let expired = tree.expire_until(usize::MAX);
+        assert!(expired.len() == 5);
+        assert!(tree.size() == 1);
+        assert!(tree.next_deadline().is_none());
+        tree.tree().validate();
+    }
+}
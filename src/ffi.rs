@@ -0,0 +1,191 @@
+// C bindings, declared in include/two_three_tree.h. The header is written by hand, and
+// test_header checks that it declares every function below with the matching C types.
+//
+// Trees and cursors are opaque handles owned by the caller, who frees them with the matching
+// free function. Every function expects handles created by this module and not yet freed, and
+// output pointers valid for writes. Build a library for C with
+// `cargo rustc --release --features ffi --crate-type staticlib` (or cdylib).
+
+// The safety contract is the same for every function, and is described above.
+#![allow(clippy::missing_safety_doc)]
+
+use crate::two_three_tree::{Cursor, Element, TwoThreeTree};
+
+// Creates an empty tree.
+#[no_mangle]
+pub extern "C" fn tt_tree_new() -> *mut TwoThreeTree {
+    Box::into_raw(Box::new(TwoThreeTree::new()))
+}
+
+// Frees a tree. Does nothing for NULL.
+#[no_mangle]
+pub unsafe extern "C" fn tt_tree_free(tree: *mut TwoThreeTree) {
+    if !tree.is_null() {
+        drop(Box::from_raw(tree));
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn tt_tree_size(tree: *const TwoThreeTree) -> usize {
+    (*tree).size()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn tt_tree_insert(tree: *mut TwoThreeTree, key: usize, value: usize) {
+    (*tree).insert(Element { key, value });
+}
+
+// Finds an element with the given key. Returns true and stores its value if found.
+#[no_mangle]
+pub unsafe extern "C" fn tt_tree_find(
+    tree: *const TwoThreeTree,
+    key: usize,
+    value: *mut usize,
+) -> bool {
+    match (*tree).find(key) {
+        Some(element) => {
+            *value = element.value;
+            true
+        }
+        None => false,
+    }
+}
+
+// Deletes an element with the given key. Returns true if the element was found.
+#[no_mangle]
+pub unsafe extern "C" fn tt_tree_delete(tree: *mut TwoThreeTree, key: usize) -> bool {
+    (*tree).delete(key)
+}
+
+// Creates a cursor before the first element of the tree.
+#[no_mangle]
+pub unsafe extern "C" fn tt_cursor_new(tree: *const TwoThreeTree) -> *mut Cursor {
+    Box::into_raw(Box::new((*tree).cursor()))
+}
+
+// Frees a cursor. Does nothing for NULL.
+#[no_mangle]
+pub unsafe extern "C" fn tt_cursor_free(cursor: *mut Cursor) {
+    if !cursor.is_null() {
+        drop(Box::from_raw(cursor));
+    }
+}
+
+// Advances the cursor and stores the next element in key order. Returns false at the end, or
+// if the tree has been mutated since the cursor was created.
+#[no_mangle]
+pub unsafe extern "C" fn tt_cursor_next(
+    cursor: *mut Cursor,
+    tree: *const TwoThreeTree,
+    key: *mut usize,
+    value: *mut usize,
+) -> bool {
+    let (cursor, tree) = (&mut *cursor, &*tree);
+    if !cursor.is_valid(tree) {
+        return false;
+    }
+    match cursor.next(tree) {
+        Some(element) => {
+            *key = element.key;
+            *value = element.value;
+            true
+        }
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ffi() {
+        unsafe {
+            let tree = tt_tree_new();
+            for key in (0..100).rev() {
+                tt_tree_insert(tree, key, key * 10);
+            }
+            assert!(tt_tree_size(tree) == 100);
+            let mut value = 0;
+            assert!(tt_tree_find(tree, 42, &mut value) && value == 420);
+            assert!(tt_tree_delete(tree, 42) && !tt_tree_find(tree, 42, &mut value));
+
+            let cursor = tt_cursor_new(tree);
+            let (mut key, mut keys) = (0, Vec::new());
+            while tt_cursor_next(cursor, tree, &mut key, &mut value) {
+                keys.push(key);
+            }
+            assert!(keys.iter().copied().eq((0..100).filter(|&k| k != 42)));
+            tt_cursor_free(cursor);
+
+            // A cursor stops once the tree is mutated.
+            let cursor = tt_cursor_new(tree);
+            tt_tree_insert(tree, 1000, 0);
+            assert!(!tt_cursor_next(cursor, tree, &mut key, &mut value));
+            tt_cursor_free(cursor);
+            tt_tree_free(tree);
+        }
+    }
+    // Converts a Rust type of the bindings to its C spelling.
+    fn c_type(rust: &str) -> String {
+        match rust.split_once(' ') {
+            Some(("*const", pointee)) => format!("const {} *", c_type(pointee)),
+            Some(("*mut", pointee)) => format!("{} *", c_type(pointee)),
+            _ => match rust {
+                "usize" => "size_t".to_string(),
+                "bool" | "TwoThreeTree" | "Cursor" => rust.to_string(),
+                _ => panic!("no C type for {}", rust),
+            },
+        }
+    }
+
+    // Declares a name of the given C type, e.g. "size_t *value".
+    fn c_declaration(c_type: &str, name: &str) -> String {
+        match c_type.ends_with('*') {
+            true => format!("{}{}", c_type, name),
+            false => format!("{} {}", c_type, name),
+        }
+    }
+
+    #[test]
+    fn test_header() {
+        let source = include_str!("ffi.rs");
+        let source = &source[..source.find("#[cfg(test)]").unwrap()];
+        let mut expected: Vec<String> = source
+            .split("extern \"C\" fn ")
+            .skip(1)
+            .map(|rest| {
+                let signature = rest[..rest.find('{').unwrap()].split_whitespace();
+                let signature = signature.collect::<Vec<_>>().join(" ");
+                let (name, rest) = signature.split_once('(').unwrap();
+                let (params, ret) = rest.split_once(')').unwrap();
+                let params: Vec<String> = params
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|param| !param.is_empty())
+                    .map(|param| {
+                        let (name, rust) = param.split_once(": ").unwrap();
+                        c_declaration(&c_type(rust), name)
+                    })
+                    .collect();
+                let ret = match ret.trim().strip_prefix("-> ") {
+                    Some(rust) => c_type(rust),
+                    None => "void".to_string(),
+                };
+                let params = match params.is_empty() {
+                    true => "void".to_string(),
+                    false => params.join(", "),
+                };
+                format!("{}({});", c_declaration(&ret, name), params)
+            })
+            .collect();
+        let mut declared: Vec<String> = include_str!("../include/two_three_tree.h")
+            .lines()
+            .filter(|line| line.contains("tt_") && line.ends_with(");"))
+            .map(str::to_string)
+            .collect();
+        expected.sort();
+        declared.sort();
+        assert!(expected.len() == 9 && declared == expected);
+    }
+}
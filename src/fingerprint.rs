@@ -0,0 +1,251 @@
+// Content fingerprints of 2-3 Trees.
+//
+// A fingerprint is a polynomial hash over the (key, value) pairs in key order, with the pairs of
+// equal keys ordered by value. It depends on the order of the pairs, but not on the order in
+// which the tree holds the elements with equal keys, which diff() ignores too. Replicas can
+// compare fingerprints cheaply before running a full diff. The hash is not cryptographic.
+//
+// A tree with the Merkle augmentation maintains a hash of every subtree instead. A subtree cannot
+// order the values of a key whose elements spread over its neighbors, so the Merkle hash is a
+// polynomial hash over the keys, of the sum of the hashes of the pairs with each key. Hashes of
+// any key range are then available in O(log n), and divergent keys between two trees are found by
+// bisection.
+
+use std::ops::RangeBounds;
+
+use crate::two_three_tree::{Augment, Element, TwoThreeTree};
+
+// Maintains the hash of every subtree.
+pub struct Merkle;
+
+// The hash of a sequence of elements.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct MerkleDigest {
+    hash: u64,
+
+    // The base raised to the number of distinct keys minus 1.
+    shift: u64,
+
+    // The first and last keys, or None for no elements.
+    keys: Option<(usize, usize)>,
+}
+
+impl MerkleDigest {
+    pub fn hash(&self) -> u64 {
+        self.hash
+    }
+}
+
+impl Augment for Merkle {
+    type Value = MerkleDigest;
+
+    fn identity() -> MerkleDigest {
+        MerkleDigest {
+            hash: 0,
+            shift: 1,
+            keys: None,
+        }
+    }
+
+    fn element(element: &Element) -> MerkleDigest {
+        MerkleDigest {
+            hash: hash_element(element),
+            shift: 1,
+            keys: Some((element.key, element.key)),
+        }
+    }
+
+    // If the left run ends with the key the right one starts with, the sums of the pairs with
+    // that key add up in the same position.
+    fn combine(left: &MerkleDigest, right: &MerkleDigest) -> MerkleDigest {
+        let (Some((first, last)), Some((next, end))) = (left.keys, right.keys) else {
+            return if left.keys.is_some() { *left } else { *right };
+        };
+        let shift = if last == next {
+            right.shift
+        } else {
+            right.shift.wrapping_mul(FINGERPRINT_BASE)
+        };
+        MerkleDigest {
+            hash: left.hash.wrapping_mul(shift).wrapping_add(right.hash),
+            shift: left.shift.wrapping_mul(shift),
+            keys: Some((first, end)),
+        }
+    }
+}
+
+// Multiplier of the polynomial hashes. Any odd constant works.
+const FINGERPRINT_BASE: u64 = 0x9e37_79b9_7f4a_7c15;
+
+impl<A: Augment> TwoThreeTree<A> {
+    // Returns an order-dependent hash of all (key, value) pairs, in O(n log d) for at most d
+    // elements with the same key. Trees with the same pairs have equal fingerprints regardless of
+    // their shape and of the order of the elements with equal keys.
+    pub fn fingerprint(&self) -> u64 {
+        let mut hash: u64 = 0;
+        let mut iter = self.iter().peekable();
+        let mut values = Vec::new();
+        while let Some(first) = iter.next() {
+            values.clear();
+            values.push(first.value);
+            while let Some(element) = iter.next_if(|e| e.key == first.key) {
+                values.push(element.value);
+            }
+            values.sort_unstable();
+            for &value in &values {
+                let element = Element {
+                    key: first.key,
+                    value,
+                };
+                hash = hash
+                    .wrapping_mul(FINGERPRINT_BASE)
+                    .wrapping_add(hash_element(&element));
+            }
+        }
+        hash
+    }
+}
+
+impl TwoThreeTree<Merkle> {
+    // Returns the Merkle hash of the whole tree, in O(1).
+    pub fn root_hash(&self) -> u64 {
+        self.query_range(..).hash
+    }
+
+    // Returns the Merkle hash of the elements with keys in the range, in O(log n).
+    // It equals the root hash of a tree holding only those elements.
+    pub fn range_hash(&self, range: impl RangeBounds<usize>) -> u64 {
+        self.query_range(range).hash
+    }
+
+    // Returns the keys whose elements differ between the two trees, in order.
+    // Key ranges with equal hashes are skipped, so each divergent key costs O(log^2 n).
+    pub fn divergent_keys(&self, other: &TwoThreeTree<Merkle>) -> Vec<usize> {
+        let mut keys = Vec::new();
+        self.divergent_keys_in(other, 0, usize::MAX, &mut keys);
+        keys
+    }
+
+    // Collects the divergent keys in [start, end], recursively.
+    fn divergent_keys_in(
+        &self,
+        other: &TwoThreeTree<Merkle>,
+        start: usize,
+        end: usize,
+        keys: &mut Vec<usize>,
+    ) {
+        if self.range_hash(start..=end) == other.range_hash(start..=end) {
+            return;
+        }
+        if start == end {
+            keys.push(start);
+            return;
+        }
+
+        // Split at the middle element of the tree with more elements in the range, so that
+        // each level halves the elements left to compare.
+        let (mine, theirs) = (self.count_range(start, end), other.count_range(start, end));
+        let (tree, count) = if mine >= theirs {
+            (self, mine)
+        } else {
+            (other, theirs)
+        };
+        let pivot = tree.select(tree.rank(start) + count / 2).unwrap().key;
+        let mid = if pivot > start { pivot - 1 } else { start };
+        self.divergent_keys_in(other, start, mid, keys);
+        self.divergent_keys_in(other, mid + 1, end, keys);
+    }
+}
+
+// Hashes a (key, value) pair.
+pub(crate) fn hash_element(element: &Element) -> u64 {
+    mix64(mix64(element.key as u64) ^ element.value as u64)
+}
+
+// The splitmix64 finalizer.
+pub(crate) fn mix64(mut x: u64) -> u64 {
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Merkle;
+    use crate::two_three_tree::{Element, TwoThreeTree};
+
+    #[test]
+    fn test_fingerprint() {
+        let mut tree = TwoThreeTree::new();
+        for i in 0..100 {
+            tree.insert(Element {
+                key: (i * 37) % 100,
+                value: i,
+            });
+        }
+        let copy = TwoThreeTree::from_sorted(tree.iter());
+        assert!(tree.fingerprint() == copy.fingerprint());
+
+        let fingerprint = tree.fingerprint();
+        assert!(tree.update(50, |value| *value += 1));
+        assert!(tree.fingerprint() != fingerprint);
+        assert!(tree.update(50, |value| *value -= 1));
+        assert!(tree.fingerprint() == fingerprint);
+        assert!(TwoThreeTree::new().fingerprint() == 0);
+
+        // Elements with equal keys may be in any order, but their number counts.
+        let pairs = |pairs: &[(usize, usize)]| TwoThreeTree::from(pairs.to_vec());
+        let a = pairs(&[(1, 10), (2, 20), (2, 21), (2, 22)]);
+        let b = pairs(&[(2, 22), (1, 10), (2, 21), (2, 20)]);
+        assert!(a.diff(&b).next().is_none() && a.fingerprint() == b.fingerprint());
+        assert!(a.fingerprint() != pairs(&[(1, 10), (2, 20), (2, 21)]).fingerprint());
+        assert!(a.fingerprint() != pairs(&[(1, 10), (2, 20), (2, 21), (2, 21)]).fingerprint());
+
+        // The pairs are hashed in order, so moving values between keys changes the fingerprint.
+        let c = pairs(&[(1, 20), (2, 10), (2, 21), (2, 22)]);
+        assert!(a.fingerprint() != c.fingerprint());
+        assert!(pairs(&[(1, 1), (2, 2)]).fingerprint() != pairs(&[(1, 2), (2, 1)]).fingerprint());
+    }
+
+    #[test]
+    fn test_merkle() {
+        let mut tree = TwoThreeTree::<Merkle>::with_augment();
+        let mut other = TwoThreeTree::<Merkle>::with_augment();
+        for i in 0..1000 {
+            tree.insert(Element {
+                key: i * 3,
+                value: i,
+            });
+            other.insert(Element {
+                key: (999 - i) * 3,
+                value: 999 - i,
+            });
+        }
+        tree.validate();
+        assert!(tree.root_hash() == other.root_hash());
+        assert!(tree.divergent_keys(&other).is_empty());
+        assert!(tree.range_hash(30..60) == tree.clone_range(30..60).root_hash());
+
+        // Elements with equal keys may be in any order, but the keys they have count.
+        let merkle = |pairs: &[(usize, usize)]| {
+            let mut tree = TwoThreeTree::<Merkle>::with_augment();
+            for &(key, value) in pairs {
+                tree.insert(Element { key, value });
+            }
+            tree.root_hash()
+        };
+        let hash = merkle(&[(1, 10), (2, 20), (2, 21), (2, 22)]);
+        assert!(hash == merkle(&[(2, 22), (1, 10), (2, 21), (2, 20)]));
+        assert!(hash != merkle(&[(1, 10), (2, 20), (2, 21)]));
+        assert!(merkle(&[(1, 1), (2, 2)]) != merkle(&[(1, 2), (2, 1)]));
+
+        assert!(other.update(300, |value| *value = 0));
+        assert!(other.delete(1500));
+        other.insert(Element { key: 7, value: 7 });
+        other.insert(Element {
+            key: usize::MAX,
+            value: 0,
+        });
+        assert!(tree.divergent_keys(&other) == [7, 300, 1500, usize::MAX]);
+    }
+}
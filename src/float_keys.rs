@@ -0,0 +1,212 @@
+// Floating-point keys, ordered by f64::total_cmp().
+//
+// A TotalF64 maps to a usize key with the same order: the sign bit is flipped for positive
+// numbers and all bits for negative ones, so that -inf < -0.0 < 0.0 < inf. NaNs have no place
+// in the numeric order, so a FloatTwoThreeTree puts them first, last or rejects them, as chosen
+// by its NanPolicy.
+
+use std::cmp::Ordering;
+use std::ops::{Bound, RangeBounds};
+
+use crate::error::TwoThreeError;
+use crate::two_three_tree::{Element, TwoThreeTree};
+
+// An f64 with a total order, as f64::total_cmp().
+#[derive(Clone, Copy, Debug)]
+pub struct TotalF64(pub f64);
+
+impl TotalF64 {
+    // Returns the usize key in the same order.
+    pub fn key(self) -> usize {
+        let bits = self.0.to_bits();
+        let key = if bits >> 63 == 1 {
+            !bits
+        } else {
+            bits | 1 << 63
+        };
+        key as usize
+    }
+
+    pub fn from_key(key: usize) -> TotalF64 {
+        let key = key as u64;
+        let bits = if key >> 63 == 1 {
+            key & !(1 << 63)
+        } else {
+            !key
+        };
+        TotalF64(f64::from_bits(bits))
+    }
+}
+
+impl PartialEq for TotalF64 {
+    fn eq(&self, other: &TotalF64) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for TotalF64 {}
+
+impl PartialOrd for TotalF64 {
+    fn partial_cmp(&self, other: &TotalF64) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for TotalF64 {
+    fn cmp(&self, other: &TotalF64) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+// Where a FloatTwoThreeTree puts NaN keys. All NaNs are then equal.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum NanPolicy {
+    // Before -inf.
+    First,
+
+    // After inf.
+    Last,
+
+    // Inserting a NaN fails with InvalidKey, and no NaN is found.
+    #[default]
+    Reject,
+}
+
+pub struct FloatTwoThreeTree {
+    // Elements keyed by TotalF64::key().
+    tree: TwoThreeTree,
+
+    nan_policy: NanPolicy,
+}
+
+impl FloatTwoThreeTree {
+    pub fn new() -> FloatTwoThreeTree {
+        FloatTwoThreeTree::with_nan_policy(NanPolicy::default())
+    }
+
+    pub fn with_nan_policy(nan_policy: NanPolicy) -> FloatTwoThreeTree {
+        FloatTwoThreeTree {
+            tree: TwoThreeTree::new(),
+            nan_policy,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    pub fn size(&self) -> usize {
+        self.tree.size()
+    }
+
+    // Returns the tree key of a float, or None for a rejected NaN. The keys of the NaNs are
+    // themselves NaN bit patterns, so they don't collide with any number.
+    fn key(&self, key: f64) -> Option<usize> {
+        if !key.is_nan() {
+            return Some(TotalF64(key).key());
+        }
+        match self.nan_policy {
+            NanPolicy::First => Some(0),
+            NanPolicy::Last => Some(usize::MAX),
+            NanPolicy::Reject => None,
+        }
+    }
+
+    // Inserts a key and its value, after the equal keys.
+    pub fn insert(&mut self, key: f64, value: usize) -> Result<(), TwoThreeError> {
+        let key = self.key(key).ok_or(TwoThreeError::InvalidKey)?;
+        self.tree.insert(Element { key, value });
+        Ok(())
+    }
+
+    // Returns the value of an element with the given key.
+    pub fn find(&self, key: f64) -> Option<usize> {
+        Some(self.tree.find(self.key(key)?)?.value)
+    }
+
+    // Removes an element with the given key, and returns its value.
+    pub fn remove(&mut self, key: f64) -> Option<usize> {
+        Some(self.tree.remove(self.key(key)?)?.value)
+    }
+
+    // Returns the keys and values in key order.
+    pub fn iter(&self) -> impl Iterator<Item = (f64, usize)> + '_ {
+        self.range(..)
+    }
+
+    // Returns the keys in the range and their values, in key order.
+    pub fn range(&self, range: impl RangeBounds<f64>) -> impl Iterator<Item = (f64, usize)> + '_ {
+        let bound = |bound: Bound<&f64>| match bound {
+            Bound::Included(&key) => self.key(key).map(Bound::Included),
+            Bound::Excluded(&key) => self.key(key).map(Bound::Excluded),
+            Bound::Unbounded => Some(Bound::Unbounded),
+        };
+        // A rejected NaN bound makes the range empty.
+        let range = bound(range.start_bound()).zip(bound(range.end_bound()));
+        range
+            .into_iter()
+            .flat_map(|range| self.tree.range(range))
+            .map(|e| (TotalF64::from_key(e.key).0, e.value))
+    }
+}
+
+impl Default for FloatTwoThreeTree {
+    fn default() -> FloatTwoThreeTree {
+        FloatTwoThreeTree::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FloatTwoThreeTree, NanPolicy, TotalF64};
+    use crate::error::TwoThreeError;
+
+    #[test]
+    fn test_float_keys() {
+        let floats = [
+            f64::NEG_INFINITY,
+            -1e300,
+            -1.5,
+            -f64::MIN_POSITIVE,
+            -0.0,
+            0.0,
+            f64::MIN_POSITIVE,
+            1.5,
+            1e300,
+            f64::INFINITY,
+        ];
+        assert!(floats
+            .windows(2)
+            .all(|w| TotalF64(w[0]).key() < TotalF64(w[1]).key()));
+        assert!(floats
+            .iter()
+            .all(|&x| TotalF64::from_key(TotalF64(x).key()) == TotalF64(x)));
+
+        let mut tree = FloatTwoThreeTree::new();
+        for (i, &x) in floats.iter().enumerate().rev() {
+            tree.insert(x, i).unwrap();
+        }
+        assert!(matches!(
+            tree.insert(f64::NAN, 0),
+            Err(TwoThreeError::InvalidKey)
+        ));
+        assert!(tree.find(f64::NAN).is_none());
+        assert!(tree.iter().map(|(_, value)| value).eq(0..floats.len()));
+        assert!(tree
+            .range(-1.5..1.5)
+            .map(|(key, _)| key)
+            .eq(floats[2..7].iter().copied()));
+        assert!(tree.find(-0.0) == Some(4) && tree.remove(0.0) == Some(5));
+
+        for (nan_policy, first) in [(NanPolicy::First, true), (NanPolicy::Last, false)] {
+            let mut tree = FloatTwoThreeTree::with_nan_policy(nan_policy);
+            for x in [1.0, f64::NAN, f64::NEG_INFINITY, -f64::NAN, f64::INFINITY] {
+                tree.insert(x, 0).unwrap();
+            }
+            let keys: Vec<f64> = tree.iter().map(|(key, _)| key).collect();
+            let nans = if first { &keys[..2] } else { &keys[3..] };
+            assert!(nans.iter().all(|x| x.is_nan()));
+            assert!(tree.range(..).count() == 5 && tree.remove(f64::NAN) == Some(0));
+        }
+    }
+}
@@ -0,0 +1,117 @@
+// A read-only, compact form of a 2-3 Tree.
+//
+// The elements are kept in a sorted array, in the key order of the tree. Lookups use binary
+// search and range scans return slices of the array.
+
+use std::cmp::Ordering;
+use std::ops::{Bound, RangeBounds};
+
+use crate::two_three_tree::{Augment, Comparator, Element, TwoThreeTree};
+
+// A read-only tree backed by a sorted array of elements.
+pub struct FrozenTwoThreeTree {
+    elements: Vec<Element>,
+
+    // Orders the keys, as in the tree it was frozen from.
+    compare: Comparator,
+}
+
+impl<A: Augment> TwoThreeTree<A> {
+    // Converts the tree into a read-only sorted array form.
+    pub fn freeze(self) -> FrozenTwoThreeTree {
+        FrozenTwoThreeTree {
+            elements: self.iter().collect(),
+            compare: self.comparator(),
+        }
+    }
+}
+
+impl FrozenTwoThreeTree {
+    pub fn is_empty(&self) -> bool {
+        self.elements.is_empty()
+    }
+
+    pub fn size(&self) -> usize {
+        self.elements.len()
+    }
+
+    // Finds an element with the given key.
+    pub fn find(&self, key: usize) -> Option<Element> {
+        let index = self.lower_bound(key);
+        self.elements.get(index).filter(|e| e.key == key).copied()
+    }
+
+    // Returns the elements with keys in the given range, in key order.
+    pub fn range(&self, range: impl RangeBounds<usize>) -> &[Element] {
+        let start = match range.start_bound() {
+            Bound::Included(&key) => self.lower_bound(key),
+            Bound::Excluded(&key) => self.upper_bound(key),
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(&key) => self.upper_bound(key),
+            Bound::Excluded(&key) => self.lower_bound(key),
+            Bound::Unbounded => self.elements.len(),
+        };
+        &self.elements[start..end.max(start)]
+    }
+
+    // Returns all elements in key order.
+    pub fn as_slice(&self) -> &[Element] {
+        &self.elements
+    }
+
+    // Returns an iterator over the elements in key order.
+    pub fn iter(&self) -> std::slice::Iter<'_, Element> {
+        self.elements.iter()
+    }
+
+    // Converts back into a mutable tree, with the same key order.
+    pub fn thaw(self) -> TwoThreeTree {
+        TwoThreeTree::build_sorted_by(self.elements, self.compare)
+    }
+
+    // Returns the index of the first element with a key >= the given key.
+    fn lower_bound(&self, key: usize) -> usize {
+        self.elements
+            .partition_point(|e| (self.compare)(&e.key, &key) == Ordering::Less)
+    }
+
+    // Returns the index of the first element with a key > the given key.
+    fn upper_bound(&self, key: usize) -> usize {
+        self.elements
+            .partition_point(|e| (self.compare)(&e.key, &key) != Ordering::Greater)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::two_three_tree::{Element, TwoThreeTree};
+
+    #[test]
+    fn test_freeze() {
+        let mut tree = TwoThreeTree::new();
+        for i in (0..100).rev() {
+            tree.insert(Element {
+                key: i * 2,
+                value: i,
+            });
+        }
+        let frozen = tree.freeze();
+        assert!(frozen.size() == 100);
+        assert!(frozen.find(42).unwrap().value == 21);
+        assert!(frozen.find(43).is_none());
+        assert!(frozen
+            .range(10..20)
+            .iter()
+            .map(|e| e.key)
+            .eq([10, 12, 14, 16, 18]));
+        assert!(frozen.range(11..=14).len() == 2);
+        assert!(frozen.range(190..).len() == 5);
+        assert!(frozen.range(21..21).is_empty());
+
+        let tree = frozen.thaw();
+        tree.validate();
+        assert!(tree.size() == 100);
+    }
+}
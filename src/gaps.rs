@@ -0,0 +1,186 @@
+// Queries for the keys absent from a 2-3 Tree, e.g. to allocate IDs on top of it.
+//
+// Subtree sizes cannot show that a key span is fully occupied, as keys may repeat. So the tree
+// maintains KeyOccupancy: the smallest and largest key of each subtree, and its number of
+// distinct keys. A subtree with as many distinct keys as its span is full, and the queries skip
+// it without descending. first_missing_key() runs in O(log n), and free_ranges() in
+// O((k + 1) log n) for k ranges.
+
+use std::ops::{ControlFlow, RangeInclusive};
+
+use crate::two_three_tree::{Augment, Element, NodeRef, TwoThreeTree};
+
+// Maintains the Occupancy of the keys of each subtree.
+pub struct KeyOccupancy;
+
+// The keys of a non-empty subtree.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Occupancy {
+    pub min: usize,
+    pub max: usize,
+
+    // Number of distinct keys.
+    pub distinct: usize,
+}
+
+impl Occupancy {
+    // Returns true if every key from min to max is present.
+    pub fn is_full(&self) -> bool {
+        self.distinct - 1 == self.max - self.min
+    }
+}
+
+impl Augment for KeyOccupancy {
+    type Value = Option<Occupancy>;
+
+    fn identity() -> Option<Occupancy> {
+        None
+    }
+
+    fn element(element: &Element) -> Option<Occupancy> {
+        Some(Occupancy {
+            min: element.key,
+            max: element.key,
+            distinct: 1,
+        })
+    }
+
+    // The runs are in key order, so they share at most one key, the max of left.
+    fn combine(left: &Option<Occupancy>, right: &Option<Occupancy>) -> Option<Occupancy> {
+        match (left, right) {
+            (Some(left), Some(right)) => Some(Occupancy {
+                min: left.min,
+                max: right.max,
+                distinct: left.distinct + right.distinct - (left.max == right.min) as usize,
+            }),
+            _ => left.or(*right),
+        }
+    }
+}
+
+impl TwoThreeTree<KeyOccupancy> {
+    // Returns the smallest key not in the tree, or None if every usize key is present.
+    pub fn first_missing_key(&self) -> Option<usize> {
+        let mut first = None;
+        let next = self.visit_gaps(|gap| {
+            first = Some(*gap.start());
+            ControlFlow::Break(())
+        });
+        first.or(next)
+    }
+
+    // Returns the maximal ranges of keys not in the tree, in order, up to usize::MAX.
+    pub fn free_ranges(&self) -> Vec<RangeInclusive<usize>> {
+        let mut ranges = Vec::new();
+        if let Some(start) = self.visit_gaps(|gap| {
+            ranges.push(gap);
+            ControlFlow::Continue(())
+        }) {
+            ranges.push(start..=usize::MAX);
+        }
+        ranges
+    }
+
+    // Calls f on the ranges of keys not in the tree, in order, except the last one, which is
+    // open up to usize::MAX. Returns the start of that range, or None if usize::MAX is present
+    // or f stopped early.
+    fn visit_gaps(
+        &self,
+        mut f: impl FnMut(RangeInclusive<usize>) -> ControlFlow<()>,
+    ) -> Option<usize> {
+        let mut next = Some(0);
+        if let Some(root) = self.root_ref() {
+            visit_node(root, &mut next, &mut f).continue_value()?;
+        }
+        next
+    }
+}
+
+// Visits the gaps of a subtree in order, skipping its full subtrees. next is the smallest key
+// which may be free, or None past usize::MAX.
+fn visit_node(
+    node: NodeRef<'_, KeyOccupancy>,
+    next: &mut Option<usize>,
+    f: &mut impl FnMut(RangeInclusive<usize>) -> ControlFlow<()>,
+) -> ControlFlow<()> {
+    let mut children = node.children();
+    for element in node.elements().map(Some).chain([None]) {
+        if let Some(child) = children.next() {
+            let occupancy = child.aug().unwrap();
+            if occupancy.is_full() {
+                occupy(occupancy.min..=occupancy.max, next, f)?;
+            } else {
+                visit_node(child, next, f)?;
+            }
+        }
+        if let Some(element) = element {
+            occupy(element.key..=element.key, next, f)?;
+        }
+    }
+    ControlFlow::Continue(())
+}
+
+// Marks a run of keys as present, after calling f on the gap before it.
+fn occupy(
+    run: RangeInclusive<usize>,
+    next: &mut Option<usize>,
+    f: &mut impl FnMut(RangeInclusive<usize>) -> ControlFlow<()>,
+) -> ControlFlow<()> {
+    let Some(start) = *next else {
+        return ControlFlow::Continue(());
+    };
+    if *run.end() < start {
+        return ControlFlow::Continue(());
+    }
+    *next = run.end().checked_add(1);
+    if *run.start() > start {
+        f(start..=*run.start() - 1)?;
+    }
+    ControlFlow::Continue(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::KeyOccupancy;
+    use crate::two_three_tree::{Element, TwoThreeTree};
+
+    fn tree(keys: impl IntoIterator<Item = usize>) -> TwoThreeTree<KeyOccupancy> {
+        let mut tree = TwoThreeTree::with_augment();
+        for key in keys {
+            tree.insert(Element { key, value: 0 });
+        }
+        tree
+    }
+
+    #[test]
+    fn test_gaps() {
+        let empty = tree([]);
+        assert!(empty.first_missing_key() == Some(0));
+        assert!(empty.free_ranges() == vec![0..=usize::MAX]);
+
+        let keys = tree([0, 1, 1, 2, 5, 7]);
+        assert!(keys.first_missing_key() == Some(3));
+        assert!(keys.free_ranges() == vec![3..=4, 6..=6, 8..=usize::MAX]);
+
+        let last = tree([usize::MAX]);
+        assert!(last.free_ranges() == vec![0..=usize::MAX - 1]);
+        let top = tree([0, usize::MAX - 1, usize::MAX]);
+        assert!(top.first_missing_key() == Some(1));
+        assert!(top.free_ranges() == vec![1..=usize::MAX - 2]);
+
+        // A dense prefix with duplicates, then holes: the full subtrees are skipped.
+        let mut dense = tree((0..3000).chain(0..1000).filter(|key| key % 1000 != 999));
+        dense.validate();
+        assert!(dense.first_missing_key() == Some(999));
+        let expected: Vec<_> = [999..=999, 1999..=1999, 2999..=usize::MAX].into();
+        assert!(dense.free_ranges() == expected);
+        for key in [999, 1999] {
+            dense.insert(Element { key, value: 0 });
+        }
+        assert!(dense.first_missing_key() == Some(2999));
+        dense.delete(5);
+        dense.delete(5);
+        assert!(dense.first_missing_key() == Some(5));
+        assert!(dense.free_ranges() == vec![5..=5, 2999..=usize::MAX]);
+    }
+}
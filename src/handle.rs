@@ -0,0 +1,165 @@
+// A 2-3 Tree whose insert returns a stable handle to the element.
+//
+// The elements live in a slot arena, and the tree orders the slot indexes by key. A handle stays
+// valid across rebalancing, and get_by_handle() reads the element in O(1) without a key lookup.
+// The generation of the slot detects handles to removed elements.
+
+use crate::arena::Arena;
+use crate::two_three_tree::{Element, TwoThreeTree};
+
+// An opaque reference to an element of a HandleTwoThreeTree.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct ElementHandle {
+    index: usize,
+    generation: u64,
+}
+
+pub struct HandleTwoThreeTree {
+    // Elements keyed by key, with the slot index as value.
+    tree: TwoThreeTree,
+
+    slots: Arena<Element>,
+}
+
+impl HandleTwoThreeTree {
+    pub fn new() -> HandleTwoThreeTree {
+        HandleTwoThreeTree {
+            tree: TwoThreeTree::new(),
+            slots: Arena::new(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    pub fn size(&self) -> usize {
+        self.tree.size()
+    }
+
+    // Inserts an element, returning its handle.
+    pub fn insert(&mut self, element: Element) -> ElementHandle {
+        let index = self.slots.insert(element);
+        self.tree.insert(Element {
+            key: element.key,
+            value: index,
+        });
+        self.handle(index)
+    }
+
+    // Returns the element of the handle, or None if it was removed.
+    pub fn get_by_handle(&self, handle: ElementHandle) -> Option<Element> {
+        if !self.is_current(handle) {
+            return None;
+        }
+        self.slots.get(handle.index).copied()
+    }
+
+    // Sets the value of the element of the handle. Returns false if it was removed.
+    pub fn set_by_handle(&mut self, handle: ElementHandle, value: usize) -> bool {
+        if !self.is_current(handle) {
+            return false;
+        }
+        match self.slots.get_mut(handle.index) {
+            Some(element) => {
+                element.value = value;
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Removes the element of the handle, in O(log n). Returns None if it was already removed.
+    pub fn remove_by_handle(&mut self, handle: ElementHandle) -> Option<Element> {
+        if !self.is_current(handle) {
+            return None;
+        }
+        let element = self.slots.remove(handle.index)?;
+        self.tree.remove_element(Element {
+            key: element.key,
+            value: handle.index,
+        });
+        Some(element)
+    }
+
+    // Returns an element with the given key and its handle.
+    pub fn find(&self, key: usize) -> Option<(ElementHandle, Element)> {
+        let index = self.tree.find(key)?.value;
+        Some((self.handle(index), *self.slots.get(index).unwrap()))
+    }
+
+    // Returns the elements and their handles in key order.
+    pub fn iter(&self) -> impl Iterator<Item = (ElementHandle, Element)> + '_ {
+        self.tree
+            .iter()
+            .map(|e| (self.handle(e.value), *self.slots.get(e.value).unwrap()))
+    }
+
+    // Returns the handle to the element in a slot.
+    fn handle(&self, index: usize) -> ElementHandle {
+        ElementHandle {
+            index,
+            generation: self.slots.generation(index),
+        }
+    }
+
+    // Returns true if the slot of the handle was not freed since the handle was given.
+    fn is_current(&self, handle: ElementHandle) -> bool {
+        self.slots.generation(handle.index) == handle.generation
+    }
+}
+
+impl Default for HandleTwoThreeTree {
+    fn default() -> HandleTwoThreeTree {
+        HandleTwoThreeTree::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HandleTwoThreeTree;
+    use crate::two_three_tree::Element;
+
+    #[test]
+    fn test_handles() {
+        let mut tree = HandleTwoThreeTree::new();
+        let handles: Vec<_> = (0..100)
+            .map(|i| {
+                tree.insert(Element {
+                    key: i % 10,
+                    value: i,
+                })
+            })
+            .collect();
+
+        // Handles survive the rebalancing caused by removals.
+        for handle in handles.iter().step_by(2) {
+            assert!(tree.remove_by_handle(*handle).is_some());
+        }
+        assert!(tree.size() == 50);
+        for (i, handle) in handles.iter().enumerate() {
+            match tree.get_by_handle(*handle) {
+                Some(element) => assert!(i % 2 == 1 && element.value == i),
+                None => assert!(i % 2 == 0),
+            }
+        }
+
+        // A reused slot does not revive a stale handle.
+        assert!(tree.remove_by_handle(handles[0]).is_none());
+        let handle = tree.insert(Element {
+            key: 3,
+            value: 1000,
+        });
+        assert!(tree.get_by_handle(handles[0]).is_none());
+        assert!(tree.set_by_handle(handle, 1001));
+        assert!(tree.get_by_handle(handle).unwrap().value == 1001);
+        assert!(tree.iter().map(|(_, e)| e.key).eq((0..10).flat_map(|key| {
+            let count = match key {
+                3 => 11,
+                _ if key % 2 == 1 => 10,
+                _ => 0,
+            };
+            std::iter::repeat_n(key, count)
+        })));
+    }
+}
@@ -0,0 +1,216 @@
+// A 2-3 Tree with an undo/redo journal.
+//
+// Each mutation is recorded together with the information needed to invert it. Only the
+// last N mutations are kept.
+
+use std::collections::VecDeque;
+
+use crate::two_three_tree::{Element, TwoThreeTree};
+
+// A recorded mutation.
+#[derive(Clone, Copy)]
+enum Mutation {
+    Insert(Element),
+    Delete(Element),
+    Update { key: usize, old: usize, new: usize },
+}
+
+// A tree whose mutations can be undone and redone.
+pub struct HistoryTree {
+    tree: TwoThreeTree,
+
+    // Mutations that can be undone, oldest first.
+    undo: VecDeque<Mutation>,
+
+    // Mutations that can be redone, most recently undone last.
+    redo: Vec<Mutation>,
+
+    // Maximum number of mutations kept for undo.
+    capacity: usize,
+}
+
+impl HistoryTree {
+    // Creates an empty tree remembering up to capacity mutations.
+    pub fn new(capacity: usize) -> HistoryTree {
+        HistoryTree {
+            tree: TwoThreeTree::new(),
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            capacity,
+        }
+    }
+
+    // Read access to the underlying tree.
+    pub fn tree(&self) -> &TwoThreeTree {
+        &self.tree
+    }
+
+    // Returns the underlying tree, dropping the history.
+    pub fn into_tree(self) -> TwoThreeTree {
+        self.tree
+    }
+
+    // Inserts an element.
+    pub fn insert(&mut self, element: Element) {
+        self.tree.insert(element);
+        self.record(Mutation::Insert(element));
+    }
+
+    // Deletes an element with the given key.
+    // Returns true if the element is found and deleted.
+    pub fn delete(&mut self, key: usize) -> bool {
+        match self.tree.remove(key) {
+            Some(element) => {
+                self.record(Mutation::Delete(element));
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Sets the value of an element with the given key.
+    // Returns true if the element is found.
+    pub fn update(&mut self, key: usize, value: usize) -> bool {
+        match self.tree.find(key) {
+            Some(element) => {
+                self.tree.set_value(element, value);
+                self.record(Mutation::Update {
+                    key,
+                    old: element.value,
+                    new: value,
+                });
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Returns the number of mutations that can be undone.
+    pub fn undo_len(&self) -> usize {
+        self.undo.len()
+    }
+
+    // Returns the number of mutations that can be redone.
+    pub fn redo_len(&self) -> usize {
+        self.redo.len()
+    }
+
+    // Reverts the last mutation. Returns false if there is nothing to undo.
+    pub fn undo(&mut self) -> bool {
+        match self.undo.pop_back() {
+            Some(mutation) => {
+                self.apply(Self::inverse(mutation));
+                self.redo.push(mutation);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Applies the last undone mutation again. Returns false if there is nothing to redo.
+    pub fn redo(&mut self) -> bool {
+        match self.redo.pop() {
+            Some(mutation) => {
+                self.apply(mutation);
+                self.undo.push_back(mutation);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Records a new mutation. This invalidates the redo history.
+    fn record(&mut self, mutation: Mutation) {
+        self.redo.clear();
+        if self.capacity == 0 {
+            return;
+        }
+        if self.undo.len() == self.capacity {
+            self.undo.pop_front();
+        }
+        self.undo.push_back(mutation);
+    }
+
+    // Returns the mutation which reverts the given one.
+    fn inverse(mutation: Mutation) -> Mutation {
+        match mutation {
+            Mutation::Insert(element) => Mutation::Delete(element),
+            Mutation::Delete(element) => Mutation::Insert(element),
+            Mutation::Update { key, old, new } => Mutation::Update {
+                key,
+                old: new,
+                new: old,
+            },
+        }
+    }
+
+    // Applies a recorded mutation to the tree. The tree is in the state the mutation was
+    // recorded against, so it cannot fail.
+    fn apply(&mut self, mutation: Mutation) {
+        match mutation {
+            Mutation::Insert(element) => self.tree.insert(element),
+            Mutation::Delete(element) => {
+                let removed = self.tree.remove_element(element);
+                assert!(removed);
+            }
+            Mutation::Update { key, old, new } => {
+                let updated = self.tree.set_value(Element { key, value: old }, new);
+                assert!(updated);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HistoryTree;
+    use crate::two_three_tree::Element;
+
+    #[test]
+    fn test_undo_redo() {
+        let mut history = HistoryTree::new(3);
+        history.insert(Element { key: 1, value: 10 });
+        history.insert(Element { key: 1, value: 11 });
+        history.insert(Element { key: 2, value: 20 });
+        assert!(history.update(2, 21));
+        assert!(history.delete(1));
+        assert!(history.undo_len() == 3);
+
+        // Undo the delete, the update and the last insert.
+        assert!(history.undo());
+        assert!(history.tree().size() == 3);
+        assert!(history.undo());
+        assert!(history.tree().find(2).unwrap().value == 20);
+        assert!(history.undo());
+        assert!(history.tree().find(2).is_none());
+        assert!(!history.undo());
+        history.tree().validate();
+
+        // Both elements with key 1 remain.
+        let mut values = Vec::new();
+        let mut tree = history.into_tree();
+        while let Some(element) = tree.remove(1) {
+            values.push(element.value);
+        }
+        values.sort();
+        assert!(values == vec![10, 11]);
+    }
+
+    #[test]
+    fn test_redo() {
+        let mut history = HistoryTree::new(10);
+        for i in 0..5 {
+            history.insert(Element { key: i, value: i });
+        }
+        assert!(history.undo());
+        assert!(history.undo());
+        assert!(history.redo());
+        assert!(history.tree().find(3).is_some());
+        assert!(history.tree().find(4).is_none());
+
+        // A new mutation drops the redo history.
+        assert!(history.delete(0));
+        assert!(!history.redo());
+        history.tree().validate();
+    }
+}
@@ -0,0 +1,131 @@
+// Allocation of unique usize IDs, backed by a 2-3 Tree of the allocated ranges.
+//
+// The tree holds disjoint, non-adjacent ranges as elements keyed by the first ID with the last
+// ID as value. Allocation hands out the smallest free ID, so the IDs stay compact and the
+// number of ranges stays small. Both allocation and release take O(log r) for r ranges.
+
+use crate::two_three_tree::{Element, TwoThreeTree};
+
+pub struct IdAllocator {
+    ranges: TwoThreeTree,
+
+    // Number of allocated IDs.
+    allocated: usize,
+}
+
+impl IdAllocator {
+    pub fn new() -> IdAllocator {
+        IdAllocator {
+            ranges: TwoThreeTree::new(),
+            allocated: 0,
+        }
+    }
+
+    // Number of allocated IDs.
+    pub fn len(&self) -> usize {
+        self.allocated
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.allocated == 0
+    }
+
+    // Allocates the smallest free ID, or returns None if all are allocated.
+    pub fn allocate(&mut self) -> Option<usize> {
+        let id = match self.ranges.first() {
+            Some(first) if first.key == 0 => first.value.checked_add(1)?,
+            _ => 0,
+        };
+        // Extend the range before the ID, if any, and merge the range after it, if any.
+        let start = match id {
+            0 => 0,
+            _ => self.ranges.remove(0).unwrap().key,
+        };
+        let end = match id.checked_add(1).and_then(|next| self.ranges.find(next)) {
+            Some(next) => self.ranges.remove(next.key).unwrap().value,
+            None => id,
+        };
+        self.ranges.insert(Element {
+            key: start,
+            value: end,
+        });
+        self.allocated += 1;
+        Some(id)
+    }
+
+    pub fn is_allocated(&self, id: usize) -> bool {
+        self.range_of(id).is_some()
+    }
+
+    // Releases an ID. Returns false if it was not allocated.
+    pub fn release(&mut self, id: usize) -> bool {
+        let Some(range) = self.range_of(id) else {
+            return false;
+        };
+        self.ranges.remove(range.key);
+        if range.key < id {
+            self.ranges.insert(Element {
+                key: range.key,
+                value: id - 1,
+            });
+        }
+        if id < range.value {
+            self.ranges.insert(Element {
+                key: id + 1,
+                value: range.value,
+            });
+        }
+        self.allocated -= 1;
+        true
+    }
+
+    // Returns the allocated ranges, as inclusive first and last IDs, in order.
+    pub fn ranges(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.ranges.iter().map(|e| (e.key, e.value))
+    }
+
+    // Returns the range holding the ID, if it is allocated.
+    fn range_of(&self, id: usize) -> Option<Element> {
+        let below = match id.checked_add(1) {
+            Some(next) => self.ranges.rank(next),
+            None => self.ranges.size(),
+        };
+        let range = self.ranges.select(below.checked_sub(1)?)?;
+        (range.value >= id).then_some(range)
+    }
+}
+
+impl Default for IdAllocator {
+    fn default() -> IdAllocator {
+        IdAllocator::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::IdAllocator;
+
+    #[test]
+    fn test_id_allocator() {
+        let mut ids = IdAllocator::new();
+        for expected in 0..10 {
+            assert!(ids.allocate() == Some(expected));
+        }
+        assert!(ids.release(3) && ids.release(4) && ids.release(7));
+        assert!(!ids.release(3));
+        assert!(ids.ranges().eq([(0, 2), (5, 6), (8, 9)]));
+        assert!(ids.len() == 7 && !ids.is_allocated(4) && ids.is_allocated(5));
+
+        // The smallest free IDs are reused, merging the ranges.
+        assert!(ids.allocate() == Some(3));
+        assert!(ids.allocate() == Some(4));
+        assert!(ids.ranges().eq([(0, 6), (8, 9)]));
+        assert!(ids.allocate() == Some(7));
+        assert!(ids.ranges().eq([(0, 9)]));
+
+        assert!(ids.release(0));
+        assert!(ids.allocate() == Some(0));
+        assert!(ids.ranges().eq([(0, 9)]));
+        assert!(ids.len() == 10);
+    }
+}
@@ -0,0 +1,240 @@
+// A table of records with a primary index by id and any number of secondary indexes.
+//
+// Each index is a 2-3 Tree from the key extracted from a record to the record id. insert(),
+// update() and remove() keep all of them consistent: if a unique index rejects a key, the
+// changes already made to the other indexes are rolled back and the table is left unchanged.
+
+use std::ops::RangeBounds;
+
+use crate::arena::Arena;
+use crate::error::TwoThreeError;
+use crate::two_three_tree::{Element, TwoThreeTree};
+
+struct SecondaryIndex<T> {
+    key: Box<dyn Fn(&T) -> usize>,
+
+    // Whether two records may not have the same key.
+    unique: bool,
+
+    // Elements keyed by the extracted key, with the record id as value.
+    tree: TwoThreeTree,
+}
+
+pub struct IndexedTable<T> {
+    // Elements keyed by id, with the arena index of the record as value.
+    primary: TwoThreeTree,
+
+    arena: Arena<T>,
+
+    indexes: Vec<SecondaryIndex<T>>,
+}
+
+// Moves the entries of the record from its old keys, if any, to its new keys. On a unique key
+// violation, restores the indexes already changed and fails with Duplicate.
+fn reindex<T>(
+    indexes: &mut [SecondaryIndex<T>],
+    id: usize,
+    new: &T,
+    old: Option<&T>,
+) -> Result<(), TwoThreeError> {
+    let keys = |index: &SecondaryIndex<T>| ((index.key)(new), old.map(|old| (index.key)(old)));
+    for i in 0..indexes.len() {
+        let (new_key, old_key) = keys(&indexes[i]);
+        if old_key == Some(new_key) {
+            continue;
+        }
+        if indexes[i].unique && indexes[i].tree.contains_key(new_key) {
+            for index in &mut indexes[..i] {
+                let (new_key, old_key) = keys(index);
+                if old_key != Some(new_key) {
+                    index.tree.remove_element(Element {
+                        key: new_key,
+                        value: id,
+                    });
+                    if let Some(key) = old_key {
+                        index.tree.insert(Element { key, value: id });
+                    }
+                }
+            }
+            return Err(TwoThreeError::Duplicate);
+        }
+        let index = &mut indexes[i];
+        if let Some(key) = old_key {
+            index.tree.remove_element(Element { key, value: id });
+        }
+        index.tree.insert(Element {
+            key: new_key,
+            value: id,
+        });
+    }
+    Ok(())
+}
+
+impl<T> IndexedTable<T> {
+    pub fn new() -> IndexedTable<T> {
+        IndexedTable {
+            primary: TwoThreeTree::new(),
+            arena: Arena::new(),
+            indexes: Vec::new(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.primary.is_empty()
+    }
+
+    pub fn size(&self) -> usize {
+        self.primary.size()
+    }
+
+    // Adds a secondary index over the records, and returns its number. Fails with Duplicate,
+    // adding nothing, if the index is unique and two records already have the same key.
+    pub fn add_index(
+        &mut self,
+        key: impl Fn(&T) -> usize + 'static,
+        unique: bool,
+    ) -> Result<usize, TwoThreeError> {
+        let mut elements: Vec<Element> = self
+            .primary
+            .iter()
+            .map(|e| Element {
+                key: key(self.arena.get(e.value).unwrap()),
+                value: e.key,
+            })
+            .collect();
+        elements.sort_by_key(|e| e.key);
+        if unique && elements.windows(2).any(|w| w[0].key == w[1].key) {
+            return Err(TwoThreeError::Duplicate);
+        }
+        self.indexes.push(SecondaryIndex {
+            key: Box::new(key),
+            unique,
+            tree: TwoThreeTree::from_sorted(elements),
+        });
+        Ok(self.indexes.len() - 1)
+    }
+
+    // Inserts a record with a new id.
+    pub fn insert(&mut self, id: usize, record: T) -> Result<(), TwoThreeError> {
+        if self.primary.contains_key(id) {
+            return Err(TwoThreeError::Duplicate);
+        }
+        reindex(&mut self.indexes, id, &record, None)?;
+        let index = self.arena.insert(record);
+        self.primary.insert(Element {
+            key: id,
+            value: index,
+        });
+        Ok(())
+    }
+
+    pub fn get(&self, id: usize) -> Option<&T> {
+        self.arena.get(self.primary.find(id)?.value)
+    }
+
+    // Replaces the record with the id, and returns the previous one.
+    pub fn update(&mut self, id: usize, record: T) -> Result<T, TwoThreeError> {
+        let index = self.primary.try_get(id)?.value;
+        let slot = self.arena.get_mut(index).unwrap();
+        reindex(&mut self.indexes, id, &record, Some(slot))?;
+        Ok(std::mem::replace(slot, record))
+    }
+
+    // Removes the record with the id, and returns it.
+    pub fn remove(&mut self, id: usize) -> Result<T, TwoThreeError> {
+        let index = self.primary.try_remove(id)?.value;
+        let record = self.arena.remove(index).unwrap();
+        for secondary in &mut self.indexes {
+            let key = (secondary.key)(&record);
+            secondary.tree.remove_element(Element { key, value: id });
+        }
+        Ok(record)
+    }
+
+    // Returns the id and record of a record with the key in the secondary index.
+    pub fn find_by(&self, index: usize, key: usize) -> Option<(usize, &T)> {
+        let id = self.indexes[index].tree.find(key)?.value;
+        Some((id, self.get(id).unwrap()))
+    }
+
+    // Returns the ids and records with a key in the range of the secondary index, in key order.
+    pub fn range_by(
+        &self,
+        index: usize,
+        range: impl RangeBounds<usize>,
+    ) -> impl Iterator<Item = (usize, &T)> + '_ {
+        self.indexes[index]
+            .tree
+            .range(range)
+            .map(|e| (e.value, self.get(e.value).unwrap()))
+    }
+}
+
+impl<T> Default for IndexedTable<T> {
+    fn default() -> IndexedTable<T> {
+        IndexedTable::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::IndexedTable;
+    use crate::error::TwoThreeError;
+
+    #[derive(Clone, Copy, PartialEq, Debug)]
+    struct User {
+        email: usize,
+        age: usize,
+    }
+
+    #[test]
+    fn test_indexed_table() {
+        let mut table = IndexedTable::new();
+        for id in 0..10 {
+            let user = User {
+                email: 100 + id,
+                age: 20 + id % 3,
+            };
+            table.insert(id, user).unwrap();
+        }
+        assert!(matches!(
+            table.add_index(|u: &User| u.age, true),
+            Err(TwoThreeError::Duplicate)
+        ));
+        let by_age = table.add_index(|u: &User| u.age, false).unwrap();
+        let by_email = table.add_index(|u: &User| u.email, true).unwrap();
+        assert!(table.find_by(by_email, 105).unwrap().0 == 5);
+        assert!(table.range_by(by_age, 21..=21).count() == 3);
+
+        // A unique violation on the email index rolls back the age index, changed first.
+        let user = User {
+            email: 103,
+            age: 50,
+        };
+        assert!(matches!(
+            table.insert(10, user),
+            Err(TwoThreeError::Duplicate)
+        ));
+        assert!(matches!(
+            table.update(4, user),
+            Err(TwoThreeError::Duplicate)
+        ));
+        assert!(table.get(4).unwrap().age == 21 && table.range_by(by_age, 50..).count() == 0);
+        assert!(table.range_by(by_age, 21..=21).count() == 3);
+
+        let user = User {
+            email: 200,
+            age: 50,
+        };
+        assert!(table.update(4, user).unwrap().email == 104);
+        assert!(table.find_by(by_email, 104).is_none());
+        assert!(table.find_by(by_age, 50).unwrap().0 == 4);
+        assert!(table.remove(4).unwrap() == user && table.remove(4).is_err());
+        assert!(table.find_by(by_email, 200).is_none() && table.size() == 9);
+        table.insert(4, user).unwrap();
+        for index in &table.indexes {
+            index.tree.validate();
+            assert!(index.tree.size() == 10);
+        }
+    }
+}
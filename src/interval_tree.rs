@@ -0,0 +1,117 @@
+// An interval tree built on the 2-3 Tree.
+//
+// Each half-open interval [start, end) is stored as an element keyed by start, with end as the
+// value. Every node maintains the maximum end in its subtree, so subtrees which end before a
+// query range are skipped.
+
+use std::ops::Range;
+
+use crate::aggregate::Max;
+use crate::two_three_tree::{Element, TwoThreeTree};
+
+// A set of half-open intervals. The same interval may be inserted more than once.
+pub struct IntervalTree {
+    tree: TwoThreeTree<Max>,
+}
+
+impl IntervalTree {
+    pub fn new() -> IntervalTree {
+        IntervalTree {
+            tree: TwoThreeTree::with_augment(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    pub fn size(&self) -> usize {
+        self.tree.size()
+    }
+
+    // Inserts an interval. Panics if the interval ends before it starts.
+    pub fn insert(&mut self, interval: Range<usize>) {
+        assert!(interval.start <= interval.end);
+        self.tree.insert(Self::element(&interval));
+    }
+
+    // Deletes an interval. Returns true if it is found and deleted.
+    pub fn delete(&mut self, interval: Range<usize>) -> bool {
+        self.tree.remove_element(Self::element(&interval))
+    }
+
+    // Returns the intervals overlapping the given range, ordered by start.
+    pub fn find_overlapping(&self, range: Range<usize>) -> Vec<Range<usize>> {
+        // An interval overlaps if it starts before the range ends and ends after it starts.
+        self.tree
+            .find_augmented(..range.end, |max_end| {
+                max_end.is_some_and(|e| e > range.start)
+            })
+            .into_iter()
+            .map(|e| e.key..e.value)
+            .collect()
+    }
+
+    // Returns the intervals containing the given point, ordered by start.
+    pub fn find_containing(&self, point: usize) -> Vec<Range<usize>> {
+        self.find_overlapping(point..point.saturating_add(1))
+    }
+
+    // Returns an iterator over the intervals ordered by start.
+    pub fn iter(&self) -> impl Iterator<Item = Range<usize>> + '_ {
+        self.tree.iter().map(|e| e.key..e.value)
+    }
+
+    fn element(interval: &Range<usize>) -> Element {
+        Element {
+            key: interval.start,
+            value: interval.end,
+        }
+    }
+}
+
+impl Default for IntervalTree {
+    fn default() -> IntervalTree {
+        IntervalTree::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::IntervalTree;
+    use std::ops::Range;
+
+    #[test]
+    fn test_find_overlapping() {
+        let mut intervals = IntervalTree::new();
+        let mut all: Vec<Range<usize>> = Vec::new();
+        for i in 0..200 {
+            let start = (i * 7919) % 1000;
+            let interval = start..start + (i * 31) % 50;
+            intervals.insert(interval.clone());
+            all.push(interval);
+        }
+        for interval in all.drain(..50) {
+            assert!(intervals.delete(interval));
+        }
+        assert!(!intervals.delete(1001..1002));
+        assert!(intervals.size() == 150);
+
+        for query in [0..10, 100..101, 500..700, 995..2000, 30..30] {
+            let mut expected: Vec<Range<usize>> = all
+                .iter()
+                .filter(|i| i.start < query.end && i.end > query.start)
+                .cloned()
+                .collect();
+            expected.sort_by_key(|i| (i.start, i.end));
+            let mut found = intervals.find_overlapping(query);
+            found.sort_by_key(|i| (i.start, i.end));
+            assert!(found == expected);
+        }
+
+        intervals.insert(5000..5010);
+        let containing = intervals.find_containing(5009);
+        assert!(containing.len() == 1 && containing[0] == (5000..5010));
+        assert!(intervals.find_containing(5010).is_empty());
+    }
+}
@@ -0,0 +1,148 @@
+// A leaderboard of player ids ordered by score, highest first.
+//
+// The board is a 2-3 Tree from score to id, so the order statistics give positions, and a
+// player's neighborhood is a range of ranks. Ranks are dense: tied players share a rank, and
+// the next score down has the next rank. A third tree counts the players per distinct score,
+// so that a dense rank follows from the number of distinct higher scores, in O(log n).
+
+use crate::two_three_tree::{Element, TwoThreeTree};
+
+pub struct Leaderboard {
+    // Elements keyed by id, with the score as value.
+    players: TwoThreeTree,
+
+    // Elements keyed by score, with the id as value.
+    board: TwoThreeTree,
+
+    // Elements keyed by distinct score, with the number of players as value.
+    scores: TwoThreeTree,
+}
+
+impl Leaderboard {
+    pub fn new() -> Leaderboard {
+        Leaderboard {
+            players: TwoThreeTree::new(),
+            board: TwoThreeTree::new(),
+            scores: TwoThreeTree::new(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.players.is_empty()
+    }
+
+    // Returns the number of players.
+    pub fn size(&self) -> usize {
+        self.players.size()
+    }
+
+    pub fn score(&self, id: usize) -> Option<usize> {
+        Some(self.players.find(id)?.value)
+    }
+
+    // Sets the score of a player, adding the player if new. Returns the previous score.
+    pub fn set_score(&mut self, id: usize, score: usize) -> Option<usize> {
+        let old = self.remove(id);
+        self.players.insert(Element {
+            key: id,
+            value: score,
+        });
+        self.board.insert(Element {
+            key: score,
+            value: id,
+        });
+        self.scores.upsert(score, 1, |count| *count += 1);
+        old
+    }
+
+    // Removes a player, and returns the score.
+    pub fn remove(&mut self, id: usize) -> Option<usize> {
+        let score = self.players.remove(id)?.value;
+        // A specific element among the tied scores, so not rekey(), which may move any of them.
+        self.board.remove_element(Element {
+            key: score,
+            value: id,
+        });
+        let mut count = 0;
+        self.scores.update(score, |c| {
+            *c -= 1;
+            count = *c;
+        });
+        if count == 0 {
+            self.scores.remove(score);
+        }
+        Some(score)
+    }
+
+    // Returns the dense rank of a player, 1 for the highest score.
+    pub fn rank_of(&self, id: usize) -> Option<usize> {
+        let score = self.score(id)?;
+        Some(self.scores.size() - self.scores.rank(score))
+    }
+
+    // Returns the n best players and their scores, highest first.
+    pub fn top(&self, n: usize) -> Vec<(usize, usize)> {
+        self.board
+            .top_k(n)
+            .into_iter()
+            .map(|e| (e.value, e.key))
+            .collect()
+    }
+
+    // Returns the players and scores around a player, up to n above and n below it, highest
+    // first, with the player itself in the middle.
+    pub fn around(&self, id: usize, n: usize) -> Vec<(usize, usize)> {
+        let Some(score) = self.score(id) else {
+            return Vec::new();
+        };
+        let position = self.board.rank(score)
+            + self
+                .board
+                .iter_from(score)
+                .position(|e| e.value == id)
+                .unwrap();
+        let mut around: Vec<(usize, usize)> = self
+            .board
+            .range_by_rank(position.saturating_sub(n)..=position.saturating_add(n))
+            .map(|e| (e.value, e.key))
+            .collect();
+        around.reverse();
+        around
+    }
+}
+
+impl Default for Leaderboard {
+    fn default() -> Leaderboard {
+        Leaderboard::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Leaderboard;
+
+    #[test]
+    fn test_leaderboard() {
+        let mut board = Leaderboard::new();
+        for (id, score) in [(1, 50), (2, 80), (3, 50), (4, 90), (5, 10), (6, 70)] {
+            assert!(board.set_score(id, score).is_none());
+        }
+        assert!(board.rank_of(4) == Some(1) && board.rank_of(2) == Some(2));
+        assert!(board.rank_of(1) == Some(4) && board.rank_of(3) == Some(4));
+        assert!(board.rank_of(5) == Some(5) && board.rank_of(7).is_none());
+        assert!(board.top(2) == [(4, 90), (2, 80)]);
+
+        let around = board.around(6, 1);
+        assert!(around == [(2, 80), (6, 70), (3, 50)]);
+        assert!(board.around(4, 2) == [(4, 90), (2, 80), (6, 70)]);
+        assert!(board.around(5, 1).len() == 2 && board.around(7, 1).is_empty());
+
+        // Moving a tied player leaves the other one in place.
+        assert!(board.set_score(3, 95) == Some(50));
+        assert!(board.rank_of(3) == Some(1) && board.rank_of(1) == Some(5));
+        assert!(board.around(1, 0) == [(1, 50)]);
+        assert!(board.remove(1) == Some(50) && board.rank_of(5) == Some(5));
+        assert!(board.size() == 5 && board.scores.size() == 5);
+        board.board.validate();
+    }
+}
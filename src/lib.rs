@@ -0,0 +1,228 @@
+// An implementation of 2-3 tree.
+//
+// Without the std feature, only the modules which need no allocator are built, for no_std
+// targets.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+#[cfg(feature = "std")]
+pub mod aggregate;
+#[cfg(feature = "std")]
+mod arena;
+#[cfg(feature = "async")]
+pub mod async_tree;
+#[cfg(feature = "bloom")]
+mod bloom;
+#[cfg(feature = "bplus")]
+pub mod bplus;
+#[cfg(feature = "std")]
+pub mod buffered;
+#[cfg(any(feature = "msgpack", feature = "cbor"))]
+mod codec;
+#[cfg(all(feature = "std", target_pointer_width = "64"))]
+pub mod composite;
+#[cfg(feature = "std")]
+pub mod concurrent;
+#[cfg(feature = "csv")]
+pub mod csv;
+#[cfg(feature = "std")]
+pub mod diff;
+pub mod element;
+#[cfg(feature = "std")]
+pub mod error;
+#[cfg(feature = "std")]
+pub mod expiry;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "std")]
+pub mod fingerprint;
+#[cfg(all(feature = "std", target_pointer_width = "64"))]
+pub mod float_keys;
+#[cfg(feature = "std")]
+pub mod frozen;
+#[cfg(feature = "std")]
+pub mod gaps;
+#[cfg(feature = "std")]
+pub mod handle;
+#[cfg(feature = "std")]
+pub mod history;
+#[cfg(feature = "std")]
+pub mod id_allocator;
+#[cfg(feature = "std")]
+pub mod indexed;
+#[cfg(feature = "std")]
+pub mod interval_tree;
+#[cfg(feature = "std")]
+pub mod leaderboard;
+#[cfg(feature = "std")]
+pub mod lru;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+#[cfg(feature = "std")]
+pub mod multiway;
+#[cfg(feature = "std")]
+pub mod order_maintenance;
+#[cfg(feature = "std")]
+pub mod paged;
+#[cfg(feature = "std")]
+pub mod persistent;
+#[cfg(feature = "std")]
+pub mod poison;
+#[cfg(feature = "std")]
+pub mod priority_queue;
+#[cfg(feature = "python")]
+pub mod python;
+#[cfg(feature = "std")]
+pub mod range_add;
+#[cfg(feature = "std")]
+pub mod red_black;
+#[cfg(feature = "std")]
+pub mod rope;
+#[cfg(feature = "std")]
+pub mod scan;
+#[cfg(feature = "std")]
+pub mod separated;
+#[cfg(feature = "std")]
+pub mod sequence;
+#[cfg(feature = "std")]
+pub mod shape;
+#[cfg(feature = "std")]
+pub mod shifted;
+#[cfg(feature = "std")]
+pub mod snapshot_file;
+#[cfg(feature = "std")]
+pub mod sorted_run;
+pub mod static_tree;
+#[cfg(feature = "std")]
+pub mod string_keys;
+#[cfg(feature = "std")]
+pub mod time_series;
+#[cfg(feature = "std")]
+pub mod tombstone;
+#[cfg(feature = "std")]
+pub mod transaction;
+#[cfg(feature = "std")]
+pub mod tree_by;
+#[cfg(feature = "std")]
+pub mod two_three_tree;
+#[cfg(feature = "std")]
+pub mod versioned;
+#[cfg(feature = "std")]
+pub mod visit;
+#[cfg(feature = "std")]
+mod wal;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+#[cfg(feature = "std")]
+pub mod workload;
+
+#[cfg(feature = "async")]
+pub use async_tree::AsyncPagedTwoThreeTree;
+#[cfg(feature = "bplus")]
+pub use bplus::{BPlusTwoThreeTree, LeafIter};
+#[cfg(feature = "std")]
+pub use buffered::{BufferedIter, BufferedTwoThreeTree};
+#[cfg(all(feature = "std", target_pointer_width = "64"))]
+pub use composite::CompositeTwoThreeTree;
+#[cfg(feature = "std")]
+pub use concurrent::ConcurrentTwoThreeMap;
+#[cfg(feature = "epoch")]
+pub use concurrent::EpochTwoThreeTree;
+#[cfg(feature = "std")]
+pub use diff::{Diff, DiffEntry};
+pub use element::Element;
+#[cfg(feature = "std")]
+pub use error::TwoThreeError;
+#[cfg(feature = "std")]
+pub use expiry::ExpiringTwoThreeTree;
+#[cfg(feature = "std")]
+pub use fingerprint::{Merkle, MerkleDigest};
+#[cfg(all(feature = "std", target_pointer_width = "64"))]
+pub use float_keys::{FloatTwoThreeTree, NanPolicy, TotalF64};
+#[cfg(feature = "std")]
+pub use frozen::FrozenTwoThreeTree;
+#[cfg(feature = "std")]
+pub use gaps::{KeyOccupancy, Occupancy};
+#[cfg(feature = "std")]
+pub use handle::{ElementHandle, HandleTwoThreeTree};
+#[cfg(feature = "std")]
+pub use history::HistoryTree;
+#[cfg(feature = "std")]
+pub use id_allocator::IdAllocator;
+#[cfg(feature = "std")]
+pub use indexed::IndexedTable;
+#[cfg(feature = "std")]
+pub use interval_tree::IntervalTree;
+#[cfg(feature = "std")]
+pub use leaderboard::Leaderboard;
+#[cfg(feature = "std")]
+pub use lru::OrderedLru;
+#[cfg(feature = "metrics")]
+pub use metrics::Metrics;
+#[cfg(feature = "std")]
+pub use multiway::{MultiwayTree, TwoThreeFourTree};
+#[cfg(feature = "std")]
+pub use order_maintenance::OrderMaintenance;
+#[cfg(feature = "std")]
+pub use paged::{CachedNodeStore, FileNodeStore, MemoryNodeStore, NodeStore, PagedTwoThreeTree};
+#[cfg(feature = "std")]
+pub use persistent::{PersistentTwoThreeTree, Snapshot};
+#[cfg(feature = "std")]
+pub use poison::{PoisonedError, PoisoningTwoThreeTree};
+#[cfg(feature = "std")]
+pub use priority_queue::TwoThreePriorityQueue;
+#[cfg(feature = "std")]
+pub use range_add::RangeAddTwoThreeTree;
+#[cfg(feature = "std")]
+pub use red_black::{Color, RedBlackNode};
+#[cfg(feature = "std")]
+pub use rope::Rope;
+#[cfg(feature = "std")]
+pub use scan::{ScanPage, ScanToken};
+#[cfg(feature = "std")]
+pub use separated::SeparatedTwoThreeTree;
+#[cfg(feature = "std")]
+pub use sequence::{Measure, Sequence};
+#[cfg(feature = "std")]
+pub use shape::Shape;
+#[cfg(feature = "std")]
+pub use shifted::ShiftedTwoThreeTree;
+#[cfg(feature = "std")]
+pub use snapshot_file::LoadError;
+pub use static_tree::StaticTwoThreeTree;
+#[cfg(feature = "std")]
+pub use string_keys::{Collation, StringTwoThreeTree};
+#[cfg(feature = "std")]
+pub use time_series::TimeSeries;
+#[cfg(feature = "std")]
+pub use tombstone::TombstoneTwoThreeTree;
+#[cfg(feature = "std")]
+pub use transaction::Transaction;
+#[cfg(feature = "std")]
+pub use tree_by::TwoThreeTreeBy;
+#[cfg(feature = "std")]
+pub use two_three_tree::{
+    Augment, Chunks, Comparator, Cursor, DuplicatePolicy, Iter, Nearest, Observer, Op, ParseError,
+    TwoThreeTree,
+};
+#[cfg(feature = "std")]
+pub use versioned::VersionedTwoThreeTree;
+#[cfg(feature = "std")]
+pub use visit::TreeVisitor;
+#[cfg(feature = "std")]
+pub use workload::{Distribution, Workload, WorkloadOp};
+
+// Compile-time checks that the trees can be shared between threads, e.g. as Arc<RwLock<_>>.
+#[cfg(feature = "std")]
+const _: fn() = || {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<TwoThreeTree>();
+    assert_send_sync::<TwoThreeTree<aggregate::Stats>>();
+    assert_send_sync::<TwoThreeTree<Merkle>>();
+    assert_send_sync::<Iter<'_>>();
+    assert_send_sync::<FrozenTwoThreeTree>();
+    assert_send_sync::<PersistentTwoThreeTree>();
+    assert_send_sync::<ConcurrentTwoThreeMap>();
+    #[cfg(feature = "epoch")]
+    assert_send_sync::<EpochTwoThreeTree>();
+};
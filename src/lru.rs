@@ -0,0 +1,134 @@
+// An LRU cache with ordered iteration, built on two 2-3 Trees: one by key, and one by access
+// time to find the least recently used key. Each key holds a single value.
+
+use std::collections::HashMap;
+use std::ops::RangeBounds;
+
+use crate::two_three_tree::{Element, TwoThreeTree};
+
+pub struct OrderedLru {
+    capacity: usize,
+
+    // The elements by key.
+    tree: TwoThreeTree,
+
+    // The keys by access time, as elements keyed by tick with the key as value.
+    recency: TwoThreeTree,
+
+    // The last access time of each key.
+    ticks: HashMap<usize, usize>,
+
+    tick: usize,
+}
+
+impl OrderedLru {
+    // Creates a cache holding up to capacity elements. Panics if the capacity is 0.
+    pub fn new(capacity: usize) -> OrderedLru {
+        assert!(capacity > 0);
+        OrderedLru {
+            capacity,
+            tree: TwoThreeTree::new(),
+            recency: TwoThreeTree::new(),
+            ticks: HashMap::new(),
+            tick: 0,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    pub fn size(&self) -> usize {
+        self.tree.size()
+    }
+
+    // Returns the value of the key, marking it as the most recently used.
+    pub fn get(&mut self, key: usize) -> Option<usize> {
+        let value = self.tree.find(key)?.value;
+        self.touch(key);
+        Some(value)
+    }
+
+    // Returns the value of the key without changing the recency.
+    pub fn peek(&self, key: usize) -> Option<usize> {
+        self.tree.find(key).map(|e| e.value)
+    }
+
+    // Sets the value of the key, marking it as the most recently used. If the cache is full,
+    // the least recently used element is evicted and returned.
+    pub fn insert(&mut self, key: usize, value: usize) -> Option<Element> {
+        if self.tree.update(key, |v| *v = value) {
+            self.touch(key);
+            return None;
+        }
+        let evicted = if self.tree.size() == self.capacity {
+            let lru = self.recency.delete_min().unwrap().value;
+            self.ticks.remove(&lru);
+            self.tree.remove(lru)
+        } else {
+            None
+        };
+        self.tree.insert(Element { key, value });
+        self.touch(key);
+        evicted
+    }
+
+    // Removes the key, returning its value.
+    pub fn remove(&mut self, key: usize) -> Option<usize> {
+        let element = self.tree.remove(key)?;
+        let tick = self.ticks.remove(&key).unwrap();
+        self.recency.remove(tick);
+        Some(element.value)
+    }
+
+    // Returns the elements in the key range, in key order, without changing the recency.
+    pub fn range(&self, range: impl RangeBounds<usize>) -> impl Iterator<Item = Element> + '_ {
+        self.tree.range(range)
+    }
+
+    // Returns the keys from the least to the most recently used.
+    pub fn keys_by_recency(&self) -> impl Iterator<Item = usize> + '_ {
+        self.recency.iter().map(|e| e.value)
+    }
+
+    // Marks the key as the most recently used.
+    fn touch(&mut self, key: usize) {
+        if let Some(tick) = self.ticks.insert(key, self.tick) {
+            self.recency.remove(tick);
+        }
+        self.recency.insert(Element {
+            key: self.tick,
+            value: key,
+        });
+        self.tick += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::OrderedLru;
+
+    #[test]
+    fn test_ordered_lru() {
+        let mut lru = OrderedLru::new(3);
+        assert!(lru.insert(30, 3).is_none());
+        assert!(lru.insert(10, 1).is_none());
+        assert!(lru.insert(20, 2).is_none());
+        assert!(lru.get(30) == Some(3));
+
+        // 10 is the least recently used.
+        let evicted = lru.insert(40, 4).unwrap();
+        assert!(evicted.key == 10 && evicted.value == 1);
+        assert!(lru.peek(10).is_none());
+        assert!(lru.keys_by_recency().eq([20, 30, 40]));
+
+        let keys: Vec<usize> = lru.range(25..).map(|e| e.key).collect();
+        assert!(keys == vec![30, 40]);
+        assert!(lru.range(35..35).next().is_none());
+
+        assert!(lru.insert(20, 5).is_none());
+        assert!(lru.remove(30) == Some(3));
+        assert!(lru.keys_by_recency().eq([40, 20]));
+        assert!(lru.size() == 2);
+    }
+}
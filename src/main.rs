@@ -1,5 +1,3 @@
-mod two_three_tree;
-
 use rand::Rng;
 use two_three_tree::{Element, TwoThreeTree};
 
@@ -0,0 +1,139 @@
+// Counters of the work done by a tree, to study rebalancing behavior across workloads.
+// Only compiled with the "metrics" feature.
+
+use std::cell::Cell;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::two_three_tree::{Augment, TwoThreeTree};
+
+// Totals since the tree was created or the metrics were last reset.
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub struct Metrics {
+    // Number of insert, delete and find operations.
+    pub inserts: u64,
+    pub deletes: u64,
+    pub finds: u64,
+
+    // Nodes split on insertion.
+    pub splits: u64,
+
+    // Nodes merged with a sibling on deletion.
+    pub merges: u64,
+
+    // Elements borrowed from a 3-node sibling on deletion.
+    pub borrows: u64,
+
+    // Keys examined on the search paths.
+    pub comparisons: u64,
+}
+
+thread_local! {
+    // Counts for the operation running on this thread, as the recursive node functions have
+    // no access to the tree.
+    static PENDING: Cell<Metrics> = Cell::new(Metrics::default());
+}
+
+// Updates the counts for the running operation.
+pub(crate) fn record(f: impl FnOnce(&mut Metrics)) {
+    PENDING.with(|pending| {
+        let mut metrics = pending.get();
+        f(&mut metrics);
+        pending.set(metrics);
+    });
+}
+
+// Takes the counts of the running operation.
+pub(crate) fn take() -> Metrics {
+    PENDING.with(|pending| pending.take())
+}
+
+// Totals held by a tree. Atomic so that lookups through &self are counted too.
+#[derive(Default)]
+pub(crate) struct MetricsCounters([AtomicU64; 7]);
+
+impl MetricsCounters {
+    pub(crate) fn add(&self, metrics: Metrics) {
+        let values = [
+            metrics.inserts,
+            metrics.deletes,
+            metrics.finds,
+            metrics.splits,
+            metrics.merges,
+            metrics.borrows,
+            metrics.comparisons,
+        ];
+        for (counter, value) in self.0.iter().zip(values) {
+            if value > 0 {
+                counter.fetch_add(value, Ordering::Relaxed);
+            }
+        }
+    }
+
+    fn load(&self) -> Metrics {
+        let [inserts, deletes, finds, splits, merges, borrows, comparisons] = self
+            .0
+            .each_ref()
+            .map(|counter| counter.load(Ordering::Relaxed));
+        Metrics {
+            inserts,
+            deletes,
+            finds,
+            splits,
+            merges,
+            borrows,
+            comparisons,
+        }
+    }
+
+    fn reset(&self) {
+        for counter in self.0.iter() {
+            counter.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+impl<A: Augment> TwoThreeTree<A> {
+    // Returns the counters accumulated by this tree.
+    pub fn metrics(&self) -> Metrics {
+        self.metrics.load()
+    }
+
+    // Sets all the counters back to zero.
+    pub fn reset_metrics(&self) {
+        self.metrics.reset()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::two_three_tree::Element;
+
+    #[test]
+    fn test_metrics() {
+        let mut tree = TwoThreeTree::new();
+        for key in 0..1000 {
+            tree.insert(Element { key, value: key });
+        }
+        let metrics = tree.metrics();
+        assert!(metrics.inserts == 1000);
+        // Sequential insertion splits about one node per element.
+        assert!(metrics.splits > 500 && metrics.splits < 1000);
+        assert!(metrics.comparisons > 1000);
+
+        tree.reset_metrics();
+        assert!(tree.find(500).is_some());
+        let metrics = tree.metrics();
+        assert!(metrics.finds == 1);
+        assert!(metrics.comparisons > 0 && metrics.comparisons <= 20);
+
+        for key in 0..1000 {
+            assert!(tree.delete(key));
+        }
+        let metrics = tree.metrics();
+        assert!(metrics.deletes == 1000);
+        assert!(metrics.merges > 0);
+        assert!(metrics.borrows > 0);
+        assert!(metrics.splits == 0);
+    }
+}
@@ -0,0 +1,315 @@
+// A balanced search tree whose nodes hold up to B elements, with the API of the 2-3 Tree.
+//
+// B = 2 gives a 2-3 tree and B = 3 a 2-3-4 tree, so that the fanout can be measured against the
+// same workload. Nodes keep their elements and children in vectors, so this tree trades the
+// fixed node layout of TwoThreeTree for the configurable order.
+
+use crate::two_three_tree::Element;
+
+pub struct MultiwayTree<const B: usize> {
+    root: Option<Box<Node>>,
+    size: usize,
+}
+
+// A 2-3-4 tree.
+pub type TwoThreeFourTree = MultiwayTree<3>;
+
+struct Node {
+    // Sorted by key. A node holds 1 to B elements.
+    elements: Vec<Element>,
+
+    // Empty for a leaf, otherwise one more than the elements.
+    children: Vec<Node>,
+}
+
+impl Node {
+    fn is_leaf(&self) -> bool {
+        self.children.is_empty()
+    }
+}
+
+impl<const B: usize> MultiwayTree<B> {
+    // The fewest elements of a node other than the root.
+    const MIN_ELEMENTS: usize = B / 2;
+
+    pub fn new() -> MultiwayTree<B> {
+        const { assert!(B >= 2) };
+        MultiwayTree {
+            root: None,
+            size: 0,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    // Returns the number of levels below the root.
+    pub fn height(&self) -> usize {
+        let mut height: usize = 0;
+        let mut node = self.root.as_deref();
+        while let Some(n) = node {
+            node = n.children.first();
+            height += 1;
+        }
+        height.saturating_sub(1)
+    }
+
+    // Inserts an element after the elements with an equal key.
+    pub fn insert(&mut self, element: Element) {
+        self.size += 1;
+        let Some(root) = self.root.as_mut() else {
+            self.root = Some(Box::new(Node {
+                elements: vec![element],
+                children: Vec::new(),
+            }));
+            return;
+        };
+        if let Some((median, right)) = Self::insert_node(root, element) {
+            let left = self.root.take().unwrap();
+            self.root = Some(Box::new(Node {
+                elements: vec![median],
+                children: vec![*left, right],
+            }));
+        }
+    }
+
+    // Finds an element with the given key.
+    pub fn find(&self, key: usize) -> Option<Element> {
+        let mut node = self.root.as_deref()?;
+        loop {
+            let index = node.elements.partition_point(|e| e.key < key);
+            if let Some(element) = node.elements.get(index).filter(|e| e.key == key) {
+                return Some(*element);
+            }
+            node = node.children.get(index)?;
+        }
+    }
+
+    pub fn delete(&mut self, key: usize) -> bool {
+        self.remove(key).is_some()
+    }
+
+    // Removes an element with the given key, and returns it.
+    pub fn remove(&mut self, key: usize) -> Option<Element> {
+        let root = self.root.as_mut()?;
+        let removed = Self::remove_node(root, key)?;
+        self.size -= 1;
+        if root.elements.is_empty() {
+            self.root = root.children.pop().map(Box::new);
+        }
+        Some(removed)
+    }
+
+    // Returns the elements in key order.
+    pub fn iter(&self) -> impl Iterator<Item = Element> + '_ {
+        // A stack of nodes with the index of their next element.
+        fn push_left<'a>(stack: &mut Vec<(&'a Node, usize)>, mut node: Option<&'a Node>) {
+            while let Some(n) = node {
+                stack.push((n, 0));
+                node = n.children.first();
+            }
+        }
+        let mut stack = Vec::new();
+        push_left(&mut stack, self.root.as_deref());
+        std::iter::from_fn(move || {
+            let (node, index) = stack.pop()?;
+            let element = node.elements[index];
+            if index + 1 < node.elements.len() {
+                stack.push((node, index + 1));
+            }
+            push_left(&mut stack, node.children.get(index + 1));
+            Some(element)
+        })
+    }
+
+    // Checks the order, the element counts and the uniform depth of the leaves.
+    pub fn validate(&self) {
+        if let Some(root) = &self.root {
+            let mut leaf_depth = None;
+            let count = Self::validate_node(root, 0, &mut leaf_depth, true);
+            assert!(count == self.size);
+        } else {
+            assert!(self.size == 0);
+        }
+        assert!(self
+            .iter()
+            .zip(self.iter().skip(1))
+            .all(|(a, b)| a.key <= b.key));
+    }
+
+    fn validate_node(
+        node: &Node,
+        depth: usize,
+        leaf_depth: &mut Option<usize>,
+        is_root: bool,
+    ) -> usize {
+        assert!(node.elements.len() <= B);
+        assert!(node.elements.len() >= if is_root { 1 } else { Self::MIN_ELEMENTS });
+        if node.is_leaf() {
+            assert!(*leaf_depth.get_or_insert(depth) == depth);
+            return node.elements.len();
+        }
+        assert!(node.children.len() == node.elements.len() + 1);
+        let mut count = node.elements.len();
+        for (i, child) in node.children.iter().enumerate() {
+            if let Some(before) = i.checked_sub(1).map(|i| node.elements[i]) {
+                assert!(child.elements.iter().all(|e| e.key >= before.key));
+            }
+            if let Some(after) = node.elements.get(i) {
+                assert!(child.elements.iter().all(|e| e.key <= after.key));
+            }
+            count += Self::validate_node(child, depth + 1, leaf_depth, false);
+        }
+        count
+    }
+
+    // Inserts into a subtree. If the node overflows, it is split and the median and the right
+    // half are returned.
+    fn insert_node(node: &mut Node, element: Element) -> Option<(Element, Node)> {
+        let index = node.elements.partition_point(|e| e.key <= element.key);
+        if node.is_leaf() {
+            node.elements.insert(index, element);
+        } else {
+            let (median, right) = Self::insert_node(&mut node.children[index], element)?;
+            node.elements.insert(index, median);
+            node.children.insert(index + 1, right);
+        }
+        if node.elements.len() <= B {
+            return None;
+        }
+
+        let mid = node.elements.len() / 2;
+        let right = Node {
+            elements: node.elements.split_off(mid + 1),
+            children: if node.is_leaf() {
+                Vec::new()
+            } else {
+                node.children.split_off(mid + 1)
+            },
+        };
+        let median = node.elements.pop().unwrap();
+        Some((median, right))
+    }
+
+    // Removes from a subtree, which may underflow.
+    fn remove_node(node: &mut Node, key: usize) -> Option<Element> {
+        let index = node.elements.partition_point(|e| e.key < key);
+        let found = node.elements.get(index).is_some_and(|e| e.key == key);
+        if node.is_leaf() {
+            return found.then(|| node.elements.remove(index));
+        }
+        let removed = if found {
+            // Replace the element by its predecessor.
+            let predecessor = Self::remove_max(&mut node.children[index]);
+            std::mem::replace(&mut node.elements[index], predecessor)
+        } else {
+            Self::remove_node(&mut node.children[index], key)?
+        };
+        Self::fix_child(node, index);
+        Some(removed)
+    }
+
+    // Removes the largest element of a subtree, which may underflow.
+    fn remove_max(node: &mut Node) -> Element {
+        if node.is_leaf() {
+            return node.elements.pop().unwrap();
+        }
+        let last = node.children.len() - 1;
+        let element = Self::remove_max(&mut node.children[last]);
+        Self::fix_child(node, last);
+        element
+    }
+
+    // Refills an underflowing child by borrowing from a sibling, or merging with it.
+    fn fix_child(node: &mut Node, index: usize) {
+        if node.children[index].elements.len() >= Self::MIN_ELEMENTS {
+            return;
+        }
+        if index > 0 && node.children[index - 1].elements.len() > Self::MIN_ELEMENTS {
+            // Rotate right through the separator.
+            let left = &mut node.children[index - 1];
+            let element = left.elements.pop().unwrap();
+            let child = left.children.pop();
+            let separator = std::mem::replace(&mut node.elements[index - 1], element);
+            let right = &mut node.children[index];
+            right.elements.insert(0, separator);
+            right.children.splice(0..0, child);
+        } else if index + 1 < node.children.len()
+            && node.children[index + 1].elements.len() > Self::MIN_ELEMENTS
+        {
+            // Rotate left through the separator.
+            let right = &mut node.children[index + 1];
+            let element = right.elements.remove(0);
+            let child = (!right.is_leaf()).then(|| right.children.remove(0));
+            let separator = std::mem::replace(&mut node.elements[index], element);
+            let left = &mut node.children[index];
+            left.elements.push(separator);
+            left.children.extend(child);
+        } else {
+            // Merge with a sibling and the separator between them.
+            let left = if index > 0 { index - 1 } else { index };
+            let right = node.children.remove(left + 1);
+            let separator = node.elements.remove(left);
+            let left = &mut node.children[left];
+            left.elements.push(separator);
+            left.elements.extend(right.elements);
+            left.children.extend(right.children);
+        }
+    }
+}
+
+impl<const B: usize> Default for MultiwayTree<B> {
+    fn default() -> MultiwayTree<B> {
+        MultiwayTree::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MultiwayTree;
+    use crate::two_three_tree::Element;
+    use crate::workload::{self, WorkloadOp};
+
+    fn random_insert_delete<const B: usize>() {
+        let mut tree = MultiwayTree::<B>::new();
+        let keys = workload::random_insert_delete(200, 3000, |op| {
+            let found = match op {
+                WorkloadOp::Insert(element) => {
+                    tree.insert(element);
+                    true
+                }
+                WorkloadOp::Delete(key) => tree
+                    .remove(key)
+                    .inspect(|e| assert!(e.key == key))
+                    .is_some(),
+                WorkloadOp::Find(_) => unreachable!(),
+            };
+            tree.validate();
+            found
+        });
+        assert!(tree.size() == keys.len());
+        assert!(tree.iter().map(|e| e.key).eq(keys.iter().copied()));
+        assert!(keys.iter().all(|&key| tree.find(key).is_some()));
+    }
+
+    #[test]
+    fn test_multiway_tree() {
+        random_insert_delete::<2>();
+        random_insert_delete::<3>();
+        random_insert_delete::<6>();
+
+        // Wider nodes make a shallower tree.
+        let mut narrow = MultiwayTree::<2>::new();
+        let mut wide = MultiwayTree::<3>::new();
+        for key in 0..1000 {
+            narrow.insert(Element { key, value: key });
+            wide.insert(Element { key, value: key });
+        }
+        assert!(wide.height() < narrow.height());
+    }
+}
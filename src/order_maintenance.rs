@@ -0,0 +1,196 @@
+// Order maintenance: a list of tags where any two tags are compared in O(1).
+//
+// Each tag has a label, increasing along the list, so is_before() compares two labels. The
+// labels are the keys of a 2-3 Tree, with the tag as value. A new tag takes the label halfway
+// to the next one. When there is no label left in between, the smallest aligned window of
+// labels around the position which is sparse enough gets its labels spread evenly, found by
+// counting the keys in the window, for O(log^2 n) amortized per insertion.
+
+use crate::two_three_tree::{Element, TwoThreeTree};
+
+// The labels are below 2^LABEL_BITS.
+const LABEL_BITS: u32 = usize::BITS - 1;
+
+// The value of the element at label 0, which stands before all tags.
+const SENTINEL: usize = usize::MAX;
+
+pub struct OrderMaintenance {
+    // Elements keyed by label, with the tag as value.
+    tree: TwoThreeTree,
+
+    // The label of each tag, or None for a removed tag.
+    labels: Vec<Option<usize>>,
+
+    // Removed tags, to be reused.
+    free: Vec<usize>,
+}
+
+impl OrderMaintenance {
+    pub fn new() -> OrderMaintenance {
+        let mut tree = TwoThreeTree::new();
+        tree.insert(Element {
+            key: 0,
+            value: SENTINEL,
+        });
+        OrderMaintenance {
+            tree,
+            labels: Vec::new(),
+            free: Vec::new(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.size() == 0
+    }
+
+    // Returns the number of tags in the list.
+    pub fn size(&self) -> usize {
+        self.tree.size() - 1
+    }
+
+    pub fn contains(&self, tag: usize) -> bool {
+        self.labels.get(tag).is_some_and(Option::is_some)
+    }
+
+    // Returns the label of a tag, panicking if it was removed.
+    fn label(&self, tag: usize) -> usize {
+        self.labels[tag].expect("tag not in the list")
+    }
+
+    // Inserts a new tag at the front of the list, and returns it.
+    pub fn insert_first(&mut self) -> usize {
+        self.insert_after_label(0)
+    }
+
+    // Inserts a new tag right after the tag, and returns it.
+    pub fn insert_after(&mut self, tag: usize) -> usize {
+        self.insert_after_label(self.label(tag))
+    }
+
+    // Removes a tag from the list. Returns false if it is not in the list.
+    pub fn remove(&mut self, tag: usize) -> bool {
+        let Some(label) = self.labels.get_mut(tag).and_then(Option::take) else {
+            return false;
+        };
+        self.tree.remove(label);
+        self.free.push(tag);
+        true
+    }
+
+    // Returns true if a comes before b in the list, in O(1).
+    pub fn is_before(&self, a: usize, b: usize) -> bool {
+        self.label(a) < self.label(b)
+    }
+
+    // Returns the tags in list order.
+    pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        self.tree.iter().skip(1).map(|e| e.value)
+    }
+
+    fn insert_after_label(&mut self, mut label: usize) -> usize {
+        let mut next = self.next_label(label);
+        if next - label < 2 {
+            label = self.relabel(label);
+            next = self.next_label(label);
+        }
+        let tag = self.free.pop().unwrap_or(self.labels.len());
+        if tag == self.labels.len() {
+            self.labels.push(None);
+        }
+        let new = label + (next - label) / 2;
+        self.labels[tag] = Some(new);
+        self.tree.insert(Element {
+            key: new,
+            value: tag,
+        });
+        tag
+    }
+
+    // Returns the label after the given one, or the end of the labels.
+    fn next_label(&self, label: usize) -> usize {
+        self.tree
+            .iter_from(label + 1)
+            .next()
+            .map_or(1 << LABEL_BITS, |e| e.key)
+    }
+
+    // Spreads evenly the labels of the smallest window around the label whose density is at
+    // most 1/4, and returns the new label of the element at the given label. The first element
+    // of the window gets the first label, so the sentinel stays at 0.
+    fn relabel(&mut self, label: usize) -> usize {
+        let mut width: usize = 4;
+        let (start, count) = loop {
+            assert!(width <= 1 << LABEL_BITS, "out of labels");
+            let start = label & !(width - 1);
+            let count = self.tree.count_range(start, start + (width - 1));
+            if count * 4 <= width {
+                break (start, count);
+            }
+            width *= 2;
+        };
+        let elements: Vec<Element> = self.tree.iter_from(start).take(count).collect();
+        for element in &elements {
+            self.tree.remove(element.key);
+        }
+        let gap = width / count;
+        let mut relabeled = label;
+        for (j, element) in elements.into_iter().enumerate() {
+            let key = start + j * gap;
+            if element.key == label {
+                relabeled = key;
+            }
+            if element.value != SENTINEL {
+                self.labels[element.value] = Some(key);
+            }
+            self.tree.insert(Element {
+                key,
+                value: element.value,
+            });
+        }
+        relabeled
+    }
+}
+
+impl Default for OrderMaintenance {
+    fn default() -> OrderMaintenance {
+        OrderMaintenance::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::OrderMaintenance;
+
+    #[test]
+    fn test_order_maintenance() {
+        let mut order = OrderMaintenance::new();
+        let mut expected = vec![order.insert_first()];
+
+        // Insertions after the same tags exhaust the gaps and force relabeling.
+        for i in 0..600 {
+            let position = match i % 4 {
+                0 => 0,
+                1 => expected.len() - 1,
+                _ => (i * 7919) % expected.len(),
+            };
+            let tag = order.insert_after(expected[position]);
+            expected.insert(position + 1, tag);
+            if i % 10 == 0 {
+                let tag = order.insert_first();
+                expected.insert(0, tag);
+            }
+        }
+        assert!(order.iter().eq(expected.iter().copied()));
+        for (a, b) in expected.windows(2).map(|w| (w[0], w[1])) {
+            assert!(order.is_before(a, b) && !order.is_before(b, a));
+        }
+
+        let removed = expected.remove(100);
+        assert!(order.remove(removed) && !order.remove(removed) && !order.contains(removed));
+        let tag = order.insert_after(expected[0]);
+        assert!(tag == removed && order.is_before(expected[0], tag));
+        assert!(order.is_before(tag, expected[1]));
+        assert!(order.size() == expected.len() + 1);
+        order.tree.validate();
+    }
+}
@@ -0,0 +1,823 @@
+// A 2-3 Tree over an abstract node store, e.g. pages of a file.
+//
+// Nodes are addressed by IDs and read and written through the NodeStore trait, so the tree
+// works the same in memory and on disk. FileNodeStore keeps each node in a fixed-size page of a
+// file, which turns the tree into a small disk-backed ordered index.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use crate::two_three_tree::Element;
+use crate::wal::Wal;
+
+// The ID of a node in a store.
+pub type NodeId = u32;
+
+// ID of no node.
+pub const NIL: NodeId = NodeId::MAX;
+
+// Size of a page of FileNodeStore, and of an encoded node.
+pub const PAGE_SIZE: usize = 64;
+pub const NODE_BYTES: usize = 1 + 2 * 16 + 3 * 4;
+
+// Identifies a FileNodeStore file.
+const MAGIC: &[u8; 8] = b"2-3TREE1";
+
+// A node with one or two elements. It is a leaf if it has no first child.
+#[derive(Clone, Copy)]
+pub struct PageNode {
+    elems: [Element; 2],
+    children: [NodeId; 3],
+    len: u8,
+}
+
+// The root and the number of elements of the tree in a store.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Header {
+    pub root: NodeId,
+    pub size: u64,
+}
+
+// Reads and writes the nodes of a tree.
+pub trait NodeStore {
+    fn read(&mut self, id: NodeId) -> io::Result<PageNode>;
+
+    fn write(&mut self, id: NodeId, node: &PageNode) -> io::Result<()>;
+
+    // Returns the ID of an unused node.
+    fn alloc(&mut self) -> io::Result<NodeId>;
+
+    // Makes a node unused.
+    fn release(&mut self, id: NodeId) -> io::Result<()>;
+
+    fn header(&self) -> Header;
+
+    fn set_header(&mut self, header: Header) -> io::Result<()>;
+
+    // Makes the writes durable.
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl PageNode {
+    const EMPTY: PageNode = PageNode {
+        elems: [Element { key: 0, value: 0 }; 2],
+        children: [NIL; 3],
+        len: 0,
+    };
+
+    fn leaf(element: Element) -> PageNode {
+        PageNode {
+            elems: [element; 2],
+            children: [NIL; 3],
+            len: 1,
+        }
+    }
+
+    fn is_leaf(&self) -> bool {
+        self.children[0] == NIL
+    }
+
+    fn elems(&self) -> &[Element] {
+        &self.elems[..self.len as usize]
+    }
+
+    // Inserts an element at a position, with the child to its right. A full node is split, and
+    // the middle element and the new right node are returned.
+    fn insert_at(
+        &mut self,
+        pos: usize,
+        element: Element,
+        right: NodeId,
+    ) -> Option<(Element, PageNode)> {
+        let len = self.len as usize;
+        let mut elems = [element; 3];
+        let mut children = [NIL; 4];
+        elems[..pos].copy_from_slice(&self.elems[..pos]);
+        elems[pos + 1..len + 1].copy_from_slice(&self.elems[pos..len]);
+        children[..pos + 1].copy_from_slice(&self.children[..pos + 1]);
+        children[pos + 1] = right;
+        children[pos + 2..len + 2].copy_from_slice(&self.children[pos + 1..len + 1]);
+
+        if len == 1 {
+            self.elems = [elems[0], elems[1]];
+            self.children = [children[0], children[1], children[2]];
+            self.len = 2;
+            return None;
+        }
+        self.elems[0] = elems[0];
+        self.children = [children[0], children[1], NIL];
+        self.len = 1;
+        let mut new = PageNode::leaf(elems[2]);
+        new.children = [children[2], children[3], NIL];
+        Some((elems[1], new))
+    }
+
+    // Removes the element and the child at the given positions.
+    fn remove_at(&mut self, elem_pos: usize, child_pos: usize) {
+        let len = self.len as usize;
+        self.elems.copy_within(elem_pos + 1..len, elem_pos);
+        self.children.copy_within(child_pos + 1..len + 1, child_pos);
+        self.children[len] = NIL;
+        self.len -= 1;
+    }
+
+    // Encodes the node in little-endian byte order.
+    pub fn encode(&self) -> [u8; NODE_BYTES] {
+        let mut bytes = [0; NODE_BYTES];
+        bytes[0] = self.len;
+        for (i, elem) in self.elems.iter().enumerate() {
+            bytes[1 + 16 * i..9 + 16 * i].copy_from_slice(&(elem.key as u64).to_le_bytes());
+            bytes[9 + 16 * i..17 + 16 * i].copy_from_slice(&(elem.value as u64).to_le_bytes());
+        }
+        for (i, child) in self.children.iter().enumerate() {
+            bytes[33 + 4 * i..37 + 4 * i].copy_from_slice(&child.to_le_bytes());
+        }
+        bytes
+    }
+
+    // Decodes a node produced by encode().
+    pub fn decode(bytes: &[u8; NODE_BYTES]) -> PageNode {
+        let u64_at = |i: usize| u64::from_le_bytes(bytes[i..i + 8].try_into().unwrap()) as usize;
+        let u32_at = |i: usize| u32::from_le_bytes(bytes[i..i + 4].try_into().unwrap());
+        let elem_at = |i: usize| Element {
+            key: u64_at(i),
+            value: u64_at(i + 8),
+        };
+        PageNode {
+            elems: [elem_at(1), elem_at(17)],
+            children: [u32_at(33), u32_at(37), u32_at(41)],
+            len: bytes[0],
+        }
+    }
+}
+
+// A store keeping the nodes in a Vec.
+pub struct MemoryNodeStore {
+    nodes: Vec<PageNode>,
+    free: Vec<NodeId>,
+    header: Header,
+}
+
+impl MemoryNodeStore {
+    pub fn new() -> MemoryNodeStore {
+        MemoryNodeStore {
+            nodes: Vec::new(),
+            free: Vec::new(),
+            header: Header { root: NIL, size: 0 },
+        }
+    }
+}
+
+impl Default for MemoryNodeStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NodeStore for MemoryNodeStore {
+    fn read(&mut self, id: NodeId) -> io::Result<PageNode> {
+        Ok(self.nodes[id as usize])
+    }
+
+    fn write(&mut self, id: NodeId, node: &PageNode) -> io::Result<()> {
+        self.nodes[id as usize] = *node;
+        Ok(())
+    }
+
+    fn alloc(&mut self) -> io::Result<NodeId> {
+        Ok(self.free.pop().unwrap_or_else(|| {
+            self.nodes.push(PageNode::EMPTY);
+            (self.nodes.len() - 1) as NodeId
+        }))
+    }
+
+    fn release(&mut self, id: NodeId) -> io::Result<()> {
+        self.free.push(id);
+        Ok(())
+    }
+
+    fn header(&self) -> Header {
+        self.header
+    }
+
+    fn set_header(&mut self, header: Header) -> io::Result<()> {
+        self.header = header;
+        Ok(())
+    }
+}
+
+// A store keeping each node in a page of a file.
+// Page 0 holds the header. Free pages are linked through the first child of their node.
+//
+// With a write-ahead log, the pages written by a mutation are buffered until the mutation sets
+// the header. They are then appended to the log together, and only written to the file once
+// the log is durable. Opening the store replays the log, so a crash cannot leave a mutation
+// half applied.
+pub struct FileNodeStore {
+    file: File,
+    header: Header,
+
+    // Head of the list of free pages.
+    free: NodeId,
+
+    // Number of pages in the file, including the header.
+    pages: NodeId,
+
+    wal: Option<Wal>,
+
+    // Pages written since the last commit to the log.
+    pending: BTreeMap<NodeId, [u8; PAGE_SIZE]>,
+}
+
+impl FileNodeStore {
+    // Opens a store file, creating an empty one if the file does not exist.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<FileNodeStore> {
+        Self::open_file(path.as_ref(), None)
+    }
+
+    // Opens a store file with a write-ahead log next to it, replaying the log first.
+    pub fn open_with_wal(path: impl AsRef<Path>) -> io::Result<FileNodeStore> {
+        let mut wal_path = path.as_ref().as_os_str().to_owned();
+        wal_path.push(".wal");
+        Self::open_file(path.as_ref(), Some(Wal::open(wal_path)?))
+    }
+
+    fn open_file(path: &Path, mut wal: Option<Wal>) -> io::Result<FileNodeStore> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        if let Some(ref mut wal) = wal {
+            wal.replay(|id, page| {
+                file.seek(SeekFrom::Start(id as u64 * PAGE_SIZE as u64))?;
+                file.write_all(page)
+            })?;
+            file.sync_all()?;
+            wal.truncate()?;
+        }
+
+        let mut store = FileNodeStore {
+            file,
+            header: Header { root: NIL, size: 0 },
+            free: NIL,
+            pages: 1,
+            wal,
+            pending: BTreeMap::new(),
+        };
+        if store.file.metadata()?.len() == 0 {
+            store.write_header()?;
+            store.commit()?;
+            return Ok(store);
+        }
+
+        let page = store.read_page(0)?;
+        if &page[0..8] != MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not a 2-3 tree file",
+            ));
+        }
+        let u32_at = |i: usize| u32::from_le_bytes(page[i..i + 4].try_into().unwrap());
+        store.header = Header {
+            root: u32_at(8),
+            size: u64::from_le_bytes(page[12..20].try_into().unwrap()),
+        };
+        store.free = u32_at(20);
+        store.pages = u32_at(24);
+        Ok(store)
+    }
+
+    fn write_header(&mut self) -> io::Result<()> {
+        let mut page = [0; PAGE_SIZE];
+        page[0..8].copy_from_slice(MAGIC);
+        page[8..12].copy_from_slice(&self.header.root.to_le_bytes());
+        page[12..20].copy_from_slice(&self.header.size.to_le_bytes());
+        page[20..24].copy_from_slice(&self.free.to_le_bytes());
+        page[24..28].copy_from_slice(&self.pages.to_le_bytes());
+        self.write_page(0, page)
+    }
+
+    fn read_page(&mut self, id: NodeId) -> io::Result<[u8; PAGE_SIZE]> {
+        if let Some(page) = self.pending.get(&id) {
+            return Ok(*page);
+        }
+        let mut page = [0; PAGE_SIZE];
+        self.file
+            .seek(SeekFrom::Start(id as u64 * PAGE_SIZE as u64))?;
+        self.file.read_exact(&mut page)?;
+        Ok(page)
+    }
+
+    fn write_page(&mut self, id: NodeId, page: [u8; PAGE_SIZE]) -> io::Result<()> {
+        if self.wal.is_some() {
+            self.pending.insert(id, page);
+            return Ok(());
+        }
+        self.file
+            .seek(SeekFrom::Start(id as u64 * PAGE_SIZE as u64))?;
+        self.file.write_all(&page)
+    }
+
+    // Logs the pending pages, then writes them to the file.
+    fn commit(&mut self) -> io::Result<()> {
+        let Some(ref mut wal) = self.wal else {
+            return Ok(());
+        };
+        wal.append(&self.pending)?;
+        for (&id, page) in self.pending.iter() {
+            self.file
+                .seek(SeekFrom::Start(id as u64 * PAGE_SIZE as u64))?;
+            self.file.write_all(page)?;
+        }
+        self.pending.clear();
+        Ok(())
+    }
+}
+
+impl NodeStore for FileNodeStore {
+    fn read(&mut self, id: NodeId) -> io::Result<PageNode> {
+        let page = self.read_page(id)?;
+        Ok(PageNode::decode(page[..NODE_BYTES].try_into().unwrap()))
+    }
+
+    fn write(&mut self, id: NodeId, node: &PageNode) -> io::Result<()> {
+        let mut page = [0; PAGE_SIZE];
+        page[..NODE_BYTES].copy_from_slice(&node.encode());
+        self.write_page(id, page)
+    }
+
+    fn alloc(&mut self) -> io::Result<NodeId> {
+        if self.free != NIL {
+            let id = self.free;
+            self.free = self.read(id)?.children[0];
+            return Ok(id);
+        }
+        self.pages += 1;
+        Ok(self.pages - 1)
+    }
+
+    fn release(&mut self, id: NodeId) -> io::Result<()> {
+        let mut node = PageNode::EMPTY;
+        node.children[0] = self.free;
+        self.write(id, &node)?;
+        self.free = id;
+        Ok(())
+    }
+
+    fn header(&self) -> Header {
+        self.header
+    }
+
+    // Setting the header completes a mutation, so this commits it to the log.
+    fn set_header(&mut self, header: Header) -> io::Result<()> {
+        self.header = header;
+        self.write_header()?;
+        self.commit()
+    }
+
+    // Syncs the file. The log is then no longer needed and is truncated.
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.sync_all()?;
+        match self.wal {
+            Some(ref mut wal) => wal.truncate(),
+            None => Ok(()),
+        }
+    }
+}
+
+// An LRU cache of nodes in front of another store.
+// Writes go through to the underlying store, so the cache never holds unwritten changes.
+pub struct CachedNodeStore<S: NodeStore> {
+    store: S,
+    capacity: usize,
+
+    // Cached nodes, with the time of their last use.
+    nodes: HashMap<NodeId, (PageNode, u64)>,
+
+    // Cached node IDs by the time of their last use, least recent first.
+    lru: BTreeMap<u64, NodeId>,
+    clock: u64,
+
+    stats: CacheStats,
+}
+
+// Hit and miss counts of a CachedNodeStore.
+#[derive(Clone, Copy, Default, PartialEq, Debug)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+impl<S: NodeStore> CachedNodeStore<S> {
+    // Wraps a store with a cache of up to capacity nodes.
+    pub fn new(store: S, capacity: usize) -> CachedNodeStore<S> {
+        assert!(capacity > 0);
+        CachedNodeStore {
+            store,
+            capacity,
+            nodes: HashMap::new(),
+            lru: BTreeMap::new(),
+            clock: 0,
+            stats: CacheStats::default(),
+        }
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.stats
+    }
+
+    pub fn into_inner(self) -> S {
+        self.store
+    }
+
+    // Caches a node as the most recently used, evicting the least recently used if full.
+    fn cache(&mut self, id: NodeId, node: PageNode) {
+        self.clock += 1;
+        if let Some((_, used)) = self.nodes.insert(id, (node, self.clock)) {
+            self.lru.remove(&used);
+        } else if self.nodes.len() > self.capacity {
+            let (_, evicted) = self.lru.pop_first().unwrap();
+            self.nodes.remove(&evicted);
+        }
+        self.lru.insert(self.clock, id);
+    }
+}
+
+impl<S: NodeStore> NodeStore for CachedNodeStore<S> {
+    fn read(&mut self, id: NodeId) -> io::Result<PageNode> {
+        let node = match self.nodes.get(&id) {
+            Some(&(node, _)) => {
+                self.stats.hits += 1;
+                node
+            }
+            None => {
+                self.stats.misses += 1;
+                self.store.read(id)?
+            }
+        };
+        self.cache(id, node);
+        Ok(node)
+    }
+
+    fn write(&mut self, id: NodeId, node: &PageNode) -> io::Result<()> {
+        self.store.write(id, node)?;
+        self.cache(id, *node);
+        Ok(())
+    }
+
+    fn alloc(&mut self) -> io::Result<NodeId> {
+        self.store.alloc()
+    }
+
+    fn release(&mut self, id: NodeId) -> io::Result<()> {
+        if let Some((_, used)) = self.nodes.remove(&id) {
+            self.lru.remove(&used);
+        }
+        self.store.release(id)
+    }
+
+    fn header(&self) -> Header {
+        self.store.header()
+    }
+
+    fn set_header(&mut self, header: Header) -> io::Result<()> {
+        self.store.set_header(header)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.store.flush()
+    }
+}
+
+// A 2-3 Tree whose nodes are kept in a NodeStore.
+pub struct PagedTwoThreeTree<S: NodeStore> {
+    store: S,
+}
+
+impl<S: NodeStore> PagedTwoThreeTree<S> {
+    // Opens the tree kept in the store.
+    pub fn new(store: S) -> PagedTwoThreeTree<S> {
+        PagedTwoThreeTree { store }
+    }
+
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    pub fn into_store(self) -> S {
+        self.store
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.store.header().root == NIL
+    }
+
+    pub fn size(&self) -> usize {
+        self.store.header().size as usize
+    }
+
+    // Finds an element with the given key.
+    pub fn find(&mut self, key: usize) -> io::Result<Option<Element>> {
+        let mut id = self.store.header().root;
+        while id != NIL {
+            let node = self.store.read(id)?;
+            if let Some(elem) = node.elems().iter().find(|e| e.key == key) {
+                return Ok(Some(*elem));
+            }
+            if node.is_leaf() {
+                break;
+            }
+            id = node.children[node.elems().iter().filter(|e| e.key < key).count()];
+        }
+        Ok(None)
+    }
+
+    // Returns all elements in key order.
+    pub fn to_vec(&mut self) -> io::Result<Vec<Element>> {
+        self.read_from(0, usize::MAX)
+    }
+
+    // Returns up to limit elements with keys >= start, in key order.
+    pub fn read_from(&mut self, start: usize, limit: usize) -> io::Result<Vec<Element>> {
+        let mut elements = Vec::with_capacity(limit.min(self.size()));
+        let root = self.store.header().root;
+        if root != NIL {
+            self.collect_node(root, start, limit, &mut elements)?;
+        }
+        Ok(elements)
+    }
+
+    // Appends the elements of a subtree with keys >= start in key order, recursively, until
+    // there are limit elements.
+    fn collect_node(
+        &mut self,
+        id: NodeId,
+        start: usize,
+        limit: usize,
+        elements: &mut Vec<Element>,
+    ) -> io::Result<()> {
+        let node = self.store.read(id)?;
+        for i in 0..=node.len as usize {
+            // The keys of a child are at most the key of the element after it.
+            let after = node.elems().get(i);
+            if !node.is_leaf() && after.is_none_or(|e| e.key >= start) {
+                self.collect_node(node.children[i], start, limit, elements)?;
+            }
+            if elements.len() == limit {
+                break;
+            }
+            if let Some(&elem) = after.filter(|e| e.key >= start) {
+                elements.push(elem);
+            }
+        }
+        Ok(())
+    }
+
+    // Inserts an element.
+    pub fn insert(&mut self, element: Element) -> io::Result<()> {
+        let mut header = self.store.header();
+        if header.root == NIL {
+            header.root = self.store.alloc()?;
+            self.store.write(header.root, &PageNode::leaf(element))?;
+        } else if let Some((middle, right)) = self.insert_node(header.root, element)? {
+            let mut root = PageNode::leaf(middle);
+            root.children = [header.root, right, NIL];
+            header.root = self.store.alloc()?;
+            self.store.write(header.root, &root)?;
+        }
+        header.size += 1;
+        self.store.set_header(header)
+    }
+
+    // Inserts recursively. Returns the middle element and the new right node if the node splits.
+    fn insert_node(
+        &mut self,
+        id: NodeId,
+        element: Element,
+    ) -> io::Result<Option<(Element, NodeId)>> {
+        let mut node = self.store.read(id)?;
+        let pos = node.elems().iter().filter(|e| e.key <= element.key).count();
+        let split = if node.is_leaf() {
+            node.insert_at(pos, element, NIL)
+        } else {
+            match self.insert_node(node.children[pos], element)? {
+                Some((middle, right)) => node.insert_at(pos, middle, right),
+                None => return Ok(None),
+            }
+        };
+        self.store.write(id, &node)?;
+        match split {
+            Some((middle, new)) => {
+                let right = self.store.alloc()?;
+                self.store.write(right, &new)?;
+                Ok(Some((middle, right)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    // Deletes an element with the given key.
+    // Returns true if the element is found and deleted.
+    pub fn delete(&mut self, key: usize) -> io::Result<bool> {
+        let mut header = self.store.header();
+        if header.root == NIL || self.delete_node(header.root, key)?.is_none() {
+            return Ok(false);
+        }
+        header.size -= 1;
+        let root = self.store.read(header.root)?;
+        if root.len == 0 {
+            self.store.release(header.root)?;
+            header.root = root.children[0];
+        }
+        self.store.set_header(header)?;
+        Ok(true)
+    }
+
+    // Deletes recursively. Returns None if the key is not found, otherwise whether the node is
+    // left without elements. Such a node has its only child first.
+    fn delete_node(&mut self, id: NodeId, key: usize) -> io::Result<Option<bool>> {
+        let mut node = self.store.read(id)?;
+        if let Some(pos) = node.elems().iter().position(|e| e.key == key) {
+            if node.is_leaf() {
+                node.remove_at(pos, 0);
+                self.store.write(id, &node)?;
+                return Ok(Some(node.len == 0));
+            }
+            // Replace the element with its predecessor, the largest element of the child before.
+            let (predecessor, empty) = self.delete_max(node.children[pos])?;
+            node.elems[pos] = predecessor;
+            self.store.write(id, &node)?;
+            return Ok(Some(empty && self.fix(id, pos)?));
+        }
+        if node.is_leaf() {
+            return Ok(None);
+        }
+        let pos = node.elems().iter().filter(|e| e.key < key).count();
+        match self.delete_node(node.children[pos], key)? {
+            Some(empty) => Ok(Some(empty && self.fix(id, pos)?)),
+            None => Ok(None),
+        }
+    }
+
+    // Deletes the largest element of a subtree. Returns it, and whether the node is left empty.
+    fn delete_max(&mut self, id: NodeId) -> io::Result<(Element, bool)> {
+        let mut node = self.store.read(id)?;
+        let last = node.len as usize - 1;
+        if node.is_leaf() {
+            let element = node.elems[last];
+            node.remove_at(last, 0);
+            self.store.write(id, &node)?;
+            return Ok((element, last == 0));
+        }
+        let (element, empty) = self.delete_max(node.children[last + 1])?;
+        Ok((element, empty && self.fix(id, last + 1)?))
+    }
+
+    // Fixes the empty child at a position, by borrowing an element from a sibling with two, or
+    // by merging it into a sibling with one. Returns true if the node is left empty.
+    fn fix(&mut self, id: NodeId, pos: usize) -> io::Result<bool> {
+        let mut node = self.store.read(id)?;
+        let child_id = node.children[pos];
+        let mut child = self.store.read(child_id)?;
+        let left_id = if pos > 0 { node.children[pos - 1] } else { NIL };
+        let right_id = if pos < node.len as usize {
+            node.children[pos + 1]
+        } else {
+            NIL
+        };
+        let (sibling_id, is_left) = if left_id != NIL && self.store.read(left_id)?.len == 2 {
+            (left_id, true)
+        } else if right_id != NIL && self.store.read(right_id)?.len == 2 {
+            (right_id, false)
+        } else if left_id != NIL {
+            (left_id, true)
+        } else {
+            (right_id, false)
+        };
+        let mut sibling = self.store.read(sibling_id)?;
+
+        if sibling.len == 2 {
+            // Rotate an element from the sibling through the parent into the child.
+            if is_left {
+                child.elems[0] = node.elems[pos - 1];
+                child.children = [sibling.children[2], child.children[0], NIL];
+                node.elems[pos - 1] = sibling.elems[1];
+                sibling.remove_at(1, 2);
+            } else {
+                child.elems[0] = node.elems[pos];
+                child.children = [child.children[0], sibling.children[0], NIL];
+                node.elems[pos] = sibling.elems[0];
+                sibling.remove_at(0, 0);
+            }
+            child.len = 1;
+            self.store.write(child_id, &child)?;
+        } else {
+            // Merge the child and an element of the parent into the sibling.
+            if is_left {
+                sibling.elems[1] = node.elems[pos - 1];
+                sibling.children[2] = child.children[0];
+                node.remove_at(pos - 1, pos);
+            } else {
+                sibling.elems = [node.elems[pos], sibling.elems[0]];
+                sibling.children = [child.children[0], sibling.children[0], sibling.children[1]];
+                node.remove_at(pos, pos);
+            }
+            sibling.len = 2;
+            self.store.release(child_id)?;
+        }
+        self.store.write(sibling_id, &sibling)?;
+        self.store.write(id, &node)?;
+        Ok(node.len == 0)
+    }
+
+    // Makes the writes durable.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.store.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CachedNodeStore, FileNodeStore, MemoryNodeStore, PagedTwoThreeTree};
+    use crate::two_three_tree::Element;
+    use crate::workload::{self, WorkloadOp};
+
+    #[test]
+    fn test_memory_store() {
+        let mut tree = PagedTwoThreeTree::new(MemoryNodeStore::new());
+        let keys = workload::random_insert_delete(200, 3000, |op| match op {
+            WorkloadOp::Insert(element) => {
+                tree.insert(element).unwrap();
+                true
+            }
+            WorkloadOp::Delete(key) => tree.delete(key).unwrap(),
+            WorkloadOp::Find(_) => unreachable!(),
+        });
+        assert!(tree.size() == keys.len());
+        assert!(tree.to_vec().unwrap().iter().map(|e| e.key).eq(keys));
+    }
+
+    #[test]
+    fn test_file_store() {
+        let path = std::env::temp_dir().join(format!("two-three-paged-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        {
+            let mut tree = PagedTwoThreeTree::new(FileNodeStore::open(&path).unwrap());
+            for i in 0..1000 {
+                tree.insert(Element {
+                    key: (i * 7) % 1000,
+                    value: i,
+                })
+                .unwrap();
+            }
+            for key in 0..500 {
+                assert!(tree.delete(key).unwrap());
+            }
+            tree.flush().unwrap();
+        }
+        let mut tree = PagedTwoThreeTree::new(FileNodeStore::open(&path).unwrap());
+        assert!(tree.size() == 500);
+        assert!(tree.find(700).unwrap().unwrap().value == 100);
+        assert!(tree.find(100).unwrap().is_none());
+        assert!(tree.to_vec().unwrap().iter().map(|e| e.key).eq(500..1000));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_cached_store() {
+        let path = std::env::temp_dir().join(format!("two-three-cached-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let store = CachedNodeStore::new(FileNodeStore::open(&path).unwrap(), 16);
+        let mut tree = PagedTwoThreeTree::new(store);
+        for i in 0..1000 {
+            tree.insert(Element { key: i, value: i }).unwrap();
+        }
+        for i in (0..1000).step_by(3) {
+            assert!(tree.delete(i).unwrap());
+        }
+        assert!(tree.find(500).unwrap().is_some());
+        let before = tree.store().stats();
+        for _ in 0..10 {
+            assert!(tree.find(500).unwrap().is_some());
+        }
+        let after = tree.store().stats();
+        assert!(after.misses == before.misses && after.hits > before.hits);
+        drop(tree);
+
+        // The cache never holds unwritten changes.
+        let mut tree = PagedTwoThreeTree::new(FileNodeStore::open(&path).unwrap());
+        assert!(tree
+            .to_vec()
+            .unwrap()
+            .iter()
+            .map(|e| e.key)
+            .eq((0..1000).filter(|i| i % 3 != 0)));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,340 @@
+// A persistent 2-3 Tree, whose snapshots share nodes with it.
+//
+// The elements are the leaves, all at the same depth, as in a Sequence, and each internal node
+// keeps the number of leaves below it and their largest key. Nodes are shared behind Arc, so
+// snapshot() only clones the root, in O(1). A mutation copies the shared nodes on its path
+// and keeps sharing the others, so each mutation after a snapshot costs O(log n) memory.
+
+use std::iter;
+use std::sync::Arc;
+
+use crate::two_three_tree::Element;
+
+#[derive(Clone)]
+enum Node {
+    Leaf(Element),
+
+    // 2 or 3 children of equal height, the number of leaves below and their largest key.
+    Internal(usize, usize, Vec<Arc<Node>>),
+}
+
+impl Node {
+    fn size(&self) -> usize {
+        match self {
+            Node::Leaf(_) => 1,
+            Node::Internal(size, _, _) => *size,
+        }
+    }
+
+    fn max_key(&self) -> usize {
+        match self {
+            Node::Leaf(element) => element.key,
+            Node::Internal(_, max_key, _) => *max_key,
+        }
+    }
+
+    fn internal(children: Vec<Arc<Node>>) -> Arc<Node> {
+        let size = children.iter().map(|child| child.size()).sum();
+        let max_key = children.last().unwrap().max_key();
+        Arc::new(Node::Internal(size, max_key, children))
+    }
+
+    fn children(&self) -> &[Arc<Node>] {
+        match self {
+            Node::Leaf(_) => &[],
+            Node::Internal(_, _, children) => children,
+        }
+    }
+}
+
+// Inserts the element after the elements with the same key below an internal node. Returns
+// the node split off on the right if it overflowed.
+fn insert(node: &mut Arc<Node>, element: Element) -> Option<Arc<Node>> {
+    let Node::Internal(size, max_key, children) = Arc::make_mut(node) else {
+        unreachable!()
+    };
+    let i = children
+        .iter()
+        .position(|child| child.max_key() > element.key);
+    if let Node::Leaf(_) = *children[0] {
+        children.insert(i.unwrap_or(children.len()), Arc::new(Node::Leaf(element)));
+    } else {
+        let i = i.unwrap_or(children.len() - 1);
+        if let Some(split) = insert(&mut children[i], element) {
+            children.insert(i + 1, split);
+        }
+    }
+    *size += 1;
+    *max_key = (*max_key).max(element.key);
+    if children.len() <= 3 {
+        return None;
+    }
+    let right = Node::internal(children.split_off(2));
+    *size -= right.size();
+    *max_key = children[1].max_key();
+    Some(right)
+}
+
+// Removes the first element with the key below an internal node, which must hold one. The
+// node may be left with a single child.
+fn remove(node: &mut Arc<Node>, key: usize) -> Element {
+    let Node::Internal(size, max_key, children) = Arc::make_mut(node) else {
+        unreachable!()
+    };
+    let i = children
+        .iter()
+        .position(|child| child.max_key() >= key)
+        .unwrap();
+    let removed = match *children[i] {
+        Node::Leaf(element) => {
+            children.remove(i);
+            element
+        }
+        Node::Internal(..) => {
+            let removed = remove(&mut children[i], key);
+            if children[i].children().len() == 1 {
+                merge(children, i);
+            }
+            removed
+        }
+    };
+    *size -= 1;
+    *max_key = children.last().unwrap().max_key();
+    removed
+}
+
+// Merges the child at i, which has a single child, with a sibling, and splits the result
+// again if it has 4 children.
+fn merge(children: &mut Vec<Arc<Node>>, i: usize) {
+    let left = if i > 0 { i - 1 } else { i };
+    let right = children.remove(left + 1);
+    let mut merged = children[left].children().to_vec();
+    merged.extend(right.children().iter().cloned());
+    if merged.len() <= 3 {
+        children[left] = Node::internal(merged);
+    } else {
+        let split = merged.split_off(2);
+        children[left] = Node::internal(merged);
+        children.insert(left + 1, Node::internal(split));
+    }
+}
+
+// Finds an element with the key below a node.
+fn find(mut node: &Node, key: usize) -> Option<Element> {
+    loop {
+        match node {
+            Node::Leaf(element) => return Some(*element).filter(|e| e.key == key),
+            Node::Internal(_, _, children) => {
+                node = children.iter().find(|child| child.max_key() >= key)?;
+            }
+        }
+    }
+}
+
+// Returns the elements below a node in key order.
+fn elements(root: Option<&Node>) -> impl Iterator<Item = Element> + '_ {
+    let mut stack: Vec<&Node> = root.into_iter().collect();
+    iter::from_fn(move || loop {
+        match stack.pop()? {
+            Node::Leaf(element) => return Some(*element),
+            Node::Internal(_, _, children) => stack.extend(children.iter().rev().map(|c| &**c)),
+        }
+    })
+}
+
+#[derive(Clone)]
+pub struct PersistentTwoThreeTree {
+    root: Option<Arc<Node>>,
+}
+
+impl PersistentTwoThreeTree {
+    pub fn new() -> PersistentTwoThreeTree {
+        PersistentTwoThreeTree { root: None }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+
+    pub fn size(&self) -> usize {
+        self.root.as_ref().map_or(0, |root| root.size())
+    }
+
+    // Finds an element with the given key.
+    pub fn find(&self, key: usize) -> Option<Element> {
+        find(self.root.as_ref()?, key)
+    }
+
+    // Inserts an element after the elements with the same key, in O(log n).
+    pub fn insert(&mut self, element: Element) {
+        let leaf = Arc::new(Node::Leaf(element));
+        let root = match self.root.take() {
+            None => leaf,
+            Some(root) if matches!(*root, Node::Leaf(_)) => {
+                if root.max_key() > element.key {
+                    Node::internal(vec![leaf, root])
+                } else {
+                    Node::internal(vec![root, leaf])
+                }
+            }
+            Some(mut root) => match insert(&mut root, element) {
+                Some(split) => Node::internal(vec![root, split]),
+                None => root,
+            },
+        };
+        self.root = Some(root);
+    }
+
+    // Removes an element with the given key, in O(log n), and returns it.
+    pub fn remove(&mut self, key: usize) -> Option<Element> {
+        let element = self.find(key)?;
+        let mut root = self.root.take().unwrap();
+        if let Node::Internal(..) = *root {
+            remove(&mut root, key);
+            self.root = match root.children() {
+                [child] => Some(Arc::clone(child)),
+                _ => Some(root),
+            };
+        }
+        Some(element)
+    }
+
+    // Returns an iterator over the elements in key order.
+    pub fn iter(&self) -> impl Iterator<Item = Element> + '_ {
+        elements(self.root.as_deref())
+    }
+
+    // Returns a read-only view of the current elements, in O(1). The view shares the nodes of
+    // the tree, and stays unchanged while the tree keeps mutating.
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot { tree: self.clone() }
+    }
+
+    // Checks the invariants of the tree, and panics if any is violated.
+    pub fn validate(&self) {
+        // Returns the height of the node.
+        fn validate_node(node: &Node) -> usize {
+            let Node::Internal(size, max_key, children) = node else {
+                return 0;
+            };
+            assert!((2..=3).contains(&children.len()));
+            assert!(*size == children.iter().map(|child| child.size()).sum::<usize>());
+            assert!(*max_key == children.last().unwrap().max_key());
+            assert!(children
+                .windows(2)
+                .all(|w| w[0].max_key() <= w[1].max_key()));
+            let height = validate_node(&children[0]);
+            assert!(children.iter().all(|child| validate_node(child) == height));
+            height + 1
+        }
+        if let Some(root) = &self.root {
+            validate_node(root);
+        }
+        assert!(self
+            .iter()
+            .zip(self.iter().skip(1))
+            .all(|(a, b)| a.key <= b.key));
+    }
+}
+
+impl Default for PersistentTwoThreeTree {
+    fn default() -> PersistentTwoThreeTree {
+        PersistentTwoThreeTree::new()
+    }
+}
+
+// A read-only view of a PersistentTwoThreeTree as of a snapshot() call.
+#[derive(Clone)]
+pub struct Snapshot {
+    tree: PersistentTwoThreeTree,
+}
+
+impl Snapshot {
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    pub fn size(&self) -> usize {
+        self.tree.size()
+    }
+
+    // Finds an element with the given key.
+    pub fn find(&self, key: usize) -> Option<Element> {
+        self.tree.find(key)
+    }
+
+    // Returns an iterator over the elements in key order.
+    pub fn iter(&self) -> impl Iterator<Item = Element> + '_ {
+        self.tree.iter()
+    }
+
+    // Returns a tree starting from the elements of the snapshot, sharing its nodes.
+    pub fn to_tree(&self) -> PersistentTwoThreeTree {
+        self.tree.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::PersistentTwoThreeTree;
+    use crate::two_three_tree::Element;
+
+    #[test]
+    fn test_persistent() {
+        let mut tree = PersistentTwoThreeTree::new();
+        let mut expected = Vec::new();
+        for i in 0..1000 {
+            let key = (i * 7919) % 500;
+            tree.insert(Element { key, value: i });
+            expected.push(Element { key, value: i });
+        }
+        // Elements with the same key stay in insertion order.
+        expected.sort_by_key(|e| e.key);
+        tree.validate();
+        assert!(tree.size() == 1000 && tree.iter().eq(expected.iter().copied()));
+
+        let snapshot = tree.snapshot();
+        assert!(Arc::ptr_eq(
+            tree.root.as_ref().unwrap(),
+            snapshot.tree.root.as_ref().unwrap()
+        ));
+        for i in 0..400 {
+            let key = (i * 31) % 500;
+            let removed = tree.remove(key).unwrap();
+            let position = expected.iter().position(|e| e.key == key).unwrap();
+            assert!(expected.remove(position) == removed);
+        }
+        assert!(tree.remove(1000).is_none());
+        tree.insert(Element {
+            key: 1000,
+            value: 0,
+        });
+        tree.validate();
+        expected.push(Element {
+            key: 1000,
+            value: 0,
+        });
+        assert!(tree.iter().eq(expected.iter().copied()));
+
+        // The snapshot is unchanged, and still shares the nodes the mutations did not reach.
+        snapshot.tree.validate();
+        assert!(snapshot.size() == 1000 && snapshot.find(1000).is_none());
+        assert!(snapshot
+            .iter()
+            .map(|e| e.key)
+            .eq((0..500).flat_map(|k| [k, k])));
+        let mut fork = snapshot.to_tree();
+        fork.insert(Element { key: 0, value: 0 });
+        let children =
+            |tree: &PersistentTwoThreeTree| tree.root.as_ref().unwrap().children().to_vec();
+        let (a, b) = (children(&fork), children(&snapshot.tree));
+        assert!(!Arc::ptr_eq(&a[0], &b[0]) && Arc::ptr_eq(a.last().unwrap(), b.last().unwrap()));
+
+        for e in expected {
+            assert!(tree.remove(e.key).is_some());
+        }
+        assert!(tree.is_empty() && snapshot.size() == 1000);
+    }
+}
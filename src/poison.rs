@@ -0,0 +1,166 @@
+// A 2-3 Tree which is poisoned when a mutation panics, like std::sync::Mutex.
+//
+// insert(), the deletions and the callbacks of update() leave the tree valid when they panic,
+// but a mutation run through mutate() may stop half done, e.g. a split_off() whose Augment
+// panics. Once a mutation has unwound, every operation returns PoisonedError instead of working
+// on a tree that may be inconsistent, until check() finds the tree valid or clear() empties it.
+
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+
+use crate::two_three_tree::{Augment, Element, TwoThreeTree};
+
+// Error returned by the operations of a poisoned tree.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PoisonedError;
+
+impl fmt::Display for PoisonedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "tree poisoned by a panic during a mutation")
+    }
+}
+
+impl std::error::Error for PoisonedError {}
+
+pub struct PoisoningTwoThreeTree<A: Augment = ()> {
+    tree: TwoThreeTree<A>,
+
+    // Set during a mutation, and left set if it unwinds.
+    poisoned: bool,
+}
+
+impl PoisoningTwoThreeTree {
+    pub fn new() -> PoisoningTwoThreeTree {
+        PoisoningTwoThreeTree::with_augment()
+    }
+}
+
+impl<A: Augment> PoisoningTwoThreeTree<A> {
+    pub fn with_augment() -> PoisoningTwoThreeTree<A> {
+        PoisoningTwoThreeTree {
+            tree: TwoThreeTree::with_augment(),
+            poisoned: false,
+        }
+    }
+
+    pub fn is_poisoned(&self) -> bool {
+        self.poisoned
+    }
+
+    pub fn is_empty(&self) -> Result<bool, PoisonedError> {
+        Ok(self.tree()?.is_empty())
+    }
+
+    pub fn size(&self) -> Result<usize, PoisonedError> {
+        Ok(self.tree()?.size())
+    }
+
+    // The tree, for the read-only operations.
+    pub fn tree(&self) -> Result<&TwoThreeTree<A>, PoisonedError> {
+        if self.poisoned {
+            return Err(PoisonedError);
+        }
+        Ok(&self.tree)
+    }
+
+    pub fn find(&self, key: usize) -> Result<Option<Element>, PoisonedError> {
+        Ok(self.tree()?.find(key))
+    }
+
+    pub fn insert(&mut self, element: Element) -> Result<(), PoisonedError> {
+        self.mutate(|tree| tree.insert(element))
+    }
+
+    pub fn remove(&mut self, key: usize) -> Result<Option<Element>, PoisonedError> {
+        self.mutate(|tree| tree.remove(key))
+    }
+
+    pub fn update(
+        &mut self,
+        key: usize,
+        f: impl FnOnce(&mut usize),
+    ) -> Result<bool, PoisonedError> {
+        self.mutate(|tree| tree.update(key, f))
+    }
+
+    // Runs any mutation of the tree, poisoning it if f panics.
+    pub fn mutate<R>(
+        &mut self,
+        f: impl FnOnce(&mut TwoThreeTree<A>) -> R,
+    ) -> Result<R, PoisonedError> {
+        if self.poisoned {
+            return Err(PoisonedError);
+        }
+        self.poisoned = true;
+        let result = f(&mut self.tree);
+        self.poisoned = false;
+        Ok(result)
+    }
+
+    // Validates a poisoned tree, and clears the poisoning if it is valid.
+    pub fn check(&mut self) -> Result<(), PoisonedError> {
+        if self.poisoned {
+            let tree = &self.tree;
+            panic::catch_unwind(AssertUnwindSafe(|| tree.validate())).map_err(|_| PoisonedError)?;
+            self.poisoned = false;
+        }
+        Ok(())
+    }
+
+    // Empties the tree and clears the poisoning.
+    pub fn clear(&mut self) {
+        self.tree = TwoThreeTree::with_augment();
+        self.poisoned = false;
+    }
+}
+
+impl Default for PoisoningTwoThreeTree {
+    fn default() -> PoisoningTwoThreeTree {
+        PoisoningTwoThreeTree::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PoisonedError, PoisoningTwoThreeTree};
+    use crate::two_three_tree::Element;
+    use std::ops::ControlFlow;
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    #[test]
+    fn test_poisoning() {
+        let mut tree = PoisoningTwoThreeTree::new();
+        for key in 0..10 {
+            tree.insert(Element { key, value: key }).unwrap();
+        }
+
+        // A panicking callback poisons the tree, though it is still valid.
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            tree.update(5, |_| panic!("update")).unwrap();
+        }));
+        assert!(result.is_err() && tree.is_poisoned());
+        assert!(tree.find(5) == Err(PoisonedError));
+        assert!(tree.insert(Element { key: 0, value: 0 }) == Err(PoisonedError));
+        assert!(tree.check().is_ok());
+        assert!(tree.size() == Ok(10));
+
+        // So does a panicking closure run through mutate(), until the tree is cleared.
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            tree.mutate(|tree| {
+                tree.for_each_in_range_mut(3.., |key, value| {
+                    *value += 1;
+                    if key == 7 {
+                        panic!("for_each_in_range_mut");
+                    }
+                    ControlFlow::Continue(())
+                })
+            })
+        }));
+        assert!(result.is_err() && tree.is_poisoned());
+        assert!(tree.remove(0) == Err(PoisonedError));
+        assert!(tree.mutate(|tree| tree.size()) == Err(PoisonedError));
+        tree.clear();
+        assert!(tree.insert(Element { key: 1, value: 1 }).is_ok());
+        assert!(tree.size() == Ok(1));
+    }
+}
@@ -0,0 +1,90 @@
+// A double-ended priority queue built on the 2-3 Tree.
+//
+// Both the minimum and the maximum can be peeked and popped in O(log n), as an alternative to
+// an interval heap. Elements with equal priorities are all kept.
+
+use crate::two_three_tree::{Element, TwoThreeTree};
+
+// A min-max priority queue of values ordered by a usize priority.
+pub struct TwoThreePriorityQueue {
+    tree: TwoThreeTree,
+}
+
+impl TwoThreePriorityQueue {
+    pub fn new() -> TwoThreePriorityQueue {
+        TwoThreePriorityQueue {
+            tree: TwoThreeTree::new(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    pub fn size(&self) -> usize {
+        self.tree.size()
+    }
+
+    // Adds a value with the given priority.
+    pub fn push(&mut self, priority: usize, value: usize) {
+        self.tree.insert(Element {
+            key: priority,
+            value,
+        });
+    }
+
+    // Returns the element with the lowest priority.
+    pub fn peek_min(&self) -> Option<Element> {
+        self.tree.first()
+    }
+
+    // Returns the element with the highest priority.
+    pub fn peek_max(&self) -> Option<Element> {
+        self.tree.last()
+    }
+
+    // Removes and returns the element with the lowest priority.
+    pub fn pop_min(&mut self) -> Option<Element> {
+        self.tree.delete_min()
+    }
+
+    // Removes and returns the element with the highest priority.
+    pub fn pop_max(&mut self) -> Option<Element> {
+        self.tree.delete_max()
+    }
+}
+
+impl Default for TwoThreePriorityQueue {
+    fn default() -> TwoThreePriorityQueue {
+        TwoThreePriorityQueue::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TwoThreePriorityQueue;
+
+    #[test]
+    fn test_min_max() {
+        let mut queue = TwoThreePriorityQueue::new();
+        assert!(queue.peek_min().is_none() && queue.pop_max().is_none());
+        for i in 0..100 {
+            queue.push((i * 7) % 50, i);
+        }
+        assert!(queue.size() == 100);
+        let mut low = Vec::new();
+        let mut high = Vec::new();
+        while !queue.is_empty() {
+            let min = queue.peek_min().unwrap();
+            assert!(queue.pop_min().unwrap().key == min.key);
+            low.push(min.key);
+            if let Some(max) = queue.peek_max() {
+                assert!(queue.pop_max().unwrap().key == max.key);
+                high.push(max.key);
+            }
+        }
+        assert!(low.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert!(high.windows(2).all(|pair| pair[0] >= pair[1]));
+        assert!(low.len() == 50 && high.len() == 50);
+    }
+}
@@ -0,0 +1,141 @@
+// Python bindings with PyO3.
+//
+// The tree is exposed as a Python class with dict-like methods. As in a dict, setting a key
+// replaces its value, so each key has at most one value. Iteration is in key order. Build the
+// extension module with `cargo rustc --release --features python --crate-type cdylib`, or maturin.
+
+use pyo3::exceptions::PyKeyError;
+use pyo3::prelude::*;
+
+use crate::two_three_tree::TwoThreeTree;
+
+#[pyclass(name = "TwoThreeTree")]
+pub struct PyTwoThreeTree {
+    tree: TwoThreeTree,
+}
+
+// Iterator over a copy of the keys of a tree.
+#[pyclass]
+pub struct PyKeyIterator {
+    keys: std::vec::IntoIter<usize>,
+}
+
+#[pymethods]
+impl PyTwoThreeTree {
+    #[new]
+    fn new() -> PyTwoThreeTree {
+        PyTwoThreeTree {
+            tree: TwoThreeTree::new(),
+        }
+    }
+
+    fn __len__(&self) -> usize {
+        self.tree.size()
+    }
+
+    fn __contains__(&self, key: usize) -> bool {
+        self.tree.contains_key(key)
+    }
+
+    fn __getitem__(&self, key: usize) -> PyResult<usize> {
+        self.tree
+            .find(key)
+            .map(|e| e.value)
+            .ok_or_else(|| PyKeyError::new_err(key))
+    }
+
+    fn __setitem__(&mut self, key: usize, value: usize) {
+        self.tree.upsert(key, value, |v| *v = value);
+    }
+
+    fn __delitem__(&mut self, key: usize) -> PyResult<()> {
+        match self.tree.delete(key) {
+            true => Ok(()),
+            false => Err(PyKeyError::new_err(key)),
+        }
+    }
+
+    fn __iter__(&self) -> PyKeyIterator {
+        PyKeyIterator {
+            keys: self.keys().into_iter(),
+        }
+    }
+
+    #[pyo3(signature = (key, default=None))]
+    fn get(&self, key: usize, default: Option<usize>) -> Option<usize> {
+        self.tree.find(key).map(|e| e.value).or(default)
+    }
+
+    fn keys(&self) -> Vec<usize> {
+        self.tree.iter().map(|e| e.key).collect()
+    }
+
+    fn values(&self) -> Vec<usize> {
+        self.tree.iter().map(|e| e.value).collect()
+    }
+
+    fn items(&self) -> Vec<(usize, usize)> {
+        self.tree.iter().map(|e| (e.key, e.value)).collect()
+    }
+
+    // Returns the (key, value) pairs with keys in [start, end), in key order.
+    fn range(&self, start: usize, end: usize) -> Vec<(usize, usize)> {
+        self.tree
+            .iter_from(start)
+            .take_while(|e| e.key < end)
+            .map(|e| (e.key, e.value))
+            .collect()
+    }
+
+    // Returns the (key, value) pair with the smallest key, or None.
+    fn first(&self) -> Option<(usize, usize)> {
+        self.tree.first().map(|e| (e.key, e.value))
+    }
+
+    // Returns the (key, value) pair with the largest key, or None.
+    fn last(&self) -> Option<(usize, usize)> {
+        self.tree.last().map(|e| (e.key, e.value))
+    }
+
+    fn clear(&mut self) {
+        self.tree = TwoThreeTree::new();
+    }
+}
+
+#[pymethods]
+impl PyKeyIterator {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(&mut self) -> Option<usize> {
+        self.keys.next()
+    }
+}
+
+// The Python module, importable as two_three_tree.
+#[pymodule]
+fn two_three_tree(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<PyTwoThreeTree>()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PyTwoThreeTree;
+
+    #[test]
+    fn test_python_tree() {
+        let mut tree = PyTwoThreeTree::new();
+        for key in (0..10).rev() {
+            tree.__setitem__(key, key * 2);
+        }
+        tree.__setitem__(3, 100);
+        assert!(tree.__len__() == 10 && tree.__contains__(3));
+        assert!(tree.__getitem__(3).unwrap() == 100);
+        assert!(tree.__delitem__(3).is_ok() && tree.__delitem__(3).is_err());
+        assert!(tree.get(3, Some(7)) == Some(7));
+        assert!(tree.keys() == [0, 1, 2, 4, 5, 6, 7, 8, 9]);
+        assert!(tree.range(4, 6) == [(4, 8), (5, 10)]);
+        assert!(tree.__iter__().keys.eq(tree.keys()));
+    }
+}
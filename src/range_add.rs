@@ -0,0 +1,146 @@
+// A 2-3 Tree supporting range_add(), which adds a delta to all the values in a key range in
+// O(log n).
+//
+// The deltas are kept apart as a difference array: +delta at the start of the range and -delta
+// just past its end, in a tree augmented with sums. The value of an element is its stored base
+// plus the prefix sum of the deltas up to its key, resolved on access. All the arithmetic wraps
+// modulo 2^64, so negative deltas and bases need no special care.
+
+use std::ops::RangeBounds;
+
+use crate::aggregate::Sum;
+use crate::two_three_tree::{inclusive_range, Element, TwoThreeTree};
+
+pub struct RangeAddTwoThreeTree {
+    // The elements with their base values.
+    tree: TwoThreeTree,
+
+    // The deltas keyed by the position where they start to apply.
+    deltas: TwoThreeTree<Sum>,
+}
+
+impl RangeAddTwoThreeTree {
+    pub fn new() -> RangeAddTwoThreeTree {
+        RangeAddTwoThreeTree {
+            tree: TwoThreeTree::new(),
+            deltas: TwoThreeTree::with_augment(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    pub fn size(&self) -> usize {
+        self.tree.size()
+    }
+
+    // Adds delta to the values of the elements in the key range, in O(log n).
+    // Elements inserted later are not affected.
+    pub fn range_add(&mut self, range: impl RangeBounds<usize>, delta: isize) {
+        let Some((start, end)) = inclusive_range(&range) else {
+            return;
+        };
+        self.deltas.insert(Element {
+            key: start,
+            value: delta as usize,
+        });
+        if end < usize::MAX {
+            self.deltas.insert(Element {
+                key: end + 1,
+                value: (delta as usize).wrapping_neg(),
+            });
+        }
+    }
+
+    pub fn insert(&mut self, element: Element) {
+        self.tree.insert(Element {
+            key: element.key,
+            value: element.value.wrapping_sub(self.pending(element.key)),
+        });
+    }
+
+    // Returns an element with the given key, with its value resolved.
+    pub fn find(&self, key: usize) -> Option<Element> {
+        self.tree.find(key).map(|e| self.resolve(e))
+    }
+
+    pub fn remove(&mut self, key: usize) -> Option<Element> {
+        let element = self.tree.remove(key)?;
+        Some(self.resolve(element))
+    }
+
+    // Returns the elements in key order with their values resolved, in O(n + m) for m deltas.
+    pub fn iter(&self) -> impl Iterator<Item = Element> + '_ {
+        let mut deltas = self.deltas.iter().peekable();
+        let mut pending: usize = 0;
+        self.tree.iter().map(move |e| {
+            while let Some(delta) = deltas.next_if(|delta| delta.key <= e.key) {
+                pending = pending.wrapping_add(delta.value);
+            }
+            Element {
+                key: e.key,
+                value: e.value.wrapping_add(pending),
+            }
+        })
+    }
+
+    // Writes the resolved values to the elements and drops the deltas, in O(n + m).
+    pub fn flatten(&mut self) {
+        let elements: Vec<Element> = self.iter().collect();
+        self.tree = TwoThreeTree::from_sorted(elements);
+        self.deltas = TwoThreeTree::with_augment();
+    }
+
+    // Returns the sum of the deltas applying to the key.
+    fn pending(&self, key: usize) -> usize {
+        self.deltas.query_range(..=key) as usize
+    }
+
+    fn resolve(&self, element: Element) -> Element {
+        Element {
+            key: element.key,
+            value: element.value.wrapping_add(self.pending(element.key)),
+        }
+    }
+}
+
+impl Default for RangeAddTwoThreeTree {
+    fn default() -> RangeAddTwoThreeTree {
+        RangeAddTwoThreeTree::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RangeAddTwoThreeTree;
+    use crate::two_three_tree::Element;
+
+    #[test]
+    fn test_range_add() {
+        let mut tree = RangeAddTwoThreeTree::new();
+        let mut expected = vec![100; 100];
+        for key in 0..100 {
+            tree.insert(Element { key, value: 100 });
+        }
+        for (start, end, delta) in [(10, 50, 5), (40, 99, -20), (0, 10, 1), (45, 45, 1000)] {
+            tree.range_add(start..=end, delta);
+            for value in expected[start..=end].iter_mut() {
+                *value = (*value as isize + delta) as usize;
+            }
+        }
+        assert!(tree.find(45).unwrap().value == 1085);
+        assert!(tree.iter().map(|e| e.value).eq(expected.iter().copied()));
+
+        // A later insertion starts from its own value.
+        tree.remove(45);
+        tree.insert(Element { key: 45, value: 7 });
+        assert!(tree.find(45).unwrap().value == 7);
+        expected[45] = 7;
+
+        tree.flatten();
+        assert!(tree.iter().map(|e| e.value).eq(expected.iter().copied()));
+        tree.range_add(.., 1);
+        assert!(tree.find(99).unwrap().value == expected[99] + 1);
+    }
+}
@@ -0,0 +1,124 @@
+// The red-black form of a 2-3 Tree, for teaching the correspondence between the two.
+//
+// A 2-node becomes a black node. A 3-node becomes a black node with a red left child holding the
+// smaller element, as in a left-leaning red-black tree. Red links are thus the glue inside
+// 3-nodes, and every path from the root crosses as many black nodes as the 2-3 tree has levels.
+//
+// TwoThreeTree::to_red_black() exports a tree, and TwoThreeTree::from_red_black() imports one.
+
+use crate::two_three_tree::Element;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Color {
+    Red,
+    Black,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct RedBlackNode {
+    pub element: Element,
+    pub color: Color,
+    pub left: Option<Box<RedBlackNode>>,
+    pub right: Option<Box<RedBlackNode>>,
+}
+
+impl RedBlackNode {
+    pub fn is_red(&self) -> bool {
+        self.color == Color::Red
+    }
+
+    // Returns the number of black nodes on every path down to a missing child, or None if the
+    // paths differ.
+    pub fn black_height(&self) -> Option<usize> {
+        let height = |child: &Option<Box<RedBlackNode>>| match child {
+            Some(child) => child.black_height(),
+            None => Some(0),
+        };
+        let left = height(&self.left)?;
+        (left == height(&self.right)?).then_some(left + usize::from(!self.is_red()))
+    }
+
+    // Returns the elements of the subtree in order.
+    pub fn elements(&self) -> Vec<Element> {
+        let mut elements = Vec::new();
+        self.collect(&mut elements);
+        elements
+    }
+
+    fn collect(&self, elements: &mut Vec<Element>) {
+        if let Some(left) = &self.left {
+            left.collect(elements);
+        }
+        elements.push(self.element);
+        if let Some(right) = &self.right {
+            right.collect(elements);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Color, RedBlackNode};
+    use crate::two_three_tree::{Element, TwoThreeTree};
+
+    // Checks that no red node has a red child, and that red links lean left.
+    fn check_red_links(node: &RedBlackNode) {
+        for child in [&node.left, &node.right].into_iter().flatten() {
+            assert!(!(node.is_red() && child.is_red()));
+            check_red_links(child);
+        }
+        assert!(!node.right.as_ref().is_some_and(|right| right.is_red()));
+    }
+
+    #[test]
+    fn test_red_black() {
+        assert!(TwoThreeTree::new().to_red_black().is_none());
+
+        let mut tree = TwoThreeTree::new();
+        for i in 0..100 {
+            tree.insert(Element {
+                key: (i * 37) % 100,
+                value: i,
+            });
+        }
+        let root = tree.to_red_black().unwrap();
+        assert!(root.color == Color::Black);
+        check_red_links(&root);
+        assert!(root.black_height().is_some());
+        assert!(root.elements() == tree.iter().collect::<Vec<_>>());
+
+        let imported = TwoThreeTree::from_red_black(&root);
+        assert!(imported.to_pretty_string() == tree.to_pretty_string());
+
+        // A right-leaning red link is a 3-node too.
+        let leaf = |key, color| RedBlackNode {
+            element: Element { key, value: key },
+            color,
+            left: None,
+            right: None,
+        };
+        let mut root = leaf(1, Color::Black);
+        root.right = Some(Box::new(leaf(2, Color::Red)));
+        let tree = TwoThreeTree::from_red_black(&root);
+        assert!(tree.to_pretty_string() == "Tree(2):\n[1:1 | 2:2]\n");
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_red_black_four_node() {
+        let leaf = |key, color| {
+            Some(Box::new(RedBlackNode {
+                element: Element { key, value: key },
+                color,
+                left: None,
+                right: None,
+            }))
+        };
+        TwoThreeTree::from_red_black(&RedBlackNode {
+            element: Element { key: 2, value: 2 },
+            color: Color::Black,
+            left: leaf(1, Color::Red),
+            right: leaf(3, Color::Red),
+        });
+    }
+}
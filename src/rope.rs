@@ -0,0 +1,287 @@
+// A rope: text stored as a Sequence of string chunks, for editors and other large texts.
+//
+// The chunks are measured in bytes and chars, so a byte or char position is found by
+// Sequence::find_by() in O(log n), and edits touch only the chunks at the edited positions.
+// Positions are byte offsets, as for str, and must lie on char boundaries.
+
+use std::fmt;
+use std::ops::Range;
+
+use crate::sequence::{Measure, Sequence};
+
+// The largest chunk created by splitting inserted text, or grown by an insertion.
+const CHUNK_BYTES: usize = 64;
+
+// The length of a text in bytes and in chars.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TextSize {
+    pub bytes: usize,
+    pub chars: usize,
+}
+
+// Measures string chunks by TextSize.
+pub struct TextMeasure;
+
+impl Measure<String> for TextMeasure {
+    type Value = TextSize;
+
+    fn identity() -> TextSize {
+        TextSize::default()
+    }
+
+    fn measure(chunk: &String) -> TextSize {
+        TextSize {
+            bytes: chunk.len(),
+            chars: chunk.chars().count(),
+        }
+    }
+
+    fn combine(left: &TextSize, right: &TextSize) -> TextSize {
+        TextSize {
+            bytes: left.bytes + right.bytes,
+            chars: left.chars + right.chars,
+        }
+    }
+}
+
+pub struct Rope {
+    // Non-empty chunks.
+    chunks: Sequence<String, TextMeasure>,
+}
+
+// Splits a text into chunks of at most CHUNK_BYTES, on char boundaries.
+fn chunks(text: &str) -> impl Iterator<Item = String> + '_ {
+    let mut rest = text;
+    std::iter::from_fn(move || {
+        if rest.is_empty() {
+            return None;
+        }
+        let mut end = rest.len().min(CHUNK_BYTES);
+        while !rest.is_char_boundary(end) {
+            end -= 1;
+        }
+        let (chunk, tail) = rest.split_at(end);
+        rest = tail;
+        Some(chunk.to_string())
+    })
+}
+
+impl Rope {
+    pub fn new() -> Rope {
+        Rope {
+            chunks: Sequence::with_measure(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chunks.is_empty()
+    }
+
+    pub fn len_bytes(&self) -> usize {
+        self.chunks.measure().bytes
+    }
+
+    pub fn len_chars(&self) -> usize {
+        self.chunks.measure().chars
+    }
+
+    // Returns the index of the chunk holding the byte position and the offset in it, or the
+    // number of chunks and 0 at the end of the text. Panics past the end.
+    fn locate(&self, pos: usize) -> (usize, usize) {
+        match self.chunks.find_by(|size| size.bytes > pos) {
+            Some((i, _, before)) => (i, pos - before.bytes),
+            None => {
+                assert!(pos == self.len_bytes());
+                (self.chunks.size(), 0)
+            }
+        }
+    }
+
+    // Splits the chunk holding the byte position so that a chunk starts there, and returns
+    // its index.
+    fn boundary(&mut self, pos: usize) -> usize {
+        let (i, offset) = self.locate(pos);
+        if offset == 0 {
+            return i;
+        }
+        let mut chunk = self.chunks.remove_at(i).unwrap();
+        let tail = chunk.split_off(offset);
+        self.chunks.insert_at(i, chunk);
+        self.chunks.insert_at(i + 1, tail);
+        i + 1
+    }
+
+    // Merges the chunks before and at the index if they fit in one chunk.
+    fn merge(&mut self, i: usize) {
+        if i == 0 || i >= self.chunks.size() {
+            return;
+        }
+        let length = |i| self.chunks.get(i).map_or(0, String::len);
+        if length(i - 1) + length(i) <= CHUNK_BYTES {
+            let right = self.chunks.remove_at(i).unwrap();
+            let mut left = self.chunks.remove_at(i - 1).unwrap();
+            left.push_str(&right);
+            self.chunks.insert_at(i - 1, left);
+        }
+    }
+
+    // Inserts text at the byte position.
+    pub fn insert_str(&mut self, pos: usize, text: &str) {
+        if text.is_empty() {
+            return;
+        }
+        // Grow the chunk ending or holding the position, if it stays small.
+        let (i, offset) = self.locate(pos);
+        let (i, offset) = match (i, offset) {
+            (0, 0) => (0, 0),
+            (i, 0) => (i - 1, self.chunks.get(i - 1).unwrap().len()),
+            at => at,
+        };
+        if let Some(chunk) = self.chunks.get(i) {
+            if chunk.len() + text.len() <= CHUNK_BYTES {
+                let mut chunk = self.chunks.remove_at(i).unwrap();
+                chunk.insert_str(offset, text);
+                self.chunks.insert_at(i, chunk);
+                return;
+            }
+        }
+        let i = self.boundary(pos);
+        for (k, chunk) in chunks(text).enumerate() {
+            self.chunks.insert_at(i + k, chunk);
+        }
+    }
+
+    // Removes the text in the byte range.
+    pub fn delete(&mut self, range: Range<usize>) {
+        assert!(range.start <= range.end);
+        let start = self.boundary(range.start);
+        let end = self.boundary(range.end);
+        let tail = self.chunks.split_off(end);
+        self.chunks.split_off(start);
+        self.chunks.append(tail);
+        self.merge(start);
+    }
+
+    // Returns the text in the byte range.
+    pub fn slice(&self, range: Range<usize>) -> String {
+        assert!(range.start <= range.end && range.end <= self.len_bytes());
+        let mut text = String::with_capacity(range.end - range.start);
+        let (i, mut offset) = self.locate(range.start);
+        for chunk in self.chunks.iter_from(i) {
+            let rest = range.end - range.start - text.len();
+            if rest == 0 {
+                break;
+            }
+            let chunk = &chunk[offset..];
+            text.push_str(&chunk[..rest.min(chunk.len())]);
+            offset = 0;
+        }
+        text
+    }
+
+    // Returns the char position of a byte position.
+    pub fn byte_to_char(&self, pos: usize) -> usize {
+        match self.chunks.find_by(|size| size.bytes > pos) {
+            Some((_, chunk, before)) => before.chars + chunk[..pos - before.bytes].chars().count(),
+            None => {
+                assert!(pos == self.len_bytes());
+                self.len_chars()
+            }
+        }
+    }
+
+    // Returns the byte position of a char position.
+    pub fn char_to_byte(&self, pos: usize) -> usize {
+        match self.chunks.find_by(|size| size.chars > pos) {
+            Some((_, chunk, before)) => {
+                let (offset, _) = chunk.char_indices().nth(pos - before.chars).unwrap();
+                before.bytes + offset
+            }
+            None => {
+                assert!(pos == self.len_chars());
+                self.len_bytes()
+            }
+        }
+    }
+}
+
+impl Default for Rope {
+    fn default() -> Rope {
+        Rope::new()
+    }
+}
+
+impl From<&str> for Rope {
+    fn from(text: &str) -> Rope {
+        Rope {
+            chunks: chunks(text).collect(),
+        }
+    }
+}
+
+impl fmt::Display for Rope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for chunk in self.chunks.iter() {
+            f.write_str(chunk)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Rope;
+
+    #[test]
+    fn test_rope() {
+        let mut text = "héllo wörld, ".repeat(40);
+        let mut rope = Rope::from(text.as_str());
+        assert!(rope.len_bytes() == text.len() && rope.len_chars() == text.chars().count());
+
+        for i in 0..200 {
+            let pos = text
+                .char_indices()
+                .nth((i * 7919) % text.chars().count())
+                .unwrap()
+                .0;
+            if i % 3 == 0 {
+                let end = text[pos..]
+                    .char_indices()
+                    .nth(5)
+                    .map_or(text.len(), |(o, _)| pos + o);
+                rope.delete(pos..end);
+                text.replace_range(pos..end, "");
+            } else {
+                let insert = if i % 2 == 0 {
+                    "ß"
+                } else {
+                    "a long insertion, longer than any chunk ever gets to be"
+                };
+                rope.insert_str(pos, insert);
+                text.insert_str(pos, insert);
+            }
+        }
+        rope.chunks.validate();
+        assert!(rope.to_string() == text);
+        assert!(rope.chunks.iter().all(|chunk| !chunk.is_empty()));
+
+        for pos in (0..text.len())
+            .filter(|&pos| text.is_char_boundary(pos))
+            .step_by(37)
+        {
+            let chars = text[..pos].chars().count();
+            assert!(rope.byte_to_char(pos) == chars && rope.char_to_byte(chars) == pos);
+            let end = (pos + 100).min(text.len());
+            if text.is_char_boundary(end) {
+                assert!(rope.slice(pos..end) == text[pos..end]);
+            }
+        }
+        assert!(rope.char_to_byte(rope.len_chars()) == text.len());
+
+        rope.delete(0..rope.len_bytes());
+        assert!(rope.is_empty() && rope.to_string().is_empty());
+        rope.insert_str(0, "ab");
+        rope.insert_str(1, "é");
+        assert!(rope.to_string() == "aéb" && rope.slice(1..3) == "é");
+    }
+}
@@ -0,0 +1,201 @@
+// Paginated scans over a 2-3 Tree.
+//
+// Each page comes with an opaque continuation token. Passing the token back returns the next
+// page, which starts after the last element of the previous one even if the tree was mutated
+// in between, including if that element was deleted. For this the elements with the same key
+// are listed by value, so a scan visits the elements in (key, value) order, and the token holds
+// the key and value of the last element.
+
+use crate::two_three_tree::{Augment, Element, TwoThreeTree};
+
+// Where the next page of a scan starts.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct ScanToken {
+    // The key and value of the last returned element.
+    key: usize,
+    value: usize,
+
+    // The number of returned elements equal to it, as elements may repeat.
+    count: usize,
+}
+
+// A page of a scan.
+pub struct ScanPage {
+    // The elements of the page, in key order.
+    pub elements: Vec<Element>,
+
+    // The token for the next page, or None if this is the last page.
+    pub next: Option<ScanToken>,
+}
+
+impl ScanToken {
+    // Encodes the token as a string, e.g. for use in a URL.
+    pub fn encode(&self) -> String {
+        format!("{:x}-{:x}-{:x}", self.key, self.value, self.count)
+    }
+
+    // Decodes a token produced by encode(). Returns None if the string is not a valid token.
+    pub fn decode(token: &str) -> Option<ScanToken> {
+        let (key, rest) = token.split_once('-')?;
+        let (value, count) = rest.split_once('-')?;
+        Some(ScanToken {
+            key: usize::from_str_radix(key, 16).ok()?,
+            value: usize::from_str_radix(value, 16).ok()?,
+            count: usize::from_str_radix(count, 16).ok()?,
+        })
+    }
+}
+
+impl<A: Augment> TwoThreeTree<A> {
+    // Returns up to limit elements, starting after the given token or at the first element.
+    // The elements with the same key are read in full, to order them by value.
+    pub fn scan(&self, start_after: Option<ScanToken>, limit: usize) -> ScanPage {
+        let mut iter = match start_after {
+            Some(token) => self.iter_from(token.key),
+            None => self.iter(),
+        }
+        .peekable();
+
+        // Read one element more than the page, to know whether a token is needed.
+        let mut elements = Vec::new();
+        let mut skipped = 0;
+        while elements.len() <= limit {
+            let Some(first) = iter.next() else {
+                break;
+            };
+            let mut group = vec![first];
+            while let Some(element) = iter.next_if(|e| e.key == first.key) {
+                group.push(element);
+            }
+            group.sort_by_key(|e| e.value);
+
+            // Skip the elements up to the token. Some of them may have been deleted since, so
+            // only the elements equal to the token element are counted.
+            let mut start = 0;
+            if let Some(token) = start_after.filter(|token| token.key == first.key) {
+                start = group.partition_point(|e| e.value < token.value);
+                skipped = group[start..]
+                    .iter()
+                    .take(token.count)
+                    .take_while(|e| e.value == token.value)
+                    .count();
+                start += skipped;
+            }
+            elements.extend_from_slice(&group[start..]);
+        }
+        let more = elements.len() > limit;
+        elements.truncate(limit);
+
+        // Only hand out a token if there is something left.
+        let next = match elements.last() {
+            Some(&last) if more => {
+                let mut count = elements.iter().rev().take_while(|&&e| e == last).count();
+                if start_after
+                    .is_some_and(|token| (token.key, token.value) == (last.key, last.value))
+                    && count == elements.len()
+                {
+                    count += skipped;
+                }
+                Some(ScanToken {
+                    key: last.key,
+                    value: last.value,
+                    count,
+                })
+            }
+            _ => None,
+        };
+        ScanPage { elements, next }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ScanToken;
+    use crate::two_three_tree::{Element, TwoThreeTree};
+
+    #[test]
+    fn test_scan() {
+        let mut tree = TwoThreeTree::new();
+        for i in 0..100 {
+            tree.insert(Element {
+                key: i / 4,
+                value: i,
+            });
+        }
+
+        let mut keys = Vec::new();
+        let mut token = None;
+        loop {
+            let page = tree.scan(token, 3);
+            keys.extend(page.elements.iter().map(|e| e.key));
+            match page.next {
+                Some(next) => token = ScanToken::decode(&next.encode()),
+                None => break,
+            }
+        }
+        assert!(keys.into_iter().eq((0..100).map(|i| i / 4)));
+
+        // Mutations between pages do not repeat or drop the remaining elements.
+        let page = tree.scan(None, 10);
+        assert!(page.elements.last().unwrap().key == 2);
+        tree.delete(0);
+        tree.insert(Element { key: 1, value: 0 });
+        let page = tree.scan(page.next, 100);
+        assert!(page.elements.len() == 90);
+        assert!(page.elements[0].key == 2);
+        assert!(page.next.is_none());
+        assert!(ScanToken::decode("x").is_none());
+    }
+
+    #[test]
+    fn test_scan_deleted_token_key() {
+        let mut tree = TwoThreeTree::new();
+        for key in 0..10 {
+            for value in 0..3 {
+                tree.insert(Element { key, value });
+            }
+        }
+
+        // The page ends with two of the three elements with key 3.
+        let page = tree.scan(None, 11);
+        assert!(page.elements.last().unwrap().key == 3);
+        for _ in 0..3 {
+            tree.delete(3);
+        }
+        let page = tree.scan(page.next, 100);
+        assert!(page.elements.len() == 18 && page.elements[0].key == 4);
+
+        // The page ends with two of the three elements with key 5, which are deleted. The third
+        // one was never returned, so the next page starts with it.
+        let page = tree.scan(None, 14);
+        assert!(
+            page.elements[12..] == [Element { key: 5, value: 0 }, Element { key: 5, value: 1 }]
+        );
+        for &element in &page.elements[12..] {
+            assert!(tree.remove_element(element));
+        }
+        let page = tree.scan(page.next, 100);
+        assert!(page.elements.len() == 13 && page.elements[0] == Element { key: 5, value: 2 });
+    }
+
+    #[test]
+    fn test_scan_repeated_elements() {
+        let mut tree = TwoThreeTree::new();
+        for value in [2, 1, 2, 0, 2] {
+            tree.insert(Element { key: 7, value });
+        }
+
+        // Pages list the elements by value, and split the repeated ones.
+        let mut values = Vec::new();
+        let mut token = None;
+        loop {
+            let page = tree.scan(token, 2);
+            values.extend(page.elements.iter().map(|e| e.value));
+            match page.next {
+                Some(next) => token = Some(next),
+                None => break,
+            }
+        }
+        assert!(values == [0, 1, 2, 2, 2]);
+    }
+}
@@ -0,0 +1,102 @@
+// A 2-3 Tree with values stored out of line, for large values.
+//
+// The nodes hold only the key and a compact handle, the index of the value in a side arena, so
+// they stay small however big the values are and lookups touch less memory. The arena reuses
+// the slots of removed values.
+
+use std::ops::RangeBounds;
+
+use crate::arena::Arena;
+use crate::two_three_tree::{Element, TwoThreeTree};
+
+pub struct SeparatedTwoThreeTree<V> {
+    // Elements keyed by key, with the arena index of the value as value.
+    tree: TwoThreeTree,
+
+    arena: Arena<V>,
+}
+
+impl<V> SeparatedTwoThreeTree<V> {
+    pub fn new() -> SeparatedTwoThreeTree<V> {
+        SeparatedTwoThreeTree {
+            tree: TwoThreeTree::new(),
+            arena: Arena::new(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    pub fn size(&self) -> usize {
+        self.tree.size()
+    }
+
+    // Inserts a key and its value, after the equal keys.
+    pub fn insert(&mut self, key: usize, value: V) {
+        let index = self.arena.insert(value);
+        self.tree.insert(Element { key, value: index });
+    }
+
+    // Returns the value of an element with the given key.
+    pub fn get(&self, key: usize) -> Option<&V> {
+        self.arena.get(self.tree.find(key)?.value)
+    }
+
+    pub fn get_mut(&mut self, key: usize) -> Option<&mut V> {
+        self.arena.get_mut(self.tree.find(key)?.value)
+    }
+
+    // Removes an element with the given key, and returns its value.
+    pub fn remove(&mut self, key: usize) -> Option<V> {
+        self.arena.remove(self.tree.remove(key)?.value)
+    }
+
+    // Returns the keys and values in key order.
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &V)> + '_ {
+        self.range(..)
+    }
+
+    // Returns the keys in the range and their values, in key order.
+    pub fn range(&self, range: impl RangeBounds<usize>) -> impl Iterator<Item = (usize, &V)> + '_ {
+        self.tree
+            .range(range)
+            .map(|e| (e.key, self.arena.get(e.value).unwrap()))
+    }
+}
+
+impl<V> Default for SeparatedTwoThreeTree<V> {
+    fn default() -> SeparatedTwoThreeTree<V> {
+        SeparatedTwoThreeTree::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SeparatedTwoThreeTree;
+
+    #[test]
+    fn test_separated_values() {
+        let mut tree = SeparatedTwoThreeTree::new();
+        for key in 0..100 {
+            tree.insert(key, vec![key as u8; 4096]);
+        }
+        assert!(tree.get(42).unwrap()[0] == 42);
+        tree.get_mut(42).unwrap()[0] = 0;
+        assert!(tree.get(42).unwrap()[..2] == [0, 42]);
+
+        for key in (0..100).step_by(2) {
+            assert!(tree.remove(key).unwrap().len() == 4096);
+        }
+        assert!(tree.remove(0).is_none() && tree.size() == 50);
+
+        // The freed slots are reused.
+        tree.insert(1000, vec![1]);
+        assert!(tree.arena.slots() == 100);
+        assert!(tree
+            .range(10..20)
+            .map(|(key, _)| key)
+            .eq((11..20).step_by(2)));
+        assert!(tree.iter().last().unwrap().1 == &vec![1]);
+    }
+}
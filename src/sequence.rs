@@ -0,0 +1,443 @@
+// A sequence with implicit positions, backed by a 2-3 Tree.
+//
+// The items are the leaves, all at the same depth, and each internal node has 2 or 3 children
+// and counts the leaves below it. A position is found by descending by those counts, so there
+// are no keys to shift: insert_at() and remove_at() split the tree at the position and join the
+// parts again, each in O(log n). Joining two trees hangs the shorter one on the facing spine of
+// the taller one, splitting the overfull nodes on the way back up as an insertion does.
+//
+// A Measure adds other sizes of the items to the nodes, which find_by() descends by, e.g. the
+// byte length of the chunks of a Rope.
+
+// A size of the items other than their count, summed in every internal node.
+// combine() must be associative with identity() as its identity element, as for Augment.
+pub trait Measure<T> {
+    type Value: Clone;
+
+    // The measure of no items.
+    fn identity() -> Self::Value;
+
+    // The measure of a single item.
+    fn measure(item: &T) -> Self::Value;
+
+    // Combines the measures of two adjacent runs of items.
+    fn combine(left: &Self::Value, right: &Self::Value) -> Self::Value;
+}
+
+// No measure. This is the default.
+impl<T> Measure<T> for () {
+    type Value = ();
+
+    fn identity() {}
+
+    fn measure(_item: &T) {}
+
+    fn combine(_left: &(), _right: &()) {}
+}
+
+enum Node<T, M: Measure<T>> {
+    Leaf(T),
+
+    // 2 or 3 children of equal height, the number of leaves below and their measure.
+    Internal(usize, M::Value, Vec<Node<T, M>>),
+}
+
+// A tree and its height, 0 for a single leaf.
+type Tree<T, M> = Option<(Node<T, M>, usize)>;
+
+impl<T, M: Measure<T>> Node<T, M> {
+    fn size(&self) -> usize {
+        match self {
+            Node::Leaf(_) => 1,
+            Node::Internal(size, _, _) => *size,
+        }
+    }
+
+    fn measure(&self) -> M::Value {
+        match self {
+            Node::Leaf(item) => M::measure(item),
+            Node::Internal(_, measure, _) => measure.clone(),
+        }
+    }
+
+    fn internal(children: Vec<Node<T, M>>) -> Node<T, M> {
+        let size = children.iter().map(Node::size).sum();
+        Node::Internal(size, Self::internal_measure(&children), children)
+    }
+
+    fn internal_measure(children: &[Node<T, M>]) -> M::Value {
+        children.iter().fold(M::identity(), |measure, child| {
+            M::combine(&measure, &child.measure())
+        })
+    }
+}
+
+// Hangs the tree b of height hb on the right spine of node at height h >= hb. Returns the new
+// node, or two nodes of height h if it overflowed.
+fn join_right<T, M: Measure<T>>(
+    node: Node<T, M>,
+    h: usize,
+    b: Node<T, M>,
+    hb: usize,
+) -> Result<Node<T, M>, [Node<T, M>; 2]> {
+    if h == hb {
+        return Err([node, b]);
+    }
+    let Node::Internal(_, _, mut children) = node else {
+        unreachable!()
+    };
+    let last = children.pop().unwrap();
+    match join_right(last, h - 1, b, hb) {
+        Ok(child) => children.push(child),
+        Err(split) => children.extend(split),
+    }
+    if children.len() <= 3 {
+        return Ok(Node::internal(children));
+    }
+    let right = children.split_off(2);
+    Err([Node::internal(children), Node::internal(right)])
+}
+
+// Hangs the tree a of height ha on the left spine of node at height h >= ha, as join_right().
+fn join_left<T, M: Measure<T>>(
+    a: Node<T, M>,
+    ha: usize,
+    node: Node<T, M>,
+    h: usize,
+) -> Result<Node<T, M>, [Node<T, M>; 2]> {
+    if h == ha {
+        return Err([a, node]);
+    }
+    let Node::Internal(_, _, mut children) = node else {
+        unreachable!()
+    };
+    let first = children.remove(0);
+    match join_left(a, ha, first, h - 1) {
+        Ok(child) => children.insert(0, child),
+        Err(split) => {
+            children.splice(0..0, split);
+        }
+    }
+    if children.len() <= 3 {
+        return Ok(Node::internal(children));
+    }
+    let right = children.split_off(2);
+    Err([Node::internal(children), Node::internal(right)])
+}
+
+// Concatenates two trees, in O(|ha - hb| + 1).
+fn join<T, M: Measure<T>>(a: Tree<T, M>, b: Tree<T, M>) -> Tree<T, M> {
+    let ((a, ha), (b, hb)) = match (a, b) {
+        (Some(a), Some(b)) => (a, b),
+        (a, None) => return a,
+        (None, b) => return b,
+    };
+    let height = ha.max(hb);
+    let joined = if ha >= hb {
+        join_right(a, ha, b, hb)
+    } else {
+        join_left(a, ha, b, hb)
+    };
+    match joined {
+        Ok(node) => Some((node, height)),
+        Err(split) => Some((Node::internal(split.into()), height + 1)),
+    }
+}
+
+// Splits a tree of height h into its first i leaves and the rest.
+fn split<T, M: Measure<T>>(node: Node<T, M>, h: usize, mut i: usize) -> (Tree<T, M>, Tree<T, M>) {
+    let children = match node {
+        Node::Internal(_, _, children) => children,
+        leaf if i == 0 => return (None, Some((leaf, 0))),
+        leaf => return (Some((leaf, 0)), None),
+    };
+    let (mut left, mut right) = (None, None);
+    for child in children {
+        let size = child.size();
+        if i >= size {
+            left = join(left, Some((child, h - 1)));
+            i -= size;
+        } else if i == 0 {
+            right = join(right, Some((child, h - 1)));
+        } else {
+            let (l, r) = split(child, h - 1, i);
+            left = join(left, l);
+            right = join(right, r);
+            i = 0;
+        }
+    }
+    (left, right)
+}
+
+pub struct Sequence<T, M: Measure<T> = ()> {
+    root: Tree<T, M>,
+}
+
+impl<T> Sequence<T> {
+    pub fn new() -> Sequence<T> {
+        Sequence::with_measure()
+    }
+
+    // Returns a mutable reference to the item at the position. Without a measure, there is
+    // nothing to update when the item changes.
+    pub fn get_mut(&mut self, i: usize) -> Option<&mut T> {
+        let mut node = &mut self.root.as_mut()?.0;
+        let mut i = i;
+        if i >= node.size() {
+            return None;
+        }
+        loop {
+            match node {
+                Node::Leaf(item) => return Some(item),
+                Node::Internal(_, _, children) => {
+                    let mut children = children.iter_mut();
+                    node = loop {
+                        let child = children.next().unwrap();
+                        if i < child.size() {
+                            break child;
+                        }
+                        i -= child.size();
+                    };
+                }
+            }
+        }
+    }
+}
+
+impl<T, M: Measure<T>> Sequence<T, M> {
+    pub fn with_measure() -> Sequence<T, M> {
+        Sequence { root: None }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+
+    pub fn size(&self) -> usize {
+        self.root.as_ref().map_or(0, |(root, _)| root.size())
+    }
+
+    // Returns the measure of all the items.
+    pub fn measure(&self) -> M::Value {
+        self.root
+            .as_ref()
+            .map_or_else(M::identity, |(root, _)| root.measure())
+    }
+
+    // Returns the item at the position.
+    pub fn get(&self, i: usize) -> Option<&T> {
+        let mut node = &self.root.as_ref()?.0;
+        let mut i = i;
+        if i >= node.size() {
+            return None;
+        }
+        loop {
+            match node {
+                Node::Leaf(item) => return Some(item),
+                Node::Internal(_, _, children) => {
+                    for child in children {
+                        if i < child.size() {
+                            node = child;
+                            break;
+                        }
+                        i -= child.size();
+                    }
+                }
+            }
+        }
+    }
+
+    // Returns the first item for which pred holds on the measure of the items up to and
+    // including it, with its position and the measure of the items before it, in O(log n).
+    // pred must be monotone: false for a prefix of the items and true for the rest.
+    pub fn find_by(&self, pred: impl Fn(&M::Value) -> bool) -> Option<(usize, &T, M::Value)> {
+        let mut node = &self.root.as_ref()?.0;
+        let mut position = 0;
+        let mut before = M::identity();
+        if !pred(&node.measure()) {
+            return None;
+        }
+        loop {
+            match node {
+                Node::Leaf(item) => return Some((position, item, before)),
+                Node::Internal(_, _, children) => {
+                    for child in children {
+                        let through = M::combine(&before, &child.measure());
+                        if pred(&through) {
+                            node = child;
+                            break;
+                        }
+                        position += child.size();
+                        before = through;
+                    }
+                }
+            }
+        }
+    }
+
+    // Inserts an item at the position, shifting the items after it. Panics if i > size().
+    pub fn insert_at(&mut self, i: usize, item: T) {
+        assert!(i <= self.size());
+        let (left, right) = self.split_root(i);
+        self.root = join(join(left, Some((Node::Leaf(item), 0))), right);
+    }
+
+    pub fn push(&mut self, item: T) {
+        self.insert_at(self.size(), item);
+    }
+
+    // Removes the item at the position, shifting the items after it.
+    pub fn remove_at(&mut self, i: usize) -> Option<T> {
+        if i >= self.size() {
+            return None;
+        }
+        let (left, right) = self.split_root(i);
+        let (right, height) = right.unwrap();
+        let (item, right) = split(right, height, 1);
+        self.root = join(left, right);
+        match item {
+            Some((Node::Leaf(item), _)) => Some(item),
+            _ => unreachable!(),
+        }
+    }
+
+    // Moves the items from position i on into a new sequence. Panics if i > size().
+    pub fn split_off(&mut self, i: usize) -> Sequence<T, M> {
+        assert!(i <= self.size());
+        let (left, right) = self.split_root(i);
+        self.root = left;
+        Sequence { root: right }
+    }
+
+    // Moves the items of the other sequence to the end of this one.
+    pub fn append(&mut self, other: Sequence<T, M>) {
+        self.root = join(self.root.take(), other.root);
+    }
+
+    // Returns the items in order.
+    pub fn iter(&self) -> impl Iterator<Item = &T> + '_ {
+        self.iter_from(0)
+    }
+
+    // Returns the items from the position on, in order. Setting up the iterator takes O(log n).
+    pub fn iter_from(&self, i: usize) -> impl Iterator<Item = &T> + '_ {
+        let mut stack: Vec<std::slice::Iter<'_, Node<T, M>>> = Vec::new();
+        let mut i = i;
+        if let Some((root, _)) = self.root.as_ref().filter(|(root, _)| i < root.size()) {
+            let mut node = root;
+            // Descend to the item, keeping the siblings after the path to continue with.
+            while let Node::Internal(_, _, children) = node {
+                let mut rest = children.iter();
+                node = loop {
+                    let child = rest.next().unwrap();
+                    if i < child.size() {
+                        break child;
+                    }
+                    i -= child.size();
+                };
+                stack.push(rest);
+            }
+            stack.push(std::slice::from_ref(node).iter());
+        }
+        std::iter::from_fn(move || loop {
+            match stack.last_mut()?.next() {
+                None => {
+                    stack.pop();
+                }
+                Some(Node::Leaf(item)) => return Some(item),
+                Some(Node::Internal(_, _, children)) => stack.push(children.iter()),
+            }
+        })
+    }
+
+    fn split_root(&mut self, i: usize) -> (Tree<T, M>, Tree<T, M>) {
+        match self.root.take() {
+            Some((root, height)) => split(root, height, i),
+            None => (None, None),
+        }
+    }
+
+    // Validates the structure of the tree.
+    pub fn validate(&self)
+    where
+        M::Value: PartialEq,
+    {
+        fn validate_node<T, M: Measure<T>>(node: &Node<T, M>, height: usize)
+        where
+            M::Value: PartialEq,
+        {
+            if let Node::Internal(size, measure, children) = node {
+                assert!(height > 0 && (2..=3).contains(&children.len()));
+                assert!(*size == children.iter().map(Node::size).sum::<usize>());
+                assert!(*measure == Node::internal_measure(children));
+                for child in children {
+                    validate_node(child, height - 1);
+                }
+            } else {
+                assert!(height == 0);
+            }
+        }
+        if let Some((root, height)) = &self.root {
+            validate_node(root, *height);
+        }
+    }
+}
+
+impl<T, M: Measure<T>> Default for Sequence<T, M> {
+    fn default() -> Sequence<T, M> {
+        Sequence::with_measure()
+    }
+}
+
+impl<T, M: Measure<T>> FromIterator<T> for Sequence<T, M> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Sequence<T, M> {
+        let mut sequence = Sequence::with_measure();
+        for item in iter {
+            sequence.push(item);
+        }
+        sequence
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Sequence;
+
+    #[test]
+    fn test_sequence() {
+        let mut sequence = Sequence::new();
+        let mut expected = Vec::new();
+        for i in 0..300 {
+            let position = (i * 7919) % (expected.len() + 1);
+            sequence.insert_at(position, i);
+            expected.insert(position, i);
+        }
+        sequence.validate();
+        assert!(sequence.iter().eq(expected.iter()) && sequence.size() == 300);
+        assert!(sequence.get(150) == Some(&expected[150]) && sequence.get(300).is_none());
+        *sequence.get_mut(10).unwrap() = 1000;
+        expected[10] = 1000;
+
+        for i in 0..100 {
+            let position = (i * 31) % expected.len();
+            assert!(sequence.remove_at(position) == Some(expected.remove(position)));
+        }
+        sequence.validate();
+        assert!(sequence.iter().eq(expected.iter()));
+
+        let mut tail = sequence.split_off(50);
+        let expected_tail = expected.split_off(50);
+        sequence.validate();
+        tail.validate();
+        assert!(tail.iter().eq(expected_tail.iter()) && sequence.iter().eq(expected.iter()));
+
+        let short: Sequence<usize> = (0..3).collect();
+        tail.append(short);
+        tail.append(Sequence::new());
+        let mut short: Sequence<usize> = (10..12).collect();
+        short.append(tail);
+        short.validate();
+        assert!(short.size() == 155 && short.get(154) == Some(&2) && short.get(1) == Some(&11));
+        assert!(sequence.split_off(0).size() == 50 && sequence.is_empty());
+    }
+}
@@ -0,0 +1,65 @@
+// A small DSL for constructing trees of an exact shape, so that tests can target specific
+// rebalancing cases instead of relying on insertion order to produce them:
+//
+//   let tree = TwoThreeTree::from_shape(node3(3, 6).children([node3(1, 2), leaf(4), leaf(7)]));
+//
+// The elements have their keys as values.
+
+// A node of the shape with its subtrees.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Shape {
+    pub(crate) keys: Vec<usize>,
+    pub(crate) children: Vec<Shape>,
+}
+
+// A 2-node with one key.
+pub fn node2(key: usize) -> Shape {
+    Shape {
+        keys: vec![key],
+        children: Vec::new(),
+    }
+}
+
+// A 3-node with two keys.
+pub fn node3(key1: usize, key2: usize) -> Shape {
+    Shape {
+        keys: vec![key1, key2],
+        children: Vec::new(),
+    }
+}
+
+// A leaf 2-node, the same as node2(key).
+pub fn leaf(key: usize) -> Shape {
+    node2(key)
+}
+
+impl Shape {
+    // Sets the subtrees of the node: 2 for a 2-node, 3 for a 3-node.
+    pub fn children(mut self, children: impl IntoIterator<Item = Shape>) -> Shape {
+        self.children = children.into_iter().collect();
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::two_three_tree::TwoThreeTree;
+
+    #[test]
+    fn test_from_shape() {
+        // A 3-node parent whose middle child can borrow from neither sibling.
+        let mut tree = TwoThreeTree::from_shape(node3(2, 4).children([leaf(1), leaf(3), leaf(5)]));
+        assert!(tree.size() == 5);
+        assert!(tree.delete(3));
+        tree.validate();
+        assert!(tree.to_pretty_string() == "Tree(4):\n(4:4)\n├── [1:1 | 2:2]\n└── (5:5)\n");
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_from_shape_invalid() {
+        // A 3-node needs three children.
+        TwoThreeTree::from_shape(node3(2, 4).children([leaf(1), leaf(3)]));
+    }
+}
@@ -0,0 +1,132 @@
+// A 2-3 Tree whose keys can all be shifted in O(1), for time-indexed schedules.
+//
+// The tree stores each key minus a global offset, so shift_keys() only changes the offset. A
+// key that cannot be stored with the current offset, e.g. a key below a positive offset, makes
+// the offset be applied to the stored keys first, in O(n).
+
+use crate::two_three_tree::{Element, TwoThreeTree};
+
+pub struct ShiftedTwoThreeTree {
+    // The elements with their keys minus the offset.
+    tree: TwoThreeTree,
+
+    offset: i128,
+}
+
+impl ShiftedTwoThreeTree {
+    pub fn new() -> ShiftedTwoThreeTree {
+        ShiftedTwoThreeTree {
+            tree: TwoThreeTree::new(),
+            offset: 0,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    pub fn size(&self) -> usize {
+        self.tree.size()
+    }
+
+    // Adds delta to every key, in O(1).
+    // Panics if a key would go below 0 or above usize::MAX.
+    pub fn shift_keys(&mut self, delta: isize) {
+        if let (Some(first), Some(last)) = (self.first(), self.last()) {
+            let delta = delta as i128;
+            assert!(
+                first.key as i128 + delta >= 0 && last.key as i128 + delta <= usize::MAX as i128
+            );
+        }
+        self.offset += delta as i128;
+    }
+
+    pub fn insert(&mut self, element: Element) {
+        let key = match self.stored(element.key) {
+            Some(key) => key,
+            None => {
+                self.apply_offset();
+                element.key
+            }
+        };
+        self.tree.insert(Element {
+            key,
+            value: element.value,
+        });
+    }
+
+    pub fn find(&self, key: usize) -> Option<Element> {
+        self.tree.find(self.stored(key)?).map(|e| self.logical(e))
+    }
+
+    pub fn remove(&mut self, key: usize) -> Option<Element> {
+        let key = self.stored(key)?;
+        self.tree.remove(key).map(|e| self.logical(e))
+    }
+
+    pub fn first(&self) -> Option<Element> {
+        self.tree.first().map(|e| self.logical(e))
+    }
+
+    pub fn last(&self) -> Option<Element> {
+        self.tree.last().map(|e| self.logical(e))
+    }
+
+    // Returns the elements in key order.
+    pub fn iter(&self) -> impl Iterator<Item = Element> + '_ {
+        self.tree.iter().map(|e| self.logical(e))
+    }
+
+    // Returns the stored key of a key, or None if it cannot be stored with the offset.
+    fn stored(&self, key: usize) -> Option<usize> {
+        usize::try_from(key as i128 - self.offset).ok()
+    }
+
+    fn logical(&self, element: Element) -> Element {
+        Element {
+            key: (element.key as i128 + self.offset) as usize,
+            value: element.value,
+        }
+    }
+
+    // Rewrites the stored keys with the offset applied, and resets the offset.
+    fn apply_offset(&mut self) {
+        let elements: Vec<Element> = self.iter().collect();
+        self.tree = TwoThreeTree::from_sorted(elements);
+        self.offset = 0;
+    }
+}
+
+impl Default for ShiftedTwoThreeTree {
+    fn default() -> ShiftedTwoThreeTree {
+        ShiftedTwoThreeTree::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ShiftedTwoThreeTree;
+    use crate::two_three_tree::Element;
+
+    #[test]
+    fn test_shift_keys() {
+        let mut tree = ShiftedTwoThreeTree::new();
+        for key in 10..20 {
+            tree.insert(Element { key, value: key });
+        }
+        tree.shift_keys(-10);
+        assert!(tree.first().unwrap().key == 0 && tree.first().unwrap().value == 10);
+        assert!(tree.find(15).is_none());
+        assert!(tree.find(5).unwrap().value == 15);
+
+        // Below the stored keys, applying the offset first.
+        tree.shift_keys(100);
+        tree.insert(Element { key: 1, value: 1 });
+        assert!(tree
+            .iter()
+            .map(|e| e.key)
+            .eq([1].into_iter().chain(100..110)));
+        assert!(tree.remove(109).unwrap().value == 19);
+        assert!(tree.last().unwrap().key == 108);
+    }
+}
@@ -0,0 +1,166 @@
+// Snapshot files of the key-value pairs of a 2-3 Tree.
+//
+// A file starts with a header: the magic bytes, the format version, the number of elements and
+// a CRC-32 of the payload. The payload holds the keys and values in key order, as
+// little-endian u64s. Loading checks all of these before building the tree.
+
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use crate::two_three_tree::{Augment, Element, TwoThreeTree};
+
+const MAGIC: &[u8; 8] = b"2-3SNAP\0";
+const VERSION: u32 = 1;
+const HEADER_SIZE: usize = 8 + 4 + 8 + 4;
+
+// Error returned by TwoThreeTree::load_from().
+#[derive(Debug)]
+pub enum LoadError {
+    Io(io::Error),
+
+    // The file is not a snapshot file.
+    BadMagic,
+
+    // The file was written in a format version this build cannot read.
+    UnsupportedVersion(u32),
+
+    // The payload does not match the header, or is not in key order.
+    Corrupt,
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::Io(error) => write!(f, "{}", error),
+            LoadError::BadMagic => write!(f, "not a snapshot file"),
+            LoadError::UnsupportedVersion(version) => {
+                write!(f, "unsupported snapshot version {}", version)
+            }
+            LoadError::Corrupt => write!(f, "corrupt snapshot file"),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+impl From<io::Error> for LoadError {
+    fn from(error: io::Error) -> LoadError {
+        LoadError::Io(error)
+    }
+}
+
+// CRC-32 (IEEE) over the bytes, continuing from a previous value.
+fn crc32(crc: u32, bytes: &[u8]) -> u32 {
+    let mut crc = !crc;
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xedb8_8320 & (crc & 1).wrapping_neg());
+        }
+    }
+    !crc
+}
+
+fn encode(element: &Element) -> [u8; 16] {
+    let mut buf = [0; 16];
+    buf[..8].copy_from_slice(&(element.key as u64).to_le_bytes());
+    buf[8..].copy_from_slice(&(element.value as u64).to_le_bytes());
+    buf
+}
+
+impl<A: Augment> TwoThreeTree<A> {
+    // Writes the elements to a snapshot file, streaming from the in-order iterator.
+    pub fn save_to(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        // The CRC goes in the header, so it is computed in a first pass.
+        let crc = self.iter().fold(0, |crc, e| crc32(crc, &encode(&e)));
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(MAGIC)?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+        writer.write_all(&(self.size() as u64).to_le_bytes())?;
+        writer.write_all(&crc.to_le_bytes())?;
+        for element in self.iter() {
+            writer.write_all(&encode(&element))?;
+        }
+        writer.into_inner().map_err(|e| e.into_error())?.sync_all()
+    }
+}
+
+impl TwoThreeTree {
+    // Reads a snapshot file written by save_to().
+    pub fn load_from(path: impl AsRef<Path>) -> Result<TwoThreeTree, LoadError> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let mut header = [0; HEADER_SIZE];
+        reader.read_exact(&mut header).map_err(|e| match e.kind() {
+            io::ErrorKind::UnexpectedEof => LoadError::BadMagic,
+            _ => LoadError::Io(e),
+        })?;
+        if &header[..8] != MAGIC {
+            return Err(LoadError::BadMagic);
+        }
+        let version = u32::from_le_bytes(header[8..12].try_into().unwrap());
+        if version != VERSION {
+            return Err(LoadError::UnsupportedVersion(version));
+        }
+        let count = u64::from_le_bytes(header[12..20].try_into().unwrap());
+        let crc = u32::from_le_bytes(header[20..24].try_into().unwrap());
+
+        let mut payload = Vec::new();
+        reader.read_to_end(&mut payload)?;
+        if Some(payload.len() as u64) != count.checked_mul(16) || crc32(0, &payload) != crc {
+            return Err(LoadError::Corrupt);
+        }
+        let mut elements = Vec::with_capacity(payload.len() / 16);
+        for chunk in payload.chunks_exact(16) {
+            let key = u64::from_le_bytes(chunk[..8].try_into().unwrap());
+            let value = u64::from_le_bytes(chunk[8..].try_into().unwrap());
+            match (usize::try_from(key), usize::try_from(value)) {
+                (Ok(key), Ok(value)) => elements.push(Element { key, value }),
+                _ => return Err(LoadError::Corrupt),
+            }
+        }
+        if !elements.is_sorted_by_key(|e| e.key) {
+            return Err(LoadError::Corrupt);
+        }
+        Ok(TwoThreeTree::from_sorted(elements))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{crc32, LoadError};
+    use crate::two_three_tree::{Element, TwoThreeTree};
+
+    #[test]
+    fn test_save_load() {
+        assert!(crc32(0, b"123456789") == 0xcbf4_3926);
+
+        let path = std::env::temp_dir().join(format!("snapshot-{}.bin", std::process::id()));
+        let tree = TwoThreeTree::from_sorted((0..1000).map(|key| Element { key, value: !key }));
+        tree.save_to(&path).unwrap();
+        let loaded = TwoThreeTree::load_from(&path).unwrap();
+        loaded.validate();
+        assert!(loaded.into_sorted_vec() == tree.into_sorted_vec());
+
+        // A flipped payload bit, a newer version and a foreign file are refused.
+        let mut bytes = std::fs::read(&path).unwrap();
+        bytes[100] ^= 1;
+        std::fs::write(&path, &bytes).unwrap();
+        assert!(matches!(
+            TwoThreeTree::load_from(&path),
+            Err(LoadError::Corrupt)
+        ));
+        bytes[100] ^= 1;
+        bytes[8] = 2;
+        std::fs::write(&path, &bytes).unwrap();
+        let result = TwoThreeTree::load_from(&path);
+        assert!(matches!(result, Err(LoadError::UnsupportedVersion(2))));
+        std::fs::write(&path, b"key,value\n").unwrap();
+        assert!(matches!(
+            TwoThreeTree::load_from(&path),
+            Err(LoadError::BadMagic)
+        ));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,91 @@
+// Sorted runs of the key-value pairs of a 2-3 Tree, e.g. to use the tree as an LSM memtable.
+//
+// A run holds the keys and values in key order, as little-endian u64s. Runs are read back with
+// read_sorted_run(), and several of them merged into a tree with TwoThreeTree::from_sorted_runs().
+
+use std::io::{self, BufWriter, Read, Write};
+
+use crate::two_three_tree::{Augment, Element, TwoThreeTree};
+
+impl<A: Augment> TwoThreeTree<A> {
+    // Writes the elements to a sorted run, streaming from the in-order iterator. If drain is
+    // set, the tree is emptied once the run is written, notifying the observers of the removals.
+    // Returns the number of elements written.
+    pub fn flush_sorted(&mut self, writer: impl Write, drain: bool) -> io::Result<usize> {
+        let mut writer = BufWriter::new(writer);
+        for element in self.iter() {
+            writer.write_all(&(element.key as u64).to_le_bytes())?;
+            writer.write_all(&(element.value as u64).to_le_bytes())?;
+        }
+        writer.flush()?;
+        let count = self.size();
+        if drain {
+            self.retain_mut(|_, _| false);
+        }
+        Ok(count)
+    }
+}
+
+// Reads the elements of a sorted run written by flush_sorted(), in key order.
+pub fn read_sorted_run(mut reader: impl Read) -> impl Iterator<Item = io::Result<Element>> {
+    let mut done = false;
+    std::iter::from_fn(move || {
+        if done {
+            return None;
+        }
+        let mut buf = [0; 16];
+        let result = match reader.read_exact(&mut buf[..1]) {
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return None,
+            Err(e) => Err(e),
+            Ok(()) => reader.read_exact(&mut buf[1..]).and_then(|()| {
+                let key = u64::from_le_bytes(buf[..8].try_into().unwrap());
+                let value = u64::from_le_bytes(buf[8..].try_into().unwrap());
+                match (usize::try_from(key), usize::try_from(value)) {
+                    (Ok(key), Ok(value)) => Ok(Element { key, value }),
+                    _ => Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "element does not fit in usize",
+                    )),
+                }
+            }),
+        };
+        done = result.is_err();
+        Some(result)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::read_sorted_run;
+    use crate::two_three_tree::{Element, TwoThreeTree};
+
+    #[test]
+    fn test_flush_sorted() {
+        // Flush two memtables, then merge the runs back.
+        let mut runs = Vec::new();
+        for memtable in 0..2 {
+            let mut tree = TwoThreeTree::new();
+            for i in 0..50 {
+                tree.insert(Element {
+                    key: i * 2 + memtable,
+                    value: i,
+                });
+            }
+            let mut run = Vec::new();
+            assert!(tree.flush_sorted(&mut run, memtable == 1).unwrap() == 50);
+            assert!(tree.size() == if memtable == 1 { 0 } else { 50 });
+            runs.push(run);
+        }
+        let tree = TwoThreeTree::from_sorted_runs(
+            runs.iter()
+                .map(|run| read_sorted_run(&run[..]).map(Result::unwrap)),
+        );
+        tree.validate();
+        assert!(tree.iter().map(|e| e.key).eq(0..100));
+
+        // A truncated run ends with an error.
+        let run = &runs[0][..20];
+        let elements: Vec<_> = read_sorted_run(run).collect();
+        assert!(elements.len() == 2 && elements[1].is_err());
+    }
+}
@@ -0,0 +1,449 @@
+// A fixed-capacity 2-3 Tree which never allocates.
+//
+// The nodes live in an inline array and refer to each other by u16 indexes. Unused nodes form
+// a free list. An insertion which would need more nodes than are free fails instead of
+// allocating, so the tree can be used without a heap, e.g. in a static or on the stack. It only
+// needs core, so it is built without the std feature, for no_std targets without an allocator.
+
+use crate::element::Element;
+
+// Index of no node.
+const NIL: u16 = u16::MAX;
+
+// Maximum height of the tree. A tree of this height has more nodes than u16 can index.
+const MAX_HEIGHT: usize = 32;
+
+// A node with one or two elements. It is a leaf if it has no first child.
+#[derive(Clone, Copy)]
+struct StaticNode<K, V> {
+    elems: [Element<K, V>; 2],
+    children: [u16; 3],
+    len: u8,
+}
+
+impl<K: Copy + Default, V: Copy + Default> StaticNode<K, V> {
+    fn empty() -> StaticNode<K, V> {
+        StaticNode {
+            elems: [Element {
+                key: K::default(),
+                value: V::default(),
+            }; 2],
+            children: [NIL; 3],
+            len: 0,
+        }
+    }
+
+    fn is_leaf(&self) -> bool {
+        self.children[0] == NIL
+    }
+
+    fn elems(&self) -> &[Element<K, V>] {
+        &self.elems[..self.len as usize]
+    }
+}
+
+// A 2-3 Tree of at most N nodes, with one or two elements each. The keys and values are
+// copied in and out of the nodes, and unused slots hold their default values.
+pub struct StaticTwoThreeTree<K, V, const N: usize> {
+    nodes: [StaticNode<K, V>; N],
+    root: u16,
+
+    // Head of the list of free nodes, linked through their first child.
+    free: u16,
+    free_count: usize,
+
+    size: usize,
+}
+
+impl<K: Ord + Copy + Default, V: Copy + Default, const N: usize> StaticTwoThreeTree<K, V, N> {
+    pub fn new() -> StaticTwoThreeTree<K, V, N> {
+        assert!(N < NIL as usize);
+        let mut nodes = [StaticNode::empty(); N];
+        for (i, node) in nodes.iter_mut().enumerate() {
+            node.children[0] = if i + 1 < N { (i + 1) as u16 } else { NIL };
+        }
+        StaticTwoThreeTree {
+            nodes,
+            root: NIL,
+            free: if N > 0 { 0 } else { NIL },
+            free_count: N,
+            size: 0,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.root == NIL
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    // Finds an element with the given key.
+    pub fn find(&self, key: K) -> Option<Element<K, V>> {
+        let mut index = self.root;
+        while index != NIL {
+            let node = &self.nodes[index as usize];
+            if let Some(elem) = node.elems().iter().find(|e| e.key == key) {
+                return Some(*elem);
+            }
+            if node.is_leaf() {
+                break;
+            }
+            index = node.children[node.elems().iter().filter(|e| e.key < key).count()];
+        }
+        None
+    }
+
+    // Inserts an element. Returns the element back if there are not enough free nodes.
+    pub fn insert(&mut self, element: Element<K, V>) -> Result<(), Element<K, V>> {
+        if self.free_count < self.nodes_needed(element.key) {
+            return Err(element);
+        }
+        if self.root == NIL {
+            self.root = self.alloc();
+            self.nodes[self.root as usize].elems[0] = element;
+            self.nodes[self.root as usize].len = 1;
+        } else if let Some((middle, right)) = self.insert_node(self.root, element) {
+            let root = self.alloc();
+            self.nodes[root as usize].elems[0] = middle;
+            self.nodes[root as usize].children = [self.root, right, NIL];
+            self.nodes[root as usize].len = 1;
+            self.root = root;
+        }
+        self.size += 1;
+        Ok(())
+    }
+
+    // Returns the number of new nodes an insertion of the key needs: one per full node at the
+    // bottom of its path, and one more for a new root if all of them are full.
+    fn nodes_needed(&self, key: K) -> usize {
+        let mut needed = 1;
+        let mut index = self.root;
+        while index != NIL {
+            let node = &self.nodes[index as usize];
+            needed = if node.len == 2 { needed + 1 } else { 0 };
+            if node.is_leaf() {
+                break;
+            }
+            index = node.children[node.elems().iter().filter(|e| e.key <= key).count()];
+        }
+        needed
+    }
+
+    // Inserts recursively. Returns the middle element and the new right node if the node splits.
+    fn insert_node(&mut self, index: u16, element: Element<K, V>) -> Option<(Element<K, V>, u16)> {
+        let node = self.nodes[index as usize];
+        let pos = node.elems().iter().filter(|e| e.key <= element.key).count();
+        if node.is_leaf() {
+            self.insert_at(index, pos, element, NIL)
+        } else {
+            let (middle, right) = self.insert_node(node.children[pos], element)?;
+            self.insert_at(index, pos, middle, right)
+        }
+    }
+
+    // Inserts an element at a position, with the child to its right. Splits a full node.
+    fn insert_at(
+        &mut self,
+        index: u16,
+        pos: usize,
+        element: Element<K, V>,
+        right: u16,
+    ) -> Option<(Element<K, V>, u16)> {
+        let node = self.nodes[index as usize];
+        let mut elems = [element; 3];
+        let mut children = [NIL; 4];
+        elems[..pos].copy_from_slice(&node.elems[..pos]);
+        elems[pos + 1..node.len as usize + 1].copy_from_slice(&node.elems[pos..node.len as usize]);
+        children[..pos + 1].copy_from_slice(&node.children[..pos + 1]);
+        children[pos + 1] = right;
+        children[pos + 2..node.len as usize + 2]
+            .copy_from_slice(&node.children[pos + 1..node.len as usize + 1]);
+
+        let node = &mut self.nodes[index as usize];
+        if node.len == 1 {
+            node.elems = [elems[0], elems[1]];
+            node.children = [children[0], children[1], children[2]];
+            node.len = 2;
+            return None;
+        }
+        node.elems[0] = elems[0];
+        node.children = [children[0], children[1], NIL];
+        node.len = 1;
+        let new = self.alloc();
+        let node = &mut self.nodes[new as usize];
+        node.elems[0] = elems[2];
+        node.children = [children[2], children[3], NIL];
+        node.len = 1;
+        Some((elems[1], new))
+    }
+
+    // Deletes an element with the given key.
+    // Returns true if the element is found and deleted.
+    pub fn delete(&mut self, key: K) -> bool {
+        if self.root == NIL || self.delete_node(self.root, key).is_none() {
+            return false;
+        }
+        self.size -= 1;
+        let root = self.root;
+        if self.nodes[root as usize].len == 0 {
+            self.root = self.nodes[root as usize].children[0];
+            self.release(root);
+        }
+        true
+    }
+
+    // Deletes recursively. Returns None if the key is not found, otherwise whether the node is
+    // left without elements. Such a node has its only child first.
+    fn delete_node(&mut self, index: u16, key: K) -> Option<bool> {
+        let node = self.nodes[index as usize];
+        if let Some(pos) = node.elems().iter().position(|e| e.key == key) {
+            if node.is_leaf() {
+                self.remove_at(index, pos, 0);
+                return Some(self.nodes[index as usize].len == 0);
+            }
+            // Replace the element with its predecessor, the largest element of the child before.
+            let (predecessor, empty) = self.delete_max(node.children[pos]);
+            self.nodes[index as usize].elems[pos] = predecessor;
+            return Some(empty && self.fix(index, pos));
+        }
+        if node.is_leaf() {
+            return None;
+        }
+        let pos = node.elems().iter().filter(|e| e.key < key).count();
+        let empty = self.delete_node(node.children[pos], key)?;
+        Some(empty && self.fix(index, pos))
+    }
+
+    // Deletes the largest element of a subtree. Returns it, and whether the node is left empty.
+    fn delete_max(&mut self, index: u16) -> (Element<K, V>, bool) {
+        let node = self.nodes[index as usize];
+        let last = node.len as usize - 1;
+        if node.is_leaf() {
+            self.remove_at(index, last, 0);
+            return (node.elems[last], last == 0);
+        }
+        let (element, empty) = self.delete_max(node.children[last + 1]);
+        (element, empty && self.fix(index, last + 1))
+    }
+
+    // Fixes the empty child at a position, by borrowing an element from a sibling with two, or
+    // by merging it into a sibling with one. Returns true if the node is left empty.
+    fn fix(&mut self, index: u16, pos: usize) -> bool {
+        let node = self.nodes[index as usize];
+        let child = node.children[pos];
+        let left = if pos > 0 { node.children[pos - 1] } else { NIL };
+        let right = if pos < node.len as usize {
+            node.children[pos + 1]
+        } else {
+            NIL
+        };
+
+        if left != NIL && self.nodes[left as usize].len == 2 {
+            let sibling = self.nodes[left as usize];
+            let c = &mut self.nodes[child as usize];
+            c.elems[0] = node.elems[pos - 1];
+            c.children = [sibling.children[2], c.children[0], NIL];
+            c.len = 1;
+            self.nodes[index as usize].elems[pos - 1] = sibling.elems[1];
+            self.remove_at(left, 1, 2);
+            return false;
+        }
+        if right != NIL && self.nodes[right as usize].len == 2 {
+            let sibling = self.nodes[right as usize];
+            let c = &mut self.nodes[child as usize];
+            c.elems[0] = node.elems[pos];
+            c.children = [c.children[0], sibling.children[0], NIL];
+            c.len = 1;
+            self.nodes[index as usize].elems[pos] = sibling.elems[0];
+            self.remove_at(right, 0, 0);
+            return false;
+        }
+
+        let only_child = self.nodes[child as usize].children[0];
+        if left != NIL {
+            let s = &mut self.nodes[left as usize];
+            s.elems[1] = node.elems[pos - 1];
+            s.children[2] = only_child;
+            s.len = 2;
+            self.remove_at(index, pos - 1, pos);
+        } else {
+            let s = &mut self.nodes[right as usize];
+            s.elems = [node.elems[pos], s.elems[0]];
+            s.children = [only_child, s.children[0], s.children[1]];
+            s.len = 2;
+            self.remove_at(index, pos, pos);
+        }
+        self.release(child);
+        self.nodes[index as usize].len == 0
+    }
+
+    // Removes the element and the child at the given positions from a node.
+    fn remove_at(&mut self, index: u16, elem_pos: usize, child_pos: usize) {
+        let node = &mut self.nodes[index as usize];
+        let len = node.len as usize;
+        node.elems.copy_within(elem_pos + 1..len, elem_pos);
+        node.children.copy_within(child_pos + 1..len + 1, child_pos);
+        node.children[len] = NIL;
+        node.len -= 1;
+    }
+
+    // Takes a node from the free list.
+    fn alloc(&mut self) -> u16 {
+        let index = self.free;
+        self.free = self.nodes[index as usize].children[0];
+        self.free_count -= 1;
+        self.nodes[index as usize] = StaticNode::empty();
+        index
+    }
+
+    // Returns a node to the free list.
+    fn release(&mut self, index: u16) {
+        self.nodes[index as usize].children[0] = self.free;
+        self.free = index;
+        self.free_count += 1;
+    }
+
+    // Returns an iterator over the elements in key order.
+    pub fn iter(&self) -> StaticIter<'_, K, V, N> {
+        let mut iter = StaticIter {
+            tree: self,
+            stack: [(NIL, 0); MAX_HEIGHT],
+            depth: 0,
+        };
+        if self.root != NIL {
+            iter.stack[0] = (self.root, 0);
+            iter.depth = 1;
+        }
+        iter
+    }
+
+    // Validates the structure of the tree.
+    pub fn validate(&self) {
+        if self.root != NIL {
+            let mut leaf_level = None;
+            let count = self.validate_node(self.root, 0, &mut leaf_level);
+            assert!(count == self.size);
+        }
+        assert!(self
+            .iter()
+            .zip(self.iter().skip(1))
+            .all(|(a, b)| a.key <= b.key));
+    }
+
+    // Validates a node recursively. Returns the number of elements in the subtree.
+    fn validate_node(&self, index: u16, level: usize, leaf_level: &mut Option<usize>) -> usize {
+        let node = &self.nodes[index as usize];
+        assert!(node.len == 1 || node.len == 2);
+        if node.is_leaf() {
+            assert!(node.children.iter().all(|&c| c == NIL));
+            assert!(*leaf_level.get_or_insert(level) == level);
+            return node.len as usize;
+        }
+        let children = &node.children[..node.len as usize + 1];
+        assert!(children.iter().all(|&c| c != NIL));
+        let below: usize = children
+            .iter()
+            .map(|&c| self.validate_node(c, level + 1, leaf_level))
+            .sum();
+        below + node.len as usize
+    }
+}
+
+impl<K: Ord + Copy + Default, V: Copy + Default, const N: usize> Default
+    for StaticTwoThreeTree<K, V, N>
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Iterator over the elements of a StaticTwoThreeTree, with its stack inline.
+pub struct StaticIter<'a, K, V, const N: usize> {
+    tree: &'a StaticTwoThreeTree<K, V, N>,
+
+    // The nodes on the path to the current element, with the step each continues from: even
+    // steps descend child step / 2, odd steps yield element step / 2.
+    stack: [(u16, u8); MAX_HEIGHT],
+    depth: usize,
+}
+
+impl<K: Ord + Copy + Default, V: Copy + Default, const N: usize> Iterator
+    for StaticIter<'_, K, V, N>
+{
+    type Item = Element<K, V>;
+
+    fn next(&mut self) -> Option<Element<K, V>> {
+        while self.depth > 0 {
+            let (index, step) = self.stack[self.depth - 1];
+            let node = &self.tree.nodes[index as usize];
+            if step as usize > 2 * node.len as usize {
+                self.depth -= 1;
+                continue;
+            }
+            self.stack[self.depth - 1].1 += 1;
+            if step % 2 == 1 {
+                return Some(node.elems[step as usize / 2]);
+            }
+            if !node.is_leaf() {
+                self.stack[self.depth] = (node.children[step as usize / 2], 0);
+                self.depth += 1;
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::StaticTwoThreeTree;
+    use crate::element::Element;
+
+    #[test]
+    fn test_static_tree() {
+        let mut tree = StaticTwoThreeTree::<u16, i64, 100>::new();
+        let mut inserted = 0;
+        while tree
+            .insert(Element {
+                key: inserted,
+                value: -(inserted as i64),
+            })
+            .is_ok()
+        {
+            inserted += 1;
+            tree.validate();
+        }
+        assert!(inserted >= 100 && tree.size() == inserted as usize);
+        assert!(tree.iter().map(|e| e.key).eq(0..inserted));
+        assert!(tree.find(50).unwrap().value == -50 && tree.find(inserted).is_none());
+
+        for key in (0..inserted).step_by(2) {
+            assert!(tree.delete(key));
+            tree.validate();
+        }
+        assert!(!tree.delete(0));
+        assert!(tree.iter().map(|e| e.key).eq((1..inserted).step_by(2)));
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_static_tree_random() {
+        use crate::workload::{self, WorkloadOp};
+
+        let mut tree = StaticTwoThreeTree::<usize, usize, 2000>::new();
+        let keys = workload::random_insert_delete(100, 3000, |op| {
+            let found = match op {
+                WorkloadOp::Insert(element) => {
+                    tree.insert(element).unwrap();
+                    true
+                }
+                WorkloadOp::Delete(key) => tree.delete(key),
+                WorkloadOp::Find(_) => unreachable!(),
+            };
+            tree.validate();
+            found
+        });
+        assert!(tree.iter().map(|e| e.key).eq(keys));
+    }
+}
@@ -0,0 +1,289 @@
+// An ordered map with string keys, for dictionaries and autocompletion.
+//
+// The tree orders the strings by their first bytes, packed big-endian into a usize key, so that
+// the key order is the byte order of the strings. The strings sharing those first bytes go to a
+// bucket, a BTreeMap by the whole string referenced by the element value, so that strings with
+// a long common prefix, e.g. URLs, still cost O(log n) to insert or remove. A prefix query is
+// then a range of keys.
+//
+// A Collation chosen at construction changes the order: the strings are then ordered, compared
+// and looked up by their sort key, e.g. case-folded, while the original strings are kept.
+
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+
+use crate::two_three_tree::{Element, TwoThreeTree};
+
+// The number of bytes of a string packed into a key.
+const KEY_BYTES: usize = usize::BITS as usize / 8;
+
+// The order of the keys of a StringTwoThreeTree.
+#[derive(Clone, Copy, Default)]
+pub enum Collation {
+    // Byte order, as str::cmp().
+    #[default]
+    Binary,
+
+    // Byte order of the lowercased strings, so that "Apple" and "apple" are the same key.
+    CaseInsensitive,
+
+    // Byte order of the sort keys computed by the function, e.g. from a locale library.
+    // Prefix queries assume that the sort key of a prefix is a prefix of the sort key.
+    Custom(fn(&str) -> String),
+}
+
+impl Collation {
+    // Returns the string whose byte order is the collation order.
+    pub fn sort_key<'a>(&self, key: &'a str) -> Cow<'a, str> {
+        match self {
+            Collation::Binary => Cow::Borrowed(key),
+            Collation::CaseInsensitive => Cow::Owned(key.to_lowercase()),
+            Collation::Custom(f) => Cow::Owned(f(key)),
+        }
+    }
+}
+
+// The value of a sort key, with the original key if it differs from the sort key.
+struct Entry<V> {
+    key: Option<String>,
+    value: V,
+}
+
+// Entries by sort key, sharing the packed bytes.
+type Bucket<V> = BTreeMap<String, Entry<V>>;
+
+pub struct StringTwoThreeTree<V> {
+    // One element per bucket, keyed by the packed bytes of the sort keys, with the bucket index
+    // as value.
+    tree: TwoThreeTree,
+
+    buckets: Vec<Bucket<V>>,
+
+    collation: Collation,
+
+    // Indexes of the free buckets.
+    free: Vec<usize>,
+
+    size: usize,
+}
+
+// Packs the first bytes of a string into a key, padding with zeros.
+fn packed_key(bytes: &[u8]) -> usize {
+    let mut key = [0; KEY_BYTES];
+    let len = bytes.len().min(KEY_BYTES);
+    key[..len].copy_from_slice(&bytes[..len]);
+    usize::from_be_bytes(key)
+}
+
+impl<V> StringTwoThreeTree<V> {
+    pub fn new() -> StringTwoThreeTree<V> {
+        StringTwoThreeTree::with_collation(Collation::Binary)
+    }
+
+    // Creates a tree ordering the keys by the collation.
+    pub fn with_collation(collation: Collation) -> StringTwoThreeTree<V> {
+        StringTwoThreeTree {
+            tree: TwoThreeTree::new(),
+            buckets: Vec::new(),
+            collation,
+            free: Vec::new(),
+            size: 0,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    // Sets the value of the key. Returns the previous value, if any. A key equal under the
+    // collation keeps its original spelling.
+    pub fn insert(&mut self, key: impl Into<String>, value: V) -> Option<V> {
+        let key = key.into();
+        let (sort_key, key) = match self.collation.sort_key(&key) {
+            Cow::Owned(sort_key) if sort_key != key => (sort_key, Some(key)),
+            _ => (key, None),
+        };
+        let packed = packed_key(sort_key.as_bytes());
+        let index = match self.tree.find(packed) {
+            Some(element) => element.value,
+            None => {
+                let index = self.free.pop().unwrap_or(self.buckets.len());
+                if index == self.buckets.len() {
+                    self.buckets.push(BTreeMap::new());
+                }
+                self.tree.insert(Element {
+                    key: packed,
+                    value: index,
+                });
+                index
+            }
+        };
+        match self.buckets[index].get_mut(&sort_key) {
+            Some(entry) => Some(std::mem::replace(&mut entry.value, value)),
+            None => {
+                self.buckets[index].insert(sort_key, Entry { key, value });
+                self.size += 1;
+                None
+            }
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<&V> {
+        let sort_key = self.collation.sort_key(key);
+        let bucket = &self.buckets[self.tree.find(packed_key(sort_key.as_bytes()))?.value];
+        Some(&bucket.get(sort_key.as_ref())?.value)
+    }
+
+    // Removes the key, returning its value.
+    pub fn remove(&mut self, key: &str) -> Option<V> {
+        let sort_key = self.collation.sort_key(key);
+        let packed = packed_key(sort_key.as_bytes());
+        let index = self.tree.find(packed)?.value;
+        let bucket = &mut self.buckets[index];
+        let value = bucket.remove(sort_key.as_ref())?.value;
+        self.size -= 1;
+        if bucket.is_empty() {
+            self.tree.remove(packed);
+            self.free.push(index);
+        }
+        Some(value)
+    }
+
+    // Returns the keys and values in collation order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &V)> + '_ {
+        self.prefix_range("")
+    }
+
+    // Returns the keys beginning with the prefix under the collation and their values, in
+    // collation order.
+    pub fn prefix_range<'a>(&'a self, prefix: &str) -> impl Iterator<Item = (&'a str, &'a V)> {
+        let prefix = self.collation.sort_key(prefix).into_owned();
+
+        // The packed keys of the strings with the prefix form a range: the prefix bytes
+        // followed by anything.
+        let fixed = prefix.len().min(KEY_BYTES);
+        let start = packed_key(prefix.as_bytes());
+        let end = start | usize::MAX.checked_shr(8 * fixed as u32).unwrap_or(0);
+        self.tree
+            .iter_from(start)
+            .take_while(move |e| e.key <= end)
+            .flat_map(move |e| {
+                let prefix = prefix.clone();
+                self.buckets[e.value]
+                    .range(prefix.clone()..)
+                    .take_while(move |(sort_key, _)| sort_key.starts_with(prefix.as_str()))
+                    .map(|(sort_key, e)| (e.key.as_deref().unwrap_or(sort_key), &e.value))
+            })
+    }
+}
+
+impl<V> Default for StringTwoThreeTree<V> {
+    fn default() -> StringTwoThreeTree<V> {
+        StringTwoThreeTree::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Collation, StringTwoThreeTree};
+
+    #[test]
+    fn test_prefix_range() {
+        let words = [
+            "fo",
+            "foo",
+            "foobar",
+            "foobarbazqux",
+            "foobarbazquux",
+            "fop",
+            "fz",
+            "",
+            "a",
+            "automatic",
+            "automaton",
+            "automatically",
+        ];
+        let mut tree = StringTwoThreeTree::new();
+        for (i, word) in words.iter().enumerate() {
+            assert!(tree.insert(*word, i).is_none());
+        }
+        assert!(tree.insert("foo", 100) == Some(1));
+        assert!(tree.size() == words.len() && tree.get("foo") == Some(&100));
+
+        let mut sorted = words.to_vec();
+        sorted.sort();
+        assert!(tree.iter().map(|(k, _)| k).eq(sorted.iter().copied()));
+        for prefix in [
+            "",
+            "f",
+            "foo",
+            "foobarbaz",
+            "foobarbazqu",
+            "automat",
+            "automatically",
+            "x",
+        ] {
+            let expected = sorted.iter().copied().filter(|w| w.starts_with(prefix));
+            assert!(tree.prefix_range(prefix).map(|(k, _)| k).eq(expected));
+        }
+
+        assert!(tree.remove("foobarbazqux") == Some(3));
+        assert!(tree.remove("foobarbazqux").is_none());
+        assert!(tree
+            .prefix_range("foobarbaz")
+            .map(|(k, _)| k)
+            .eq(["foobarbazquux"]));
+        assert!(tree.get("automaton") == Some(&10));
+
+        // Strings sharing more than the packed bytes go to the same bucket.
+        let mut tree = StringTwoThreeTree::new();
+        for i in (0..1000).rev() {
+            tree.insert(format!("https://example.com/{:04}", i), i);
+        }
+        for i in (0..1000).step_by(2) {
+            assert!(tree.remove(&format!("https://example.com/{:04}", i)) == Some(i));
+        }
+        assert!(tree.tree.size() == 1 && tree.size() == 500);
+        assert!(tree
+            .prefix_range("https://example.com/09")
+            .map(|(_, value)| *value)
+            .eq((901..1000).step_by(2)));
+    }
+
+    #[test]
+    fn test_collation() {
+        let mut tree = StringTwoThreeTree::with_collation(Collation::CaseInsensitive);
+        for (i, word) in ["banana", "Apple", "cherry", "apricot", "Avocado"]
+            .iter()
+            .enumerate()
+        {
+            tree.insert(*word, i);
+        }
+        assert!(tree.insert("APPLE", 10) == Some(1));
+        assert!(tree.get("apple") == Some(&10));
+        assert!(tree
+            .iter()
+            .map(|(k, _)| k)
+            .eq(["Apple", "apricot", "Avocado", "banana", "cherry"]));
+        assert!(tree
+            .prefix_range("AP")
+            .map(|(k, _)| k)
+            .eq(["Apple", "apricot"]));
+        assert!(tree.remove("BANANA") == Some(0));
+
+        // A locale hook, here ignoring accents on e.
+        let fold = |key: &str| key.replace(['é', 'è'], "e");
+        let mut tree = StringTwoThreeTree::with_collation(Collation::Custom(fold));
+        for word in ["eta", "étude", "ezra", "fa"] {
+            tree.insert(word, ());
+        }
+        assert!(tree
+            .iter()
+            .map(|(k, _)| k)
+            .eq(["eta", "étude", "ezra", "fa"]));
+    }
+}
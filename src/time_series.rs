@@ -0,0 +1,130 @@
+// Helpers for trees keyed by timestamp, e.g. the samples of a monitoring agent.
+//
+// The oldest samples are the leftmost elements, so evicting them is a run of delete_min() calls
+// down the left spine, and the newest are read with top_k(). A TimeSeries packages these as a
+// buffer which keeps a fixed span of time, like a ring buffer sized in time rather than count.
+
+use std::cmp::Ordering;
+use std::ops::RangeBounds;
+
+use crate::two_three_tree::{Augment, Element, TwoThreeTree};
+
+impl<A: Augment> TwoThreeTree<A> {
+    // Removes the elements with keys before t, in O(k log n) for k removed elements.
+    // Returns the number of removed elements.
+    pub fn evict_older_than(&mut self, t: usize) -> usize {
+        let compare = self.comparator();
+        let mut evicted = 0;
+        while self
+            .first()
+            .is_some_and(|e| compare(&e.key, &t) == Ordering::Less)
+        {
+            self.delete_min();
+            evicted += 1;
+        }
+        evicted
+    }
+
+    // Returns the n elements with the largest keys, newest first.
+    pub fn latest(&self, n: usize) -> Vec<Element> {
+        self.top_k(n)
+    }
+
+    // Returns the elements with keys in the range, oldest first.
+    pub fn window(&self, range: impl RangeBounds<usize>) -> impl Iterator<Item = Element> + '_ {
+        self.range(range)
+    }
+}
+
+// Samples keyed by timestamp, keeping those within the retention of the newest timestamp.
+pub struct TimeSeries {
+    tree: TwoThreeTree,
+
+    retention: usize,
+}
+
+impl TimeSeries {
+    // Creates a series which keeps all samples.
+    pub fn new() -> TimeSeries {
+        TimeSeries::with_retention(usize::MAX)
+    }
+
+    // Creates a series which keeps the samples less than retention older than the newest one.
+    // Panics if retention is 0.
+    pub fn with_retention(retention: usize) -> TimeSeries {
+        assert!(retention > 0);
+        TimeSeries {
+            tree: TwoThreeTree::new(),
+            retention,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    pub fn size(&self) -> usize {
+        self.tree.size()
+    }
+
+    pub fn tree(&self) -> &TwoThreeTree {
+        &self.tree
+    }
+
+    // Records a sample, evicting the samples which fall out of the retention. A sample older
+    // than the retention allows is dropped.
+    pub fn push(&mut self, t: usize, value: usize) {
+        let newest = self.tree.last().map_or(t, |e| e.key.max(t));
+        let oldest = newest.saturating_sub(self.retention - 1);
+        if t >= oldest {
+            self.tree.insert(Element { key: t, value });
+        }
+        self.tree.evict_older_than(oldest);
+    }
+
+    // Returns the n newest samples, newest first.
+    pub fn latest(&self, n: usize) -> Vec<Element> {
+        self.tree.latest(n)
+    }
+
+    // Returns the samples with timestamps in the range, oldest first.
+    pub fn window(&self, range: impl RangeBounds<usize>) -> impl Iterator<Item = Element> + '_ {
+        self.tree.window(range)
+    }
+}
+
+impl Default for TimeSeries {
+    fn default() -> TimeSeries {
+        TimeSeries::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TimeSeries;
+    use crate::two_three_tree::{Element, TwoThreeTree};
+
+    #[test]
+    fn test_time_series() {
+        let mut tree = TwoThreeTree::new();
+        for t in (0..100).rev() {
+            tree.insert(Element { key: t, value: t });
+        }
+        assert!(tree.evict_older_than(90) == 90 && tree.size() == 10);
+        assert!(tree.evict_older_than(90) == 0);
+        assert!(tree.latest(2).iter().map(|e| e.key).eq([99, 98]));
+        assert!(tree.window(95..97).map(|e| e.key).eq([95, 96]));
+        tree.validate();
+
+        let mut series = TimeSeries::with_retention(10);
+        for t in 0..25 {
+            series.push(t, t * 2);
+        }
+        assert!(series.size() == 10 && series.tree().first().unwrap().key == 15);
+        series.push(3, 0);
+        assert!(series.size() == 10);
+        series.push(30, 60);
+        assert!(series.window(..).map(|e| e.key).eq([21, 22, 23, 24, 30]));
+        assert!(series.latest(1)[0].value == 60);
+    }
+}
@@ -0,0 +1,136 @@
+// A 2-3 Tree whose deletes leave tombstones instead of restructuring, for burst-delete workloads.
+//
+// The tree orders slot indexes by key, and a slot without a value is a tombstone. remove() marks
+// the slot in O(log n) without touching the nodes, and compact() rebuilds the tree from the live
+// elements in O(n), purging the tombstones. Until then they cost memory and lookups skip them.
+
+use crate::two_three_tree::{Element, TwoThreeTree};
+
+pub struct TombstoneTwoThreeTree {
+    // Elements keyed by key, with the slot index as value.
+    tree: TwoThreeTree,
+
+    // The value of each slot, or None for a tombstone.
+    slots: Vec<Option<usize>>,
+
+    tombstones: usize,
+}
+
+impl TombstoneTwoThreeTree {
+    pub fn new() -> TombstoneTwoThreeTree {
+        TombstoneTwoThreeTree {
+            tree: TwoThreeTree::new(),
+            slots: Vec::new(),
+            tombstones: 0,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.size() == 0
+    }
+
+    // Returns the number of live elements.
+    pub fn size(&self) -> usize {
+        self.tree.size() - self.tombstones
+    }
+
+    // Returns the number of tombstones left by removals since the last compaction.
+    pub fn tombstones(&self) -> usize {
+        self.tombstones
+    }
+
+    pub fn insert(&mut self, element: Element) {
+        self.slots.push(Some(element.value));
+        self.tree.insert(Element {
+            key: element.key,
+            value: self.slots.len() - 1,
+        });
+    }
+
+    // Returns a live element with the given key.
+    pub fn find(&self, key: usize) -> Option<Element> {
+        self.live_slot(key).map(|(_, element)| element)
+    }
+
+    // Replaces a live element with the given key by a tombstone, and returns it.
+    pub fn remove(&mut self, key: usize) -> Option<Element> {
+        let (slot, element) = self.live_slot(key)?;
+        self.slots[slot] = None;
+        self.tombstones += 1;
+        Some(element)
+    }
+
+    // Returns the live elements in key order.
+    pub fn iter(&self) -> impl Iterator<Item = Element> + '_ {
+        self.tree
+            .iter()
+            .filter_map(|e| self.slots[e.value].map(|value| Element { key: e.key, value }))
+    }
+
+    // Rebuilds the tree from the live elements, in O(n).
+    pub fn compact(&mut self) {
+        if self.tombstones == 0 {
+            return;
+        }
+        let mut slots = Vec::with_capacity(self.size());
+        let tree = TwoThreeTree::from_sorted(self.tree.iter().filter_map(|e| {
+            let value = self.slots[e.value]?;
+            slots.push(Some(value));
+            Some(Element {
+                key: e.key,
+                value: slots.len() - 1,
+            })
+        }));
+        self.tree = tree;
+        self.slots = slots;
+        self.tombstones = 0;
+    }
+
+    // Returns the slot and the element of a live element with the given key, skipping the
+    // tombstones of this key.
+    fn live_slot(&self, key: usize) -> Option<(usize, Element)> {
+        self.tree
+            .iter_from(key)
+            .take_while(|e| e.key == key)
+            .find_map(|e| {
+                let value = self.slots[e.value]?;
+                Some((e.value, Element { key, value }))
+            })
+    }
+}
+
+impl Default for TombstoneTwoThreeTree {
+    fn default() -> TombstoneTwoThreeTree {
+        TombstoneTwoThreeTree::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TombstoneTwoThreeTree;
+    use crate::two_three_tree::Element;
+
+    #[test]
+    fn test_tombstones() {
+        let mut tree = TombstoneTwoThreeTree::new();
+        for i in 0..100 {
+            tree.insert(Element {
+                key: i % 50,
+                value: i,
+            });
+        }
+        for key in 0..25 {
+            assert!(tree.remove(key).unwrap().key == key);
+        }
+        assert!(tree.size() == 75 && tree.tombstones() == 25);
+        assert!(tree.find(10).unwrap().value == 60);
+        assert!(tree.remove(10).unwrap().value == 60);
+        assert!(tree.find(10).is_none() && tree.remove(10).is_none());
+
+        let before: Vec<Element> = tree.iter().collect();
+        tree.compact();
+        assert!(tree.size() == 74 && tree.tombstones() == 0);
+        assert!(tree.iter().eq(before));
+        assert!(tree.find(30).is_some());
+    }
+}
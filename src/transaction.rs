@@ -0,0 +1,101 @@
+// Transactional mutation of a 2-3 Tree.
+//
+// Mutations are buffered in the transaction and applied with TwoThreeTree::apply_batch() on
+// commit, so either all of them take effect or none of them do.
+
+use crate::two_three_tree::{Op, TwoThreeTree};
+
+// A pending set of mutations on a tree. Dropping it without commit discards the mutations.
+pub struct Transaction<'a> {
+    tree: &'a mut TwoThreeTree,
+
+    // Buffered operations, in order.
+    ops: Vec<Op>,
+}
+
+impl TwoThreeTree {
+    // Begins a transaction on the tree.
+    pub fn begin(&mut self) -> Transaction<'_> {
+        Transaction {
+            tree: self,
+            ops: Vec::new(),
+        }
+    }
+}
+
+impl<'a> Transaction<'a> {
+    // Buffers the insertion of an element.
+    pub fn insert(&mut self, key: usize, value: usize) {
+        self.ops.push(Op::Insert(key, value));
+    }
+
+    // Buffers the deletion of an element with the given key.
+    pub fn delete(&mut self, key: usize) {
+        self.ops.push(Op::Delete(key));
+    }
+
+    // Buffers the update of the value of an element with the given key.
+    pub fn update(&mut self, key: usize, f: impl FnOnce(usize) -> usize + 'static) {
+        self.ops.push(Op::Update(key, Box::new(f)));
+    }
+
+    // Returns the number of buffered operations.
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+
+    // Read access to the tree as it was before the transaction.
+    pub fn tree(&self) -> &TwoThreeTree {
+        self.tree
+    }
+
+    // Applies all buffered operations atomically.
+    // Returns false and leaves the tree unchanged if any delete or update misses its key.
+    pub fn commit(self) -> bool {
+        self.tree.apply_batch(self.ops)
+    }
+
+    // Discards all buffered operations.
+    pub fn rollback(self) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::two_three_tree::{Element, TwoThreeTree};
+
+    #[test]
+    fn test_commit_rollback() {
+        let mut tree = TwoThreeTree::new();
+        for i in 0..10 {
+            tree.insert(Element { key: i, value: i });
+        }
+
+        // Rollback leaves the tree unchanged.
+        let mut txn = tree.begin();
+        txn.insert(20, 20);
+        txn.delete(1);
+        txn.rollback();
+        assert!(tree.size() == 10);
+        assert!(tree.find(20).is_none());
+
+        // A failing operation aborts the whole transaction.
+        let mut txn = tree.begin();
+        txn.delete(2);
+        txn.update(30, |v| v + 1);
+        assert!(!txn.commit());
+        assert!(tree.find(2).is_some());
+
+        let mut txn = tree.begin();
+        txn.delete(2);
+        txn.update(3, |v| v + 100);
+        assert!(txn.len() == 2);
+        assert!(txn.commit());
+        tree.validate();
+        assert!(tree.find(2).is_none());
+        assert!(tree.find(3).unwrap().value == 103);
+    }
+}
@@ -0,0 +1,85 @@
+// A 2-3 Tree of whole values, ordered by a key extracted from each value.
+//
+// The key lives only inside the value: the values are stored out of line in a
+// SeparatedTwoThreeTree under the extracted key, and the key is extracted again only when a value
+// is inserted. Values must not change their key while in the tree, which the API ensures by
+// lending only shared references.
+
+use std::ops::RangeBounds;
+
+use crate::separated::SeparatedTwoThreeTree;
+
+pub struct TwoThreeTreeBy<T, F: Fn(&T) -> usize> {
+    // The values, keyed by the extracted key.
+    tree: SeparatedTwoThreeTree<T>,
+
+    key: F,
+}
+
+impl<T, F: Fn(&T) -> usize> TwoThreeTreeBy<T, F> {
+    pub fn new(key: F) -> TwoThreeTreeBy<T, F> {
+        TwoThreeTreeBy {
+            tree: SeparatedTwoThreeTree::new(),
+            key,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    pub fn size(&self) -> usize {
+        self.tree.size()
+    }
+
+    // Inserts a value, after the values with an equal key.
+    pub fn insert(&mut self, value: T) {
+        self.tree.insert((self.key)(&value), value);
+    }
+
+    // Returns a value with the given key.
+    pub fn find(&self, key: usize) -> Option<&T> {
+        self.tree.get(key)
+    }
+
+    // Removes a value with the given key, and returns it.
+    pub fn remove(&mut self, key: usize) -> Option<T> {
+        self.tree.remove(key)
+    }
+
+    // Returns the values in key order.
+    pub fn iter(&self) -> impl Iterator<Item = &T> + '_ {
+        self.range(..)
+    }
+
+    // Returns the values with a key in the range, in key order.
+    pub fn range(&self, range: impl RangeBounds<usize>) -> impl Iterator<Item = &T> + '_ {
+        self.tree.range(range).map(|(_, value)| value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TwoThreeTreeBy;
+
+    struct Employee {
+        id: usize,
+        name: &'static str,
+    }
+
+    #[test]
+    fn test_tree_by() {
+        let mut tree = TwoThreeTreeBy::new(|e: &Employee| e.id);
+        for (id, name) in [(30, "carol"), (10, "alice"), (20, "bob"), (40, "dave")] {
+            tree.insert(Employee { id, name });
+        }
+        assert!(tree.find(20).unwrap().name == "bob" && tree.find(25).is_none());
+        assert!(tree.iter().map(|e| e.id).eq([10, 20, 30, 40]));
+        assert!(tree.range(15..=30).map(|e| e.name).eq(["bob", "carol"]));
+
+        assert!(tree.remove(10).unwrap().name == "alice");
+        tree.insert(Employee { id: 5, name: "eve" });
+        assert!(tree.size() == 4);
+        assert!(tree.iter().next().unwrap().name == "eve");
+    }
+}
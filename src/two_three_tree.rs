@@ -4,50 +4,309 @@
 // This implementation uses recursion to traverse down and up the tree, thus avoid
 // having a parent pointer in the node. This also helps to conform to the borrow checker.
 
-use std::cmp::Ordering;
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BTreeMap, BinaryHeap, HashMap};
+use std::fmt;
+use std::ops::{Bound, ControlFlow, Range, RangeBounds};
+use std::panic::{self, AssertUnwindSafe};
 
-// For simplicity, assume an Element has a usize key and value.
-// This can be parameterized.
-#[derive(Clone, Copy)]
-pub struct Element {
-    pub key: usize,
-    pub value: usize,
+#[cfg(feature = "bloom")]
+use crate::bloom::BloomFilter;
+pub use crate::element::Element;
+#[cfg(feature = "metrics")]
+use crate::metrics::{self, MetricsCounters};
+use crate::red_black::{Color, RedBlackNode};
+use crate::shape::Shape;
+
+// A single operation applied by TwoThreeTree::apply_batch().
+pub enum Op {
+    // Inserts an element with the given key and value.
+    Insert(usize, usize),
+
+    // Deletes an element with the given key.
+    Delete(usize),
+
+    // Replaces the value of an element with the given key with the result of the function.
+    Update(usize, Box<dyn FnOnce(usize) -> usize>),
 }
 
-impl std::cmp::PartialEq for Element {
-    fn eq(&self, other: &Element) -> bool {
-        self.key == other.key
-    }
+// What insert() does with an element whose key is already in the tree.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DuplicatePolicy {
+    // Keeps both elements, the new one after the existing ones. This is the default.
+    #[default]
+    Allow,
+
+    // Replaces the value of an existing element with the key.
+    Replace,
+
+    // Keeps the existing element and drops the new one.
+    Ignore,
+}
+
+// Orders the keys of a tree. It must be a total order, Equal only for equal keys. If it panics,
+// insert() and the deletions leave the tree unchanged, as they compare keys before mutating.
+pub type Comparator = fn(&usize, &usize) -> Ordering;
+
+// Receives notifications of the mutations of a tree, e.g. to keep a secondary index in sync.
+// All methods are called after the tree has been mutated.
+pub trait Observer {
+    // Called when an element is inserted.
+    fn on_insert(&mut self, _element: &Element) {}
+
+    // Called when an element is deleted.
+    fn on_remove(&mut self, _element: &Element) {}
+
+    // Called when the value of an element is changed.
+    fn on_update(&mut self, _old: &Element, _new: &Element) {}
+}
+
+// A user-defined aggregate over the elements of a subtree, stored in every node.
+// combine() must be associative with identity() as its identity element, i.e. a monoid. It is
+// applied in key order, so it need not be commutative. identity() must not panic. insert() and
+// the deletions restructure the tree before computing the aggregates, so a panic in element() or
+// combine() loses no element, and the aggregates left out of date are recomputed by the next
+// mutation. The other mutations, e.g. split_off(), still need functions that do not panic.
+pub trait Augment {
+    type Value: Clone + PartialEq;
+
+    // The aggregate of no elements.
+    fn identity() -> Self::Value;
+
+    // The aggregate of a single element.
+    fn element(element: &Element) -> Self::Value;
+
+    // Combines the aggregates of two adjacent runs of elements.
+    fn combine(left: &Self::Value, right: &Self::Value) -> Self::Value;
+}
+
+// No augmentation. This is the default.
+impl Augment for () {
+    type Value = ();
+
+    fn identity() {}
+
+    fn element(_element: &Element) {}
+
+    fn combine(_left: &(), _right: &()) {}
+}
+
+// Error returned by TwoThreeTree::parse(), with the 1-based number of the offending line.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseError {
+    pub line: usize,
+    pub message: String,
 }
 
-impl std::cmp::PartialOrd for Element {
-    fn partial_cmp(&self, other: &Element) -> Option<std::cmp::Ordering> {
-        self.key.partial_cmp(&other.key)
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
     }
 }
 
+impl std::error::Error for ParseError {}
+
+// A parsed line of the pretty printer output: line number, depth and elements of a node.
+type ParsedLine = (usize, usize, Element, Option<Element>);
+
+// A subtree built from parsed lines: the node, its height and its smallest and largest keys.
+type ParsedSubtree<A> = (Box<TwoThreeNode<A>>, usize, usize, usize);
+
+// A step of the descent to a key, as returned by TwoThreeTree::path_to().
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PathStep {
+    // The key was compared with an element of the node at the given depth, the root being 0.
+    Compare {
+        depth: usize,
+        element: Element,
+        ordering: Ordering,
+    },
+
+    // The search went down to child 1, 2 or 3 of the node at the given depth.
+    Descend {
+        depth: usize,
+        child: u8,
+    },
+}
+
+// The result of TwoThreeTree::find_nearest().
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Nearest<'a> {
+    // An element with the key.
+    Exact(&'a Element),
+
+    // The closest element, with a smaller key.
+    Below(&'a Element),
+
+    // The closest element, with a larger key.
+    Above(&'a Element),
+
+    // The tree is empty.
+    Empty,
+}
+
 // A node in the tere. No parent pointer here.
-struct TwoThreeNode {
+struct TwoThreeNode<A: Augment> {
     elem1: Element,
     elem2: Option<Element>,
-    child1: Option<Box<TwoThreeNode>>,
-    child2: Option<Box<TwoThreeNode>>,
-    child3: Option<Box<TwoThreeNode>>,
+    child1: Option<Box<TwoThreeNode<A>>>,
+    child2: Option<Box<TwoThreeNode<A>>>,
+    child3: Option<Box<TwoThreeNode<A>>>,
+
+    // Number of elements in the subtree.
+    size: usize,
+
+    // Aggregate of the elements in the subtree.
+    aug: A::Value,
+
+    // The aggregate is out of date, until refresh_stale(). The parent of a stale node is stale.
+    stale: bool,
 }
 
 // A 2-3 Tree.
-pub struct TwoThreeTree {
-    root: Option<Box<TwoThreeNode>>,
+pub struct TwoThreeTree<A: Augment = ()> {
+    root: Option<Box<TwoThreeNode<A>>>,
 
     // Number of elements in the tree.
     size: usize,
+
+    // Orders the keys, by their natural order unless set by with_comparator().
+    compare: Comparator,
+
+    // What insert() does with a key already in the tree.
+    policy: DuplicatePolicy,
+
+    // Observers notified of mutations.
+    observers: Vec<Box<dyn Observer + Send + Sync>>,
+
+    // Incremented on every mutation, to detect stale cursors.
+    generation: u64,
+
+    // Cached smallest and largest elements, refreshed on every mutation.
+    min: Option<Element>,
+    max: Option<Element>,
+
+    // Filter for fast negative lookups, if enabled.
+    #[cfg(feature = "bloom")]
+    pub(crate) bloom: Option<BloomFilter>,
+
+    // Operation counters, if enabled.
+    #[cfg(feature = "metrics")]
+    pub(crate) metrics: MetricsCounters,
 }
 
 // Used in Insertion phase.
-struct InsertSubtree {
+struct InsertSubtree<A: Augment> {
     parent_element: Element,
-    child1: Box<TwoThreeNode>,
-    child2: Box<TwoThreeNode>,
+    child1: Box<TwoThreeNode<A>>,
+    child2: Box<TwoThreeNode<A>>,
+}
+
+// A subtree and its height, 0 for a leaf, or None for an empty subtree.
+type Subtree<A> = Option<(Box<TwoThreeNode<A>>, usize)>;
+
+// The result of joining onto a node: the node, or its two halves and the separator between
+// them if it overflowed.
+type Joined<A> =
+    Result<Box<TwoThreeNode<A>>, (Box<TwoThreeNode<A>>, Element, Box<TwoThreeNode<A>>)>;
+
+// Builds a tree from elements pushed in key order, without buffering them. levels[h] holds the
+// pending (subtree of height h, separator) pairs of the node being filled at height h + 1, and
+// leaf the pending elements of the leaf being filled.
+struct SortedBuilder<A: Augment> {
+    leaf: Vec<Element>,
+    levels: Vec<Vec<(Box<TwoThreeNode<A>>, Element)>>,
+    last_key: Option<usize>,
+    size: usize,
+}
+
+impl<A: Augment> SortedBuilder<A> {
+    fn new() -> SortedBuilder<A> {
+        SortedBuilder {
+            leaf: Vec::new(),
+            levels: Vec::new(),
+            last_key: None,
+            size: 0,
+        }
+    }
+
+    // Appends an element. Panics if it is smaller than the previous one.
+    fn push(&mut self, element: Element) {
+        assert!(self.last_key.is_none_or(|key| key <= element.key));
+        self.last_key = Some(element.key);
+        self.size += 1;
+        if self.leaf.len() < 2 {
+            self.leaf.push(element);
+            return;
+        }
+        let mut node = TwoThreeTree::new_node(self.leaf[0]);
+        node.elem2 = Some(self.leaf[1]);
+        TwoThreeTree::update_node(&mut node);
+        self.leaf.clear();
+        self.push_pair(0, node, element);
+    }
+
+    // Appends a full subtree and the element after it at the given height. A third pair
+    // completes a 3-node, which moves up a level.
+    fn push_pair(&mut self, height: usize, child: Box<TwoThreeNode<A>>, separator: Element) {
+        if self.levels.len() == height {
+            self.levels.push(Vec::with_capacity(3));
+        }
+        self.levels[height].push((child, separator));
+        if self.levels[height].len() < 3 {
+            return;
+        }
+        let mut pairs = std::mem::take(&mut self.levels[height]).into_iter();
+        let (child1, elem1) = pairs.next().unwrap();
+        let (child2, elem2) = pairs.next().unwrap();
+        let (child3, separator) = pairs.next().unwrap();
+        let mut node = TwoThreeTree::new_node(elem1);
+        node.elem2 = Some(elem2);
+        node.child1 = Some(child1);
+        node.child2 = Some(child2);
+        node.child3 = Some(child3);
+        TwoThreeTree::update_node(&mut node);
+        self.push_pair(height + 1, node, separator);
+    }
+
+    // Joins the pending subtrees, tallest first, then inserts the remaining elements.
+    fn finish(self) -> TwoThreeTree<A> {
+        let mut root: Option<(Box<TwoThreeNode<A>>, usize)> = None;
+        let mut separator = None;
+        for (height, pairs) in self.levels.into_iter().enumerate().rev() {
+            for (child, next) in pairs {
+                root = Some(match (root, separator) {
+                    (Some(node), Some(separator)) => {
+                        TwoThreeTree::join(Some(node), separator, Some((child, height)))
+                    }
+                    _ => (child, height),
+                });
+                separator = Some(next);
+            }
+        }
+
+        let mut tree = TwoThreeTree::with_augment();
+        tree.root = root.map(|(node, _)| node);
+        tree.size = tree.root.as_ref().map_or(0, |node| node.size);
+        for element in separator.into_iter().chain(self.leaf) {
+            tree.insert(element);
+        }
+        debug_assert!(tree.size == self.size);
+        tree
+    }
+}
+
+// A rebalancing action, reported to the metrics and tracing features.
+#[derive(Clone, Copy, Debug)]
+enum Rebalance {
+    // A node overflowed on insertion and was split in two.
+    Split,
+
+    // A node underflowed on deletion and was merged with a sibling.
+    Merge,
+
+    // A node underflowed on deletion and borrowed an element from a sibling.
+    Borrow,
 }
 
 // Tracks the phase of the deletion operation.
@@ -67,18 +326,136 @@ struct DeleteState {
     // The deletion element key.
     key: usize,
 
+    // Orders the keys.
+    compare: Comparator,
+
     // The current phase of the operation.
     phase: DeletePhase,
 
     // The predecessor of the element to be deleted.
     predecessor: Option<Element>,
+
+    // The element that was deleted.
+    removed: Option<Element>,
 }
 
 impl TwoThreeTree {
     pub fn new() -> TwoThreeTree {
+        TwoThreeTree::with_augment()
+    }
+
+    // Creates an empty tree ordering its keys by compare rather than by their natural order,
+    // e.g. descending. Whatever refers to key order follows compare, e.g. first(), range(),
+    // scan(), evict_older_than() and diff(), and a range starts at the key coming first in it.
+    pub fn with_comparator(compare: Comparator) -> TwoThreeTree {
+        TwoThreeTree {
+            compare,
+            ..TwoThreeTree::new()
+        }
+    }
+
+    // Creates an empty tree whose insert() applies the policy to keys already in the tree.
+    pub fn with_policy(policy: DuplicatePolicy) -> TwoThreeTree {
+        TwoThreeTree {
+            policy,
+            ..TwoThreeTree::new()
+        }
+    }
+
+    // Builds a tree from elements sorted by key, in O(n).
+    // Panics if the elements are not sorted.
+    pub fn from_sorted(elements: impl IntoIterator<Item = Element>) -> TwoThreeTree {
+        TwoThreeTree::build_sorted(elements)
+    }
+
+    // Builds a tree from several runs sorted by key, e.g. runs spilled by an external sort, in
+    // O(n log k). The runs are merged and streamed into the tree, so the input is never buffered.
+    // Panics if a run is not sorted.
+    pub fn from_sorted_runs<I: IntoIterator<Item = Element>>(
+        runs: impl IntoIterator<Item = I>,
+    ) -> TwoThreeTree {
+        let mut runs: Vec<_> = runs
+            .into_iter()
+            .map(|run| run.into_iter().peekable())
+            .collect();
+
+        // Merge the runs with a heap of (key, run).
+        let mut heap: BinaryHeap<Reverse<(usize, usize)>> = runs
+            .iter_mut()
+            .enumerate()
+            .filter_map(|(run, elements)| Some(Reverse((elements.peek()?.key, run))))
+            .collect();
+        let mut builder = SortedBuilder::new();
+        while let Some(Reverse((_, run))) = heap.pop() {
+            builder.push(runs[run].next().unwrap());
+            if let Some(next) = runs[run].peek() {
+                heap.push(Reverse((next.key, run)));
+            }
+        }
+        builder.finish()
+    }
+
+    // Builds a tree of exactly the given shape. Panics if the shape is not a valid 2-3 tree.
+    pub fn from_shape(shape: Shape) -> TwoThreeTree {
+        let mut tree = TwoThreeTree::with_augment();
+        let root = TwoThreeTree::build_shape(&shape);
+        tree.size = root.size;
+        tree.root = Some(root);
+        tree.refresh_bounds();
+        tree.validate();
+        tree
+    }
+
+    // Builds a tree from its red-black form, e.g. the output of to_red_black(). Red links may
+    // lean either way. Panics if a node has two red children or a red child of a red node, or if
+    // the result is not a valid 2-3 tree.
+    pub fn from_red_black(root: &RedBlackNode) -> TwoThreeTree {
+        let mut tree = TwoThreeTree::with_augment();
+        let root = TwoThreeTree::build_red_black(root);
+        tree.size = root.size;
+        tree.root = Some(root);
+        tree.refresh_bounds();
+        tree.validate();
+        tree
+    }
+
+    // Reconstructs a tree from the output of to_pretty_string(), e.g. to replay a structure
+    // captured in a bug report.
+    pub fn parse(text: &str) -> Result<TwoThreeTree, ParseError> {
+        TwoThreeTree::parse_text(text)
+    }
+}
+
+impl<A: Augment> TwoThreeTree<A> {
+    // Creates an empty tree maintaining the augmentation A.
+    pub fn with_augment() -> TwoThreeTree<A> {
         TwoThreeTree {
             root: None,
             size: 0,
+            compare: usize::cmp,
+            policy: DuplicatePolicy::Allow,
+            observers: Vec::new(),
+            generation: 0,
+            min: None,
+            max: None,
+            #[cfg(feature = "bloom")]
+            bloom: None,
+            #[cfg(feature = "metrics")]
+            metrics: MetricsCounters::default(),
+        }
+    }
+
+    // Returns the order of the keys.
+    pub(crate) fn comparator(&self) -> Comparator {
+        self.compare
+    }
+
+    // Creates an empty tree with the same key order and duplicate policy.
+    fn empty_like(&self) -> TwoThreeTree<A> {
+        TwoThreeTree {
+            compare: self.compare,
+            policy: self.policy,
+            ..Self::with_augment()
         }
     }
 
@@ -90,153 +467,921 @@ impl TwoThreeTree {
         self.size
     }
 
-    // Prints a textual representation of the tree.
-    pub fn print(&self) {
-        if let Some(ref root_node) = self.root {
-            println!("Tree({}):", self.size);
-            Self::print_node(root_node, 0);
-        } else {
-            println!("Empty tree");
+    // Returns the mutation generation. It changes whenever the tree is mutated.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    // Returns the element with the smallest key, in O(1).
+    pub fn first(&self) -> Option<Element> {
+        self.min
+    }
+
+    // Returns the element with the largest key, in O(1).
+    pub fn last(&self) -> Option<Element> {
+        self.max
+    }
+
+    // Marks the tree as mutated: stale cursors are detected and the cached bounds and the stale
+    // aggregates refreshed. With the paranoid feature, debug builds also check the invariants
+    // after every mutation.
+    fn mutated(&mut self) {
+        self.generation += 1;
+        self.refresh_bounds();
+        self.refresh_aggregates();
+        #[cfg(all(feature = "paranoid", debug_assertions))]
+        self.validate();
+    }
+
+    // Recomputes the cached smallest and largest elements by walking the outer spines.
+    fn refresh_bounds(&mut self) {
+        self.min = self.find_first();
+        self.max = self.find_last();
+    }
+
+    // Recomputes the stale aggregates, left by insert() and the deletions.
+    fn refresh_aggregates(&mut self) {
+        if let Some(root) = self.root.as_mut() {
+            Self::refresh_stale(root);
         }
     }
 
-    // Prints a node recursively.
-    fn print_node(node: &TwoThreeNode, indent: usize) {
-        for _ in 0..indent {
-            print!("| ");
+    fn find_first(&self) -> Option<Element> {
+        let mut node = self.root.as_deref()?;
+        while let Some(ref child1) = node.child1 {
+            node = child1;
         }
-        print!("Element: {}", node.elem1.key);
-        if let Some(elem2) = node.elem2 {
-            print!(" {}", elem2.key);
+        Some(node.elem1)
+    }
+
+    fn find_last(&self) -> Option<Element> {
+        let mut node = self.root.as_deref()?;
+        while let Some(child) = node.child3.as_deref().or(node.child2.as_deref()) {
+            node = child;
         }
-        println!();
-        if let Some(ref child1) = node.child1 {
-            Self::print_node(child1, indent + 1);
+        node.elem2.or(Some(node.elem1))
+    }
+
+    // Returns the element with the given rank, i.e. the (rank + 1)-th smallest, in O(log n).
+    pub fn select(&self, rank: usize) -> Option<Element> {
+        if rank >= self.size {
+            return None;
         }
-        if let Some(ref child2) = node.child2 {
-            Self::print_node(child2, indent + 1);
+        let mut node = self.root.as_deref()?;
+        let mut rank = rank;
+        loop {
+            let child_size =
+                |child: &Option<Box<TwoThreeNode<A>>>| child.as_ref().map_or(0, |c| c.size);
+            let size1 = child_size(&node.child1);
+            if rank < size1 {
+                node = node.child1.as_deref().unwrap();
+                continue;
+            }
+            if rank == size1 {
+                return Some(node.elem1);
+            }
+            rank -= size1 + 1;
+            let size2 = child_size(&node.child2);
+            if rank < size2 {
+                node = node.child2.as_deref().unwrap();
+                continue;
+            }
+            if rank == size2 {
+                return node.elem2;
+            }
+            rank -= size2 + 1;
+            node = node.child3.as_deref().unwrap();
         }
-        if let Some(ref child3) = node.child3 {
-            Self::print_node(child3, indent + 1);
+    }
+
+    // Returns the first element whose key fails the predicate, in O(log n).
+    // The predicate must be monotone: true for a prefix of the keys in order and false for the
+    // rest, as for slice::partition_point(). Returns None if it holds for all keys.
+    pub fn partition_point(&self, pred: impl Fn(usize) -> bool) -> Option<Element> {
+        let mut result = None;
+        let mut next = self.root.as_deref();
+        while let Some(node) = next {
+            if !pred(node.elem1.key) {
+                result = Some(node.elem1);
+                next = node.child1.as_deref();
+            } else if node.elem2.is_some_and(|elem2| !pred(elem2.key)) {
+                result = node.elem2;
+                next = node.child2.as_deref();
+            } else if node.elem2.is_some() {
+                next = node.child3.as_deref();
+            } else {
+                next = node.child2.as_deref();
+            }
         }
+        result
     }
 
-    // Inserts an element.
-    pub fn insert(&mut self, element: Element) {
-        match &mut self.root {
-            None => {
-                self.root = Some(Self::new_node(element));
+    // Returns the number of elements with keys less than the given key, in O(log n).
+    pub fn rank(&self, key: usize) -> usize {
+        self.count_before(key, false)
+    }
+
+    // Returns the number of elements with keys less than the given key, or equal to it too if
+    // inclusive, in O(log n).
+    fn count_before(&self, key: usize, inclusive: bool) -> usize {
+        let before = |e: Element| match (self.compare)(&e.key, &key) {
+            Ordering::Less => true,
+            Ordering::Equal => inclusive,
+            Ordering::Greater => false,
+        };
+        let mut rank = 0;
+        let mut next = self.root.as_deref();
+        while let Some(node) = next {
+            let child_size =
+                |child: &Option<Box<TwoThreeNode<A>>>| child.as_ref().map_or(0, |c| c.size);
+            if !before(node.elem1) {
+                next = node.child1.as_deref();
+                continue;
             }
-            Some(ref mut root_node) => {
-                if let Some(new_subtree) = Self::insert_node(root_node.as_mut(), &element) {
-                    let mut new_root = Self::new_node(new_subtree.parent_element);
-                    new_root.child1 = Some(new_subtree.child1);
-                    new_root.child2 = Some(new_subtree.child2);
-                    self.root = Some(new_root);
+            rank += child_size(&node.child1) + 1;
+            match node.elem2 {
+                Some(elem2) if before(elem2) => {
+                    rank += child_size(&node.child2) + 1;
+                    next = node.child3.as_deref();
                 }
+                _ => next = node.child2.as_deref(),
             }
         }
-        self.size += 1;
+        rank
     }
 
-    // Inserts a node, recursively.
-    fn insert_node(node: &mut TwoThreeNode, element: &Element) -> Option<InsertSubtree> {
-        if let Some(ref mut child) = node.child1 {
-            // Not a leaf node.
-            if element.key <= node.elem1.key {
-                // Insert element in child1 subtree.
-                let result = Self::insert_node(child, element);
-                if let Some(new_subtree) = result {
-                    match node.elem2 {
-                        None => {
-                            //    (a)           (result.parent_element, a)
-                            //  /    \      =>    /           |           \
-                            // result (b)     result.child1 result.child2 (b)
-                            node.elem2 = Some(node.elem1);
-                            node.elem1 = new_subtree.parent_element;
-                            node.child3 = node.child2.take();
-                            node.child1 = Some(new_subtree.child1);
-                            node.child2 = Some(new_subtree.child2);
-                            return None;
-                        }
-                        Some(elem2) => {
-                            //      (a,b)                         (a)
-                            //    /    |  \     =>             /       \
-                            // result (c) (d)      result.parent         (b)
-                            //                        /      \            /  \
-                            //               result.child1 result.child2 (c) (d)
-                            let mut left_node = Self::new_node(new_subtree.parent_element);
-                            left_node.child1 = Some(new_subtree.child1);
-                            left_node.child2 = Some(new_subtree.child2);
+    // Returns the number of elements with keys in [start, end], in O(log n).
+    pub(crate) fn count_range(&self, start: usize, end: usize) -> usize {
+        self.count_before(end, true)
+            .saturating_sub(self.count_before(start, false))
+    }
 
-                            let mut right_node = Self::new_node(elem2);
-                            right_node.child1 = node.child2.take();
-                            right_node.child2 = node.child3.take();
+    // Returns the median element. For an even number of elements, the lower one of the two
+    // middle elements is returned.
+    pub fn median(&self) -> Option<Element> {
+        self.select(self.size.checked_sub(1)? / 2)
+    }
 
-                            return Some(InsertSubtree {
-                                parent_element: node.elem1,
-                                child1: left_node,
-                                child2: right_node,
-                            });
-                        }
+    // Returns the element at the given percentile, from 0 to 100, using the nearest-rank method:
+    // the smallest element such that at least p percent of the elements are less or equal.
+    pub fn percentile(&self, p: f64) -> Option<Element> {
+        assert!((0.0..=100.0).contains(&p));
+        let rank = (p / 100.0 * self.size as f64).ceil() as usize;
+        self.select(rank.saturating_sub(1))
+    }
+
+    // Returns an iterator over the pairs of consecutive elements, in key order.
+    pub fn windows(&self) -> Windows<'_, A> {
+        Windows {
+            iter: self.iter(),
+            previous: None,
+        }
+    }
+
+    // Returns an iterator over batches of n elements, in key order. Panics if n is 0.
+    pub fn chunks(&self, n: usize) -> Chunks<'_, A> {
+        assert!(n > 0);
+        Chunks {
+            iter: self.iter(),
+            size: n,
+        }
+    }
+
+    // Returns an iterator over the elements in key order.
+    pub fn iter(&self) -> Iter<'_, A> {
+        let mut iter = Iter { stack: Vec::new() };
+        if let Some(ref root) = self.root {
+            iter.stack.push((root, 0));
+        }
+        iter
+    }
+
+    // Returns a parallel iterator over the elements in key order.
+    // The tree is split at internal nodes into subtrees which Rayon processes independently.
+    #[cfg(feature = "rayon")]
+    pub fn par_iter(&self) -> impl rayon::iter::ParallelIterator<Item = Element> + '_
+    where
+        A::Value: Sync,
+    {
+        use rayon::iter::ParallelIterator;
+
+        let parts = self
+            .root
+            .as_deref()
+            .map(IterPart::Node)
+            .into_iter()
+            .collect();
+        rayon::iter::split(parts, |mut parts: Vec<IterPart<'_, A>>| {
+            // A single subtree is split into its children and elements.
+            if let [IterPart::Node(node)] = parts[..] {
+                if let (Some(child1), Some(child2)) = (&node.child1, &node.child2) {
+                    parts = vec![
+                        IterPart::Node(child1),
+                        IterPart::Element(node.elem1),
+                        IterPart::Node(child2),
+                    ];
+                    if let (Some(elem2), Some(child3)) = (node.elem2, &node.child3) {
+                        parts.push(IterPart::Element(elem2));
+                        parts.push(IterPart::Node(child3));
                     }
-                } else {
-                    return None;
                 }
             }
-
-            if node.elem2.is_none() || element.key <= node.elem2.unwrap().key {
-                // Insert element under child2 subtree.
-                let result = Self::insert_node(node.child2.as_mut().unwrap(), element);
-                if let Some(new_subtree) = result {
-                    match node.elem2 {
-                        None => {
-                            //   (a)           (a, result.parent_element)
-                            //  /   \      =>    /     |         \
-                            // (b) result       b, result.child1 result.child2
-                            node.elem2 = Some(new_subtree.parent_element);
-                            node.child2 = Some(new_subtree.child1);
-                            node.child3 = Some(new_subtree.child2);
-                            return None;
-                        }
-                        Some(elem2) => {
-                            //     (a, b)                 result.parent_element
-                            //   /   |    \      =>   (a)                       (b)
-                            //  (c) result (d)       /  \                     /   \
-                            //                      (c) result.child1  result.child2 (d)
-                            let mut left_node = Self::new_node(node.elem1);
-                            left_node.child1 = node.child1.take();
-                            left_node.child2 = Some(new_subtree.child1);
-                            let mut right_node = Self::new_node(elem2);
-                            right_node.child1 = Some(new_subtree.child2);
-                            right_node.child2 = node.child3.take();
-                            return Some(InsertSubtree {
-                                parent_element: new_subtree.parent_element,
-                                child1: left_node,
-                                child2: right_node,
-                            });
-                        }
-                    }
-                } else {
-                    return None;
+            if parts.len() < 2 {
+                return (parts, None);
+            }
+            let right = parts.split_off(parts.len() / 2);
+            (parts, Some(right))
+        })
+        .flat_map_iter(|parts| {
+            parts.into_iter().flat_map(|part| {
+                let (node, element) = match part {
+                    IterPart::Node(node) => (Some((node, 0)), None),
+                    IterPart::Element(element) => (None, Some(element)),
+                };
+                Iter {
+                    stack: node.into_iter().collect(),
                 }
+                .chain(element)
+            })
+        })
+    }
+
+    // Returns an iterator starting at the first element with a key >= the given key.
+    // Setting up the iterator takes O(log n), so the last seen key can be used to resume an
+    // iteration.
+    pub fn iter_from(&self, key: usize) -> Iter<'_, A> {
+        let mut iter = Iter { stack: Vec::new() };
+        let mut next = self.root.as_deref();
+        while let Some(node) = next {
+            // Position the node after the elements less than the key, and continue with the
+            // child holding the keys in between.
+            let at_most = |e: Element| (self.compare)(&key, &e.key) != Ordering::Greater;
+            if at_most(node.elem1) {
+                iter.stack.push((node, 1));
+                next = node.child1.as_deref();
+            } else if node.elem2.is_none_or(at_most) {
+                iter.stack.push((node, 3));
+                next = node.child2.as_deref();
+            } else {
+                iter.stack.push((node, 5));
+                next = node.child3.as_deref();
             }
+        }
+        iter
+    }
 
-            // Insert element under child3 subtree.
-            let result = Self::insert_node(node.child3.as_mut().unwrap(), element);
-            if let Some(new_subtree) = result {
-                //    (a,b)                     (b)
-                //   /  |  \           =>     /     \
-                //  (c) (d) result           (a)     (result.parent)
-                //                          /  \      /             \
-                //                         (c) (d) result.child1 result.child2
-                let mut left_node = Self::new_node(node.elem1);
-                left_node.child1 = node.child1.take();
-                left_node.child2 = node.child2.take();
-                let mut right_node = Self::new_node(new_subtree.parent_element);
-                right_node.child1 = Some(new_subtree.child1);
-                right_node.child2 = Some(new_subtree.child2);
-                return Some(InsertSubtree {
+    // Returns an iterator over the elements with keys in the range, in key order. Setting up the
+    // iterator takes O(log n). As for_each_in_range_mut(), the range runs in the order of the tree.
+    pub fn range(&self, range: impl RangeBounds<usize>) -> impl Iterator<Item = Element> + '_ {
+        let bounds = KeyBounds::new(&range, self.compare);
+        let iter = match bounds.start {
+            Bound::Included(start) | Bound::Excluded(start) => self.iter_from(start),
+            Bound::Unbounded => self.iter(),
+        };
+        iter.skip_while(move |e| !bounds.after_start(e.key))
+            .take_while(move |e| bounds.before_end(e.key))
+    }
+
+    // Returns an iterator over the elements with ranks in the range, i.e. by position in key
+    // order rather than by key. Setting up the iterator takes O(log n).
+    pub fn range_by_rank(&self, range: impl RangeBounds<usize>) -> std::iter::Take<Iter<'_, A>> {
+        let (start, end) = inclusive_range(&range).unwrap_or((1, 0));
+        let count = if start <= end {
+            end.saturating_add(1).min(self.size).saturating_sub(start)
+        } else {
+            0
+        };
+        self.iter_at_rank(start).take(count)
+    }
+
+    // Returns the k elements with the smallest keys, in ascending key order, in O(log n + k).
+    pub fn bottom_k(&self, k: usize) -> Vec<Element> {
+        self.range_by_rank(..k).collect()
+    }
+
+    // Returns the k elements with the largest keys, in descending key order, in O(log n + k).
+    pub fn top_k(&self, k: usize) -> Vec<Element> {
+        let mut top: Vec<Element> = self.range_by_rank(self.size.saturating_sub(k)..).collect();
+        top.reverse();
+        top
+    }
+
+    // Returns an iterator starting at the element with the given rank.
+    fn iter_at_rank(&self, rank: usize) -> Iter<'_, A> {
+        let child_size =
+            |child: &Option<Box<TwoThreeNode<A>>>| child.as_ref().map_or(0, |c| c.size);
+        let mut iter = Iter { stack: Vec::new() };
+        let mut rank = rank;
+        let mut next = self.root.as_deref();
+        while let Some(node) = next {
+            // As in iter_from(), with the ranks within the subtree in place of the keys.
+            let size1 = child_size(&node.child1);
+            if rank <= size1 {
+                iter.stack.push((node, 1));
+                next = node.child1.as_deref();
+                continue;
+            }
+            rank -= size1 + 1;
+            if node.elem2.is_none() || rank <= child_size(&node.child2) {
+                iter.stack.push((node, 3));
+                next = node.child2.as_deref();
+            } else {
+                rank -= child_size(&node.child2) + 1;
+                iter.stack.push((node, 5));
+                next = node.child3.as_deref();
+            }
+        }
+        iter
+    }
+
+    // Consumes the tree, returning its (key, value) pairs in key order.
+    pub fn into_sorted_vec(self) -> Vec<(usize, usize)> {
+        let mut pairs = Vec::new();
+        self.dump_into(&mut pairs);
+        pairs
+    }
+
+    // Appends all (key, value) pairs in key order to the buffer.
+    // The buffer grows at most once, so a reused buffer is not reallocated per element.
+    pub fn dump_into(&self, buf: &mut Vec<(usize, usize)>) {
+        buf.reserve(self.size);
+        if let Some(ref root) = self.root {
+            Self::dump_node(root, buf);
+        }
+    }
+
+    // Appends the pairs of a subtree in key order, recursively.
+    fn dump_node(node: &TwoThreeNode<A>, buf: &mut Vec<(usize, usize)>) {
+        if let Some(ref child1) = node.child1 {
+            Self::dump_node(child1, buf);
+        }
+        buf.push((node.elem1.key, node.elem1.value));
+        if let Some(ref child2) = node.child2 {
+            Self::dump_node(child2, buf);
+        }
+        if let Some(elem2) = node.elem2 {
+            buf.push((elem2.key, elem2.value));
+        }
+        if let Some(ref child3) = node.child3 {
+            Self::dump_node(child3, buf);
+        }
+    }
+
+    // Returns a read-only view of the root node, or None if the tree is empty.
+    pub fn root_ref(&self) -> Option<NodeRef<'_, A>> {
+        self.root.as_deref().map(|node| NodeRef { node })
+    }
+
+    // Returns an iterator over the levels of the tree, each a vector of node views.
+    pub fn levels(&self) -> Levels<'_, A> {
+        Levels {
+            next: self.root_ref().into_iter().collect(),
+        }
+    }
+
+    // Returns a cursor positioned before the first element.
+    // Unlike an iterator, a cursor does not borrow the tree. It panics when used after the tree
+    // has been mutated.
+    pub fn cursor(&self) -> Cursor {
+        Cursor {
+            generation: self.generation,
+            last: None,
+        }
+    }
+
+    // Prints a textual representation of the tree.
+    pub fn print(&self) {
+        print!("{}", self.to_pretty_string());
+    }
+
+    // Renders the tree with box-drawing characters, one node per line with the children of a
+    // node indented below it, so that the nodes of a level line up. Elements are shown as
+    // key:value, 2-nodes in parentheses and 3-nodes in brackets:
+    //
+    //   Tree(4):
+    //   (2:20)
+    //   ├── (1:10)
+    //   └── [3:30 | 4:40]
+    pub fn to_pretty_string(&self) -> String {
+        let mut out = String::new();
+        if let Some(ref root_node) = self.root {
+            out.push_str(&format!("Tree({}):\n", self.size));
+            Self::pretty_node(root_node, &mut String::new(), &mut out);
+        } else {
+            out.push_str("Empty tree\n");
+        }
+        out
+    }
+
+    // Renders a node and its subtrees recursively. The prefix holds the vertical lines of the
+    // ancestors that have further children.
+    fn pretty_node(node: &TwoThreeNode<A>, prefix: &mut String, out: &mut String) {
+        match node.elem2 {
+            None => out.push_str(&format!("({}:{})\n", node.elem1.key, node.elem1.value)),
+            Some(elem2) => out.push_str(&format!(
+                "[{}:{} | {}:{}]\n",
+                node.elem1.key, node.elem1.value, elem2.key, elem2.value
+            )),
+        }
+        let children: Vec<&TwoThreeNode<A>> = [&node.child1, &node.child2, &node.child3]
+            .into_iter()
+            .flatten()
+            .map(|child| child.as_ref())
+            .collect();
+        for (i, child) in children.iter().enumerate() {
+            let last = i + 1 == children.len();
+            out.push_str(prefix);
+            out.push_str(if last { "└── " } else { "├── " });
+            let len = prefix.len();
+            prefix.push_str(if last { "    " } else { "│   " });
+            Self::pretty_node(child, prefix, out);
+            prefix.truncate(len);
+        }
+    }
+
+    // Reconstructs a tree maintaining the augmentation A from the output of
+    // to_pretty_string(). The exact shape is kept; the tree must be valid.
+    pub fn parse_text(text: &str) -> Result<TwoThreeTree<A>, ParseError> {
+        let error = |line: usize, message: &str| ParseError {
+            line,
+            message: message.to_string(),
+        };
+        let mut lines = (1..)
+            .zip(text.lines())
+            .filter(|(_, text)| !text.trim().is_empty());
+        let (line, header) = lines.next().ok_or_else(|| error(1, "missing header"))?;
+
+        let mut tree = TwoThreeTree::with_augment();
+        if header.trim() == "Empty tree" {
+            return match lines.next() {
+                Some((line, _)) => Err(error(line, "unexpected node in an empty tree")),
+                None => Ok(tree),
+            };
+        }
+        let size: usize = header
+            .trim()
+            .strip_prefix("Tree(")
+            .and_then(|s| s.strip_suffix("):"))
+            .and_then(|s| s.parse().ok())
+            .ok_or_else(|| error(line, "expected Tree(<size>):"))?;
+
+        let nodes = lines
+            .map(|(line, text)| Self::parse_line(line, text))
+            .collect::<Result<Vec<_>, _>>()?;
+        if nodes.is_empty() {
+            return Err(error(line, "missing root node"));
+        }
+        let mut pos = 0;
+        let (root, _, _, _) = Self::parse_node(&nodes, &mut pos, 0)?;
+        if pos < nodes.len() {
+            return Err(error(
+                nodes[pos].0,
+                "unexpected node after the root subtree",
+            ));
+        }
+        if root.size != size {
+            return Err(error(
+                line,
+                &format!("header size {} but {} elements", size, root.size),
+            ));
+        }
+        tree.root = Some(root);
+        tree.size = size;
+        tree.refresh_bounds();
+        Ok(tree)
+    }
+
+    // Parses a line such as "│   └── [3:30 | 4:40]". The depth is the number of 4-character
+    // columns before the node.
+    fn parse_line(line: usize, text: &str) -> Result<ParsedLine, ParseError> {
+        let error = |message: &str| ParseError {
+            line,
+            message: message.to_string(),
+        };
+        let mut label = text.trim_end();
+        let mut depth = 0;
+        while let Some(rest) = ["│   ", "    ", "├── ", "└── "]
+            .iter()
+            .find_map(|column| label.strip_prefix(column))
+        {
+            label = rest;
+            depth += 1;
+        }
+
+        let parse_element = |s: &str| -> Result<Element, ParseError> {
+            let (key, value) = s
+                .split_once(':')
+                .ok_or_else(|| error("expected key:value"))?;
+            match (key.trim().parse(), value.trim().parse()) {
+                (Ok(key), Ok(value)) => Ok(Element { key, value }),
+                _ => Err(error("invalid key or value")),
+            }
+        };
+        if let Some(inner) = label.strip_prefix('(').and_then(|s| s.strip_suffix(')')) {
+            return Ok((line, depth, parse_element(inner)?, None));
+        }
+        if let Some(inner) = label.strip_prefix('[').and_then(|s| s.strip_suffix(']')) {
+            let (elem1, elem2) = inner.split_once('|').ok_or_else(|| error("expected |"))?;
+            return Ok((
+                line,
+                depth,
+                parse_element(elem1)?,
+                Some(parse_element(elem2)?),
+            ));
+        }
+        Err(error("expected (key:value) or [key:value | key:value]"))
+    }
+
+    // Builds the subtree of the node at the given position, recursively, checking its shape
+    // and ordering.
+    fn parse_node(
+        nodes: &[ParsedLine],
+        pos: &mut usize,
+        depth: usize,
+    ) -> Result<ParsedSubtree<A>, ParseError> {
+        let (line, node_depth, elem1, elem2) = nodes[*pos];
+        let error = |message: &str| ParseError {
+            line,
+            message: message.to_string(),
+        };
+        if node_depth != depth {
+            return Err(error("unexpected indentation"));
+        }
+        *pos += 1;
+
+        let mut node = Self::new_node(elem1);
+        node.elem2 = elem2;
+        let mut children = Vec::new();
+        while *pos < nodes.len() && nodes[*pos].1 > depth {
+            children.push(Self::parse_node(nodes, pos, depth + 1)?);
+        }
+
+        // The keys separating the children, in order.
+        let keys: Vec<usize> = [Some(elem1), elem2]
+            .iter()
+            .flatten()
+            .map(|e| e.key)
+            .collect();
+        if keys.windows(2).any(|pair| pair[0] > pair[1]) {
+            return Err(error("elements out of order"));
+        }
+        if children.is_empty() {
+            Self::update_node(&mut node);
+            return Ok((node, 1, keys[0], *keys.last().unwrap()));
+        }
+        if children.len() != keys.len() + 1 {
+            return Err(error(&format!(
+                "a node with {} elements needs {} children",
+                keys.len(),
+                keys.len() + 1
+            )));
+        }
+        let height = children[0].1;
+        if children.iter().any(|child| child.1 != height) {
+            return Err(error("leaves at different depths"));
+        }
+        for (i, key) in keys.iter().enumerate() {
+            if children[i].3 > *key || children[i + 1].2 < *key {
+                return Err(error("children out of order"));
+            }
+        }
+
+        let (min, max) = (children[0].2, children[children.len() - 1].3);
+        let mut children = children.into_iter().map(|child| child.0);
+        node.child1 = children.next();
+        node.child2 = children.next();
+        node.child3 = children.next();
+        Self::update_node(&mut node);
+        Ok((node, height + 1, min, max))
+    }
+
+    // Builds the nodes of a shape recursively.
+    fn build_shape(shape: &Shape) -> Box<TwoThreeNode<A>> {
+        let element = |key| Element { key, value: key };
+        assert!(!shape.keys.is_empty() && shape.keys.len() <= 2);
+        assert!(shape.children.is_empty() || shape.children.len() == shape.keys.len() + 1);
+        let mut node = Self::new_node(element(shape.keys[0]));
+        node.elem2 = shape.keys.get(1).copied().map(element);
+        let mut children = shape.children.iter().map(Self::build_shape);
+        node.child1 = children.next();
+        node.child2 = children.next();
+        node.child3 = children.next();
+        Self::update_node(&mut node);
+        node
+    }
+
+    // Builds the node of a black node and its red children, recursively.
+    fn build_red_black(node: &RedBlackNode) -> Box<TwoThreeNode<A>> {
+        assert!(!node.is_red());
+        let is_red = |child: &Option<Box<RedBlackNode>>| child.as_ref().is_some_and(|c| c.is_red());
+        let (elements, children) = match (is_red(&node.left), is_red(&node.right)) {
+            (false, false) => (vec![node.element], vec![&node.left, &node.right]),
+            (true, false) => {
+                let left = node.left.as_ref().unwrap();
+                (
+                    vec![left.element, node.element],
+                    vec![&left.left, &left.right, &node.right],
+                )
+            }
+            (false, true) => {
+                let right = node.right.as_ref().unwrap();
+                (
+                    vec![node.element, right.element],
+                    vec![&node.left, &right.left, &right.right],
+                )
+            }
+            (true, true) => panic!("a black node with two red children is a 4-node"),
+        };
+
+        let mut node = Self::new_node(elements[0]);
+        node.elem2 = elements.get(1).copied();
+        if children.iter().any(|child| child.is_some()) {
+            assert!(children.iter().all(|child| child.is_some()));
+            let mut children = children
+                .into_iter()
+                .flatten()
+                .map(|c| Self::build_red_black(c));
+            node.child1 = children.next();
+            node.child2 = children.next();
+            node.child3 = children.next();
+        }
+        Self::update_node(&mut node);
+        node
+    }
+
+    // Returns the tree as a left-leaning red-black tree, or None if it is empty. See red_black.rs
+    // for the correspondence.
+    pub fn to_red_black(&self) -> Option<Box<RedBlackNode>> {
+        self.root.as_deref().map(Self::red_black_node)
+    }
+
+    fn red_black_node(node: &TwoThreeNode<A>) -> Box<RedBlackNode> {
+        let convert =
+            |child: &Option<Box<TwoThreeNode<A>>>| child.as_deref().map(Self::red_black_node);
+        let new = |element, color, left, right| {
+            Box::new(RedBlackNode {
+                element,
+                color,
+                left,
+                right,
+            })
+        };
+        match node.elem2 {
+            None => new(
+                node.elem1,
+                Color::Black,
+                convert(&node.child1),
+                convert(&node.child2),
+            ),
+            Some(elem2) => {
+                let left = new(
+                    node.elem1,
+                    Color::Red,
+                    convert(&node.child1),
+                    convert(&node.child2),
+                );
+                new(elem2, Color::Black, Some(left), convert(&node.child3))
+            }
+        }
+    }
+
+    // Renders the tree as a Mermaid flowchart, for markdown documents and issue reports.
+    // As in to_pretty_string(), 2-nodes are drawn rounded and 3-nodes as rectangles.
+    pub fn to_mermaid(&self) -> String {
+        let mut out = String::from("flowchart TD\n");
+        if let Some(ref root_node) = self.root {
+            Self::mermaid_node(root_node, &mut 0, &mut out);
+        }
+        out
+    }
+
+    // Renders a node and its edges recursively, numbering the nodes in preorder.
+    // Returns the number of the node.
+    fn mermaid_node(node: &TwoThreeNode<A>, next_id: &mut usize, out: &mut String) -> usize {
+        let id = *next_id;
+        *next_id += 1;
+        match node.elem2 {
+            None => out.push_str(&format!(
+                "    n{}(\"{}:{}\")\n",
+                id, node.elem1.key, node.elem1.value
+            )),
+            Some(elem2) => out.push_str(&format!(
+                "    n{}[\"{}:{} | {}:{}\"]\n",
+                id, node.elem1.key, node.elem1.value, elem2.key, elem2.value
+            )),
+        }
+        for child in [&node.child1, &node.child2, &node.child3]
+            .into_iter()
+            .flatten()
+        {
+            let child_id = Self::mermaid_node(child, next_id, out);
+            out.push_str(&format!("    n{} --> n{}\n", id, child_id));
+        }
+        id
+    }
+
+    // Inserts an element. If its key is already in the tree, the duplicate policy applies.
+    pub fn insert(&mut self, element: Element) {
+        match self.policy {
+            DuplicatePolicy::Allow => (),
+            DuplicatePolicy::Replace => {
+                if self.update(element.key, |value| *value = element.value) {
+                    return;
+                }
+            }
+            DuplicatePolicy::Ignore => {
+                if self.find_element(element.key).is_some() {
+                    return;
+                }
+            }
+        }
+        #[cfg(feature = "tracing")]
+        let _span =
+            tracing::debug_span!("insert", key = element.key, depth = tracing::field::Empty)
+                .entered();
+        let compare = self.compare;
+        match &mut self.root {
+            None => {
+                self.root = Some(Self::new_stale_node(element));
+            }
+            Some(ref mut root_node) => {
+                if let Some(new_subtree) = Self::insert_node(root_node.as_mut(), &element, compare)
+                {
+                    let mut new_root = Self::new_stale_node(new_subtree.parent_element);
+                    new_root.child1 = Some(new_subtree.child1);
+                    new_root.child2 = Some(new_subtree.child2);
+                    Self::resize_node(&mut new_root);
+                    self.root = Some(new_root);
+                }
+            }
+        }
+        self.size += 1;
+        #[cfg(feature = "bloom")]
+        self.bloom_insert(element.key);
+        #[cfg(feature = "metrics")]
+        self.metrics.add(metrics::Metrics {
+            inserts: 1,
+            ..metrics::take()
+        });
+        #[cfg(feature = "tracing")]
+        _span.record("depth", self.height());
+        for observer in self.observers.iter_mut() {
+            observer.on_insert(&element);
+        }
+        self.mutated();
+    }
+
+    // Inserts a node, recursively.
+    // The sizes of the node, or of the subtree returned by a split, are refreshed, and their
+    // aggregates marked stale.
+    fn insert_node(
+        node: &mut TwoThreeNode<A>,
+        element: &Element,
+        compare: Comparator,
+    ) -> Option<InsertSubtree<A>> {
+        #[cfg(feature = "metrics")]
+        metrics::record(|m| m.comparisons += 1 + node.elem2.is_some() as u64);
+        let mut result = Self::insert_node_restructure(node, element, compare);
+        if result.is_some() {
+            Self::rebalanced(Rebalance::Split);
+        }
+        match result {
+            None => Self::resize_node(node),
+            Some(ref mut new_subtree) => {
+                Self::resize_node(&mut new_subtree.child1);
+                Self::resize_node(&mut new_subtree.child2);
+            }
+        }
+        result
+    }
+
+    // Reports a rebalancing action. A no-op unless the metrics or tracing features are enabled.
+    #[allow(unused_variables)]
+    fn rebalanced(action: Rebalance) {
+        #[cfg(feature = "metrics")]
+        metrics::record(|m| match action {
+            Rebalance::Split => m.splits += 1,
+            Rebalance::Merge => m.merges += 1,
+            Rebalance::Borrow => m.borrows += 1,
+        });
+        #[cfg(feature = "tracing")]
+        tracing::trace!(?action, "rebalanced");
+    }
+
+    // Returns the number of levels of the tree. All leaves are at the same depth.
+    fn height(&self) -> usize {
+        let mut height = 0;
+        let mut node = self.root.as_deref();
+        while let Some(n) = node {
+            height += 1;
+            node = n.child1.as_deref();
+        }
+        height
+    }
+
+    // Inserts a node below the given node, splitting it if it overflows.
+    fn insert_node_restructure(
+        node: &mut TwoThreeNode<A>,
+        element: &Element,
+        compare: Comparator,
+    ) -> Option<InsertSubtree<A>> {
+        let less = |a: &Element, b: &Element| compare(&a.key, &b.key) == Ordering::Less;
+        if let Some(ref mut child) = node.child1 {
+            // Not a leaf node. An element goes after the elements with the same key.
+            if less(element, &node.elem1) {
+                // Insert element in child1 subtree.
+                let result = Self::insert_node(child, element, compare);
+                if let Some(new_subtree) = result {
+                    match node.elem2 {
+                        None => {
+                            //    (a)           (result.parent_element, a)
+                            //  /    \      =>    /           |           \
+                            // result (b)     result.child1 result.child2 (b)
+                            node.elem2 = Some(node.elem1);
+                            node.elem1 = new_subtree.parent_element;
+                            node.child3 = node.child2.take();
+                            node.child1 = Some(new_subtree.child1);
+                            node.child2 = Some(new_subtree.child2);
+                            return None;
+                        }
+                        Some(elem2) => {
+                            //      (a,b)                         (a)
+                            //    /    |  \     =>             /       \
+                            // result (c) (d)      result.parent         (b)
+                            //                        /      \            /  \
+                            //               result.child1 result.child2 (c) (d)
+                            let mut left_node = Self::new_stale_node(new_subtree.parent_element);
+                            left_node.child1 = Some(new_subtree.child1);
+                            left_node.child2 = Some(new_subtree.child2);
+
+                            let mut right_node = Self::new_stale_node(elem2);
+                            right_node.child1 = node.child2.take();
+                            right_node.child2 = node.child3.take();
+
+                            return Some(InsertSubtree {
+                                parent_element: node.elem1,
+                                child1: left_node,
+                                child2: right_node,
+                            });
+                        }
+                    }
+                } else {
+                    return None;
+                }
+            }
+
+            if node.elem2.is_none_or(|elem2| less(element, &elem2)) {
+                // Insert element under child2 subtree.
+                let result = Self::insert_node(node.child2.as_mut().unwrap(), element, compare);
+                if let Some(new_subtree) = result {
+                    match node.elem2 {
+                        None => {
+                            //   (a)           (a, result.parent_element)
+                            //  /   \      =>    /     |         \
+                            // (b) result       b, result.child1 result.child2
+                            node.elem2 = Some(new_subtree.parent_element);
+                            node.child2 = Some(new_subtree.child1);
+                            node.child3 = Some(new_subtree.child2);
+                            return None;
+                        }
+                        Some(elem2) => {
+                            //     (a, b)                 result.parent_element
+                            //   /   |    \      =>   (a)                       (b)
+                            //  (c) result (d)       /  \                     /   \
+                            //                      (c) result.child1  result.child2 (d)
+                            let mut left_node = Self::new_stale_node(node.elem1);
+                            left_node.child1 = node.child1.take();
+                            left_node.child2 = Some(new_subtree.child1);
+                            let mut right_node = Self::new_stale_node(elem2);
+                            right_node.child1 = Some(new_subtree.child2);
+                            right_node.child2 = node.child3.take();
+                            return Some(InsertSubtree {
+                                parent_element: new_subtree.parent_element,
+                                child1: left_node,
+                                child2: right_node,
+                            });
+                        }
+                    }
+                } else {
+                    return None;
+                }
+            }
+
+            // Insert element under child3 subtree.
+            let result = Self::insert_node(node.child3.as_mut().unwrap(), element, compare);
+            if let Some(new_subtree) = result {
+                //    (a,b)                     (b)
+                //   /  |  \           =>     /     \
+                //  (c) (d) result           (a)     (result.parent)
+                //                          /  \      /             \
+                //                         (c) (d) result.child1 result.child2
+                let mut left_node = Self::new_stale_node(node.elem1);
+                left_node.child1 = node.child1.take();
+                left_node.child2 = node.child2.take();
+                let mut right_node = Self::new_stale_node(new_subtree.parent_element);
+                right_node.child1 = Some(new_subtree.child1);
+                right_node.child2 = Some(new_subtree.child2);
+                return Some(InsertSubtree {
                     parent_element: node.elem2.unwrap(),
                     child1: left_node,
                     child2: right_node,
@@ -248,27 +1393,27 @@ impl TwoThreeTree {
 
         // Handle leaf node.
         if let Some(elem2) = node.elem2 {
-            if element.key < node.elem1.key {
+            if less(element, &node.elem1) {
                 return Some(InsertSubtree {
                     parent_element: node.elem1,
-                    child1: Self::new_node(*element),
-                    child2: Self::new_node(elem2),
+                    child1: Self::new_stale_node(*element),
+                    child2: Self::new_stale_node(elem2),
                 });
             }
-            if element.key < elem2.key {
+            if less(element, &elem2) {
                 return Some(InsertSubtree {
                     parent_element: *element,
-                    child1: Self::new_node(node.elem1),
-                    child2: Self::new_node(elem2),
+                    child1: Self::new_stale_node(node.elem1),
+                    child2: Self::new_stale_node(elem2),
                 });
             }
             return Some(InsertSubtree {
                 parent_element: elem2,
-                child1: Self::new_node(node.elem1),
-                child2: Self::new_node(*element),
+                child1: Self::new_stale_node(node.elem1),
+                child2: Self::new_stale_node(*element),
             });
         }
-        if node.elem1.key <= element.key {
+        if !less(element, &node.elem1) {
             node.elem2 = Some(*element);
         } else {
             node.elem2 = Some(node.elem1);
@@ -280,53 +1425,336 @@ impl TwoThreeTree {
     // Deletes an element with the given key.
     // Returns true if the element is found and deleted.
     pub fn delete(&mut self, key: usize) -> bool {
+        self.remove(key).is_some()
+    }
+
+    // Deletes an element with the given key.
+    // Returns the deleted element, or None if it is not found.
+    pub fn remove(&mut self, key: usize) -> Option<Element> {
+        self.delete_with(key, Self::delete_node)
+    }
+
+    // Deletes the element with the smallest key, walking down the leftmost spine only.
+    // Returns the deleted element, or None if the tree is empty.
+    pub fn delete_min(&mut self) -> Option<Element> {
+        self.delete_with(0, Self::delete_min_node)
+    }
+
+    // Deletes the element with the largest key, walking down the rightmost spine only.
+    // Returns the deleted element, or None if the tree is empty.
+    pub fn delete_max(&mut self) -> Option<Element> {
+        self.delete_with(0, Self::delete_max_node)
+    }
+
+    // Runs a deletion from the root with the given recursive function, then fixes up the root.
+    fn delete_with(
+        &mut self,
+        key: usize,
+        delete: fn(&mut TwoThreeNode<A>, &mut DeleteState),
+    ) -> Option<Element> {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!("delete", key, depth = self.height()).entered();
         let mut state = DeleteState {
             key,
+            compare: self.compare,
             phase: DeletePhase::Downwards,
             predecessor: None,
+            removed: None,
         };
 
         if let Some(ref mut root) = self.root {
-            Self::delete_node(root, &mut state);
+            delete(root, &mut state);
+            Self::resize_node(root);
             match state.phase {
                 DeletePhase::Done(success) => {
                     if success {
                         self.size -= 1;
                     }
-                    success
                 }
                 DeletePhase::FixHole => {
                     self.root = root.child1.take();
                     self.size -= 1;
-                    true
                 }
                 DeletePhase::Downwards => panic!(),
             }
+        }
+        #[cfg(feature = "metrics")]
+        self.metrics.add(metrics::Metrics {
+            deletes: 1,
+            ..metrics::take()
+        });
+        #[cfg(feature = "tracing")]
+        tracing::debug!(removed = state.removed.is_some());
+        if let Some(ref removed) = state.removed {
+            #[cfg(feature = "bloom")]
+            self.bloom_remove(1);
+            for observer in self.observers.iter_mut() {
+                observer.on_remove(removed);
+            }
+            self.mutated();
         } else {
-            false
+            self.refresh_aggregates();
         }
+        state.removed
     }
 
-    // Deletes node recursively.
-    fn delete_node(node: &mut TwoThreeNode, state: &mut DeleteState) {
-        let child_num: u8;
-        match node.child1 {
-            // This is a leaf.
-            None => {
-                if node.elem1.key == state.key {
-                    if let Some(elem2) = node.elem2 {
-                        // Just move elem2 to elem1.
-                        node.elem1 = elem2;
-                        node.elem2 = None;
-                        state.phase = DeletePhase::Done(true);
-                        return;
-                    }
-                    // Leaf node is to be deleted.
-                    state.phase = DeletePhase::FixHole;
+    // Deletes the element with exactly the given key and value.
+    // Among elements with equal keys, delete() may pick any of them. The values are then
+    // swapped so that the remaining elements are the expected ones.
+    pub(crate) fn remove_element(&mut self, element: Element) -> bool {
+        if self.count(element.key, Some(element.value)) == 0 {
+            return false;
+        }
+        let removed = self.remove(element.key).unwrap();
+        if removed.value != element.value {
+            self.set_value(element, removed.value);
+        }
+        true
+    }
+
+    // Mutates the value of an element with the given key in a single traversal.
+    // Returns false if the key is not found.
+    pub fn update(&mut self, key: usize, f: impl FnOnce(&mut usize)) -> bool {
+        match self.modify(key, None, |e| f(&mut e.value)) {
+            Some((old, new)) => {
+                self.mutated();
+                self.notify_update(&old, &new);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Calls f on the elements in the key range, in order, with a mutable reference to the value.
+    // f returns ControlFlow::Break to stop early, which is then returned.
+    pub fn for_each_in_range_mut(
+        &mut self,
+        range: impl RangeBounds<usize>,
+        mut f: impl FnMut(usize, &mut usize) -> ControlFlow<()>,
+    ) -> ControlFlow<()> {
+        let bounds = KeyBounds::new(&range, self.compare);
+        let Some(root) = self.root.as_mut() else {
+            return ControlFlow::Continue(());
+        };
+        let mut changes = Vec::new();
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            Self::for_each_node_mut(root, bounds, &mut f, &mut changes)
+        }));
+
+        // If f panicked, the values changed before it are kept, but the aggregates above them
+        // were not refreshed yet.
+        if result.is_err() && !changes.is_empty() {
+            Self::refresh_subtree(self.root.as_mut().unwrap());
+        }
+        if !changes.is_empty() {
+            self.mutated();
+            for (old, new) in changes {
+                self.notify_update(&old, &new);
+            }
+        }
+        result.unwrap_or_else(|payload| panic::resume_unwind(payload))
+    }
+
+    // Recomputes the sizes and aggregates of a subtree, recursively.
+    fn refresh_subtree(node: &mut TwoThreeNode<A>) {
+        for child in [&mut node.child1, &mut node.child2, &mut node.child3]
+            .into_iter()
+            .flatten()
+        {
+            Self::refresh_subtree(child);
+        }
+        Self::update_node(node);
+    }
+
+    // Visits the elements of a subtree within the bounds, recursively. The changed elements
+    // are recorded, and the aggregates of the changed subtrees refreshed.
+    fn for_each_node_mut(
+        node: &mut TwoThreeNode<A>,
+        bounds: KeyBounds,
+        f: &mut impl FnMut(usize, &mut usize) -> ControlFlow<()>,
+        changes: &mut Vec<(Element, Element)>,
+    ) -> ControlFlow<()> {
+        let changed = changes.len();
+        let flow = Self::for_each_node_mut_steps(node, bounds, f, changes);
+        if changes.len() > changed {
+            Self::update_node(node);
+        }
+        flow
+    }
+
+    fn for_each_node_mut_steps(
+        node: &mut TwoThreeNode<A>,
+        bounds: KeyBounds,
+        f: &mut impl FnMut(usize, &mut usize) -> ControlFlow<()>,
+        changes: &mut Vec<(Element, Element)>,
+    ) -> ControlFlow<()> {
+        let visit = |element: &mut Element,
+                     f: &mut dyn FnMut(usize, &mut usize) -> ControlFlow<()>,
+                     changes: &mut Vec<(Element, Element)>| {
+            let old = *element;
+            let mut value = old.value;
+            let flow = f(element.key, &mut value);
+            if value != old.value {
+                element.value = value;
+                changes.push((old, *element));
+            }
+            flow
+        };
+        if let Some(ref mut child1) = node.child1 {
+            if bounds.after_start(node.elem1.key) {
+                Self::for_each_node_mut(child1, bounds, f, changes)?;
+            }
+        }
+        if !bounds.before_end(node.elem1.key) {
+            return ControlFlow::Continue(());
+        }
+        if bounds.after_start(node.elem1.key) {
+            visit(&mut node.elem1, f, changes)?;
+        }
+        if let Some(ref mut child2) = node.child2 {
+            if node.elem2.is_none_or(|elem2| bounds.after_start(elem2.key)) {
+                Self::for_each_node_mut(child2, bounds, f, changes)?;
+            }
+        }
+        if let Some(ref mut elem2) = node.elem2 {
+            if !bounds.before_end(elem2.key) {
+                return ControlFlow::Continue(());
+            }
+            if bounds.after_start(elem2.key) {
+                visit(elem2, f, changes)?;
+            }
+            if let Some(ref mut child3) = node.child3 {
+                Self::for_each_node_mut(child3, bounds, f, changes)?;
+            }
+        }
+        ControlFlow::Continue(())
+    }
+
+    // Mutates the value of an element with the given key, or inserts an element with the
+    // default value if the key is not found.
+    pub fn upsert(&mut self, key: usize, default: usize, f: impl FnOnce(&mut usize)) {
+        if !self.update(key, f) {
+            self.insert(Element {
+                key,
+                value: default,
+            });
+        }
+    }
+
+    // Moves an element with the given key to a new key. Returns false if it is not found.
+    // If no other element lies between the old and the new key, the key is changed in place in
+    // O(log n) without restructuring. Otherwise the element is deleted and inserted again.
+    // Observers see the move as a removal followed by an insertion.
+    // If the new key is already in the tree, the duplicate policy applies: under Replace the
+    // moved element replaces the existing one, and under Ignore the tree is left unchanged and
+    // false is returned.
+    pub fn rekey(&mut self, old_key: usize, new_key: usize) -> bool {
+        if self.policy != DuplicatePolicy::Allow
+            && (self.compare)(&old_key, &new_key) != Ordering::Equal
+            && self.find(new_key).is_some()
+        {
+            if self.policy == DuplicatePolicy::Ignore {
+                return false;
+            }
+            let Some(old) = self.remove(old_key) else {
+                return false;
+            };
+            self.insert(Element {
+                key: new_key,
+                value: old.value,
+            });
+            return true;
+        }
+        // The number of elements from the old key up to the new one, excluded.
+        let between = match (self.compare)(&old_key, &new_key) {
+            Ordering::Less => self.rank(new_key) - self.rank(old_key),
+            Ordering::Greater => {
+                self.count_before(old_key, true) - self.count_before(new_key, true)
+            }
+            Ordering::Equal => return self.find(old_key).is_some(),
+        };
+        if between == 1 {
+            match self.modify(old_key, None, |e| e.key = new_key) {
+                Some((old, new)) => {
+                    self.mutated();
+                    #[cfg(feature = "bloom")]
+                    {
+                        self.bloom_remove(1);
+                        self.bloom_insert(new_key);
+                    }
+                    for observer in self.observers.iter_mut() {
+                        observer.on_remove(&old);
+                        observer.on_insert(&new);
+                    }
+                }
+                None => return false,
+            }
+        } else {
+            match self.remove(old_key) {
+                Some(old) => self.insert(Element {
+                    key: new_key,
+                    value: old.value,
+                }),
+                None => return false,
+            }
+        }
+        true
+    }
+
+    // Sets the value of the element with exactly the given key and value.
+    // Returns false if there is no such element.
+    pub(crate) fn set_value(&mut self, element: Element, value: usize) -> bool {
+        match self.modify(element.key, Some(element.value), |e| e.value = value) {
+            Some((old, new)) => {
+                self.mutated();
+                self.notify_update(&old, &new);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Adds an observer to be notified of every subsequent mutation.
+    pub fn add_observer(&mut self, observer: Box<dyn Observer + Send + Sync>) {
+        self.observers.push(observer);
+    }
+
+    // Removes all observers.
+    pub fn clear_observers(&mut self) {
+        self.observers.clear();
+    }
+
+    // Notifies the observers of a value change.
+    fn notify_update(&mut self, old: &Element, new: &Element) {
+        for observer in self.observers.iter_mut() {
+            observer.on_update(old, new);
+        }
+    }
+
+    // Deletes node recursively.
+    fn delete_node(node: &mut TwoThreeNode<A>, state: &mut DeleteState) {
+        #[cfg(feature = "metrics")]
+        metrics::record(|m| m.comparisons += 1 + node.elem2.is_some() as u64);
+        let child_num: u8;
+        match node.child1 {
+            // This is a leaf.
+            None => {
+                if (state.compare)(&node.elem1.key, &state.key) == Ordering::Equal {
+                    state.removed = Some(node.elem1);
+                    if let Some(elem2) = node.elem2 {
+                        // Just move elem2 to elem1.
+                        node.elem1 = elem2;
+                        node.elem2 = None;
+                        state.phase = DeletePhase::Done(true);
+                        return;
+                    }
+                    // Leaf node is to be deleted.
+                    state.phase = DeletePhase::FixHole;
                     return;
                 }
                 if let Some(elem2) = node.elem2 {
-                    if elem2.key == state.key {
+                    if (state.compare)(&elem2.key, &state.key) == Ordering::Equal {
+                        state.removed = Some(elem2);
                         node.elem2 = None;
                         state.phase = DeletePhase::Done(true);
                         return;
@@ -339,14 +1767,14 @@ impl TwoThreeTree {
 
             // Not leaf. Recursively go down the tree.
             Some(ref mut child1) => {
-                match state.key.cmp(&node.elem1.key) {
+                match (state.compare)(&state.key, &node.elem1.key) {
                     Ordering::Less => {
                         Self::delete_node(child1, state);
                         child_num = 1;
                     }
                     Ordering::Greater => {
                         if let Some(elem2) = node.elem2 {
-                            match state.key.cmp(&elem2.key) {
+                            match (state.compare)(&state.key, &elem2.key) {
                                 Ordering::Less => {
                                     Self::delete_node(node.child2.as_mut().unwrap(), state);
                                     child_num = 2;
@@ -357,6 +1785,7 @@ impl TwoThreeTree {
                                 }
                                 Ordering::Equal => {
                                     // Matched. Find successor node.
+                                    state.removed = Some(elem2);
                                     Self::find_predecessor(node.child2.as_mut().unwrap(), state);
                                     node.elem2 = Some(state.predecessor.unwrap());
                                     child_num = 2;
@@ -369,6 +1798,7 @@ impl TwoThreeTree {
                     }
                     Ordering::Equal => {
                         // Matched. Find succcessor node.
+                        state.removed = Some(node.elem1);
                         Self::find_predecessor(child1, state);
                         node.elem1 = state.predecessor.unwrap();
                         child_num = 1;
@@ -377,10 +1807,35 @@ impl TwoThreeTree {
             }
         }
         Self::delete_node_upward(node, child_num, state);
+        Self::resize_children(node);
+    }
+
+    // Deletes the leftmost element of a subtree recursively.
+    fn delete_min_node(node: &mut TwoThreeNode<A>, state: &mut DeleteState) {
+        if let Some(ref mut child1) = node.child1 {
+            Self::delete_min_node(child1, state);
+            Self::delete_node_upward(node, 1, state);
+            Self::resize_children(node);
+            return;
+        }
+        state.removed = Some(node.elem1);
+        if let Some(elem2) = node.elem2.take() {
+            node.elem1 = elem2;
+            state.phase = DeletePhase::Done(true);
+        } else {
+            // Leaf node is to be deleted.
+            state.phase = DeletePhase::FixHole;
+        }
+    }
+
+    // Deletes the rightmost element of a subtree. This is the same walk as for the predecessor.
+    fn delete_max_node(node: &mut TwoThreeNode<A>, state: &mut DeleteState) {
+        Self::find_predecessor(node, state);
+        state.removed = state.predecessor;
     }
 
     // Upward phase of the node deletion operation.
-    fn delete_node_upward(node: &mut TwoThreeNode, child_num: u8, state: &mut DeleteState) {
+    fn delete_node_upward(node: &mut TwoThreeNode<A>, child_num: u8, state: &mut DeleteState) {
         // Handle upward traversal.
         match state.phase {
             DeletePhase::Done(_) => (),
@@ -400,6 +1855,7 @@ impl TwoThreeTree {
                             // (o)  (b)           (a,b)
                             //  |   / \          /  |  \
                             // (c) (d) (e)      (c) (d) (e)
+                            Self::rebalanced(Rebalance::Merge);
                             Self::add_left(child2, node.elem1, child1.child1.take());
                             node.child1 = node.child2.take();
                         } else {
@@ -408,6 +1864,7 @@ impl TwoThreeTree {
                             // (o)  (b,c)        (a)    (c)
                             //  |   / | \        / \    / \
                             // (d) (e)(f)(g)   (d) (e) (f)(g)
+                            Self::rebalanced(Rebalance::Borrow);
                             child1.elem1 = node.elem1;
                             (node.elem1, child1.child2) = Self::trim_left(child2);
                             state.phase = DeletePhase::Done(true);
@@ -420,6 +1877,7 @@ impl TwoThreeTree {
                             // (b)   (o)            (b,a)
                             // /  \   |            /  |  \
                             // ..    (c)           ..    (c)
+                            Self::rebalanced(Rebalance::Merge);
                             Self::add_right(child1, node.elem1, child2.child1.take());
                         } else {
                             //      (a)               (c)
@@ -427,6 +1885,7 @@ impl TwoThreeTree {
                             //  (b,c)   (o)        (b)   (a)
                             //  / | \    |        / \    /  \
                             // (d)(e)(f) (g)    (d) (e) (f) (g)
+                            Self::rebalanced(Rebalance::Borrow);
                             child2.elem1 = node.elem1;
                             child2.child2 = child2.child1.take();
                             (node.elem1, child2.child1) = Self::trim_right(child1);
@@ -446,6 +1905,7 @@ impl TwoThreeTree {
                         //   (o)  (c)  ..   =>       (a,c)   ..
                         //    |   / \                /  | \
                         //  (d) (e) (f)             (d)(e)(f)
+                        Self::rebalanced(Rebalance::Merge);
                         Self::add_left(child2, node.elem1, child1.child1.take());
                         Self::trim_left(node);
                     } else {
@@ -454,6 +1914,7 @@ impl TwoThreeTree {
                         //   (o)  (c,d)  ..   =>       (a)  (d)  ..
                         //    |   / | \                / \   / \
                         //   (d) (e)(f)(g)            (d)(e)(f)(g)
+                        Self::rebalanced(Rebalance::Borrow);
                         child1.elem1 = node.elem1;
                         (node.elem1, child1.child2) = Self::trim_left(child2);
                     }
@@ -464,6 +1925,7 @@ impl TwoThreeTree {
                         //   (c)   (o)  ..   =>      (c,a)  ..
                         //   / \    |                / \
                         //  (d)(e) (f)            (d)(e)(f)
+                        Self::rebalanced(Rebalance::Merge);
                         Self::add_right(child1, node.elem1, child2.child1.take());
                         node.elem1 = node.elem2.take().unwrap();
                         node.child2 = node.child3.take();
@@ -473,6 +1935,7 @@ impl TwoThreeTree {
                         // (c,d)  (o)  ..   =>      (c)  (a)   ..
                         // / | \   |                / \  /  \
                         // ..  (e) (f)              ..  (e) (f)
+                        Self::rebalanced(Rebalance::Borrow);
                         child2.elem1 = node.elem1;
                         child2.child2 = child2.child1.take();
                         (node.elem1, child2.child1) = Self::trim_right(child1);
@@ -483,6 +1946,7 @@ impl TwoThreeTree {
                     //  ..  (c)  (o)   =>      ..  (c,b)
                     //      / \   |                / | \
                     //    .. (d) (e)              .. (d)(e)
+                    Self::rebalanced(Rebalance::Merge);
                     Self::add_right(child2, node.elem2.take().unwrap(), child3.child1.take());
                     node.child3 = None;
                 } else {
@@ -491,6 +1955,7 @@ impl TwoThreeTree {
                     //  .. (c,d) (o)   =>      ..  (c)  (b)
                     //     / | \   |               / \  / \
                     //      .. (e) (f)            ..   (e)(f)
+                    Self::rebalanced(Rebalance::Borrow);
                     child3.elem1 = node.elem2.unwrap();
                     child3.child2 = child3.child1.take();
                     let result = Self::trim_right(child2);
@@ -507,10 +1972,136 @@ impl TwoThreeTree {
 
     // Finds an element with the given key.
     pub fn find(&self, key: usize) -> Option<Element> {
+        self.lookup(key).copied()
+    }
+
+    // Finds an element with the given key, counted and traced as a find.
+    fn lookup(&self, key: usize) -> Option<&Element> {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!("find", key, depth = tracing::field::Empty).entered();
+        let found = self.find_element(key);
+        #[cfg(feature = "metrics")]
+        self.metrics.add(metrics::Metrics {
+            finds: 1,
+            ..metrics::take()
+        });
+        #[cfg(feature = "tracing")]
+        tracing::debug!(found = found.is_some());
+        found
+    }
+
+    // Returns the steps taken by find() to reach the key, ending with the matching comparison,
+    // or None if the key is not in the tree.
+    pub fn path_to(&self, key: usize) -> Option<Vec<PathStep>> {
+        let mut path = Vec::new();
+        let mut node = self.root.as_deref()?;
+        for depth in 0.. {
+            let mut child = 1;
+            for element in Some(node.elem1).into_iter().chain(node.elem2) {
+                let ordering = (self.compare)(&key, &element.key);
+                path.push(PathStep::Compare {
+                    depth,
+                    element,
+                    ordering,
+                });
+                match ordering {
+                    Ordering::Equal => return Some(path),
+                    Ordering::Less => break,
+                    Ordering::Greater => child += 1,
+                }
+            }
+            node = match child {
+                1 => node.child1.as_deref(),
+                2 => node.child2.as_deref(),
+                _ => node.child3.as_deref(),
+            }?;
+            path.push(PathStep::Descend { depth, child });
+        }
+        unreachable!()
+    }
+
+    // Returns the depth of the node holding the key, the root being 0, or None if the key is
+    // not in the tree.
+    pub fn depth_of(&self, key: usize) -> Option<usize> {
+        match self.path_to(key)?.last() {
+            Some(PathStep::Compare { depth, .. }) => Some(*depth),
+            _ => None,
+        }
+    }
+
+    // Returns the number of elements at each depth, the root being at index 0.
+    pub fn depth_histogram(&self) -> Vec<usize> {
+        let mut histogram = Vec::new();
+        if let Some(ref root) = self.root {
+            Self::depth_histogram_node(root, 0, &mut histogram);
+        }
+        histogram
+    }
+
+    fn depth_histogram_node(node: &TwoThreeNode<A>, depth: usize, histogram: &mut Vec<usize>) {
+        if histogram.len() == depth {
+            histogram.push(0);
+        }
+        histogram[depth] += 1 + node.elem2.is_some() as usize;
+        for child in [&node.child1, &node.child2, &node.child3]
+            .into_iter()
+            .flatten()
+        {
+            Self::depth_histogram_node(child, depth + 1, histogram);
+        }
+    }
+
+    // Returns an element with the key, or else the element with the closest key, in a single
+    // descent. On a tie between a smaller and a larger key, the smaller one is returned.
+    pub fn find_nearest(&self, key: usize) -> Nearest<'_> {
+        let mut below: Option<&Element> = None;
+        let mut above: Option<&Element> = None;
+        let mut node = self.root.as_deref();
+        while let Some(n) = node {
+            let mut child = n.child1.as_deref();
+            for (element, next) in [
+                (Some(&n.elem1), n.child2.as_deref()),
+                (n.elem2.as_ref(), n.child3.as_deref()),
+            ] {
+                let Some(element) = element else {
+                    break;
+                };
+                match (self.compare)(&key, &element.key) {
+                    Ordering::Equal => return Nearest::Exact(element),
+                    Ordering::Less => {
+                        above = Some(element);
+                        break;
+                    }
+                    Ordering::Greater => {
+                        below = Some(element);
+                        child = next;
+                    }
+                }
+            }
+            node = child;
+        }
+        match (below, above) {
+            (Some(b), Some(a)) if a.key.abs_diff(key) < b.key.abs_diff(key) => Nearest::Above(a),
+            (Some(b), _) => Nearest::Below(b),
+            (None, Some(a)) => Nearest::Above(a),
+            (None, None) => Nearest::Empty,
+        }
+    }
+
+    fn find_element(&self, key: usize) -> Option<&Element> {
         if let Some(ref root) = self.root {
             let mut node = root;
+            #[cfg(feature = "tracing")]
+            let mut depth = 0;
             loop {
-                match key.cmp(&node.elem1.key) {
+                #[cfg(feature = "metrics")]
+                metrics::record(|m| m.comparisons += 1 + node.elem2.is_some() as u64);
+                #[cfg(feature = "tracing")]
+                {
+                    depth += 1;
+                    tracing::Span::current().record("depth", depth);
+                }
+                match (self.compare)(&key, &node.elem1.key) {
                     Ordering::Less => {
                         if let Some(ref child1) = node.child1 {
                             node = child1;
@@ -519,8 +2110,8 @@ impl TwoThreeTree {
                         }
                     }
                     Ordering::Greater => {
-                        if let Some(elem2) = node.elem2 {
-                            match key.cmp(&elem2.key) {
+                        if let Some(ref elem2) = node.elem2 {
+                            match (self.compare)(&key, &elem2.key) {
                                 Ordering::Less => {
                                     if let Some(ref child2) = node.child2 {
                                         node = child2;
@@ -544,7 +2135,7 @@ impl TwoThreeTree {
                         }
                     }
                     Ordering::Equal => {
-                        return Some(node.elem1);
+                        return Some(&node.elem1);
                     }
                 }
             }
@@ -552,231 +2143,2570 @@ impl TwoThreeTree {
         None
     }
 
-    // Converts a 2-node to a 3-node, adding a node and child on the left side.
-    fn add_left(node: &mut TwoThreeNode, elem1: Element, child1: Option<Box<TwoThreeNode>>) {
-        node.elem2 = Some(node.elem1);
-        node.elem1 = elem1;
-        node.child3 = node.child2.take();
-        node.child2 = node.child1.take();
-        node.child1 = child1;
+    // Returns the number of elements with the given key, and the given value if any.
+    pub(crate) fn count(&self, key: usize, value: Option<usize>) -> usize {
+        match self.root {
+            Some(ref root) => Self::count_node(root, key, value, self.compare),
+            None => 0,
+        }
+    }
+
+    // Counts the matching elements, recursively.
+    // Equal keys may be on either side of an element, so more than one child may be visited.
+    fn count_node(
+        node: &TwoThreeNode<A>,
+        key: usize,
+        value: Option<usize>,
+        compare: Comparator,
+    ) -> usize {
+        let order = |e: &Element| compare(&key, &e.key);
+        let matches =
+            |e: &Element| order(e) == Ordering::Equal && value.is_none_or(|value| e.value == value);
+        let mut count = 0;
+        if matches(&node.elem1) {
+            count += 1;
+        }
+        if node.elem2.as_ref().is_some_and(matches) {
+            count += 1;
+        }
+        if let Some(ref child1) = node.child1 {
+            if order(&node.elem1) != Ordering::Greater {
+                count += Self::count_node(child1, key, value, compare);
+            }
+            if order(&node.elem1) != Ordering::Less
+                && node
+                    .elem2
+                    .is_none_or(|elem2| order(&elem2) != Ordering::Greater)
+            {
+                count += Self::count_node(node.child2.as_ref().unwrap(), key, value, compare);
+            }
+            if let Some(elem2) = node.elem2 {
+                if order(&elem2) != Ordering::Less {
+                    count += Self::count_node(node.child3.as_ref().unwrap(), key, value, compare);
+                }
+            }
+        }
+        count
+    }
+
+    // Applies f to one element with the given key, and the given value if any.
+    // f may only change the key if the ordering of the elements is preserved. Returns the element
+    // before and after the change, or None if it is not found.
+    fn modify(
+        &mut self,
+        key: usize,
+        value: Option<usize>,
+        f: impl FnOnce(&mut Element),
+    ) -> Option<(Element, Element)> {
+        match self.root {
+            Some(ref mut root) => Self::modify_node(root, key, value, self.compare, &mut Some(f)),
+            None => None,
+        }
+    }
+
+    // Modifies a matching element, recursively. The aggregates on the path are refreshed on
+    // the way back up. Like count_node(), all children which may hold the key are visited.
+    fn modify_node<F: FnOnce(&mut Element)>(
+        node: &mut TwoThreeNode<A>,
+        key: usize,
+        value: Option<usize>,
+        compare: Comparator,
+        f: &mut Option<F>,
+    ) -> Option<(Element, Element)> {
+        let order = |e: &Element| compare(&key, &e.key);
+        let matches =
+            |e: &Element| order(e) == Ordering::Equal && value.is_none_or(|value| e.value == value);
+        let element = if matches(&node.elem1) {
+            Some(&mut node.elem1)
+        } else if node.elem2.as_ref().is_some_and(matches) {
+            node.elem2.as_mut()
+        } else {
+            None
+        };
+        let result = if let Some(element) = element {
+            // Changed on a copy, so that a panic in f leaves the element as it was.
+            let old = *element;
+            let mut new = old;
+            (f.take().unwrap())(&mut new);
+            *element = new;
+            Some((old, new))
+        } else if let Some(ref mut child1) = node.child1 {
+            let mut result = None;
+            if order(&node.elem1) != Ordering::Greater {
+                result = Self::modify_node(child1, key, value, compare, f);
+            }
+            if result.is_none()
+                && order(&node.elem1) != Ordering::Less
+                && node
+                    .elem2
+                    .is_none_or(|elem2| order(&elem2) != Ordering::Greater)
+            {
+                let child2 = node.child2.as_mut().unwrap();
+                result = Self::modify_node(child2, key, value, compare, f);
+            }
+            if result.is_none()
+                && node
+                    .elem2
+                    .is_some_and(|elem2| order(&elem2) != Ordering::Less)
+            {
+                let child3 = node.child3.as_mut().unwrap();
+                result = Self::modify_node(child3, key, value, compare, f);
+            }
+            result
+        } else {
+            None
+        };
+        if result.is_some() {
+            Self::update_node(node);
+        }
+        result
+    }
+
+    // Applies a batch of operations atomically.
+    // The whole batch is checked first: every Delete and Update must find its key, taking
+    // the earlier operations of the batch into account. If any operation would fail, the
+    // tree is left unchanged and false is returned. Otherwise all operations are applied.
+    pub fn apply_batch(&mut self, ops: impl IntoIterator<Item = Op>) -> bool {
+        let ops: Vec<Op> = ops.into_iter().collect();
+
+        // Check phase: track the number of elements per key as the batch would leave it.
+        let mut counts: HashMap<usize, usize> = HashMap::new();
+        for op in ops.iter() {
+            let key = match op {
+                Op::Insert(key, _) | Op::Delete(key) | Op::Update(key, _) => *key,
+            };
+            let count = counts.entry(key).or_insert_with(|| self.count(key, None));
+            match op {
+                // Under the Replace and Ignore policies, an existing key is not added again.
+                Op::Insert(..) if *count > 0 && self.policy != DuplicatePolicy::Allow => (),
+                Op::Insert(..) => *count += 1,
+                Op::Delete(_) => {
+                    if *count == 0 {
+                        return false;
+                    }
+                    *count -= 1;
+                }
+                Op::Update(..) => {
+                    if *count == 0 {
+                        return false;
+                    }
+                }
+            }
+        }
+
+        // Apply phase: none of the operations can fail now.
+        for op in ops {
+            match op {
+                Op::Insert(key, value) => self.insert(Element { key, value }),
+                Op::Delete(key) => {
+                    let deleted = self.delete(key);
+                    debug_assert!(deleted);
+                }
+                Op::Update(key, f) => {
+                    let updated = self.update(key, |value| *value = f(*value));
+                    debug_assert!(updated);
+                }
+            }
+        }
+        true
+    }
+
+    // Returns true if there is an element with the given key.
+    // With the bloom feature and an enabled filter, most absent keys are rejected without
+    // traversing the tree.
+    pub fn contains_key(&self, key: usize) -> bool {
+        #[cfg(feature = "bloom")]
+        if self
+            .bloom
+            .as_ref()
+            .is_some_and(|bloom| !bloom.may_contain(key))
+        {
+            return false;
+        }
+        self.find(key).is_some()
+    }
+
+    // Converts a 2-node to a 3-node, adding a node and child on the left side.
+    fn add_left(node: &mut TwoThreeNode<A>, elem1: Element, child1: Option<Box<TwoThreeNode<A>>>) {
+        node.elem2 = Some(node.elem1);
+        node.elem1 = elem1;
+        node.child3 = node.child2.take();
+        node.child2 = node.child1.take();
+        node.child1 = child1;
+    }
+
+    // Converts a 2-node to a 3-node, adding a node and child on the right side.
+    fn add_right(node: &mut TwoThreeNode<A>, elem2: Element, child3: Option<Box<TwoThreeNode<A>>>) {
+        node.elem2 = Some(elem2);
+        node.child3 = child3;
+    }
+
+    // Converts a 3-node to a 2-node, removing right element and right child.
+    fn trim_right(node: &mut TwoThreeNode<A>) -> (Element, Option<Box<TwoThreeNode<A>>>) {
+        (node.elem2.take().unwrap(), node.child3.take())
+    }
+
+    // Converts a 3-node to a 2-node, removing left element and left child.
+    fn trim_left(node: &mut TwoThreeNode<A>) -> (Element, Option<Box<TwoThreeNode<A>>>) {
+        let result = (node.elem1, node.child1.take());
+        node.elem1 = node.elem2.take().unwrap();
+        node.child1 = node.child2.take();
+        node.child2 = node.child3.take();
+        result
+    }
+
+    // Walk down the tree to the predecessor of a node.
+    fn find_predecessor(node: &mut TwoThreeNode<A>, state: &mut DeleteState) {
+        if let Some(ref mut child3) = node.child3 {
+            Self::find_predecessor(child3, state);
+            Self::delete_node_upward(node, 3, state);
+            Self::resize_children(node);
+        } else if let Some(ref mut child2) = node.child2 {
+            Self::find_predecessor(child2, state);
+            Self::delete_node_upward(node, 2, state);
+            Self::resize_children(node);
+        } else {
+            // Reached leaf node. Save the predecessor element.
+            if node.elem2.is_some() {
+                state.predecessor = node.elem2.take();
+                state.phase = DeletePhase::Done(true);
+            } else {
+                state.predecessor = Some(node.elem1);
+                state.phase = DeletePhase::FixHole;
+            }
+        }
+    }
+
+    // Builds a tree from elements sorted by key, in O(n).
+    // Panics if the elements are not sorted.
+    pub fn build_sorted(elements: impl IntoIterator<Item = Element>) -> TwoThreeTree<A> {
+        Self::build_sorted_by(elements, usize::cmp)
+    }
+
+    // Builds a tree from elements sorted by key in the order of compare, in O(n).
+    pub(crate) fn build_sorted_by(
+        elements: impl IntoIterator<Item = Element>,
+        compare: Comparator,
+    ) -> TwoThreeTree<A> {
+        let elements: Vec<Element> = elements.into_iter().collect();
+        assert!(elements
+            .windows(2)
+            .all(|pair| compare(&pair[0].key, &pair[1].key) != Ordering::Greater));
+
+        let mut tree = TwoThreeTree {
+            compare,
+            ..Self::with_augment()
+        };
+        if !elements.is_empty() {
+            // Find the smallest height whose full tree holds all the elements.
+            let mut height = 0;
+            while Self::max_elements(height) < elements.len() {
+                height += 1;
+            }
+            tree.root = Some(Self::build_node(&elements, height));
+            tree.size = elements.len();
+            tree.refresh_bounds();
+        }
+        tree
+    }
+
+    // Moves the elements with keys >= the given key into a new tree, in O(log n), by splitting
+    // the nodes on the search path and joining the pieces on each side. Observers stay with
+    // this tree and are notified of the moved elements as removals.
+    pub fn split_off(&mut self, key: usize) -> TwoThreeTree<A> {
+        let mut moved = self.empty_like();
+        let compare = self.compare;
+        if self
+            .max
+            .is_none_or(|max| compare(&max.key, &key) == Ordering::Less)
+        {
+            return moved;
+        }
+        let height = self.height() - 1;
+        let root = self.root.take().map(|root| (root, height));
+        let (below, above) = Self::split(root, key, compare);
+        self.root = below.map(|(node, _)| node);
+        self.size = self.root.as_ref().map_or(0, |node| node.size);
+        moved.root = above.map(|(node, _)| node);
+        moved.size = moved.root.as_ref().map_or(0, |node| node.size);
+        moved.refresh_bounds();
+        self.mutated();
+        #[cfg(feature = "bloom")]
+        self.bloom_remove(moved.size);
+        for observer in self.observers.iter_mut() {
+            for element in moved.iter() {
+                observer.on_remove(&element);
+            }
+        }
+        moved
+    }
+
+    // Splits a subtree into the elements with keys below the key and the rest. The pieces of
+    // each node are joined from the split point outwards, so the joins add up to O(height).
+    fn split(subtree: Subtree<A>, key: usize, compare: Comparator) -> (Subtree<A>, Subtree<A>) {
+        let Some((mut node, height)) = subtree else {
+            return (None, None);
+        };
+        let elements: Vec<Element> = [Some(node.elem1), node.elem2]
+            .into_iter()
+            .flatten()
+            .collect();
+        let mut children: Vec<Subtree<A>> =
+            [node.child1.take(), node.child2.take(), node.child3.take()]
+                .into_iter()
+                .take(elements.len() + 1)
+                .map(|child| child.map(|child| (child, height - 1)))
+                .collect();
+        let i = elements
+            .iter()
+            .take_while(|e| compare(&e.key, &key) == Ordering::Less)
+            .count();
+        let (mut below, mut above) = Self::split(children[i].take(), key, compare);
+        for j in (0..i).rev() {
+            below = Some(Self::join(children[j].take(), elements[j], below));
+        }
+        for j in i..elements.len() {
+            above = Some(Self::join(above, elements[j], children[j + 1].take()));
+        }
+        (below, above)
+    }
+
+    // Joins two subtrees with the separator between them, in O(height difference + 1). The
+    // separator and the shorter subtree go on the facing spine of the taller one, splitting the
+    // nodes which overflow on the way back up as an insertion does.
+    fn join(
+        left: Subtree<A>,
+        separator: Element,
+        right: Subtree<A>,
+    ) -> (Box<TwoThreeNode<A>>, usize) {
+        // The height of the node which takes the separator next to the subtree.
+        let level = |subtree: &Subtree<A>| subtree.as_ref().map_or(0, |(_, height)| height + 1);
+        let (left_level, right_level) = (level(&left), level(&right));
+        let (joined, height) = if left_level > right_level {
+            let (node, height) = left.unwrap();
+            let child = right.map(|(node, _)| node);
+            (
+                Self::append(node, height, separator, child, right_level),
+                height,
+            )
+        } else if left_level < right_level {
+            let (node, height) = right.unwrap();
+            let child = left.map(|(node, _)| node);
+            (
+                Self::prepend(child, left_level, separator, node, height),
+                height,
+            )
+        } else {
+            let mut node = Self::new_node(separator);
+            node.child1 = left.map(|(node, _)| node);
+            node.child2 = right.map(|(node, _)| node);
+            Self::update_node(&mut node);
+            return (node, left_level);
+        };
+        match joined {
+            Ok(node) => (node, height),
+            Err((left, separator, right)) => {
+                let mut root = Self::new_node(separator);
+                root.child1 = Some(left);
+                root.child2 = Some(right);
+                Self::update_node(&mut root);
+                (root, height + 1)
+            }
+        }
+    }
+
+    // Appends a separator and a subtree, None if empty, to the right spine of a node of the
+    // given height, at the node of height level.
+    fn append(
+        mut node: Box<TwoThreeNode<A>>,
+        height: usize,
+        separator: Element,
+        child: Option<Box<TwoThreeNode<A>>>,
+        level: usize,
+    ) -> Joined<A> {
+        let (separator, child) = if height == level {
+            (separator, child)
+        } else {
+            let slot = if node.elem2.is_some() {
+                &mut node.child3
+            } else {
+                &mut node.child2
+            };
+            match Self::append(slot.take().unwrap(), height - 1, separator, child, level) {
+                Ok(last) => {
+                    *slot = Some(last);
+                    Self::update_node(&mut node);
+                    return Ok(node);
+                }
+                Err((last, separator, right)) => {
+                    *slot = Some(last);
+                    (separator, Some(right))
+                }
+            }
+        };
+
+        if node.elem2.is_none() {
+            node.elem2 = Some(separator);
+            node.child3 = child;
+            Self::update_node(&mut node);
+            return Ok(node);
+        }
+
+        // Split the 3-node with 4 children into two 2-nodes.
+        let mut right = Self::new_node(separator);
+        right.child1 = node.child3.take();
+        right.child2 = child;
+        Self::update_node(&mut right);
+        let separator = node.elem2.take().unwrap();
+        Self::update_node(&mut node);
+        Err((node, separator, right))
+    }
+
+    // Prepends a subtree, None if empty, and a separator to the left spine of a node of the
+    // given height, at the node of height level.
+    fn prepend(
+        child: Option<Box<TwoThreeNode<A>>>,
+        level: usize,
+        separator: Element,
+        mut node: Box<TwoThreeNode<A>>,
+        height: usize,
+    ) -> Joined<A> {
+        let (child, separator) = if height == level {
+            (child, separator)
+        } else {
+            let first = node.child1.take().unwrap();
+            match Self::prepend(child, level, separator, first, height - 1) {
+                Ok(first) => {
+                    node.child1 = Some(first);
+                    Self::update_node(&mut node);
+                    return Ok(node);
+                }
+                Err((left, separator, first)) => {
+                    node.child1 = Some(first);
+                    (Some(left), separator)
+                }
+            }
+        };
+
+        if node.elem2.is_none() {
+            node.elem2 = Some(std::mem::replace(&mut node.elem1, separator));
+            node.child3 = node.child2.take();
+            node.child2 = node.child1.take();
+            node.child1 = child;
+            Self::update_node(&mut node);
+            return Ok(node);
+        }
+
+        // Split the 3-node with 4 children into two 2-nodes.
+        let mut left = Self::new_node(separator);
+        left.child1 = child;
+        left.child2 = node.child1.take();
+        Self::update_node(&mut left);
+        let separator = std::mem::replace(&mut node.elem1, node.elem2.take().unwrap());
+        node.child1 = node.child2.take();
+        node.child2 = node.child3.take();
+        Self::update_node(&mut node);
+        Err((left, separator, node))
+    }
+
+    // Splits the tree into the elements with keys below, within and above the range, with two
+    // split_off() calls in O(log n).
+    pub fn cut(
+        mut self,
+        range: Range<usize>,
+    ) -> (TwoThreeTree<A>, TwoThreeTree<A>, TwoThreeTree<A>) {
+        let mut middle = self.split_off(range.start);
+        let above = middle.split_off(range.end.max(range.start));
+        (self, middle, above)
+    }
+
+    // Merges the other tree into this one with a sorted two-way merge, in O(n + m).
+    // Elements with equal keys are paired in order, and the closure resolves each pair from
+    // the key and the values in this and the other tree.
+    pub fn merge_with(
+        &mut self,
+        other: TwoThreeTree<A>,
+        mut resolve: impl FnMut(usize, usize, usize) -> usize,
+    ) {
+        let mut merged = Vec::with_capacity(self.size + other.size);
+        let mut inserted = Vec::new();
+        let mut updated = Vec::new();
+        let mut mine = self.iter().peekable();
+        let mut theirs = other.iter().peekable();
+        let order = |a: Element, b: Element| (self.compare)(&a.key, &b.key);
+        loop {
+            match (mine.peek().copied(), theirs.peek().copied()) {
+                (Some(a), Some(b)) if order(a, b) == Ordering::Equal => {
+                    let resolved = Element {
+                        key: a.key,
+                        value: resolve(a.key, a.value, b.value),
+                    };
+                    if resolved.value != a.value {
+                        updated.push((a, resolved));
+                    }
+                    merged.push(resolved);
+                    mine.next();
+                    theirs.next();
+                }
+                (Some(a), Some(b)) if order(a, b) == Ordering::Less => {
+                    merged.push(a);
+                    mine.next();
+                }
+                (Some(a), None) => {
+                    merged.push(a);
+                    mine.next();
+                }
+                (_, Some(b)) => {
+                    merged.push(b);
+                    inserted.push(b);
+                    theirs.next();
+                }
+                (None, None) => break,
+            }
+        }
+        drop(mine);
+        if inserted.is_empty() && updated.is_empty() {
+            return;
+        }
+        self.replace_sorted(merged);
+        for observer in self.observers.iter_mut() {
+            for element in inserted.iter() {
+                observer.on_insert(element);
+            }
+            for (old, new) in updated.iter() {
+                observer.on_update(old, new);
+            }
+        }
+    }
+
+    // Replaces the elements with the given sorted elements, keeping the observers.
+    fn replace_sorted(&mut self, elements: Vec<Element>) {
+        let tree = Self::build_sorted_by(elements, self.compare);
+        self.root = tree.root;
+        self.size = tree.size;
+        self.mutated();
+        #[cfg(feature = "bloom")]
+        self.rebuild_bloom_filter();
+    }
+
+    // Calls f on every element in key order, with a mutable reference to the value, and keeps
+    // only the elements for which f returns true. Runs in O(n), rebuilding the tree if any
+    // element is changed or removed.
+    pub fn retain_mut(&mut self, mut f: impl FnMut(usize, &mut usize) -> bool) {
+        let mut kept = Vec::with_capacity(self.size);
+        let mut removed = Vec::new();
+        let mut changed = Vec::new();
+        for element in self.iter() {
+            let mut new = element;
+            if !f(new.key, &mut new.value) {
+                removed.push(element);
+                continue;
+            }
+            if new.value != element.value {
+                changed.push((element, new));
+            }
+            kept.push(new);
+        }
+        if removed.is_empty() && changed.is_empty() {
+            return;
+        }
+        self.replace_sorted(kept);
+        for observer in self.observers.iter_mut() {
+            for element in removed.iter() {
+                observer.on_remove(element);
+            }
+            for (old, new) in changed.iter() {
+                observer.on_update(old, new);
+            }
+        }
+    }
+
+    // Returns a new tree with copies of the elements in the key range, in O(log n + k).
+    pub fn clone_range(&self, range: impl RangeBounds<usize>) -> TwoThreeTree<A> {
+        let bounds = KeyBounds::new(&range, self.compare);
+        let elements = match bounds.start {
+            Bound::Included(start) | Bound::Excluded(start) => self.iter_from(start),
+            Bound::Unbounded => self.iter(),
+        };
+        let elements = elements
+            .skip_while(|e| !bounds.after_start(e.key))
+            .take_while(|e| bounds.before_end(e.key));
+        TwoThreeTree {
+            policy: self.policy,
+            ..Self::build_sorted_by(elements, self.compare)
+        }
+    }
+
+    // Returns a new tree of the same shape with each value replaced by f(value), called in key
+    // order. Unlike inserting the mapped elements, no comparisons or rebalancing are needed.
+    pub fn map_values(&self, mut f: impl FnMut(usize) -> usize) -> TwoThreeTree<A> {
+        self.map_elements(|_, value| f(value))
+    }
+
+    // As map_values(), with each value replaced by f(key, value).
+    pub fn map_elements(&self, mut f: impl FnMut(usize, usize) -> usize) -> TwoThreeTree<A> {
+        let mut tree = self.empty_like();
+        tree.root = self.root.as_ref().map(|root| Self::map_node(root, &mut f));
+        tree.size = self.size;
+        tree.refresh_bounds();
+        tree
+    }
+
+    // Copies a subtree mapping the values, recursively.
+    fn map_node(
+        node: &TwoThreeNode<A>,
+        f: &mut impl FnMut(usize, usize) -> usize,
+    ) -> Box<TwoThreeNode<A>> {
+        let map = |e: Element, f: &mut dyn FnMut(usize, usize) -> usize| Element {
+            key: e.key,
+            value: f(e.key, e.value),
+        };
+        let child1 = node.child1.as_ref().map(|child| Self::map_node(child, f));
+        let mut copy = Self::new_node(map(node.elem1, f));
+        let child2 = node.child2.as_ref().map(|child| Self::map_node(child, f));
+        copy.elem2 = node.elem2.map(|elem2| map(elem2, f));
+        copy.child1 = child1;
+        copy.child2 = child2;
+        copy.child3 = node.child3.as_ref().map(|child| Self::map_node(child, f));
+        Self::update_node(&mut copy);
+        copy
+    }
+
+    // Returns the number of elements in a tree of the given height made of 3-nodes only.
+    fn max_elements(height: usize) -> usize {
+        let mut nodes: usize = 1;
+        for _ in 0..height {
+            nodes = nodes.saturating_mul(3);
+        }
+        nodes.saturating_mul(3).saturating_sub(1)
+    }
+
+    // Builds a subtree of the given height from sorted elements, recursively.
+    // The number of elements must fit in a subtree of this height, with 2 to 3 children per node.
+    fn build_node(elements: &[Element], height: usize) -> Box<TwoThreeNode<A>> {
+        if height == 0 {
+            let mut node = Self::new_node(elements[0]);
+            node.elem2 = elements.get(1).copied();
+            Self::update_node(&mut node);
+            return node;
+        }
+
+        // Use 2 children if they can hold the elements, otherwise 3. Spread the elements
+        // evenly over the children.
+        let num_children = if elements.len() - 1 <= 2 * Self::max_elements(height - 1) {
+            2
+        } else {
+            3
+        };
+        let per_child = elements.len() - (num_children - 1);
+        let mut children = Vec::with_capacity(num_children);
+        let mut separators = Vec::with_capacity(num_children - 1);
+        let mut start = 0;
+        for i in 0..num_children {
+            let len = per_child / num_children + usize::from(i < per_child % num_children);
+            children.push(Self::build_node(&elements[start..start + len], height - 1));
+            start += len;
+            if i + 1 < num_children {
+                separators.push(elements[start]);
+                start += 1;
+            }
+        }
+
+        let mut children = children.into_iter();
+        let mut node = Self::new_node(separators[0]);
+        node.elem2 = separators.get(1).copied();
+        node.child1 = children.next();
+        node.child2 = children.next();
+        node.child3 = children.next();
+        Self::update_node(&mut node);
+        node
+    }
+
+    // Creates a new node.
+    fn new_node(element: Element) -> Box<TwoThreeNode<A>> {
+        Box::new(TwoThreeNode {
+            elem1: element,
+            elem2: None,
+            child1: None,
+            child2: None,
+            child3: None,
+            size: 1,
+            aug: A::element(&element),
+            stale: false,
+        })
+    }
+
+    // Creates a new node with a stale aggregate, to be computed once the tree is restructured.
+    fn new_stale_node(element: Element) -> Box<TwoThreeNode<A>> {
+        Box::new(TwoThreeNode {
+            elem1: element,
+            elem2: None,
+            child1: None,
+            child2: None,
+            child3: None,
+            size: 1,
+            aug: A::identity(),
+            stale: true,
+        })
+    }
+
+    // Recomputes the size and the aggregate of a node from its elements and children.
+    // The node stays stale if one of its children is.
+    fn update_node(node: &mut TwoThreeNode<A>) {
+        (node.size, node.aug) = Self::compute_node(node);
+        node.stale = [&node.child1, &node.child2, &node.child3]
+            .into_iter()
+            .flatten()
+            .any(|child| child.stale);
+    }
+
+    // Recomputes the size of a node, and marks its aggregate stale. No Augment function runs,
+    // so a node can be resized while subtrees are being moved.
+    fn resize_node(node: &mut TwoThreeNode<A>) {
+        node.size = 1 + node.elem2.is_some() as usize;
+        for child in [&node.child1, &node.child2, &node.child3]
+            .into_iter()
+            .flatten()
+        {
+            node.size += child.size;
+        }
+        node.stale = true;
+    }
+
+    // Resizes the children of a node, then the node.
+    // Used after elements and subtrees have been moved between siblings.
+    fn resize_children(node: &mut TwoThreeNode<A>) {
+        for child in [&mut node.child1, &mut node.child2, &mut node.child3]
+            .into_iter()
+            .flatten()
+        {
+            Self::resize_node(child);
+        }
+        Self::resize_node(node);
+    }
+
+    // Recomputes the stale aggregates of a subtree, children first. If an Augment function
+    // panics, the nodes not refreshed yet and their parents stay stale.
+    fn refresh_stale(node: &mut TwoThreeNode<A>) {
+        if !node.stale {
+            return;
+        }
+        for child in [&mut node.child1, &mut node.child2, &mut node.child3]
+            .into_iter()
+            .flatten()
+        {
+            Self::refresh_stale(child);
+        }
+        Self::update_node(node);
+    }
+
+    // Computes the size and the aggregate of a node, in key order.
+    fn compute_node(node: &TwoThreeNode<A>) -> (usize, A::Value) {
+        let mut size = 0;
+        let mut aug = A::identity();
+        let parts = [
+            (node.child1.as_deref(), None),
+            (None, Some(&node.elem1)),
+            (node.child2.as_deref(), None),
+            (None, node.elem2.as_ref()),
+            (node.child3.as_deref(), None),
+        ];
+        for part in parts {
+            match part {
+                (Some(child), _) => {
+                    size += child.size;
+                    aug = A::combine(&aug, &child.aug);
+                }
+                (None, Some(element)) => {
+                    size += 1;
+                    aug = A::combine(&aug, &A::element(element));
+                }
+                (None, None) => (),
+            }
+        }
+        (size, aug)
+    }
+
+    // Returns the aggregate of the elements with keys in the range, in O(log n).
+    pub fn query_range(&self, range: impl RangeBounds<usize>) -> A::Value {
+        match &self.root {
+            Some(root) => Self::query_node(root, KeyBounds::new(&range, self.compare), None, None),
+            None => A::identity(),
+        }
+    }
+
+    // Returns the elements with keys in the range for which pred holds, in key order.
+    // pred is also applied to the aggregates of subtrees, which are skipped when it fails. So pred
+    // must hold for a subtree whenever it holds for one of its elements, e.g. a lower bound on a
+    // maximum. The cost is O(log n) per returned element.
+    pub fn find_augmented(
+        &self,
+        range: impl RangeBounds<usize>,
+        pred: impl Fn(&A::Value) -> bool,
+    ) -> Vec<Element> {
+        let mut result = Vec::new();
+        if let Some(root) = &self.root {
+            let bounds = KeyBounds::new(&range, self.compare);
+            Self::find_augmented_node(root, bounds, &pred, &mut result);
+        }
+        result
+    }
+
+    // Collects the matching elements of a subtree, recursively.
+    fn find_augmented_node(
+        node: &TwoThreeNode<A>,
+        bounds: KeyBounds,
+        pred: &impl Fn(&A::Value) -> bool,
+        result: &mut Vec<Element>,
+    ) {
+        if !pred(&node.aug) {
+            return;
+        }
+        let matches = |e: &Element| bounds.contains(e.key) && pred(&A::element(e));
+        if let Some(ref child1) = node.child1 {
+            if bounds.after_start(node.elem1.key) {
+                Self::find_augmented_node(child1, bounds, pred, result);
+            }
+        }
+        if matches(&node.elem1) {
+            result.push(node.elem1);
+        }
+        if let Some(ref child2) = node.child2 {
+            if bounds.before_end(node.elem1.key)
+                && node.elem2.is_none_or(|elem2| bounds.after_start(elem2.key))
+            {
+                Self::find_augmented_node(child2, bounds, pred, result);
+            }
+        }
+        if let Some(elem2) = node.elem2 {
+            if matches(&elem2) {
+                result.push(elem2);
+            }
+            if let Some(ref child3) = node.child3 {
+                if bounds.before_end(elem2.key) {
+                    Self::find_augmented_node(child3, bounds, pred, result);
+                }
+            }
+        }
+    }
+
+    // Aggregates the elements with keys within the bounds, recursively.
+    // All keys of the subtree are known to be in [low, high], None being unbounded.
+    fn query_node(
+        node: &TwoThreeNode<A>,
+        bounds: KeyBounds,
+        low: Option<usize>,
+        high: Option<usize>,
+    ) -> A::Value {
+        if bounds.covers(low, high) {
+            return node.aug.clone();
+        }
+        let mut aug = A::identity();
+        let elem1 = node.elem1.key;
+        let elem2 = node.elem2.map(|elem2| elem2.key);
+        if let Some(ref child1) = node.child1 {
+            if bounds.after_start(elem1) {
+                aug = A::combine(&aug, &Self::query_node(child1, bounds, low, Some(elem1)));
+            }
+        }
+        if bounds.contains(elem1) {
+            aug = A::combine(&aug, &A::element(&node.elem1));
+        }
+        if let Some(ref child2) = node.child2 {
+            let high2 = elem2.or(high);
+            if high2.is_none_or(|high2| bounds.after_start(high2)) && bounds.before_end(elem1) {
+                aug = A::combine(&aug, &Self::query_node(child2, bounds, Some(elem1), high2));
+            }
+        }
+        if let Some(elem2) = elem2 {
+            if bounds.contains(elem2) {
+                aug = A::combine(&aug, &A::element(node.elem2.as_ref().unwrap()));
+            }
+            if let Some(ref child3) = node.child3 {
+                if bounds.before_end(elem2) {
+                    aug = A::combine(&aug, &Self::query_node(child3, bounds, Some(elem2), high));
+                }
+            }
+        }
+        aug
+    }
+
+    // Validates the structure of the tree.
+    pub fn validate(&self) {
+        if let Some(ref root) = self.root {
+            let mut state = ValidateState::new(self.compare);
+            Self::validate_node(root, 0, &mut state);
+            assert!(state.elements == self.size);
+        }
+        let pair = |e: Option<Element>| e.map(|e| (e.key, e.value));
+        assert!(pair(self.min) == pair(self.find_first()));
+        assert!(pair(self.max) == pair(self.find_last()));
+    }
+
+    // Validates a node recursively.
+    fn validate_node(node: &TwoThreeNode<A>, level: usize, state: &mut ValidateState) {
+        state.elements += 1;
+
+        // Check the size and the aggregate, unless stale. The children are checked recursively.
+        let (size, aug) = Self::compute_node(node);
+        assert!(node.size == size);
+        assert!(node.stale || node.aug == aug);
+        assert!(
+            node.stale
+                || [&node.child1, &node.child2, &node.child3]
+                    .into_iter()
+                    .flatten()
+                    .all(|child| !child.stale)
+        );
+
+        // Check that elems are ordered.
+        if let Some(elem2) = node.elem2 {
+            assert!((state.compare)(&node.elem1.key, &elem2.key) != Ordering::Greater);
+            state.elements += 1;
+        }
+
+        // For leaf node.
+        if node.child1.is_none() {
+            assert!(node.child2.is_none());
+            assert!(node.child3.is_none());
+
+            // All leaves should be at the same level.
+            if state.leaf_level == 0 {
+                state.leaf_level = level;
+            } else {
+                assert!(level == state.leaf_level);
+            }
+            return;
+        }
+
+        // There should be at least 2 children.
+        let child1 = node.child1.as_ref().unwrap();
+        let child2 = node.child2.as_ref().unwrap();
+
+        // Check child1, child2 ordering.
+        Self::validate_node_less_than(child1, node.elem1.key, state.compare);
+        Self::validate_node_greater_than(child2, node.elem1.key, state.compare);
+
+        if let Some(elem2) = node.elem2 {
+            // Check child3 ordering.
+            let child3 = node.child3.as_ref().unwrap();
+            Self::validate_node_greater_than(child3, elem2.key, state.compare);
+        }
+
+        // Check the children.
+        Self::validate_node(child1, level + 1, state);
+        Self::validate_node(child2, level + 1, state);
+        if let Some(ref child3) = node.child3 {
+            Self::validate_node(child3, level + 1, state);
+        }
+    }
+
+    // Checks that the node's elements are less than the given value.
+    fn validate_node_less_than(node: &TwoThreeNode<A>, key_value: usize, compare: Comparator) {
+        assert!(compare(&node.elem1.key, &key_value) != Ordering::Greater);
+        if let Some(elem2) = node.elem2 {
+            assert!(compare(&elem2.key, &key_value) != Ordering::Greater);
+        }
+    }
+
+    // Checks that the node's elements are greater than the given value.
+    fn validate_node_greater_than(node: &TwoThreeNode<A>, key_value: usize, compare: Comparator) {
+        assert!(compare(&node.elem1.key, &key_value) != Ordering::Less);
+        if let Some(elem2) = node.elem2 {
+            assert!(compare(&elem2.key, &key_value) != Ordering::Less);
+        }
+    }
+}
+
+impl<A: Augment> Default for TwoThreeTree<A> {
+    fn default() -> Self {
+        TwoThreeTree::with_augment()
+    }
+}
+
+// Returns the value of an element with the key, like BTreeMap. Panics if the key is not found.
+impl<A: Augment> std::ops::Index<&usize> for TwoThreeTree<A> {
+    type Output = usize;
+
+    fn index(&self, key: &usize) -> &usize {
+        &self.lookup(*key).expect("key not found").value
+    }
+}
+
+impl From<BTreeMap<usize, usize>> for TwoThreeTree {
+    fn from(map: BTreeMap<usize, usize>) -> TwoThreeTree {
+        TwoThreeTree::from_sorted(map.into_iter().map(|(key, value)| Element { key, value }))
+    }
+}
+
+// Elements with equal keys collapse into one entry, keeping the last value in key order.
+impl From<TwoThreeTree> for BTreeMap<usize, usize> {
+    fn from(tree: TwoThreeTree) -> BTreeMap<usize, usize> {
+        tree.iter().map(|e| (e.key, e.value)).collect()
+    }
+}
+
+impl From<Vec<(usize, usize)>> for TwoThreeTree {
+    fn from(mut pairs: Vec<(usize, usize)>) -> TwoThreeTree {
+        pairs.sort_by_key(|pair| pair.0);
+        TwoThreeTree::from_sorted(pairs.into_iter().map(|(key, value)| Element { key, value }))
+    }
+}
+
+// A read-only view of a node, to walk the structure of a tree.
+pub struct NodeRef<'a, A: Augment = ()> {
+    node: &'a TwoThreeNode<A>,
+}
+
+impl<A: Augment> Clone for NodeRef<'_, A> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<A: Augment> Copy for NodeRef<'_, A> {}
+
+impl<'a, A: Augment> NodeRef<'a, A> {
+    // The one or two elements of the node, in order.
+    pub fn elements(&self) -> impl Iterator<Item = Element> + 'a {
+        Some(self.node.elem1).into_iter().chain(self.node.elem2)
+    }
+
+    // The subtrees of the node, in order: none for a leaf, else one more than the elements.
+    pub fn children(&self) -> impl Iterator<Item = NodeRef<'a, A>> + 'a {
+        let node = self.node;
+        [&node.child1, &node.child2, &node.child3]
+            .into_iter()
+            .flatten()
+            .map(|child| NodeRef { node: child })
+    }
+
+    pub fn is_leaf(&self) -> bool {
+        self.node.child1.is_none()
+    }
+
+    // Number of elements in the subtree.
+    pub fn size(&self) -> usize {
+        self.node.size
+    }
+
+    // Aggregate of the elements in the subtree.
+    pub fn aug(&self) -> &'a A::Value {
+        &self.node.aug
+    }
+}
+
+// A breadth-first iterator over the levels of a tree, from the root down to the leaves.
+// Each level is the nodes at that depth, from left to right.
+pub struct Levels<'a, A: Augment = ()> {
+    next: Vec<NodeRef<'a, A>>,
+}
+
+impl<'a, A: Augment> Iterator for Levels<'a, A> {
+    type Item = Vec<NodeRef<'a, A>>;
+
+    fn next(&mut self) -> Option<Vec<NodeRef<'a, A>>> {
+        if self.next.is_empty() {
+            return None;
+        }
+        let below = self.next.iter().flat_map(|node| node.children()).collect();
+        Some(std::mem::replace(&mut self.next, below))
+    }
+}
+
+// A part of the tree iterated by one Rayon job.
+#[cfg(feature = "rayon")]
+enum IterPart<'a, A: Augment> {
+    Node(&'a TwoThreeNode<A>),
+    Element(Element),
+}
+
+// An in-order iterator over the elements of a tree.
+pub struct Iter<'a, A: Augment = ()> {
+    // The nodes on the path to the current element. Each node is paired with the step it
+    // continues from: 0 to descend child1, 1 to yield elem1, 2 to descend child2,
+    // 3 to yield elem2, 4 to descend child3, 5 when done.
+    stack: Vec<(&'a TwoThreeNode<A>, u8)>,
+}
+
+impl<'a, A: Augment> Iterator for Iter<'a, A> {
+    type Item = Element;
+
+    fn next(&mut self) -> Option<Element> {
+        while let Some((node, step)) = self.stack.last_mut() {
+            let node: &'a TwoThreeNode<A> = node;
+            *step += 1;
+            match *step - 1 {
+                0 => {
+                    if let Some(ref child1) = node.child1 {
+                        self.stack.push((child1, 0));
+                    }
+                }
+                1 => return Some(node.elem1),
+                2 => {
+                    if let Some(ref child2) = node.child2 {
+                        self.stack.push((child2, 0));
+                    }
+                }
+                3 => {
+                    if node.elem2.is_some() {
+                        return node.elem2;
+                    }
+                }
+                4 => {
+                    if let Some(ref child3) = node.child3 {
+                        self.stack.push((child3, 0));
+                    }
+                }
+                _ => {
+                    self.stack.pop();
+                }
+            }
+        }
+        None
+    }
+}
+
+// An iterator over the pairs of consecutive elements of a tree, in key order.
+pub struct Windows<'a, A: Augment = ()> {
+    iter: Iter<'a, A>,
+    previous: Option<Element>,
+}
+
+impl<A: Augment> Iterator for Windows<'_, A> {
+    type Item = (Element, Element);
+
+    fn next(&mut self) -> Option<(Element, Element)> {
+        let previous = match self.previous {
+            Some(previous) => previous,
+            None => self.iter.next()?,
+        };
+        let next = self.iter.next()?;
+        self.previous = Some(next);
+        Some((previous, next))
+    }
+}
+
+// An iterator over batches of consecutive elements of a tree, in key order. All batches have
+// the requested size but the last one, which may be smaller.
+pub struct Chunks<'a, A: Augment = ()> {
+    iter: Iter<'a, A>,
+    size: usize,
+}
+
+impl<A: Augment> Iterator for Chunks<'_, A> {
+    type Item = Vec<Element>;
+
+    fn next(&mut self) -> Option<Vec<Element>> {
+        let chunk: Vec<Element> = self.iter.by_ref().take(self.size).collect();
+        if chunk.is_empty() {
+            None
+        } else {
+            Some(chunk)
+        }
+    }
+}
+
+impl<'a, A: Augment> IntoIterator for &'a TwoThreeTree<A> {
+    type Item = Element;
+    type IntoIter = Iter<'a, A>;
+
+    fn into_iter(self) -> Iter<'a, A> {
+        self.iter()
+    }
+}
+
+// A position in the in-order traversal of a tree, which does not borrow the tree.
+pub struct Cursor {
+    // Generation of the tree the cursor was created on.
+    generation: u64,
+
+    // The key of the last returned element, and the number of returned elements with that key.
+    last: Option<(usize, usize)>,
+}
+
+impl Cursor {
+    // Returns true if the tree has not been mutated since the cursor was created.
+    pub fn is_valid<A: Augment>(&self, tree: &TwoThreeTree<A>) -> bool {
+        self.generation == tree.generation
+    }
+
+    // Returns the next element, or None at the end.
+    // Panics if the tree has been mutated since the cursor was created.
+    pub fn next<A: Augment>(&mut self, tree: &TwoThreeTree<A>) -> Option<Element> {
+        assert!(
+            self.is_valid(tree),
+            "tree was mutated while a cursor was in use"
+        );
+        let element = match self.last {
+            None => tree.iter().next(),
+            Some((key, count)) => tree.iter_from(key).nth(count),
+        }?;
+        self.last = match self.last {
+            Some((key, count)) if key == element.key => Some((key, count + 1)),
+            _ => Some((element.key, 1)),
+        };
+        Some(element)
+    }
+}
+
+// Converts a range of keys to inclusive bounds, or None if the range is empty.
+pub(crate) fn inclusive_range(range: &impl RangeBounds<usize>) -> Option<(usize, usize)> {
+    let start = match range.start_bound() {
+        Bound::Included(&key) => key,
+        Bound::Excluded(&key) => key.checked_add(1)?,
+        Bound::Unbounded => 0,
+    };
+    let end = match range.end_bound() {
+        Bound::Included(&key) => key,
+        Bound::Excluded(&key) => key.checked_sub(1)?,
+        Bound::Unbounded => usize::MAX,
+    };
+    if start <= end {
+        Some((start, end))
+    } else {
+        None
+    }
+}
+
+// Tracks the leaf level observed during validation recursion.
+struct ValidateState {
+    leaf_level: usize,
+    elements: usize,
+    compare: Comparator,
+}
+
+impl ValidateState {
+    fn new(compare: Comparator) -> ValidateState {
+        ValidateState {
+            leaf_level: 0,
+            elements: 0,
+            compare,
+        }
+    }
+}
+
+// The bounds of a key range, compared in the order of a tree.
+#[derive(Clone, Copy)]
+struct KeyBounds {
+    start: Bound<usize>,
+    end: Bound<usize>,
+    compare: Comparator,
+}
+
+impl KeyBounds {
+    fn new(range: &impl RangeBounds<usize>, compare: Comparator) -> KeyBounds {
+        KeyBounds {
+            start: range.start_bound().cloned(),
+            end: range.end_bound().cloned(),
+            compare,
+        }
+    }
+
+    // Returns true if the key is not before the start of the range.
+    fn after_start(&self, key: usize) -> bool {
+        match self.start {
+            Bound::Included(start) => (self.compare)(&key, &start) != Ordering::Less,
+            Bound::Excluded(start) => (self.compare)(&key, &start) == Ordering::Greater,
+            Bound::Unbounded => true,
+        }
+    }
+
+    // Returns true if the key is not after the end of the range.
+    fn before_end(&self, key: usize) -> bool {
+        match self.end {
+            Bound::Included(end) => (self.compare)(&key, &end) != Ordering::Greater,
+            Bound::Excluded(end) => (self.compare)(&key, &end) == Ordering::Less,
+            Bound::Unbounded => true,
+        }
+    }
+
+    fn contains(&self, key: usize) -> bool {
+        self.after_start(key) && self.before_end(key)
+    }
+
+    // Returns true if the range holds all keys in [low, high], None being unbounded.
+    fn covers(&self, low: Option<usize>, high: Option<usize>) -> bool {
+        let start = match low {
+            Some(low) => self.after_start(low),
+            None => matches!(self.start, Bound::Unbounded),
+        };
+        let end = match high {
+            Some(high) => self.before_end(high),
+            None => matches!(self.end, Bound::Unbounded),
+        };
+        start && end
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Augment, DuplicatePolicy, Element, Observer, Op, TwoThreeTree};
+    use std::cell::Cell;
+    use std::cmp::Ordering;
+    use std::collections::BTreeMap;
+    use std::ops::Bound;
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+    use std::sync::{Arc, Mutex};
+
+    use crate::diff::DiffEntry;
+
+    fn insert(tree: &mut TwoThreeTree, key: usize) {
+        println!("== Insert {}", key);
+        tree.insert(Element { key, value: key });
+        tree.print();
+        tree.validate();
+
+        let found_element = tree.find(key);
+        assert!(found_element.unwrap().key == key);
+    }
+
+    fn delete(tree: &mut TwoThreeTree, key: usize) {
+        println!("== Delete {}", key);
+        assert!(tree.delete(key));
+        tree.print();
+        tree.validate();
+    }
+
+    #[test]
+    fn test_simple_1() {
+        let mut tree = TwoThreeTree::new();
+        insert(&mut tree, 2);
+        insert(&mut tree, 1);
+        insert(&mut tree, 3);
+        insert(&mut tree, 5);
+        insert(&mut tree, 4);
+        assert!(tree.size() == 5);
+        delete(&mut tree, 3);
+        assert!(tree.find(3).is_none());
+        delete(&mut tree, 1);
+        delete(&mut tree, 2);
+        delete(&mut tree, 4);
+        delete(&mut tree, 5);
+    }
+
+    #[test]
+    fn test_ordered_insert_delete() {
+        let num_elements = 50;
+
+        let mut tree = TwoThreeTree::new();
+        for i in 0..num_elements {
+            insert(&mut tree, i);
+        }
+        for i in 0..num_elements {
+            delete(&mut tree, i);
+        }
+        assert!(tree.is_empty());
+
+        for i in (0..num_elements).rev() {
+            insert(&mut tree, i);
+        }
+        for i in 0..num_elements {
+            delete(&mut tree, i);
+        }
+    }
+
+    #[test]
+    fn test_random_insert_delete() {
+        let num_elements = 80;
+
+        let mut tree = TwoThreeTree::new();
+        let mut elements: Vec<usize> = Vec::new();
+        for i in 0..num_elements {
+            let elem = (num_elements + i * 71329) & 0xfffffff;
+            elements.push(elem);
+            insert(&mut tree, elem);
+        }
+        let mut n = 0;
+        for _ in 0..elements.len() {
+            n = (n + 13) % elements.len();
+            delete(&mut tree, elements[n]);
+        }
+        assert!(tree.is_empty());
+    }
+
+    #[test]
+    fn test_apply_batch() {
+        let mut tree = TwoThreeTree::new();
+        for i in 0..20 {
+            insert(&mut tree, i);
+        }
+
+        // Delete of a missing key rejects the whole batch.
+        assert!(!tree.apply_batch(vec![Op::Insert(100, 100), Op::Delete(50)]));
+        assert!(tree.size() == 20);
+        assert!(tree.find(100).is_none());
+
+        // Later operations see the effect of earlier ones.
+        assert!(tree.apply_batch(vec![
+            Op::Insert(50, 1),
+            Op::Update(50, Box::new(|v| v + 1)),
+            Op::Delete(3),
+            Op::Delete(4),
+            Op::Update(7, Box::new(|v| v * 10)),
+        ]));
+        tree.validate();
+        assert!(tree.size() == 19);
+        assert!(tree.find(50).unwrap().value == 2);
+        assert!(tree.find(7).unwrap().value == 70);
+        assert!(tree.find(3).is_none());
+        assert!(!tree.apply_batch(vec![Op::Delete(3)]));
+    }
+
+    // Records the notifications as strings.
+    struct Recorder {
+        log: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl Observer for Recorder {
+        fn on_insert(&mut self, element: &Element) {
+            self.log.lock().unwrap().push(format!("+{}", element.key));
+        }
+
+        fn on_remove(&mut self, element: &Element) {
+            self.log.lock().unwrap().push(format!("-{}", element.key));
+        }
+
+        fn on_update(&mut self, old: &Element, new: &Element) {
+            let entry = format!("{}:{}->{}", old.key, old.value, new.value);
+            self.log.lock().unwrap().push(entry);
+        }
+    }
+
+    #[test]
+    fn test_observer() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let mut tree = TwoThreeTree::new();
+        tree.insert(Element { key: 1, value: 1 });
+        tree.add_observer(Box::new(Recorder { log: log.clone() }));
+
+        tree.insert(Element { key: 2, value: 2 });
+        assert!(!tree.delete(5));
+        assert!(tree.delete(1));
+        assert!(tree.apply_batch(vec![Op::Update(2, Box::new(|v| v + 1))]));
+        assert!(*log.lock().unwrap() == vec!["+2", "-1", "2:2->3"]);
+
+        tree.clear_observers();
+        tree.insert(Element { key: 3, value: 3 });
+        assert!(log.lock().unwrap().len() == 3);
+    }
+
+    #[test]
+    fn test_iter() {
+        let mut tree = TwoThreeTree::new();
+        for i in (0..40).rev() {
+            tree.insert(Element {
+                key: i / 2,
+                value: i,
+            });
+        }
+        let keys: Vec<usize> = tree.iter().map(|e| e.key).collect();
+        assert!(keys == (0..40).map(|i| i / 2).collect::<Vec<usize>>());
+        assert!((&tree).into_iter().count() == 40);
+        assert!(TwoThreeTree::new().iter().next().is_none());
+    }
+
+    #[test]
+    fn test_cursor() {
+        let mut tree = TwoThreeTree::new();
+        for i in 0..30 {
+            tree.insert(Element {
+                key: i / 3,
+                value: i,
+            });
+        }
+        let mut cursor = tree.cursor();
+        let mut keys = Vec::new();
+        while let Some(element) = cursor.next(&tree) {
+            keys.push(element.key);
+        }
+        assert!(keys == (0..30).map(|i| i / 3).collect::<Vec<usize>>());
+
+        // Mutating the tree invalidates the cursor.
+        let generation = tree.generation();
+        let mut cursor = tree.cursor();
+        cursor.next(&tree);
+        tree.insert(Element {
+            key: 100,
+            value: 100,
+        });
+        assert!(tree.generation() != generation);
+        assert!(!cursor.is_valid(&tree));
+        assert!(catch_unwind(AssertUnwindSafe(|| cursor.next(&tree))).is_err());
+    }
+
+    #[test]
+    fn test_from_sorted() {
+        for n in 0..200 {
+            let tree = TwoThreeTree::from_sorted((0..n).map(|i| Element { key: i, value: i }));
+            tree.validate();
+            assert!(tree.size() == n);
+            assert!(tree.iter().map(|e| e.key).eq(0..n));
+        }
+    }
+
+    #[test]
+    fn test_btree_map_conversion() {
+        let map: BTreeMap<usize, usize> = (0..100).map(|i| (i * 3, i)).collect();
+        let mut tree = TwoThreeTree::from(map.clone());
+        tree.validate();
+        assert!(tree.find(27).unwrap().value == 9);
+        delete(&mut tree, 27);
+        insert(&mut tree, 28);
+        let mut expected = map;
+        expected.remove(&27);
+        expected.insert(28, 28);
+        assert!(BTreeMap::from(tree) == expected);
+
+        let tree = TwoThreeTree::from(vec![(5, 50), (1, 10), (3, 30)]);
+        tree.validate();
+        assert!(tree.iter().map(|e| e.value).eq([10, 30, 50]));
+    }
+
+    #[test]
+    fn test_dump_into() {
+        let mut tree = TwoThreeTree::new();
+        for i in (0..50).rev() {
+            tree.insert(Element {
+                key: i,
+                value: i * 2,
+            });
+        }
+        let mut buf = vec![(1000, 1000)];
+        tree.dump_into(&mut buf);
+        assert!(buf.len() == 51);
+        assert!(buf[1..].iter().copied().eq((0..50).map(|i| (i, i * 2))));
+        assert!(tree.into_sorted_vec() == buf[1..]);
+    }
+
+    // Sums the values of the elements.
+    struct ValueSum;
+
+    impl Augment for ValueSum {
+        type Value = usize;
+
+        fn identity() -> usize {
+            0
+        }
+
+        fn element(element: &Element) -> usize {
+            element.value
+        }
+
+        fn combine(left: &usize, right: &usize) -> usize {
+            left + right
+        }
+    }
+
+    #[test]
+    fn test_query_range() {
+        let mut tree = TwoThreeTree::<ValueSum>::with_augment();
+        let mut elements: Vec<usize> = Vec::new();
+        for i in 0..200 {
+            let key = (i * 7919) % 101;
+            elements.push(key);
+            tree.insert(Element { key, value: i });
+            tree.validate();
+        }
+        let mut n = 0;
+        for _ in 0..100 {
+            n = (n + 13) % elements.len();
+            assert!(tree.delete(elements.swap_remove(n)));
+            tree.validate();
+        }
+        tree.apply_batch(vec![Op::Update(elements[0], Box::new(|v| v + 1000))]);
+        tree.validate();
+
+        let pairs = tree.iter().collect::<Vec<Element>>();
+        for (start, end) in [(0, 101), (10, 20), (33, 33), (50, 40), (0, 0), (100, 200)] {
+            let expected: usize = pairs
+                .iter()
+                .filter(|e| start <= e.key && e.key < end)
+                .map(|e| e.value)
+                .sum();
+            assert!(tree.query_range(start..end) == expected);
+        }
+        assert!(tree.query_range(..) == pairs.iter().map(|e| e.value).sum::<usize>());
+    }
+
+    #[test]
+    fn test_select_percentile() {
+        let mut tree = TwoThreeTree::new();
+        assert!(tree.median().is_none());
+        assert!(tree.percentile(50.0).is_none());
+        for i in (1..=100).rev() {
+            tree.insert(Element {
+                key: i * 10,
+                value: i,
+            });
+        }
+        for rank in 0..100 {
+            assert!(tree.select(rank).unwrap().key == (rank + 1) * 10);
+        }
+        assert!(tree.select(100).is_none());
+        assert!(tree.median().unwrap().key == 500);
+        assert!(tree.percentile(0.0).unwrap().key == 10);
+        assert!(tree.percentile(90.0).unwrap().key == 900);
+        assert!(tree.percentile(90.5).unwrap().key == 910);
+        assert!(tree.percentile(100.0).unwrap().key == 1000);
+        tree.delete(1000);
+        assert!(tree.median().unwrap().key == 500);
+    }
+
+    #[test]
+    fn test_iter_from() {
+        let mut tree = TwoThreeTree::new();
+        for i in 0..100 {
+            insert(&mut tree, i * 2);
+        }
+        assert!(tree.iter_from(0).map(|e| e.key).eq((0..100).map(|i| i * 2)));
+        assert!(tree
+            .iter_from(51)
+            .map(|e| e.key)
+            .eq((26..100).map(|i| i * 2)));
+        assert!(tree
+            .iter_from(52)
+            .map(|e| e.key)
+            .eq((26..100).map(|i| i * 2)));
+        assert!(tree.iter_from(199).next().is_none());
+
+        // Resume a paginated iteration from the last seen key.
+        let mut keys = Vec::new();
+        let mut next = 0;
+        loop {
+            let page: Vec<usize> = tree.iter_from(next).take(7).map(|e| e.key).collect();
+            match page.last() {
+                Some(&last) => next = last + 1,
+                None => break,
+            }
+            keys.extend(page);
+        }
+        assert!(keys.into_iter().eq((0..100).map(|i| i * 2)));
+    }
+
+    #[test]
+    fn test_partition_point() {
+        let mut tree = TwoThreeTree::new();
+        assert!(tree.partition_point(|_| true).is_none());
+        for i in 0..100 {
+            tree.insert(Element {
+                key: i * 3,
+                value: i,
+            });
+        }
+        for bound in 0..310 {
+            let expected = tree.iter().find(|e| e.key >= bound);
+            let found = tree.partition_point(|key| key < bound);
+            assert!(found.map(|e| e.key) == expected.map(|e| e.key));
+        }
+        assert!(tree.partition_point(|_| false).unwrap().key == 0);
+    }
+
+    #[test]
+    fn test_update_upsert() {
+        let mut tree = TwoThreeTree::new();
+        for i in 0..20 {
+            insert(&mut tree, i);
+        }
+        assert!(tree.update(7, |v| *v += 100));
+        assert!(tree.find(7).unwrap().value == 107);
+        assert!(!tree.update(70, |v| *v += 100));
+
+        // Count occurrences.
+        for key in [3, 30, 30, 3, 30] {
+            tree.upsert(key, 1, |v| *v += 1);
+        }
+        tree.validate();
+        assert!(tree.find(3).unwrap().value == 5);
+        assert!(tree.find(30).unwrap().value == 3);
+        assert!(tree.size() == 21);
+    }
+
+    #[test]
+    fn test_rekey() {
+        let mut tree = TwoThreeTree::new();
+        for i in 0..50 {
+            insert(&mut tree, i * 10);
+        }
+        assert!(tree.rank(0) == 0);
+        assert!(tree.rank(15) == 2);
+        assert!(tree.rank(1000) == 50);
+
+        // In place, between the same neighbors.
+        assert!(tree.rekey(100, 105));
+        assert!(tree.rekey(200, 191));
+        // Across other elements.
+        assert!(tree.rekey(300, 5));
+        assert!(tree.rekey(0, 1000));
+        assert!(!tree.rekey(301, 302));
+        tree.validate();
+        assert!(tree.find(105).unwrap().value == 100);
+        assert!(tree.find(191).unwrap().value == 200);
+        assert!(tree.find(5).unwrap().value == 300);
+        assert!(tree.find(1000).unwrap().value == 0);
+        assert!(tree.find(100).is_none() && tree.find(300).is_none());
+        assert!(tree.size() == 50);
+    }
+
+    #[test]
+    fn test_delete_min_max() {
+        let mut tree = TwoThreeTree::new();
+        assert!(tree.delete_min().is_none());
+        assert!(tree.delete_max().is_none());
+        for i in 0..60 {
+            insert(&mut tree, (i * 37) % 60);
+        }
+        for i in 0..30 {
+            assert!(tree.delete_min().unwrap().key == i);
+            tree.validate();
+            assert!(tree.delete_max().unwrap().key == 59 - i);
+            tree.validate();
+        }
+        assert!(tree.is_empty());
+    }
+
+    #[test]
+    fn test_cached_first_last() {
+        let mut tree = TwoThreeTree::new();
+        assert!(tree.first().is_none() && tree.last().is_none());
+        for i in 0..50 {
+            insert(&mut tree, (i * 7) % 50);
+        }
+        assert!(tree.first().unwrap().key == 0 && tree.last().unwrap().key == 49);
+        assert!(tree.update(0, |v| *v = 100));
+        assert!(tree.first().unwrap().value == 100);
+        assert!(tree.rekey(49, 75));
+        assert!(tree.last().unwrap().key == 75);
+        delete(&mut tree, 0);
+        assert!(tree.first().unwrap().key == 1);
+        tree.validate();
+        let tree = TwoThreeTree::from_sorted((3..9).map(|key| Element { key, value: key }));
+        assert!(tree.first().unwrap().key == 3 && tree.last().unwrap().key == 8);
+    }
+
+    #[test]
+    fn test_cut() {
+        let mut tree = TwoThreeTree::new();
+        for i in 0..100 {
+            insert(&mut tree, i % 50);
+        }
+        let (below, middle, above) = tree.cut(10..20);
+        for (part, size, first, last) in [
+            (&below, 20, 0, 9),
+            (&middle, 20, 10, 19),
+            (&above, 60, 20, 49),
+        ] {
+            part.validate();
+            assert!(part.size() == size);
+            assert!(part.first().unwrap().key == first && part.last().unwrap().key == last);
+        }
+
+        let mut tree = TwoThreeTree::from_sorted((0..10).map(|key| Element { key, value: key }));
+        assert!(tree.split_off(10).is_empty());
+        let all = tree.split_off(0);
+        assert!(tree.is_empty() && tree.first().is_none());
+        assert!(all.size() == 10);
+        all.validate();
+
+        // Splits at every key, through nodes of all shapes, keep the order and the sizes.
+        for n in [1, 2, 3, 7, 40, 300] {
+            for key in 0..=n / 2 + 1 {
+                let mut tree = TwoThreeTree::new();
+                for i in 0..n {
+                    let key = (i * 7) % n / 2;
+                    tree.insert(Element { key, value: i });
+                }
+                let elements: Vec<Element> = tree.iter().collect();
+                let above = tree.split_off(key);
+                tree.validate();
+                above.validate();
+                assert!(tree.iter().all(|e| e.key < key) && above.iter().all(|e| e.key >= key));
+                assert!(tree.iter().chain(above.iter()).eq(elements));
+            }
+        }
+    }
+
+    #[test]
+    fn test_clone_range() {
+        let mut tree = TwoThreeTree::new();
+        for i in 0..100 {
+            insert(&mut tree, i);
+        }
+        let copy = tree.clone_range(40..=59);
+        copy.validate();
+        assert!(copy.size() == 20 && tree.size() == 100);
+        assert!(copy.iter().map(|e| e.key).eq(40..60));
+        assert!(tree.clone_range(200..).is_empty());
+        #[allow(clippy::reversed_empty_ranges)]
+        let empty = tree.clone_range(50..40);
+        assert!(empty.is_empty());
+    }
+
+    #[test]
+    fn test_merge_with() {
+        let mut tree = TwoThreeTree::from_sorted((0..10).map(|key| Element { key, value: 1 }));
+        let other = TwoThreeTree::from_sorted((5..15).map(|key| Element { key, value: 2 }));
+        tree.merge_with(other, |_, a, b| a + b);
+        tree.validate();
+        let pairs = tree.into_sorted_vec();
+        assert!(pairs.len() == 15);
+        assert!(pairs
+            .iter()
+            .all(|&(key, value)| value == [1, 3, 2][(key >= 5) as usize + (key >= 10) as usize]));
+    }
+
+    #[test]
+    fn test_shared_between_threads() {
+        let mut tree = TwoThreeTree::new();
+        for i in 0..1000 {
+            tree.insert(Element {
+                key: i,
+                value: i * 2,
+            });
+        }
+        let tree = std::sync::Arc::new(std::sync::RwLock::new(tree));
+        let readers: Vec<_> = (0..4)
+            .map(|t| {
+                let tree = std::sync::Arc::clone(&tree);
+                std::thread::spawn(move || {
+                    let tree = tree.read().unwrap();
+                    for i in (t..1000).step_by(4) {
+                        assert!(tree.find(i).unwrap().value == i * 2);
+                    }
+                    tree.iter().count()
+                })
+            })
+            .collect();
+        for reader in readers {
+            assert!(reader.join().unwrap() == 1000);
+        }
+        tree.write().unwrap().insert(Element {
+            key: 1000,
+            value: 0,
+        });
+        assert!(tree.read().unwrap().size() == 1001);
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn test_par_iter() {
+        use rayon::iter::ParallelIterator;
+
+        let tree = TwoThreeTree::from_sorted((0..10000).map(|key| Element { key, value: key }));
+        let elements: Vec<Element> = tree.par_iter().collect();
+        assert!(elements.iter().map(|e| e.key).eq(0..10000));
+        assert!(tree.par_iter().map(|e| e.value).sum::<usize>() == 49995000);
+        assert!(TwoThreeTree::new().par_iter().count() == 0);
+    }
+
+    #[cfg(feature = "tracing")]
+    #[test]
+    fn test_tracing() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use tracing::span::{Attributes, Id, Record};
+        use tracing::{Event, Metadata, Subscriber};
+
+        // Counts the spans by name and the events.
+        #[derive(Default)]
+        struct Counter {
+            spans: Mutex<BTreeMap<&'static str, usize>>,
+            events: AtomicUsize,
+            next_id: AtomicUsize,
+        }
+
+        impl Subscriber for Counter {
+            fn enabled(&self, _: &Metadata<'_>) -> bool {
+                true
+            }
+            fn new_span(&self, span: &Attributes<'_>) -> Id {
+                *self
+                    .spans
+                    .lock()
+                    .unwrap()
+                    .entry(span.metadata().name())
+                    .or_default() += 1;
+                Id::from_u64(self.next_id.fetch_add(1, Ordering::Relaxed) as u64 + 1)
+            }
+            fn record(&self, _: &Id, _: &Record<'_>) {}
+            fn record_follows_from(&self, _: &Id, _: &Id) {}
+            fn event(&self, _: &Event<'_>) {
+                self.events.fetch_add(1, Ordering::Relaxed);
+            }
+            fn enter(&self, _: &Id) {}
+            fn exit(&self, _: &Id) {}
+        }
+
+        let counter = Arc::new(Counter::default());
+        tracing::subscriber::with_default(counter.clone(), || {
+            let mut tree = TwoThreeTree::new();
+            for key in 0..10 {
+                insert(&mut tree, key);
+            }
+            assert!(tree.find(3).is_some());
+            assert!(tree.delete(3));
+        });
+        let spans = counter.spans.lock().unwrap();
+        assert!(spans["insert"] == 10);
+        assert!(spans["find"] >= 1);
+        assert!(spans["delete"] == 1);
+        // The splits are reported, besides one event per find and delete.
+        assert!(counter.events.load(Ordering::Relaxed) > spans["find"] + 1);
+    }
+
+    #[cfg(all(feature = "paranoid", debug_assertions))]
+    #[test]
+    fn test_paranoid() {
+        let mut tree = TwoThreeTree::from_sorted((0..100).map(|key| Element { key, value: key }));
+        // Corrupt the ordering of the root; the next mutation catches it.
+        tree.root.as_mut().unwrap().elem1.key = 1000;
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            tree.insert(Element { key: 5, value: 5 });
+        }));
+        assert!(result.is_err());
     }
 
-    // Converts a 2-node to a 3-node, adding a node and child on the right side.
-    fn add_right(node: &mut TwoThreeNode, elem2: Element, child3: Option<Box<TwoThreeNode>>) {
-        node.elem2 = Some(elem2);
-        node.child3 = child3;
+    #[test]
+    fn test_pretty_string() {
+        assert!(TwoThreeTree::new().to_pretty_string() == "Empty tree\n");
+        let mut tree = TwoThreeTree::new();
+        for key in 1..=8 {
+            tree.insert(Element {
+                key,
+                value: key * 10,
+            });
+        }
+        let expected = "\
+Tree(8):
+(4:40)
+├── (2:20)
+│   ├── (1:10)
+│   └── (3:30)
+└── (6:60)
+    ├── (5:50)
+    └── [7:70 | 8:80]
+";
+        assert!(tree.to_pretty_string() == expected);
     }
 
-    // Converts a 3-node to a 2-node, removing right element and right child.
-    fn trim_right(node: &mut TwoThreeNode) -> (Element, Option<Box<TwoThreeNode>>) {
-        (node.elem2.take().unwrap(), node.child3.take())
+    #[test]
+    fn test_to_mermaid() {
+        assert!(TwoThreeTree::new().to_mermaid() == "flowchart TD\n");
+        let tree = TwoThreeTree::from(vec![(1, 10), (2, 20), (3, 30)]);
+        let expected = "\
+flowchart TD
+    n0(\"2:20\")
+    n1(\"1:10\")
+    n0 --> n1
+    n2(\"3:30\")
+    n0 --> n2
+";
+        assert!(tree.to_mermaid() == expected);
     }
 
-    // Converts a 3-node to a 2-node, removing left element and left child.
-    fn trim_left(node: &mut TwoThreeNode) -> (Element, Option<Box<TwoThreeNode>>) {
-        let result = (node.elem1, node.child1.take());
-        node.elem1 = node.elem2.take().unwrap();
-        node.child1 = node.child2.take();
-        node.child2 = node.child3.take();
-        result
+    #[test]
+    fn test_parse() {
+        let mut tree = TwoThreeTree::new();
+        for key in [50, 20, 80, 20, 10, 60, 30, 90, 70, 40, 35, 55] {
+            tree.insert(Element {
+                key,
+                value: key + 1,
+            });
+        }
+        let text = tree.to_pretty_string();
+        let parsed = TwoThreeTree::parse(&text).unwrap();
+        parsed.validate();
+        assert!(parsed.to_pretty_string() == text);
+        assert!(parsed.size() == tree.size());
+        let empty: TwoThreeTree = TwoThreeTree::parse("Empty tree\n").unwrap();
+        assert!(empty.is_empty());
+
+        let error = |text| TwoThreeTree::parse(text).err().unwrap();
+        assert!(error("Tree(2):\n(1:1)\n").line == 1);
+        assert!(error("Tree(2):\n(2:2)\n├── (1:1)\n").line == 2);
+        assert!(error("Tree(3):\n(2:2)\n├── (3:3)\n└── (1:1)\n").line == 2);
+        assert!(error("Tree(4):\n(2:2)\n├── (1:1)\n└── (x:3)\n").line == 4);
     }
 
-    // Walk down the tree to the predecessor of a node.
-    fn find_predecessor(node: &mut TwoThreeNode, state: &mut DeleteState) {
-        if let Some(ref mut child3) = node.child3 {
-            Self::find_predecessor(child3, state);
-            Self::delete_node_upward(node, 3, state);
-        } else if let Some(ref mut child2) = node.child2 {
-            Self::find_predecessor(child2, state);
-            Self::delete_node_upward(node, 2, state);
-        } else {
-            // Reached leaf node. Save the predecessor element.
-            if node.elem2.is_some() {
-                state.predecessor = node.elem2.take();
-                state.phase = DeletePhase::Done(true);
-            } else {
-                state.predecessor = Some(node.elem1);
-                state.phase = DeletePhase::FixHole;
+    #[test]
+    fn test_node_ref() {
+        // Returns the number of elements and the depth of the leaves, checking the structure.
+        fn walk(node: super::NodeRef<'_>) -> (usize, usize) {
+            let elements = node.elements().count();
+            let children: Vec<(usize, usize)> = node.children().map(walk).collect();
+            if node.is_leaf() {
+                assert!(children.is_empty());
+                return (elements, 1);
             }
+            assert!(children.len() == elements + 1);
+            assert!(children.iter().all(|child| child.1 == children[0].1));
+            let size = elements + children.iter().map(|child| child.0).sum::<usize>();
+            assert!(size == node.size());
+            (size, children[0].1 + 1)
         }
+
+        assert!(TwoThreeTree::new().root_ref().is_none());
+        let tree = TwoThreeTree::from_sorted((0..100).map(|key| Element { key, value: key }));
+        let root = tree.root_ref().unwrap();
+        assert!(walk(root) == (100, 5));
     }
 
-    // Creates a new node.
-    fn new_node(element: Element) -> Box<TwoThreeNode> {
-        Box::new(TwoThreeNode {
-            elem1: element,
-            elem2: None,
-            child1: None,
-            child2: None,
-            child3: None,
-        })
+    #[test]
+    fn test_path_to() {
+        use super::PathStep::{Compare, Descend};
+        use std::cmp::Ordering::{Equal, Greater, Less};
+
+        // [3 | 5] with the children [1 | 2], (4) and (6).
+        let tree = TwoThreeTree::from(vec![(1, 1), (2, 2), (3, 3), (4, 4), (5, 5), (6, 6)]);
+        let element = |key| Element { key, value: key };
+        let path = tree.path_to(4).unwrap();
+        assert!(
+            path == vec![
+                Compare {
+                    depth: 0,
+                    element: element(3),
+                    ordering: Greater
+                },
+                Compare {
+                    depth: 0,
+                    element: element(5),
+                    ordering: Less
+                },
+                Descend { depth: 0, child: 2 },
+                Compare {
+                    depth: 1,
+                    element: element(4),
+                    ordering: Equal
+                },
+            ]
+        );
+        assert!(tree.path_to(7).is_none());
+        assert!(TwoThreeTree::new().path_to(0).is_none());
     }
 
-    // Validates the structure of the tree.
-    pub fn validate(&self) {
-        if let Some(ref root) = self.root {
-            let mut state = ValidateState::new();
-            Self::validate_node(root, 0, &mut state);
-            assert!(state.elements == self.size);
-        }
+    #[test]
+    fn test_depth_of() {
+        // [3 | 5] with the children [1 | 2], (4) and (6).
+        let tree = TwoThreeTree::from(vec![(1, 1), (2, 2), (3, 3), (4, 4), (5, 5), (6, 6)]);
+        assert!(tree.depth_of(5) == Some(0));
+        assert!(tree.depth_of(2) == Some(1));
+        assert!(tree.depth_of(7).is_none());
+        assert!(tree.depth_histogram() == vec![2, 4]);
+
+        // Most elements sit in the leaves of a balanced tree.
+        let tree = TwoThreeTree::from_sorted((0..1000).map(|key| Element { key, value: key }));
+        let histogram = tree.depth_histogram();
+        assert!(histogram.iter().sum::<usize>() == 1000);
+        assert!(histogram.len() <= 10);
+        assert!(*histogram.last().unwrap() > 500);
     }
 
-    // Validates a node recursively.
-    fn validate_node(node: &TwoThreeNode, level: usize, state: &mut ValidateState) {
-        state.elements += 1;
+    #[test]
+    fn test_levels() {
+        assert!(TwoThreeTree::new().levels().next().is_none());
+        // [3 | 5] with the children [1 | 2], (4) and (6).
+        let tree = TwoThreeTree::from(vec![(1, 1), (2, 2), (3, 3), (4, 4), (5, 5), (6, 6)]);
+        let keys: Vec<Vec<Vec<usize>>> = tree
+            .levels()
+            .map(|level| {
+                level
+                    .iter()
+                    .map(|node| node.elements().map(|e| e.key).collect())
+                    .collect()
+            })
+            .collect();
+        assert!(keys == vec![vec![vec![3, 5]], vec![vec![1, 2], vec![4], vec![6]]]);
+    }
 
-        // Check that elems are ordered.
-        if let Some(elem2) = node.elem2 {
-            assert!(node.elem1.key <= elem2.key);
-            state.elements += 1;
-        }
+    #[test]
+    fn test_for_each_in_range_mut() {
+        use std::ops::ControlFlow;
 
-        // For leaf node.
-        if node.child1.is_none() {
-            assert!(node.child2.is_none());
-            assert!(node.child3.is_none());
+        let mut tree: TwoThreeTree<crate::aggregate::Stats> =
+            TwoThreeTree::build_sorted((0..100).map(|key| Element { key, value: 1 }));
+        let flow = tree.for_each_in_range_mut(10..20, |key, value| {
+            *value = key;
+            ControlFlow::Continue(())
+        });
+        assert!(flow.is_continue());
+        tree.validate();
+        assert!(tree.iter().filter(|e| e.value == e.key).count() == 11);
+        assert!(tree.find(20).unwrap().value == 1);
 
-            // All leaves should be at the same level.
-            if state.leaf_level == 0 {
-                state.leaf_level = level;
-            } else {
-                assert!(level == state.leaf_level);
+        // Stops at the first value above 15.
+        let mut visited = 0;
+        let flow = tree.for_each_in_range_mut(.., |_, value| {
+            visited += 1;
+            if *value > 15 {
+                return ControlFlow::Break(());
             }
-            return;
-        }
-
-        // There should be at least 2 children.
-        let child1 = node.child1.as_ref().unwrap();
-        let child2 = node.child2.as_ref().unwrap();
+            *value = 0;
+            ControlFlow::Continue(())
+        });
+        assert!(flow.is_break());
+        assert!(visited == 17);
+        tree.validate();
+        assert!(tree.find(16).unwrap().value == 16);
+        assert!(tree.find(15).unwrap().value == 0);
+    }
 
-        // Check child1, child2 ordering.
-        Self::validate_node_less_than(child1, node.elem1.key);
-        Self::validate_node_greater_than(child2, node.elem1.key);
+    #[test]
+    fn test_index() {
+        let tree = TwoThreeTree::from(vec![(1, 10), (2, 20), (3, 30)]);
+        assert!(tree[&2] == 20);
+        let result = catch_unwind(AssertUnwindSafe(|| tree[&4]));
+        assert!(result.is_err());
+    }
 
-        if let Some(elem2) = node.elem2 {
-            // Check child3 ordering.
-            let child3 = node.child3.as_ref().unwrap();
-            Self::validate_node_greater_than(child3, elem2.key);
+    #[test]
+    fn test_default() {
+        #[derive(Default)]
+        struct Index {
+            by_id: TwoThreeTree,
+            by_score: TwoThreeTree<crate::aggregate::Stats>,
         }
 
-        // Check the children.
-        Self::validate_node(child1, level + 1, state);
-        Self::validate_node(child2, level + 1, state);
-        if let Some(ref child3) = node.child3 {
-            Self::validate_node(child3, level + 1, state);
-        }
+        let mut index = Index::default();
+        assert!(index.by_id.is_empty() && index.by_score.is_empty());
+        index.by_id.insert(Element { key: 1, value: 2 });
+        index.by_id.validate();
     }
 
-    // Checks that the node's elements are less than the given value.
-    fn validate_node_less_than(node: &TwoThreeNode, key_value: usize) {
-        assert!(node.elem1.key <= key_value);
-        if let Some(elem2) = node.elem2 {
-            assert!(elem2.key <= key_value);
+    #[test]
+    fn test_comparator() {
+        let mut tree = TwoThreeTree::with_comparator(|a, b| b.cmp(a));
+        for i in 0..200 {
+            tree.insert(Element {
+                key: (i * 37) % 100,
+                value: i,
+            });
         }
+        for key in (0..100).step_by(3) {
+            assert!(tree.delete(key) && tree.delete(key) && !tree.delete(key));
+        }
+        tree.validate();
+        let keys: Vec<usize> = (0..100).rev().filter(|key| key % 3 != 0).collect();
+        let twice: Vec<usize> = keys.iter().flat_map(|&key| [key, key]).collect();
+        assert!(tree.iter().map(|e| e.key).eq(twice.iter().copied()));
+        assert!(tree.size() == twice.len() && tree.first().unwrap().key == 98);
+        assert!(tree.find(50).is_some() && tree.find(51).is_none());
+        assert!(tree.rank(95) == 4 && tree.count(97, None) == 2);
+        assert!(tree.iter_from(51).next().unwrap().key == 50);
+
+        // Ranges run in the order of the tree, so they start at the larger key.
+        let (from, to) = (Bound::Included(60), Bound::Included(55));
+        let mut visited = Vec::new();
+        let _ = tree.for_each_in_range_mut((from, to), |key, _| {
+            visited.push(key);
+            std::ops::ControlFlow::Continue(())
+        });
+        assert!(visited == [59, 59, 58, 58, 56, 56, 55, 55]);
+        assert!(tree.clone_range(..90).iter().all(|e| e.key > 90));
+        let (from, to) = (Bound::Included(10), Bound::Excluded(5));
+        assert!(tree.find_augmented((from, to), |_| true).len() == 6);
+        assert!(tree.rekey(50, 49) && tree.find(49).is_some());
+
+        let mut below = tree.split_off(50);
+        below.validate();
+        tree.validate();
+        assert!(tree.iter().all(|e| e.key > 50) && below.iter().all(|e| e.key <= 50));
+        below.insert(Element { key: 0, value: 0 });
+        assert!(below.last().unwrap().key == 0);
+
+        // The helpers of the other modules follow the comparator too.
+        let descending = || {
+            let mut tree = TwoThreeTree::with_comparator(|a, b| b.cmp(a));
+            for key in 0..10 {
+                tree.insert(Element { key, value: key });
+            }
+            tree
+        };
+        let keys =
+            |elements: &[Element]| -> Vec<usize> { elements.iter().map(|e| e.key).collect() };
+        let mut tree = descending();
+        let (from, to) = (Bound::Included(6), Bound::Excluded(3));
+        assert!(tree.window((from, to)).map(|e| e.key).eq([6, 5, 4]));
+        assert!(tree.range(..5).map(|e| e.key).eq([9, 8, 7, 6]));
+        let page = tree.scan(None, 4);
+        assert!(keys(&page.elements) == [9, 8, 7, 6]);
+        assert!(keys(&tree.scan(page.next, 10).elements) == [5, 4, 3, 2, 1, 0]);
+        let mut other = descending();
+        other.insert(Element { key: 10, value: 10 });
+        let edits: Vec<DiffEntry> = tree.diff(&other).collect();
+        assert!(edits == [DiffEntry::Added { key: 10, value: 10 }]);
+        let frozen = other.freeze();
+        assert!(keys(frozen.range((Bound::Included(8), Bound::Included(6)))) == [8, 7, 6]);
+        let thawed = frozen.thaw();
+        thawed.validate();
+        assert!(thawed.first().unwrap().key == 10 && thawed.find(3).is_some());
+        assert!(tree.evict_older_than(7) == 2 && tree.first().unwrap().key == 7);
     }
 
-    // Checks that the node's elements are greater than the given value.
-    fn validate_node_greater_than(node: &TwoThreeNode, key_value: usize) {
-        assert!(node.elem1.key >= key_value);
-        if let Some(elem2) = node.elem2 {
-            assert!(elem2.key >= key_value);
+    #[test]
+    fn test_policy() {
+        let mut tree = TwoThreeTree::with_policy(DuplicatePolicy::Replace);
+        tree.insert(Element { key: 1, value: 10 });
+        tree.insert(Element { key: 1, value: 11 });
+        assert!(tree.size() == 1 && tree.find(1).unwrap().value == 11);
+
+        let mut tree = TwoThreeTree::with_policy(DuplicatePolicy::Ignore);
+        tree.insert(Element { key: 1, value: 10 });
+        tree.insert(Element { key: 1, value: 11 });
+        assert!(tree.size() == 1 && tree.find(1).unwrap().value == 10);
+        assert!(!tree.apply_batch([Op::Insert(1, 12), Op::Delete(1), Op::Delete(1)]));
+        assert!(tree.apply_batch([Op::Insert(2, 20), Op::Insert(2, 21), Op::Delete(2)]));
+        assert!(tree.size() == 1);
+
+        // The policy carries over to the trees split off.
+        let mut above = tree.split_off(0);
+        above.insert(Element { key: 1, value: 12 });
+        assert!(above.size() == 1 && above.find(1).unwrap().value == 10);
+    }
+
+    #[test]
+    fn test_duplicate_order() {
+        let mut tree = TwoThreeTree::new();
+        for i in 0..2000 {
+            tree.insert(Element {
+                key: (i * 7919) % 37,
+                value: i,
+            });
+            if i % 3 == 0 {
+                tree.delete((i * 31) % 37);
+            }
         }
+        tree.validate();
+        // Equal keys come in insertion order.
+        assert!(tree
+            .iter()
+            .zip(tree.iter().skip(1))
+            .all(|(a, b)| a.key < b.key || a.value < b.value));
     }
-}
 
-// Tracks the leaf level observed during validation recursion.
-struct ValidateState {
-    leaf_level: usize,
-    elements: usize,
-}
+    #[test]
+    fn test_range() {
+        let mut tree = TwoThreeTree::new();
+        for key in (0..100).chain([50, 50]) {
+            tree.insert(Element { key, value: key });
+        }
+        let keys = |range: (Bound<usize>, Bound<usize>)| -> Vec<usize> {
+            tree.range(range).map(|e| e.key).collect()
+        };
+        assert!(keys((Bound::Included(49), Bound::Excluded(52))) == [49, 50, 50, 50, 51]);
+        assert!(keys((Bound::Excluded(50), Bound::Included(51))) == [51]);
+        assert!(keys((Bound::Included(98), Bound::Unbounded)) == [98, 99]);
+        assert!(keys((Bound::Included(60), Bound::Included(50))).is_empty());
+        assert!(tree.range(..).count() == 102 && tree.range(100..).next().is_none());
+    }
 
-impl ValidateState {
-    fn new() -> ValidateState {
-        ValidateState {
-            leaf_level: 0,
-            elements: 0,
+    #[test]
+    fn test_rekey_policy() {
+        let elements = |tree: &TwoThreeTree| -> Vec<(usize, usize)> {
+            tree.iter().map(|e| (e.key, e.value)).collect()
+        };
+        for policy in [
+            DuplicatePolicy::Allow,
+            DuplicatePolicy::Replace,
+            DuplicatePolicy::Ignore,
+        ] {
+            let mut tree = TwoThreeTree::with_policy(policy);
+            for key in [10, 20, 30] {
+                tree.insert(Element { key, value: key });
+            }
+            // In place, then across another element.
+            let moved = [tree.rekey(10, 20), tree.rekey(20, 30)];
+            tree.validate();
+            match policy {
+                DuplicatePolicy::Allow => {
+                    assert!(moved == [true, true]);
+                    assert!(tree.iter().map(|e| e.key).eq([20, 30, 30]));
+                }
+                DuplicatePolicy::Replace => {
+                    assert!(moved == [true, true]);
+                    assert!(elements(&tree) == [(30, 10)]);
+                }
+                DuplicatePolicy::Ignore => {
+                    assert!(moved == [false, false]);
+                    assert!(elements(&tree) == [(10, 10), (20, 20), (30, 30)]);
+                    assert!(!tree.rekey(10, 30) && tree.size() == 3);
+                }
+            }
         }
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::{Element, TwoThreeTree};
+    #[test]
+    fn test_map_values() {
+        let mut tree = TwoThreeTree::new();
+        for key in [5, 3, 8, 1, 4, 7, 9, 2, 6] {
+            tree.insert(Element { key, value: key });
+        }
+        let mut order = Vec::new();
+        let mapped = tree.map_values(|value| {
+            order.push(value);
+            value * 10
+        });
+        mapped.validate();
+        assert!(order == (1..=9).collect::<Vec<_>>());
+        assert!(mapped.iter().all(|e| e.value == e.key * 10));
+        assert!(mapped.depth_histogram() == tree.depth_histogram());
 
-    fn insert(tree: &mut TwoThreeTree, key: usize) {
-        println!("== Insert {}", key);
-        tree.insert(Element {
-            key: key,
-            value: key,
+        let mapped = mapped.map_elements(|key, value| value - key);
+        mapped.validate();
+        assert!(mapped.iter().all(|e| e.value == e.key * 9));
+    }
+
+    #[test]
+    fn test_retain_mut() {
+        let mut tree = TwoThreeTree::from_sorted((0..100).map(|key| Element { key, value: key }));
+        // Ages the values by one, dropping the expired ones.
+        tree.retain_mut(|_, age| {
+            *age += 1;
+            *age < 50
         });
-        tree.print();
         tree.validate();
+        assert!(tree.size() == 49);
+        assert!(tree.iter().all(|e| e.value == e.key + 1));
+        assert!(tree.last().unwrap().key == 48);
+    }
 
-        let found_element = tree.find(key);
-        assert!(found_element.unwrap().key == key);
+    #[test]
+    fn test_windows() {
+        assert!(TwoThreeTree::new().windows().next().is_none());
+        let tree = TwoThreeTree::from(vec![(1, 10)]);
+        assert!(tree.windows().next().is_none());
+
+        let tree = TwoThreeTree::from(vec![(1, 10), (4, 40), (9, 90)]);
+        let pairs: Vec<((usize, usize), (usize, usize))> = tree
+            .windows()
+            .map(|(a, b)| ((a.key, a.value), (b.key, b.value)))
+            .collect();
+        assert!(pairs == vec![((1, 10), (4, 40)), ((4, 40), (9, 90))]);
     }
 
-    fn delete(tree: &mut TwoThreeTree, key: usize) {
-        println!("== Delete {}", key);
-        assert!(tree.delete(key));
-        tree.print();
-        tree.validate();
+    #[test]
+    fn test_chunks() {
+        assert!(TwoThreeTree::new().chunks(3).next().is_none());
+        let tree = TwoThreeTree::from_sorted((0..10).map(|key| Element { key, value: key }));
+        let chunks: Vec<Vec<usize>> = tree
+            .chunks(4)
+            .map(|chunk| chunk.iter().map(|e| e.key).collect())
+            .collect();
+        assert!(chunks == vec![vec![0, 1, 2, 3], vec![4, 5, 6, 7], vec![8, 9]]);
     }
 
     #[test]
-    fn test_simple_1() {
-        let mut tree = TwoThreeTree::new();
-        insert(&mut tree, 2);
-        insert(&mut tree, 1);
-        insert(&mut tree, 3);
-        insert(&mut tree, 5);
-        insert(&mut tree, 4);
-        assert!(tree.size() == 5);
-        delete(&mut tree, 3);
-        assert!(tree.find(3).is_none());
-        delete(&mut tree, 1);
-        delete(&mut tree, 2);
-        delete(&mut tree, 4);
-        delete(&mut tree, 5);
+    fn test_from_sorted_runs() {
+        for num_runs in [0, 1, 3, 7] {
+            for len in [0, 1, 2, 5, 40, 300] {
+                let runs: Vec<Vec<Element>> = (0..num_runs)
+                    .map(|run| {
+                        (0..len)
+                            .map(|i| Element {
+                                key: (i * (run + 2)) / 3,
+                                value: run * 1000 + i,
+                            })
+                            .collect()
+                    })
+                    .collect();
+                let mut expected: Vec<usize> = runs.iter().flatten().map(|e| e.key).collect();
+                expected.sort();
+
+                let tree = TwoThreeTree::from_sorted_runs(runs);
+                tree.validate();
+                assert!(tree.size() == expected.len());
+                assert!(tree.iter().map(|e| e.key).eq(expected));
+            }
+        }
     }
 
     #[test]
-    fn test_ordered_insert_delete() {
-        let num_elements = 50;
+    fn test_panicking_callbacks() {
+        let mut tree: TwoThreeTree<crate::aggregate::Sum> =
+            TwoThreeTree::build_sorted((0..100).map(|key| Element { key, value: 1 }));
 
-        let mut tree = TwoThreeTree::new();
-        for i in 0..num_elements {
-            insert(&mut tree, i);
+        // A panic in update() leaves the element unchanged.
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            tree.update(50, |value| {
+                *value = 100;
+                panic!("update");
+            })
+        }));
+        assert!(result.is_err());
+        tree.validate();
+        assert!(tree.find(50).unwrap().value == 1);
+
+        // A panic in for_each_in_range_mut() keeps the earlier changes, with valid aggregates.
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            tree.for_each_in_range_mut(.., |key, value| {
+                *value = 2;
+                if key == 60 {
+                    panic!("for_each_in_range_mut");
+                }
+                std::ops::ControlFlow::Continue(())
+            })
+        }));
+        assert!(result.is_err());
+        tree.validate();
+        assert!(tree.iter().filter(|e| e.value == 2).count() == 60);
+        assert!(tree.find(60).unwrap().value == 1);
+
+        // A panicking Augment loses no element in insert() and delete(). The aggregates left
+        // stale are recomputed by the next mutation.
+        thread_local! {
+            static FAIL: Cell<bool> = const { Cell::new(false) };
         }
-        for i in 0..num_elements {
-            delete(&mut tree, i);
+        struct Fragile;
+
+        impl Augment for Fragile {
+            type Value = usize;
+
+            fn identity() -> usize {
+                0
+            }
+
+            fn element(element: &Element) -> usize {
+                element.value
+            }
+
+            fn combine(left: &usize, right: &usize) -> usize {
+                assert!(!FAIL.get(), "combine");
+                left + right
+            }
         }
-        assert!(tree.is_empty());
 
-        for i in (0..num_elements).rev() {
-            insert(&mut tree, i);
+        let mut tree: TwoThreeTree<Fragile> = TwoThreeTree::with_augment();
+        for key in 0..200 {
+            FAIL.set(key % 3 == 0);
+            let result = catch_unwind(AssertUnwindSafe(|| {
+                tree.insert(Element { key, value: key })
+            }));
+            assert!(result.is_err() == (key % 3 == 0));
+            FAIL.set(false);
+            tree.validate();
         }
-        for i in 0..num_elements {
-            delete(&mut tree, i);
+        for key in (0..200).step_by(2) {
+            FAIL.set(key % 3 == 0);
+            let result = catch_unwind(AssertUnwindSafe(|| tree.delete(key)));
+            assert!(result.map_or(key % 3 == 0, |deleted| deleted && key % 3 != 0));
+            FAIL.set(false);
+            tree.validate();
+        }
+        assert!(!tree.delete(0));
+        tree.validate();
+        assert!(tree.iter().map(|e| e.key).eq((1..200).step_by(2)));
+        assert!(tree.query_range(..) == (1..200).step_by(2).sum::<usize>());
+
+        // A panicking comparator leaves the tree unchanged. It panics between 13 and its
+        // neighbors, i.e. deep in the tree.
+        fn compare(a: &usize, b: &usize) -> Ordering {
+            assert!(a.abs_diff(*b) != 1 || (*a != 13 && *b != 13), "compare");
+            a.cmp(b)
         }
+        let mut tree = TwoThreeTree::with_comparator(compare);
+        for key in (0..100).filter(|&key| key != 13) {
+            tree.insert(Element { key, value: key });
+        }
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            tree.insert(Element { key: 13, value: 13 })
+        }));
+        assert!(result.is_err());
+        assert!(catch_unwind(AssertUnwindSafe(|| tree.delete(13))).is_err());
+        tree.validate();
+        assert!(tree.size() == 99 && tree.iter().map(|e| e.key).eq((0..100).filter(|&k| k != 13)));
     }
 
     #[test]
-    fn test_random_insert_delete() {
-        let num_elements = 80;
+    fn test_find_nearest() {
+        use super::Nearest;
+
+        assert!(TwoThreeTree::new().find_nearest(5) == Nearest::Empty);
+        let tree = TwoThreeTree::from_sorted((1..20).map(|i| Element {
+            key: i * 10,
+            value: i,
+        }));
+        assert!(tree.find_nearest(50) == Nearest::Exact(&Element { key: 50, value: 5 }));
+        assert!(tree.find_nearest(53) == Nearest::Below(&Element { key: 50, value: 5 }));
+        assert!(tree.find_nearest(55) == Nearest::Below(&Element { key: 50, value: 5 }));
+        assert!(tree.find_nearest(57) == Nearest::Above(&Element { key: 60, value: 6 }));
+        assert!(tree.find_nearest(0) == Nearest::Above(&Element { key: 10, value: 1 }));
+        assert!(
+            tree.find_nearest(1000)
+                == Nearest::Below(&Element {
+                    key: 190,
+                    value: 19
+                })
+        );
+        for key in 0..200 {
+            let expected = tree
+                .iter()
+                .min_by_key(|e| (e.key.abs_diff(key), e.key))
+                .unwrap();
+            match tree.find_nearest(key) {
+                Nearest::Exact(e) | Nearest::Below(e) | Nearest::Above(e) => {
+                    assert!(*e == expected)
+                }
+                Nearest::Empty => unreachable!(),
+            }
+        }
+    }
 
+    #[test]
+    fn test_range_by_rank() {
         let mut tree = TwoThreeTree::new();
-        let mut elements: Vec<usize> = Vec::new();
-        for i in 0..num_elements {
-            let elem = (num_elements + i * 71329) & 0xfffffff;
-            elements.push(elem);
-            insert(&mut tree, elem);
+        assert!(tree.range_by_rank(..).next().is_none());
+        for key in (0..500).rev() {
+            tree.insert(Element {
+                key: key * 2,
+                value: key,
+            });
         }
-        let mut n = 0;
-        for _ in 0..elements.len() {
-            n = (n + 13) % elements.len();
-            delete(&mut tree, elements[n]);
+        for (start, end) in [(0, 0), (0, 1), (100, 200), (250, 251), (499, 500), (10, 5)] {
+            assert!(tree
+                .range_by_rank(start..end)
+                .map(|e| e.value)
+                .eq(start..end.min(500)));
         }
-        assert!(tree.is_empty());
+        assert!(tree.range_by_rank(498..).map(|e| e.value).eq([498, 499]));
+        assert!(tree.range_by_rank(..=2).map(|e| e.key).eq([0, 2, 4]));
+        assert!(tree.range_by_rank(500..).next().is_none());
+        for rank in 0..500 {
+            assert!(tree.range_by_rank(rank..).next() == tree.select(rank));
+        }
+    }
+
+    #[test]
+    fn test_top_k() {
+        let mut tree = TwoThreeTree::new();
+        assert!(tree.top_k(3).is_empty() && tree.bottom_k(3).is_empty());
+        for (player, score) in [50, 20, 90, 70, 20, 10].into_iter().enumerate() {
+            tree.insert(Element {
+                key: score,
+                value: player,
+            });
+        }
+        assert!(tree.top_k(3).iter().map(|e| e.key).eq([90, 70, 50]));
+        assert!(tree.bottom_k(3).iter().map(|e| e.key).eq([10, 20, 20]));
+        assert!(tree.top_k(10).len() == 6 && tree.top_k(0).is_empty());
+        assert!(tree.top_k(1)[0].value == 2);
+    }
+
+    #[test]
+    fn test_element_equality() {
+        let a = Element { key: 1, value: 10 };
+        let b = Element { key: 1, value: 20 };
+        assert!(a != b && a < b && b < Element { key: 2, value: 0 });
+        assert!(Element::from((1, 10)) == a && <(usize, usize)>::from(b) == (1, 20));
+
+        // The tree still treats the elements as having equal keys.
+        let mut tree = TwoThreeTree::new();
+        tree.insert(b);
+        tree.insert(a);
+        assert!(tree.size() == 2 && tree.find(1).is_some_and(|e| e.key == 1));
+        assert!(tree.iter().all(|e| e == a || e == b));
+
+        // Elements of other key and value types, e.g. for callers keeping their own pairs.
+        let named = Element::from(("b", String::from("x")));
+        assert!(named < Element::from(("b", String::from("y"))) && named.key == "b");
+        assert!(<(&str, String)>::from(named.clone()) == ("b", String::from("x")));
+        assert!(named != Element::from(("b", String::from("z"))));
     }
 }
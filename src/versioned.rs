@@ -0,0 +1,136 @@
+// A versioned map on top of a 2-3 Tree, for time-travel queries.
+//
+// Every mutation creates a new version. Each key keeps all the values it had, so the state at
+// any past version can be read back. The tree maps each key to records of its values, and a
+// deletion is recorded as a record without a value.
+
+use std::iter;
+
+use crate::two_three_tree::{Element, TwoThreeTree};
+
+// A value of a key from a version on.
+struct Record {
+    version: u64,
+
+    // None if the key was deleted.
+    value: Option<usize>,
+}
+
+// A map whose past versions remain readable.
+pub struct VersionedTwoThreeTree {
+    // Maps each key to the indexes of its records.
+    tree: TwoThreeTree,
+
+    records: Vec<Record>,
+
+    // The latest version. Version 0 is the empty map.
+    version: u64,
+}
+
+impl VersionedTwoThreeTree {
+    pub fn new() -> VersionedTwoThreeTree {
+        VersionedTwoThreeTree {
+            tree: TwoThreeTree::new(),
+            records: Vec::new(),
+            version: 0,
+        }
+    }
+
+    // Returns the latest version.
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    // Sets the value of a key. Returns the new version.
+    pub fn insert(&mut self, key: usize, value: usize) -> u64 {
+        self.record(key, Some(value))
+    }
+
+    // Deletes a key. Returns the new version, or None if the key is not in the map.
+    pub fn remove(&mut self, key: usize) -> Option<u64> {
+        self.get(key)?;
+        Some(self.record(key, None))
+    }
+
+    // Returns the latest value of a key.
+    pub fn get(&self, key: usize) -> Option<usize> {
+        self.get_at(self.version, key)
+    }
+
+    // Returns the value of a key as of the given version.
+    pub fn get_at(&self, version: u64, key: usize) -> Option<usize> {
+        let indexes = self.tree.iter_from(key).take_while(|e| e.key == key);
+        self.value_at(version, indexes.map(|e| e.value))
+    }
+
+    // Returns the elements of the map as of the given version, in key order.
+    pub fn iter_at(&self, version: u64) -> impl Iterator<Item = Element> + '_ {
+        let mut entries = self.tree.iter().peekable();
+        iter::from_fn(move || loop {
+            let key = entries.peek()?.key;
+            let indexes = iter::from_fn(|| entries.next_if(|e| e.key == key)).map(|e| e.value);
+            if let Some(value) = self.value_at(version, indexes) {
+                return Some(Element { key, value });
+            }
+        })
+    }
+
+    // Appends a record for a key in a new version.
+    fn record(&mut self, key: usize, value: Option<usize>) -> u64 {
+        self.version += 1;
+        self.tree.insert(Element {
+            key,
+            value: self.records.len(),
+        });
+        self.records.push(Record {
+            version: self.version,
+            value,
+        });
+        self.version
+    }
+
+    // Returns the value of the latest of the records at or before the version.
+    fn value_at(&self, version: u64, indexes: impl Iterator<Item = usize>) -> Option<usize> {
+        indexes
+            .map(|index| &self.records[index])
+            .filter(|record| record.version <= version)
+            .max_by_key(|record| record.version)?
+            .value
+    }
+}
+
+impl Default for VersionedTwoThreeTree {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::VersionedTwoThreeTree;
+
+    #[test]
+    fn test_versioned() {
+        let mut map = VersionedTwoThreeTree::new();
+        let v1 = map.insert(1, 10);
+        let v2 = map.insert(2, 20);
+        let v3 = map.insert(1, 11);
+        let v4 = map.remove(2).unwrap();
+        assert!(map.remove(2).is_none());
+        assert!(map.version() == v4);
+
+        assert!(map.get_at(0, 1).is_none());
+        assert!(map.get_at(v1, 1) == Some(10) && map.get_at(v1, 2).is_none());
+        assert!(map.get_at(v2, 1) == Some(10) && map.get_at(v2, 2) == Some(20));
+        assert!(map.get_at(v3, 1) == Some(11));
+        assert!(map.get(1) == Some(11) && map.get(2).is_none());
+
+        let pairs = |version| -> Vec<(usize, usize)> {
+            map.iter_at(version).map(|e| (e.key, e.value)).collect()
+        };
+        assert!(pairs(0).is_empty());
+        assert!(pairs(v2) == [(1, 10), (2, 20)]);
+        assert!(pairs(v3) == [(1, 11), (2, 20)]);
+        assert!(pairs(v4) == [(1, 11)]);
+    }
+}
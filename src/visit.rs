@@ -0,0 +1,150 @@
+// Recursive traversals of a 2-3 Tree with visitor hooks, for analyses that need more than the
+// in-order sequence of elements.
+
+use std::ops::ControlFlow;
+
+use crate::two_three_tree::{Augment, Element, NodeRef, TwoThreeTree};
+
+// Hooks called by TwoThreeTree::visit(). Each hook may return ControlFlow::Break to stop the
+// traversal. The depth of the root is 0.
+pub trait TreeVisitor<A: Augment = ()> {
+    // Called on a node before its subtrees.
+    fn pre_order(&mut self, _node: NodeRef<'_, A>, _depth: usize) -> ControlFlow<()> {
+        ControlFlow::Continue(())
+    }
+
+    // Called on each element in key order, with the depth of its node.
+    fn in_order(&mut self, _element: Element, _depth: usize) -> ControlFlow<()> {
+        ControlFlow::Continue(())
+    }
+
+    // Called on a node after its subtrees.
+    fn post_order(&mut self, _node: NodeRef<'_, A>, _depth: usize) -> ControlFlow<()> {
+        ControlFlow::Continue(())
+    }
+}
+
+// Adapts a folding function to an in-order visitor.
+struct Fold<B, F> {
+    acc: Option<B>,
+    f: F,
+}
+
+impl<A: Augment, B, F: FnMut(B, Element) -> ControlFlow<B, B>> TreeVisitor<A> for Fold<B, F> {
+    fn in_order(&mut self, element: Element, _depth: usize) -> ControlFlow<()> {
+        match (self.f)(self.acc.take().unwrap(), element) {
+            ControlFlow::Continue(acc) => {
+                self.acc = Some(acc);
+                ControlFlow::Continue(())
+            }
+            ControlFlow::Break(acc) => {
+                self.acc = Some(acc);
+                ControlFlow::Break(())
+            }
+        }
+    }
+}
+
+impl<A: Augment> TwoThreeTree<A> {
+    // Walks the tree depth-first, calling the hooks of the visitor.
+    // Returns ControlFlow::Break if a hook stopped the traversal.
+    pub fn visit<V: TreeVisitor<A>>(&self, visitor: &mut V) -> ControlFlow<()> {
+        match self.root_ref() {
+            Some(root) => Self::visit_node(root, 0, visitor),
+            None => ControlFlow::Continue(()),
+        }
+    }
+
+    fn visit_node<V: TreeVisitor<A>>(
+        node: NodeRef<'_, A>,
+        depth: usize,
+        visitor: &mut V,
+    ) -> ControlFlow<()> {
+        visitor.pre_order(node, depth)?;
+        let mut children = node.children();
+        for element in node.elements() {
+            if let Some(child) = children.next() {
+                Self::visit_node(child, depth + 1, visitor)?;
+            }
+            visitor.in_order(element, depth)?;
+        }
+        if let Some(child) = children.next() {
+            Self::visit_node(child, depth + 1, visitor)?;
+        }
+        visitor.post_order(node, depth)
+    }
+
+    // Folds the elements in key order. The function returns ControlFlow::Break to stop early
+    // with the given result.
+    pub fn fold<B>(&self, init: B, f: impl FnMut(B, Element) -> ControlFlow<B, B>) -> B {
+        let mut fold = Fold { acc: Some(init), f };
+        let _ = self.visit(&mut fold);
+        fold.acc.unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TreeVisitor;
+    use crate::two_three_tree::{Element, NodeRef, TwoThreeTree};
+    use std::ops::ControlFlow;
+
+    #[test]
+    fn test_visit() {
+        // Records the traversal, stopping at the given key.
+        struct Recorder {
+            events: Vec<String>,
+            stop: usize,
+        }
+
+        impl TreeVisitor for Recorder {
+            fn pre_order(&mut self, node: NodeRef<'_>, depth: usize) -> ControlFlow<()> {
+                let first = node.elements().next().unwrap().key;
+                self.events.push(format!("pre {} {}", first, depth));
+                ControlFlow::Continue(())
+            }
+            fn in_order(&mut self, element: Element, _depth: usize) -> ControlFlow<()> {
+                self.events.push(format!("in {}", element.key));
+                if element.key == self.stop {
+                    return ControlFlow::Break(());
+                }
+                ControlFlow::Continue(())
+            }
+            fn post_order(&mut self, node: NodeRef<'_>, _depth: usize) -> ControlFlow<()> {
+                let first = node.elements().next().unwrap().key;
+                self.events.push(format!("post {}", first));
+                ControlFlow::Continue(())
+            }
+        }
+
+        // (2) with the children (1) and (3).
+        let tree = TwoThreeTree::from(vec![(1, 1), (2, 2), (3, 3)]);
+        let mut recorder = Recorder {
+            events: Vec::new(),
+            stop: usize::MAX,
+        };
+        assert!(tree.visit(&mut recorder).is_continue());
+        let expected = "pre 2 0,pre 1 1,in 1,post 1,in 2,pre 3 1,in 3,post 3,post 2";
+        assert!(recorder.events.join(",") == expected);
+
+        recorder.events.clear();
+        recorder.stop = 2;
+        assert!(tree.visit(&mut recorder).is_break());
+        assert!(recorder.events.join(",") == "pre 2 0,pre 1 1,in 1,post 1,in 2");
+    }
+
+    #[test]
+    fn test_fold() {
+        let tree = TwoThreeTree::from_sorted((0..100).map(|key| Element { key, value: key }));
+        assert!(tree.fold(0, |sum, e| ControlFlow::Continue(sum + e.value)) == 4950);
+        // Stops at the first element above 10.
+        let first = tree.fold(None, |_, e| {
+            if e.key > 10 {
+                ControlFlow::Break(Some(e.key))
+            } else {
+                ControlFlow::Continue(None)
+            }
+        });
+        assert!(first == Some(11));
+    }
+}
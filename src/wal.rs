@@ -0,0 +1,139 @@
+// A write-ahead log of page images.
+//
+// Each record holds the pages written by one mutation, followed by a checksum. Replaying a
+// record writes the same pages again, so replaying is idempotent. A torn record at the end of
+// the log, e.g. after a crash during an append, fails its checksum and is ignored.
+
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use crate::paged::{NodeId, PAGE_SIZE};
+
+// Size of a logged page: its ID and its contents.
+const ENTRY_SIZE: usize = 4 + PAGE_SIZE;
+
+pub(crate) struct Wal {
+    file: File,
+}
+
+impl Wal {
+    pub(crate) fn open(path: impl AsRef<Path>) -> io::Result<Wal> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        Ok(Wal { file })
+    }
+
+    // Appends a record of the pages and makes it durable.
+    pub(crate) fn append(&mut self, pages: &BTreeMap<NodeId, [u8; PAGE_SIZE]>) -> io::Result<()> {
+        let mut record = Vec::with_capacity(4 + pages.len() * ENTRY_SIZE + 8);
+        record.extend_from_slice(&(pages.len() as u32).to_le_bytes());
+        for (id, page) in pages.iter() {
+            record.extend_from_slice(&id.to_le_bytes());
+            record.extend_from_slice(page);
+        }
+        record.extend_from_slice(&checksum(&record).to_le_bytes());
+        self.file.seek(SeekFrom::End(0))?;
+        self.file.write_all(&record)?;
+        self.file.sync_data()
+    }
+
+    // Calls apply for each page of the complete records, in log order.
+    pub(crate) fn replay(
+        &mut self,
+        mut apply: impl FnMut(NodeId, &[u8; PAGE_SIZE]) -> io::Result<()>,
+    ) -> io::Result<()> {
+        let mut log = Vec::new();
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.read_to_end(&mut log)?;
+
+        let mut rest = &log[..];
+        while rest.len() >= 4 {
+            let count = u32::from_le_bytes(rest[..4].try_into().unwrap()) as usize;
+            let Some(len) = count
+                .checked_mul(ENTRY_SIZE)
+                .and_then(|len| len.checked_add(4))
+            else {
+                break;
+            };
+            if rest.len() < len + 8 {
+                break;
+            }
+            let (record, sum) = (&rest[..len], &rest[len..len + 8]);
+            if checksum(record) != u64::from_le_bytes(sum.try_into().unwrap()) {
+                break;
+            }
+            for entry in record[4..].chunks_exact(ENTRY_SIZE) {
+                let id = NodeId::from_le_bytes(entry[..4].try_into().unwrap());
+                apply(id, entry[4..].try_into().unwrap())?;
+            }
+            rest = &rest[len + 8..];
+        }
+        Ok(())
+    }
+
+    // Empties the log, once all its pages are durable in the store file.
+    pub(crate) fn truncate(&mut self) -> io::Result<()> {
+        self.file.set_len(0)?;
+        self.file.sync_all()
+    }
+}
+
+// The 64-bit FNV-1a hash.
+fn checksum(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::paged::{FileNodeStore, PagedTwoThreeTree};
+    use crate::two_three_tree::Element;
+
+    #[test]
+    fn test_recovery() {
+        let path = std::env::temp_dir().join(format!("two-three-wal-{}", std::process::id()));
+        let wal_path = path.with_extension("wal");
+        let saved = path.with_extension("saved");
+        let mut tree = PagedTwoThreeTree::new(FileNodeStore::open_with_wal(&path).unwrap());
+        for i in 0..100 {
+            tree.insert(Element { key: i, value: i }).unwrap();
+        }
+        tree.flush().unwrap();
+        std::fs::copy(&path, &saved).unwrap();
+        for i in 100..300 {
+            tree.insert(Element { key: i, value: i }).unwrap();
+        }
+        for i in 0..50 {
+            assert!(tree.delete(i).unwrap());
+        }
+        drop(tree);
+
+        // Lose all page writes since the flush, and tear the last record of the log.
+        std::fs::copy(&saved, &path).unwrap();
+        let log = std::fs::OpenOptions::new()
+            .write(true)
+            .open(&wal_path)
+            .unwrap();
+        log.set_len(log.metadata().unwrap().len() - 10).unwrap();
+
+        let mut tree = PagedTwoThreeTree::new(FileNodeStore::open_with_wal(&path).unwrap());
+        assert!(tree.size() == 251);
+        assert!(tree
+            .to_vec()
+            .unwrap()
+            .iter()
+            .map(|e| e.key)
+            .eq((49..300).collect::<Vec<_>>()));
+        assert!(std::fs::metadata(&wal_path).unwrap().len() == 0);
+        for file in [&path, &wal_path, &saved] {
+            std::fs::remove_file(file).unwrap();
+        }
+    }
+}
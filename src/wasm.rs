@@ -0,0 +1,100 @@
+// JavaScript bindings with wasm-bindgen.
+//
+// Keys and values are usize, which is a 32-bit number in WebAssembly. Ranges are returned as
+// typed arrays of keys and values, which are cheaper to pass than arrays of objects.
+
+use wasm_bindgen::prelude::*;
+
+use crate::two_three_tree::{Element, TwoThreeTree};
+
+// A 2-3 Tree, exported to JavaScript as TwoThreeTree.
+#[wasm_bindgen(js_name = TwoThreeTree)]
+pub struct WasmTwoThreeTree {
+    tree: TwoThreeTree,
+}
+
+// The elements in a key range, as parallel arrays.
+#[wasm_bindgen]
+pub struct WasmRange {
+    keys: Vec<usize>,
+    values: Vec<usize>,
+}
+
+#[wasm_bindgen(js_class = TwoThreeTree)]
+impl WasmTwoThreeTree {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> WasmTwoThreeTree {
+        WasmTwoThreeTree {
+            tree: TwoThreeTree::new(),
+        }
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn size(&self) -> usize {
+        self.tree.size()
+    }
+
+    pub fn insert(&mut self, key: usize, value: usize) {
+        self.tree.insert(Element { key, value });
+    }
+
+    // Returns the value of an element with the given key, or undefined.
+    pub fn find(&self, key: usize) -> Option<usize> {
+        self.tree.find(key).map(|e| e.value)
+    }
+
+    // Deletes an element with the given key. Returns true if the element was found.
+    pub fn delete(&mut self, key: usize) -> bool {
+        self.tree.delete(key)
+    }
+
+    // Returns the elements with keys in [start, end), in key order.
+    pub fn range(&self, start: usize, end: usize) -> WasmRange {
+        let (keys, values) = self
+            .tree
+            .iter_from(start)
+            .take_while(|e| e.key < end)
+            .map(|e| (e.key, e.value))
+            .unzip();
+        WasmRange { keys, values }
+    }
+}
+
+impl Default for WasmTwoThreeTree {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[wasm_bindgen]
+impl WasmRange {
+    // The keys, as a Uint32Array.
+    #[wasm_bindgen(getter)]
+    pub fn keys(&self) -> Vec<usize> {
+        self.keys.clone()
+    }
+
+    // The values, as a Uint32Array.
+    #[wasm_bindgen(getter)]
+    pub fn values(&self) -> Vec<usize> {
+        self.values.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::WasmTwoThreeTree;
+
+    #[test]
+    fn test_wasm_tree() {
+        let mut tree = WasmTwoThreeTree::new();
+        for key in 0..100 {
+            tree.insert(key, key + 1);
+        }
+        assert!(tree.delete(50) && tree.size() == 99);
+        assert!(tree.find(10) == Some(11) && tree.find(50).is_none());
+        let range = tree.range(48, 53);
+        assert!(range.keys() == [48, 49, 51, 52]);
+        assert!(range.values() == [49, 50, 52, 53]);
+    }
+}
@@ -0,0 +1,224 @@
+// Generators of operation streams for benchmarks and stress tests.
+//
+// A workload yields an endless stream of inserts, deletes and finds. Keys are drawn from a
+// configurable distribution over [0, key_space), and streams are reproducible from a seed.
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::two_three_tree::{Augment, Element, TwoThreeTree};
+
+// How keys are drawn.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Distribution {
+    // Every key is equally likely.
+    Uniform,
+
+    // Key k has probability proportional to 1 / (k + 1)^exponent, so small keys are hot.
+    Zipf { exponent: f64 },
+
+    // Keys in increasing order, wrapping around.
+    Sequential,
+
+    // Keys in decreasing order, wrapping around.
+    Reverse,
+
+    // Keys within spread of one of a number of evenly spaced centers.
+    Clustered { clusters: usize, spread: usize },
+}
+
+// An operation of a workload.
+#[derive(Clone, Copy)]
+pub enum WorkloadOp {
+    Insert(Element),
+    Delete(usize),
+    Find(usize),
+}
+
+// An endless stream of operations.
+pub struct Workload {
+    distribution: Distribution,
+    key_space: usize,
+
+    // Relative weights of inserts, deletes and finds.
+    mix: [u32; 3],
+
+    rng: StdRng,
+
+    // Number of keys drawn, for the sequential distributions.
+    drawn: usize,
+
+    // Cumulative probabilities of the keys, for the Zipf distribution.
+    zipf_cdf: Vec<f64>,
+}
+
+impl Workload {
+    // Creates a workload with equal numbers of inserts, deletes and finds.
+    pub fn new(distribution: Distribution, key_space: usize, seed: u64) -> Workload {
+        assert!(key_space > 0);
+        let zipf_cdf = match distribution {
+            Distribution::Zipf { exponent } => {
+                let mut total = 0.0;
+                let mut cdf: Vec<f64> = (0..key_space)
+                    .map(|k| {
+                        total += 1.0 / ((k + 1) as f64).powf(exponent);
+                        total
+                    })
+                    .collect();
+                cdf.iter_mut().for_each(|p| *p /= total);
+                cdf
+            }
+            _ => Vec::new(),
+        };
+        Workload {
+            distribution,
+            key_space,
+            mix: [1, 1, 1],
+            rng: StdRng::seed_from_u64(seed),
+            drawn: 0,
+            zipf_cdf,
+        }
+    }
+
+    // Sets the relative weights of inserts, deletes and finds.
+    pub fn with_mix(mut self, inserts: u32, deletes: u32, finds: u32) -> Workload {
+        assert!(inserts + deletes + finds > 0);
+        self.mix = [inserts, deletes, finds];
+        self
+    }
+
+    // Draws the next key.
+    pub fn next_key(&mut self) -> usize {
+        self.drawn += 1;
+        match self.distribution {
+            Distribution::Uniform => self.rng.gen_range(0..self.key_space),
+            Distribution::Zipf { .. } => {
+                let p: f64 = self.rng.gen();
+                self.zipf_cdf
+                    .partition_point(|&q| q < p)
+                    .min(self.key_space - 1)
+            }
+            Distribution::Sequential => (self.drawn - 1) % self.key_space,
+            Distribution::Reverse => self.key_space - 1 - (self.drawn - 1) % self.key_space,
+            Distribution::Clustered { clusters, spread } => {
+                let center =
+                    self.rng.gen_range(0..clusters.max(1)) * self.key_space / clusters.max(1);
+                let offset = self.rng.gen_range(0..=2 * spread);
+                (center + offset)
+                    .saturating_sub(spread)
+                    .min(self.key_space - 1)
+            }
+        }
+    }
+
+    // Applies operations to a tree, returning the number of finds which found an element.
+    pub fn run<A: Augment>(&mut self, tree: &mut TwoThreeTree<A>, ops: usize) -> usize {
+        let mut found = 0;
+        for op in self.take(ops) {
+            match op {
+                WorkloadOp::Insert(element) => tree.insert(element),
+                WorkloadOp::Delete(key) => {
+                    tree.delete(key);
+                }
+                WorkloadOp::Find(key) => found += tree.find(key).is_some() as usize,
+            }
+        }
+        found
+    }
+}
+
+impl Iterator for Workload {
+    type Item = WorkloadOp;
+
+    fn next(&mut self) -> Option<WorkloadOp> {
+        let key = self.next_key();
+        let [inserts, deletes, finds] = self.mix;
+        let pick = self.rng.gen_range(0..inserts + deletes + finds);
+        Some(if pick < inserts {
+            WorkloadOp::Insert(Element {
+                key,
+                value: self.drawn,
+            })
+        } else if pick < inserts + deletes {
+            WorkloadOp::Delete(key)
+        } else {
+            WorkloadOp::Find(key)
+        })
+    }
+}
+
+// Runs a seeded mix of inserts and deletes over the keys below key_space, for the randomized
+// tests of the trees. apply runs an operation on the tree under test and returns, for a delete,
+// whether the key was found, which is checked against a model. Returns the keys left, sorted.
+#[cfg(test)]
+pub(crate) fn random_insert_delete(
+    key_space: usize,
+    ops: usize,
+    mut apply: impl FnMut(WorkloadOp) -> bool,
+) -> Vec<usize> {
+    let mut keys = Vec::new();
+    let workload = Workload::new(Distribution::Uniform, key_space, 42).with_mix(3, 2, 0);
+    for op in workload.take(ops) {
+        match op {
+            WorkloadOp::Insert(element) => {
+                apply(op);
+                keys.push(element.key);
+            }
+            WorkloadOp::Delete(key) => {
+                let found = keys.iter().position(|&k| k == key);
+                assert!(apply(op) == found.is_some());
+                if let Some(i) = found {
+                    keys.swap_remove(i);
+                }
+            }
+            WorkloadOp::Find(_) => unreachable!(),
+        }
+    }
+    keys.sort();
+    keys
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Distribution, Workload};
+    use crate::two_three_tree::TwoThreeTree;
+
+    #[test]
+    fn test_distributions() {
+        let keys = |distribution, n| -> Vec<usize> {
+            let mut workload = Workload::new(distribution, 100, 7);
+            (0..n).map(|_| workload.next_key()).collect()
+        };
+        assert!(keys(Distribution::Sequential, 150)
+            .iter()
+            .copied()
+            .eq((0..100).chain(0..50)));
+        assert!(keys(Distribution::Reverse, 3) == [99, 98, 97]);
+        assert!(keys(Distribution::Uniform, 1000) == keys(Distribution::Uniform, 1000));
+
+        let zipf = keys(Distribution::Zipf { exponent: 1.2 }, 10000);
+        let count = |key| zipf.iter().filter(|&&k| k == key).count();
+        assert!(count(0) > count(1) && count(1) > count(10));
+
+        let clustered = keys(
+            Distribution::Clustered {
+                clusters: 4,
+                spread: 2,
+            },
+            1000,
+        );
+        assert!(clustered
+            .iter()
+            .all(|&k| [0, 25, 50, 75].iter().any(|&c| k.abs_diff(c) <= 2)));
+    }
+
+    #[test]
+    fn test_run() {
+        let mut tree = TwoThreeTree::new();
+        let mut workload =
+            Workload::new(Distribution::Zipf { exponent: 1.0 }, 1000, 1).with_mix(3, 1, 2);
+        let found = workload.run(&mut tree, 10000);
+        tree.validate();
+        assert!(found > 0 && !tree.is_empty());
+    }
+}